@@ -1,35 +1,459 @@
-use std::path::PathBuf;
+use std::{
+    fs::{self, OpenOptions},
+    io::{self, Write},
+    path::{Path, PathBuf},
+    str::FromStr,
+    time::Duration,
+};
 
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
 
 use crate::utils::get_config_dir;
 use log::debug;
 
+/// A commented `config.toml` listing every setting with its default.
+pub const DEFAULT_CONFIG: &str = include_str!("default_config.toml");
+
 /// Represents the main application configuration structure.
 ///
 /// Holds settings related to different parts of the application.
-#[derive(Deserialize, Debug, Default)]
+#[derive(Deserialize, Serialize, Debug, Default, Clone, PartialEq)]
 pub struct Config {
     pub database: Option<DatabaseConfig>,
+    pub history: Option<HistoryConfig>,
     pub keybinds: Option<KeybindingConfig>,
+    pub stats: Option<StatsConfig>,
+    pub ui: Option<UiConfig>,
+    pub search: Option<SearchConfig>,
+}
+
+/// Configuration for recording history.
+#[derive(Deserialize, Serialize, Debug, Default, Clone, PartialEq)]
+pub struct HistoryConfig {
+    /// Whether commands run in the shell are recorded.
+    pub enabled: Option<bool>,
+    /// Whether the database rejects commands already stored with the same cwd and timestamp.
+    pub unique: Option<bool>,
 }
 
 /// Configuration related to keybindings.
-#[derive(Deserialize, Debug, Default)]
+#[derive(Deserialize, Serialize, Debug, Default, Clone, PartialEq)]
 pub struct KeybindingConfig {
     /// Whether to skip setting up keybindings during initialization.
     pub skip_keybinds: Option<bool>,
 }
 
+/// Configuration for searching history.
+#[derive(Deserialize, Serialize, Debug, Default, Clone, PartialEq)]
+pub struct SearchConfig {
+    /// The tokenizer of the search index, applied when the database is created.
+    /// Changing it for an existing database requires a `raven reindex`.
+    pub tokenizer: Option<Tokenizer>,
+    /// Milliseconds an interactive search may run before it is abandoned, 0 never abandons it.
+    pub timeout_ms: Option<u64>,
+    /// Which words of a fuzzy search also match longer words starting with them.
+    pub prefix_policy: Option<PrefixPolicy>,
+    /// How search results are reordered after they are fetched.
+    pub ranker: Option<RankerKind>,
+}
+
+/// Which words of a fuzzy search match as prefixes of longer words, the others only match
+/// whole words.
+#[derive(Deserialize, Serialize, Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum PrefixPolicy {
+    /// Every word, `git st` finds `git status` and `gitk stash`.
+    #[default]
+    All,
+    /// Only the last word, as it is still being typed. `git st` finds `git status` but not
+    /// `gitk stash`.
+    LastOnly,
+    /// No word, `git status` only finds commands containing both whole words.
+    None,
+}
+
+impl PrefixPolicy {
+    /// The name of the policy as used in the config and on the command line.
+    #[must_use]
+    pub fn name(self) -> &'static str {
+        match self {
+            PrefixPolicy::All => "all",
+            PrefixPolicy::LastOnly => "last-only",
+            PrefixPolicy::None => "none",
+        }
+    }
+}
+
+impl FromStr for PrefixPolicy {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        [
+            PrefixPolicy::All,
+            PrefixPolicy::LastOnly,
+            PrefixPolicy::None,
+        ]
+        .into_iter()
+        .find(|policy| policy.name() == value)
+        .ok_or_else(|| format!("invalid prefix policy '{value}', expected all, last-only or none"))
+    }
+}
+
+/// The rankers which can reorder search results, see `[search] ranker`.
+#[derive(Deserialize, Serialize, Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum RankerKind {
+    /// Keep the order of the database, best bm25 match first when ordering by relevance and
+    /// most recent first otherwise.
+    #[default]
+    Bm25Recency,
+    /// Commands run more often among the results first. Each page of results is counted and
+    /// ranked on its own.
+    Frequency,
+}
+
+/// The `SQLite` FTS5 tokenizers supported by the search index.
+#[derive(Deserialize, Serialize, Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Tokenizer {
+    /// Matches whole words and word prefixes.
+    #[default]
+    Unicode61,
+    /// Like `unicode61`, but also matches other forms of English words (`install` finds `installed`).
+    Porter,
+    /// Matches any part of a command (`stall` finds `install`), search terms need at least three
+    /// characters.
+    Trigram,
+}
+
+impl Tokenizer {
+    /// The name of the tokenizer as used by FTS5.
+    #[must_use]
+    pub fn name(self) -> &'static str {
+        match self {
+            Tokenizer::Unicode61 => "unicode61",
+            Tokenizer::Porter => "porter",
+            Tokenizer::Trigram => "trigram",
+        }
+    }
+}
+
+/// Configuration for the interactive search UI.
+#[derive(Deserialize, Serialize, Debug, Default, Clone, PartialEq)]
+pub struct UiConfig {
+    /// Whether long commands wrap in the preview pane instead of being clipped.
+    pub wrap_preview: Option<bool>,
+    /// How precisely the time since a command ran is shown in the history list.
+    pub relative_precision: Option<RelativePrecision>,
+    /// Commands run less than this many seconds ago are shown as `just now`, 0 never does.
+    pub just_now_secs: Option<u64>,
+    /// Render the interactive search inline in this many rows instead of fullscreen.
+    pub height: Option<u16>,
+    /// Color of the time since a command ran when it succeeded.
+    pub success_color: Option<String>,
+    /// Color of the time since a command ran when it was interrupted by a signal, e.g. Ctrl+C.
+    pub interrupted_color: Option<String>,
+    /// Color of the time since a command ran when it failed.
+    pub error_color: Option<String>,
+    /// Milliseconds to wait after the last keystroke before searching, 0 searches on every key.
+    pub search_debounce_ms: Option<u64>,
+    /// The most commands listed when scoped to the current directory or project.
+    pub limit_cwd: Option<usize>,
+    /// The most commands listed when scoped to everything or to a session.
+    pub limit_all: Option<usize>,
+}
+
+/// The precision of the relative times shown in the interactive search UI.
+#[derive(Deserialize, Serialize, Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum RelativePrecision {
+    /// Only the most significant unit, e.g. `1m` for 75 seconds.
+    #[default]
+    Coarse,
+    /// The total in the next smaller unit, e.g. `75s` for 75 seconds or `90m` for an hour and a
+    /// half.
+    Exact,
+}
+
+/// Configuration for how commands are grouped in stats and deduplication.
+#[derive(Deserialize, Serialize, Debug, Default, Clone, PartialEq)]
+pub struct StatsConfig {
+    /// Whether a leading `sudo ` is ignored when grouping commands.
+    pub strip_sudo: Option<bool>,
+}
+
 /// Configuration settings specific to the database.
 ///
 /// Allows specifying the directory path and filename for the database.
-#[derive(Deserialize, Debug, Default)]
+#[derive(Deserialize, Serialize, Debug, Default, Clone, PartialEq)]
 pub struct DatabaseConfig {
     pub database_path: Option<PathBuf>,
     pub database_file: Option<String>,
 }
 
+impl Config {
+    /// The tokenizer of the search index, defaults to `unicode61`.
+    #[must_use]
+    pub fn tokenizer(&self) -> Tokenizer {
+        self.search
+            .as_ref()
+            .and_then(|config| config.tokenizer)
+            .unwrap_or_default()
+    }
+
+    /// Which words of a fuzzy search match as prefixes, defaults to `all`.
+    #[must_use]
+    pub fn prefix_policy(&self) -> PrefixPolicy {
+        self.search
+            .as_ref()
+            .and_then(|config| config.prefix_policy)
+            .unwrap_or_default()
+    }
+
+    /// How search results are reordered after they are fetched, defaults to `bm25-recency`.
+    #[must_use]
+    pub fn ranker(&self) -> RankerKind {
+        self.search
+            .as_ref()
+            .and_then(|config| config.ranker)
+            .unwrap_or_default()
+    }
+
+    /// How long a search may run before it is abandoned, `None` when it may run for as long as
+    /// it takes. Defaults to `None`.
+    #[must_use]
+    pub fn search_timeout(&self) -> Option<Duration> {
+        self.search
+            .as_ref()
+            .and_then(|config| config.timeout_ms)
+            .filter(|timeout| *timeout > 0)
+            .map(Duration::from_millis)
+    }
+
+    /// Whether commands run in the shell are recorded, defaults to true.
+    #[must_use]
+    pub fn history_enabled(&self) -> bool {
+        self.history
+            .as_ref()
+            .and_then(|config| config.enabled)
+            .unwrap_or(true)
+    }
+
+    /// Whether the database rejects duplicate commands, defaults to false.
+    #[must_use]
+    pub fn unique_history(&self) -> bool {
+        self.history
+            .as_ref()
+            .and_then(|config| config.unique)
+            .unwrap_or(false)
+    }
+
+    /// Whether long commands wrap in the preview pane, defaults to true.
+    #[must_use]
+    pub fn wrap_preview(&self) -> bool {
+        self.ui
+            .as_ref()
+            .and_then(|config| config.wrap_preview)
+            .unwrap_or(true)
+    }
+
+    /// The precision of relative times in the history list, defaults to `coarse`.
+    #[must_use]
+    pub fn relative_precision(&self) -> RelativePrecision {
+        self.ui
+            .as_ref()
+            .and_then(|config| config.relative_precision)
+            .unwrap_or_default()
+    }
+
+    /// How recently a command must have run to be shown as `just now` in the history list,
+    /// defaults to 5 seconds.
+    #[must_use]
+    pub fn just_now(&self) -> Duration {
+        Duration::from_secs(
+            self.ui
+                .as_ref()
+                .and_then(|config| config.just_now_secs)
+                .unwrap_or(5),
+        )
+    }
+
+    /// The number of rows the interactive search is rendered inline in, fullscreen when `None`.
+    #[must_use]
+    pub fn height(&self) -> Option<u16> {
+        self.ui.as_ref().and_then(|config| config.height)
+    }
+
+    /// The color of commands which succeeded in the history list, defaults to `blue`.
+    #[must_use]
+    pub fn success_color(&self) -> &str {
+        self.ui
+            .as_ref()
+            .and_then(|config| config.success_color.as_deref())
+            .unwrap_or("blue")
+    }
+
+    /// The color of commands interrupted by a signal in the history list, defaults to `yellow`.
+    #[must_use]
+    pub fn interrupted_color(&self) -> &str {
+        self.ui
+            .as_ref()
+            .and_then(|config| config.interrupted_color.as_deref())
+            .unwrap_or("yellow")
+    }
+
+    /// The color of commands which failed in the history list, defaults to `red`.
+    #[must_use]
+    pub fn error_color(&self) -> &str {
+        self.ui
+            .as_ref()
+            .and_then(|config| config.error_color.as_deref())
+            .unwrap_or("red")
+    }
+
+    /// How long the interactive search waits after a keystroke before searching, defaults to
+    /// 50 milliseconds.
+    #[must_use]
+    pub fn search_debounce(&self) -> Duration {
+        Duration::from_millis(
+            self.ui
+                .as_ref()
+                .and_then(|config| config.search_debounce_ms)
+                .unwrap_or(50),
+        )
+    }
+
+    /// The most commands the interactive search lists when scoped to the current directory or
+    /// project, defaults to 500.
+    #[must_use]
+    pub fn limit_cwd(&self) -> usize {
+        self.ui
+            .as_ref()
+            .and_then(|config| config.limit_cwd)
+            .unwrap_or(500)
+    }
+
+    /// The most commands the interactive search lists when scoped to everything or to a
+    /// session, defaults to 500.
+    #[must_use]
+    pub fn limit_all(&self) -> usize {
+        self.ui
+            .as_ref()
+            .and_then(|config| config.limit_all)
+            .unwrap_or(500)
+    }
+
+    /// Whether a leading `sudo ` is ignored when grouping commands, defaults to false.
+    #[must_use]
+    pub fn strip_sudo(&self) -> bool {
+        self.stats
+            .as_ref()
+            .and_then(|config| config.strip_sudo)
+            .unwrap_or(false)
+    }
+
+    /// A copy of this config with the defaults of unset settings filled in, as they are in effect.
+    ///
+    /// The database location is left as configured, its default is resolved by the database.
+    #[must_use]
+    pub fn effective(&self) -> Config {
+        let ui = self.ui.clone().unwrap_or_default();
+        Config {
+            database: self.database.clone(),
+            history: Some(HistoryConfig {
+                enabled: Some(self.history_enabled()),
+                unique: Some(self.unique_history()),
+            }),
+            keybinds: Some(KeybindingConfig {
+                skip_keybinds: Some(
+                    self.keybinds
+                        .as_ref()
+                        .and_then(|config| config.skip_keybinds)
+                        .unwrap_or(false),
+                ),
+            }),
+            stats: Some(StatsConfig {
+                strip_sudo: Some(self.strip_sudo()),
+            }),
+            ui: Some(UiConfig {
+                wrap_preview: Some(self.wrap_preview()),
+                relative_precision: Some(self.relative_precision()),
+                just_now_secs: Some(self.just_now().as_secs()),
+                height: ui.height,
+                success_color: Some(self.success_color().to_string()),
+                interrupted_color: Some(self.interrupted_color().to_string()),
+                error_color: Some(self.error_color().to_string()),
+                search_debounce_ms: u64::try_from(self.search_debounce().as_millis()).ok(),
+                limit_cwd: Some(self.limit_cwd()),
+                limit_all: Some(self.limit_all()),
+            }),
+            search: Some(SearchConfig {
+                tokenizer: Some(self.tokenizer()),
+                timeout_ms: Some(
+                    self.search_timeout()
+                        .and_then(|timeout| u64::try_from(timeout.as_millis()).ok())
+                        .unwrap_or(0),
+                ),
+                prefix_policy: Some(self.prefix_policy()),
+                ranker: Some(self.ranker()),
+            }),
+        }
+    }
+
+    /// Serialize the config in the `config.toml` format.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the config can not be represented as TOML.
+    pub fn to_toml(&self) -> Result<String, toml::ser::Error> {
+        toml::to_string(self)
+    }
+
+    /// Point the database configuration at a specific database file.
+    ///
+    /// Replaces both the configured `database_path` and `database_file`.
+    ///
+    /// * `file`: Path to the database file to use.
+    pub fn set_database_file(&mut self, file: &Path) {
+        let database = self.database.get_or_insert_with(DatabaseConfig::default);
+        database.database_path = file.parent().map(Path::to_path_buf);
+        database.database_file = file
+            .file_name()
+            .map(|name| name.to_string_lossy().into_owned());
+    }
+}
+
+/// The path of the `config.toml` file, which may not exist.
+#[must_use]
+pub fn config_path() -> PathBuf {
+    get_config_dir().join("config.toml")
+}
+
+/// Write the commented default config to `path`, creating its directory.
+///
+/// * `path`: Where to write the config file.
+/// * `force`: Overwrite an existing file instead of failing.
+///
+/// # Errors
+///
+/// Returns an error with kind `AlreadyExists` if the file exists and `force` is not set, or if
+/// the file could not be written.
+pub fn write_default_config(path: &Path, force: bool) -> io::Result<()> {
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    let mut file = if force {
+        OpenOptions::new()
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .open(path)?
+    } else {
+        OpenOptions::new().write(true).create_new(true).open(path)?
+    };
+    file.write_all(DEFAULT_CONFIG.as_bytes())
+}
+
 /// Loads the application configuration from a `config.toml` file.
 ///
 /// The configuration file is expected to be located in the platform-specific
@@ -49,7 +473,7 @@ pub struct DatabaseConfig {
 /// - `Err(Box<dyn std::error::Error>)`: An error occurred during file reading or TOML parsing.
 pub fn load_config() -> Result<Config, Box<dyn std::error::Error>> {
     // Find the config file path
-    let config_path = get_config_dir().join("config.toml");
+    let config_path = config_path();
 
     // Read the file if it exists
     let config_str = if config_path.exists() {
@@ -71,3 +495,99 @@ pub fn load_config() -> Result<Config, Box<dyn std::error::Error>> {
 
     Ok(config)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_default_config_lists_every_setting() {
+        assert_eq!(
+            toml::from_str::<Config>(DEFAULT_CONFIG).expect("Failed to parse default config"),
+            Config {
+                database: Some(DatabaseConfig::default()),
+                history: Some(HistoryConfig::default()),
+                keybinds: Some(KeybindingConfig::default()),
+                stats: Some(StatsConfig::default()),
+                ui: Some(UiConfig::default()),
+                search: Some(SearchConfig::default()),
+            }
+        );
+
+        // Uncommenting every setting gives the defaults in effect.
+        let uncommented = DEFAULT_CONFIG
+            .lines()
+            .map(|line| {
+                line.strip_prefix("# ")
+                    .filter(|l| l.contains(" = "))
+                    .unwrap_or(line)
+            })
+            .collect::<Vec<&str>>()
+            .join("\n");
+        let mut config =
+            toml::from_str::<Config>(&uncommented).expect("Failed to parse uncommented config");
+        config.database = None;
+        config.ui.as_mut().unwrap().height = None;
+        let mut expected = Config::default().effective();
+        expected.database = None;
+        assert_eq!(config, expected);
+    }
+
+    #[test]
+    fn test_prefix_policy_from_str() {
+        for policy in [
+            PrefixPolicy::All,
+            PrefixPolicy::LastOnly,
+            PrefixPolicy::None,
+        ] {
+            assert_eq!(policy.name().parse(), Ok(policy));
+        }
+        assert!("last".parse::<PrefixPolicy>().is_err());
+
+        let config: Config = toml::from_str("[search]\nprefix_policy = \"last-only\"")
+            .expect("Failed to parse config");
+        assert_eq!(config.prefix_policy(), PrefixPolicy::LastOnly);
+    }
+
+    #[test]
+    fn test_ranker() {
+        assert_eq!(Config::default().ranker(), RankerKind::Bm25Recency);
+        let config: Config =
+            toml::from_str("[search]\nranker = \"frequency\"").expect("Failed to parse config");
+        assert_eq!(config.ranker(), RankerKind::Frequency);
+    }
+
+    #[test]
+    fn test_to_toml_round_trip() {
+        let config: Config = toml::from_str(
+            r#"
+            [database]
+            database_file = "test.db"
+
+            [ui]
+            wrap_preview = false
+            height = 12
+
+            [search]
+            tokenizer = "trigram"
+            "#,
+        )
+        .expect("Failed to parse config");
+
+        let shown = config.to_toml().expect("Failed to serialize config");
+        assert_eq!(
+            toml::from_str::<Config>(&shown).expect("Failed to parse shown config"),
+            config
+        );
+
+        let effective = config.effective();
+        let shown = effective.to_toml().expect("Failed to serialize config");
+        assert_eq!(
+            toml::from_str::<Config>(&shown).expect("Failed to parse shown config"),
+            effective
+        );
+        assert_eq!(effective.strip_sudo(), config.strip_sudo());
+        assert_eq!(effective.tokenizer(), Tokenizer::Trigram);
+        assert!(shown.contains("relative_precision = \"coarse\""));
+    }
+}
@@ -1,4 +1,9 @@
-use std::{env, path::PathBuf};
+use std::{
+    env,
+    path::{Path, PathBuf},
+};
+
+use log::warn;
 
 #[must_use]
 pub fn get_current_dir() -> String {
@@ -12,15 +17,47 @@ pub fn get_current_dir() -> String {
     }
 }
 
-/// Fetch the home directory on unix systems via the $HOME env variable.
+/// Fetch the id of the current shell session.
+///
+/// The shell init script exports `$RAVEN_SESSION_ID` once per shell, returns an empty string if it
+/// is not set.
+#[must_use]
+pub fn get_session_id() -> String {
+    env::var("RAVEN_SESSION_ID").unwrap_or_default()
+}
+
+/// Fetch the terminal device of the current process, such as `/dev/pts/3`.
 ///
-/// # Panics
+/// Uses `$TTY`, which zsh sets, and otherwise resolves the device standard input is connected to.
+/// Returns an empty string if neither is a terminal.
+#[must_use]
+pub fn get_tty() -> String {
+    if let Ok(tty) = env::var("TTY")
+        && !tty.is_empty()
+    {
+        return tty;
+    }
+    std::fs::read_link("/proc/self/fd/0")
+        .ok()
+        .map(|path| path.display().to_string())
+        .filter(|path| path.starts_with("/dev/pts/") || path.starts_with("/dev/tty"))
+        .unwrap_or_default()
+}
+
+/// Fetch the home directory on unix systems via the $HOME env variable.
 ///
-/// Panics if $HOME variable is not set.
+/// Minimal environments such as containers and cron jobs may not set `$HOME`, a `raven` directory
+/// in the temp directory is used instead so raven keeps working, with a logged warning.
 #[must_use]
 pub fn get_home_dir() -> PathBuf {
-    let home = std::env::var("HOME").expect("$HOME not found");
-    PathBuf::from(home)
+    match env::var_os("HOME") {
+        Some(home) if !home.is_empty() => PathBuf::from(home),
+        _ => {
+            let fallback = env::temp_dir().join("raven");
+            warn!("$HOME is not set, using {}", fallback.display());
+            fallback
+        }
+    }
 }
 
 /// Fetch the data directory for raven to store data.
@@ -43,3 +80,77 @@ pub fn get_config_dir() -> PathBuf {
         .map_or_else(|_| get_home_dir().join(".config"), PathBuf::from);
     data_dir.join("raven")
 }
+
+/// Find the root of the git repository containing `dir`.
+///
+/// Walks up from `dir` to the first directory with a `.git` entry, which is a file rather than a
+/// directory in worktrees and submodules. Returns `None` if `dir` is not inside a repository.
+#[must_use]
+pub fn find_repo_root(dir: &Path) -> Option<PathBuf> {
+    dir.ancestors()
+        .find(|ancestor| ancestor.join(".git").exists())
+        .map(Path::to_path_buf)
+}
+
+/// Normalize a command into a key for grouping equivalent commands, such as in `raven stats --top`.
+///
+/// Trims the command and collapses runs of whitespace into a single space. The stored command is
+/// never changed, this is only used for comparisons.
+///
+/// * `command`: The command to normalize.
+/// * `strip_sudo`: Also drop a leading `sudo `, see `Config::strip_sudo`.
+#[must_use]
+pub fn canonical_command(command: &str, strip_sudo: bool) -> String {
+    let mut words = command.split_whitespace().peekable();
+    if strip_sudo && words.peek() == Some(&"sudo") {
+        words.next();
+    }
+    words.collect::<Vec<&str>>().join(" ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_canonical_command_whitespace() {
+        assert_eq!(canonical_command("  ls -la  ", false), "ls -la");
+        assert_eq!(
+            canonical_command("git\tcommit   -m x", false),
+            "git commit -m x"
+        );
+        assert_eq!(canonical_command("echo\n", false), "echo");
+        assert_eq!(canonical_command("   ", false), "");
+    }
+
+    #[test]
+    fn test_find_repo_root() {
+        let tmp = tempfile::tempdir().expect("Failed to create temp dir");
+        let outer = tmp.path().join("outer");
+        let nested = outer.join("vendor").join("inner");
+        let deep = nested.join("src").join("bin");
+        std::fs::create_dir_all(outer.join(".git")).unwrap();
+        std::fs::create_dir_all(&deep).unwrap();
+        // Worktrees and submodules have a `.git` file pointing at the real git dir.
+        std::fs::write(nested.join(".git"), "gitdir: ../../.git/modules/inner").unwrap();
+
+        assert_eq!(find_repo_root(&outer), Some(outer.clone()));
+        assert_eq!(find_repo_root(&outer.join("vendor")), Some(outer.clone()));
+        assert_eq!(find_repo_root(&nested), Some(nested.clone()));
+        assert_eq!(find_repo_root(&deep), Some(nested));
+        assert_eq!(find_repo_root(tmp.path()), None);
+    }
+
+    #[test]
+    fn test_canonical_command_sudo() {
+        assert_eq!(canonical_command("sudo  apt update", true), "apt update");
+        assert_eq!(
+            canonical_command("sudo apt update", false),
+            "sudo apt update"
+        );
+        // Only a leading `sudo` word is removed.
+        assert_eq!(canonical_command("sudoedit file", true), "sudoedit file");
+        assert_eq!(canonical_command("echo sudo", true), "echo sudo");
+        assert_eq!(canonical_command("sudo", true), "");
+    }
+}
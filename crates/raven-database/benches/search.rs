@@ -0,0 +1,89 @@
+//! Benchmarks for searching large histories.
+//!
+//! Run with `cargo bench -p raven-database`, results are grouped by the number of rows seeded.
+use criterion::{BenchmarkId, Criterion, criterion_group, criterion_main};
+use raven_database::{
+    HistoryFilters, MatchMode,
+    database::{Database, sqlite::Sqlite},
+    history::model::History,
+};
+use time::OffsetDateTime;
+
+/// The number of history rows seeded for each benchmark.
+const SIZES: [usize; 3] = [10_000, 100_000, 1_000_000];
+
+/// The limit used by the interactive search.
+const LIMIT: usize = 500;
+
+const PROGRAMS: [&str; 8] = [
+    "git", "cargo", "ls", "cd", "docker", "kubectl", "vim", "grep",
+];
+const ARGS: [&str; 8] = [
+    "status",
+    "test --all",
+    "-la",
+    "build --release",
+    "ps -a",
+    "get pods",
+    "src/main.rs",
+    "-rn TODO",
+];
+const CWDS: [&str; 4] = ["/home/raven", "/home/raven/src", "/tmp", "/var/log"];
+
+/// Build an in-memory database seeded with `size` distinct commands.
+fn seeded_db(size: usize) -> Sqlite {
+    let start = OffsetDateTime::now_utc().unix_timestamp() - i64::try_from(size).unwrap();
+    let history = (0..size)
+        .map(|i| {
+            History::builder()
+                .id(-1)
+                .timestamp(
+                    OffsetDateTime::from_unix_timestamp(start + i64::try_from(i).unwrap()).unwrap(),
+                )
+                .command(format!(
+                    "{} {} {i}",
+                    PROGRAMS[i % PROGRAMS.len()],
+                    ARGS[(i / PROGRAMS.len()) % ARGS.len()]
+                ))
+                .cwd(CWDS[i % CWDS.len()].to_string())
+                .exit_code(i64::from(i % 10 == 0))
+                .build()
+        })
+        .collect::<Vec<History>>();
+
+    let mut db = Sqlite::memory();
+    db.save_bulk(&history).expect("Failed to seed database");
+    db
+}
+
+fn bench_search(c: &mut Criterion) {
+    let mut group = c.benchmark_group("search");
+    group.sample_size(20);
+
+    for size in SIZES {
+        let db = seeded_db(size);
+        let filters = |mode: MatchMode| HistoryFilters {
+            limit: Some(LIMIT),
+            mode,
+            ..Default::default()
+        };
+
+        group.bench_with_input(BenchmarkId::new("empty", size), &db, |b, db| {
+            b.iter(|| db.search("", filters(MatchMode::Fuzzy)).unwrap());
+        });
+        group.bench_with_input(BenchmarkId::new("prefix", size), &db, |b, db| {
+            b.iter(|| db.search("git st", filters(MatchMode::Prefix)).unwrap());
+        });
+        group.bench_with_input(BenchmarkId::new("fuzzy", size), &db, |b, db| {
+            b.iter(|| db.search("cargo test", filters(MatchMode::Fuzzy)).unwrap());
+        });
+        group.bench_with_input(BenchmarkId::new("total", size), &db, |b, db| {
+            b.iter(|| db.get_history_total().unwrap());
+        });
+    }
+
+    group.finish();
+}
+
+criterion_group!(benches, bench_search);
+criterion_main!(benches);
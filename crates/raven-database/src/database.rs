@@ -1,11 +1,45 @@
 use core::fmt;
+use std::io::{self, Write};
 
-use crate::{HistoryFilters, history::model::History};
+use time::OffsetDateTime;
+
+use crate::{
+    HistoryFilters,
+    export::{ExportFormat, Exporter},
+    history::model::{History, HistoryUpdate},
+    import::aliases::Alias,
+};
 pub mod sqlite;
 
-#[derive(Debug, Clone)]
+/// The category of a `DatabaseError`, for callers which need to react to specific failures.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum DatabaseErrorKind {
+    /// Any error without a more specific kind.
+    #[default]
+    Other,
+    /// A write was rejected because the row was changed since it was read.
+    Conflict,
+    /// A delete was rejected because no filter narrowed it down.
+    Unfiltered,
+    /// A save was rejected because the history is already stored, see `[history] unique`.
+    Duplicate,
+    /// A search ran for longer than `[search] timeout_ms` and was abandoned.
+    Timeout,
+}
+
+#[derive(Debug, Clone, Default)]
 pub struct DatabaseError {
     pub msg: String,
+    pub kind: DatabaseErrorKind,
+}
+
+impl From<io::Error> for DatabaseError {
+    fn from(value: io::Error) -> Self {
+        Self {
+            msg: format!("{value}"),
+            kind: DatabaseErrorKind::Other,
+        }
+    }
 }
 
 impl fmt::Display for DatabaseError {
@@ -19,16 +53,28 @@ pub trait Database {
     /// * `history`:
     ///
     /// # Errors
-    /// Will return `Err` if the database Encountered an issue.
+    /// Will return `Err` if the database Encountered an issue, with the `Duplicate` kind if
+    /// duplicates are rejected and `history` is already stored.
     fn save(&mut self, history: &History) -> Result<i64, DatabaseError>;
 
     /// Save a vec of `History` objects to the database.
+    ///
+    /// If duplicates are rejected, those already stored are skipped and have no id returned.
     /// * `history`:
     ///
     /// # Errors
     /// Will return `Err` if the database Encountered an issue.
     fn save_bulk(&mut self, history: &[History]) -> Result<Vec<i64>, DatabaseError>;
 
+    /// Save a vec of `History` objects, skipping those whose command, cwd and timestamp are
+    /// already stored, such as when the same history file is imported again.
+    ///
+    /// Returns the ids of the saved objects, the skipped ones have none.
+    ///
+    /// # Errors
+    /// Will return `Err` if the database Encountered an issue.
+    fn save_bulk_dedup(&mut self, history: &[History]) -> Result<Vec<i64>, DatabaseError>;
+
     /// Fetch a `History` object by its id from the database.
     /// * `history`:
     ///
@@ -43,15 +89,125 @@ pub trait Database {
     /// This function will return an error if the database encountered an issue.
     fn get_history_total(&self) -> Result<i64, DatabaseError>;
 
-    /// Writes all `History` object fields back to the database.
+    /// Lists the most recently active shell sessions, most recent first.
+    ///
+    /// Each entry is the session id, the time of its latest command and its command count.
+    ///
+    /// * `n`: The maximum number of sessions to return.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the database encountered an issue.
+    fn recent_sessions(
+        &self,
+        n: usize,
+    ) -> Result<Vec<(String, OffsetDateTime, i64)>, DatabaseError>;
+
+    /// Lists the most recently run distinct commands, most recent first.
+    ///
+    /// Each command is the newest entry it was run as. Backed by a small cache updated on every
+    /// write, so it stays fast however much history there is, at the cost of only covering the
+    /// last couple of hundred distinct commands.
+    ///
+    /// * `n`: The maximum number of commands to return.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the database encountered an issue.
+    fn recent_commands(&self, n: usize) -> Result<Vec<History>, DatabaseError>;
+
+    /// Records shell aliases, replacing any earlier expansion of the same name.
+    ///
+    /// Returns the number of aliases recorded.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the database encountered an issue, in which case
+    /// none are recorded.
+    fn save_aliases(&mut self, aliases: &[Alias]) -> Result<usize, DatabaseError>;
+
+    /// Lists every recorded shell alias, ordered by name.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the database encountered an issue.
+    fn aliases(&self) -> Result<Vec<Alias>, DatabaseError>;
+
+    /// Finds the most recently active session other than `current`.
+    ///
+    /// * `current`: The session id of the running shell.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the database encountered an issue.
+    fn previous_session(&self, current: &str) -> Result<Option<String>, DatabaseError> {
+        // At most one of the two most recent sessions can be the current one.
+        Ok(self
+            .recent_sessions(2)?
+            .into_iter()
+            .map(|(session_id, _, _)| session_id)
+            .find(|session_id| session_id != current))
+    }
+
+    /// Fetch the commands run right before and after the entry with `id`, by timestamp.
+    ///
+    /// Returns up to `window` commands on each side, both ordered oldest first, or nothing if
+    /// the entry does not exist.
+    ///
+    /// * `same_session`: Only include commands run in the same shell session as the entry.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the database encountered an issue.
+    fn neighbors(
+        &self,
+        id: i64,
+        window: usize,
+        same_session: bool,
+    ) -> Result<(Vec<History>, Vec<History>), DatabaseError>;
+
+    /// The name of the storage backend, such as `sqlite`, for diagnostics and for code which
+    /// depends on what the backend supports.
+    fn backend_name(&self) -> &'static str;
+
+    /// Gets the schema version the database is currently migrated to.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the database encountered an issue.
+    fn schema_version(&self) -> Result<u32, DatabaseError>;
+
+    /// Checks the database for corruption, including the search index and its triggers.
+    ///
+    /// Returns `false` if any problem was found, each problem is logged as an error.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the checks could not be run.
+    fn health_check(&self) -> Result<bool, DatabaseError>;
+
+    /// Writes all `History` object fields except `id` back to the database, and moves
+    /// `updated_at` forward.
     /// NOTE: This overrides existing data.
     ///
+    /// The write is rejected with a `DatabaseErrorKind::Conflict` error if the row was updated
+    /// since `history` was read.
+    ///
     /// * `history`:
     ///
     /// # Errors
     /// Will return `Err` if the database Encountered an issue.
     fn update(&self, history: &History) -> Result<(), DatabaseError>;
 
+    /// Writes only the fields set in `update` to the history entry with the given id.
+    ///
+    /// * `id`: The unique ID of the history entry to update.
+    /// * `update`: The fields to change, `None` fields keep their stored value.
+    ///
+    /// # Errors
+    /// Will return `Err` if the database Encountered an issue.
+    fn update_partial(&self, id: i64, update: &HistoryUpdate) -> Result<(), DatabaseError>;
+
     /// Delete a history entry by its unique ID.
     ///
     /// * `id`: The ID of the history entry to delete.
@@ -60,6 +216,74 @@ pub trait Database {
     /// Will return `Err` if the database encountered an issue during deletion.
     fn delete(&self, id: i64) -> Result<(), DatabaseError>;
 
+    /// Delete several history entries at once, either all of them or none.
+    ///
+    /// * `ids`: The IDs of the history entries to delete, unknown IDs are ignored.
+    ///
+    /// Returns the number of entries deleted.
+    ///
+    /// # Errors
+    /// Will return `Err` if the database encountered an issue during deletion.
+    fn delete_bulk(&self, ids: &[i64]) -> Result<usize, DatabaseError>;
+
+    /// Move the history of directory `from` to `to`, including that of its subdirectories.
+    ///
+    /// Only whole path components match, so rewriting `/home/me/old` leaves `/home/me/older`
+    /// untouched.
+    ///
+    /// Returns the number of entries rewritten.
+    ///
+    /// # Errors
+    /// Will return `Err` if `from` is the root directory, or if the database encountered an issue.
+    fn rewrite_cwd(&self, from: &str, to: &str) -> Result<usize, DatabaseError>;
+
+    /// Delete every history entry matching `filters`, ignoring `limit` and `mode`.
+    ///
+    /// Filters which narrow nothing down would delete the whole table, so they are refused
+    /// unless `force_all` is set.
+    ///
+    /// Returns the number of entries deleted.
+    ///
+    /// # Errors
+    /// Will return `Err` with the `Unfiltered` kind if `filters` is empty and `force_all` is not
+    /// set, or if the database encountered an issue during deletion.
+    fn delete_where(
+        &self,
+        filters: HistoryFilters,
+        force_all: bool,
+    ) -> Result<usize, DatabaseError>;
+
+    /// Delete every history entry matching `query` and `filters`, as found by `search` but
+    /// ignoring `limit`.
+    ///
+    /// An empty query with filters which narrow nothing down would delete the whole table, so it
+    /// is refused.
+    ///
+    /// Returns the number of entries deleted.
+    ///
+    /// # Errors
+    /// Will return `Err` with the `Unfiltered` kind if neither `query` nor `filters` narrow the
+    /// history down, or if the database encountered an issue during deletion.
+    fn delete_matching(&self, query: &str, filters: HistoryFilters)
+    -> Result<usize, DatabaseError>;
+
+    /// Delete every history entry run from `after` up to `before`, both inclusive.
+    ///
+    /// A missing bound leaves that side of the range open. Without either bound the whole table
+    /// would be deleted, so that is refused unless `force_all` is set.
+    ///
+    /// Returns the number of entries deleted.
+    ///
+    /// # Errors
+    /// Will return `Err` with the `Unfiltered` kind if neither bound is given and `force_all` is
+    /// not set, or if the database encountered an issue during deletion.
+    fn delete_range(
+        &self,
+        after: Option<OffsetDateTime>,
+        before: Option<OffsetDateTime>,
+        force_all: bool,
+    ) -> Result<usize, DatabaseError>;
+
     /// Search over history records and return a list of matching results.
     ///
     /// * `limit`: The maximum amount of results to return.
@@ -67,4 +291,53 @@ pub trait Database {
     /// # Errors
     /// Will return `Err` if the database Encountered an issue.
     fn search(&self, query: &str, filters: HistoryFilters) -> Result<Vec<History>, DatabaseError>;
+
+    /// Stream the history records matching a search to `f`, without collecting them.
+    ///
+    /// # Errors
+    /// Will return `Err` if the database Encountered an issue.
+    fn search_each(
+        &self,
+        query: &str,
+        filters: HistoryFilters,
+        f: &mut dyn FnMut(History),
+    ) -> Result<(), DatabaseError>;
+
+    /// Count the history records matching a search, ignoring any result limit.
+    ///
+    /// # Errors
+    /// Will return `Err` if the database Encountered an issue.
+    fn search_count(&self, query: &str, filters: HistoryFilters) -> Result<i64, DatabaseError>;
+
+    /// Count the history records matching the filters, such as the commands run in a directory.
+    ///
+    /// # Errors
+    /// Will return `Err` if the database Encountered an issue.
+    fn count(&self, filters: HistoryFilters) -> Result<i64, DatabaseError>;
+
+    /// Stream the history records matching `filters` to `writer` in `format`.
+    ///
+    /// Records are written as they are read, in the order of `search_each`.
+    ///
+    /// Returns the number of records written.
+    ///
+    /// # Errors
+    /// Will return `Err` if the database Encountered an issue, or writing to `writer` failed.
+    fn export(
+        &self,
+        format: ExportFormat,
+        writer: &mut dyn Write,
+        filters: HistoryFilters,
+    ) -> Result<usize, DatabaseError> {
+        let mut exporter = Exporter::new(format, writer)?;
+        // The first write error stops the export, the remaining rows are skipped.
+        let mut written = Ok(());
+        self.search_each("", filters, &mut |h| {
+            if written.is_ok() {
+                written = exporter.push(&h);
+            }
+        })?;
+        written?;
+        Ok(exporter.finish()?)
+    }
 }
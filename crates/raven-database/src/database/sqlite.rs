@@ -1,33 +1,106 @@
-use std::fs;
+use std::{
+    collections::HashMap,
+    fs,
+    path::PathBuf,
+    sync::Arc,
+    time::{Duration, Instant},
+};
 
 mod query;
 use crate::MatchMode;
-use log::{debug, error};
-use query::{Query, SqlString};
+use log::{debug, error, warn};
+use query::{Query, SelectStatement, SqlString};
 use raven_common::{
-    config::{Config, load_config},
+    config::{Config, PrefixPolicy, Tokenizer, load_config},
     utils::get_data_dir,
 };
-use rusqlite::{Connection, DropBehavior, OpenFlags, ToSql, named_params, types::ToSqlOutput};
+use regex::Regex;
+use rusqlite::{
+    Connection, DropBehavior, OpenFlags, Row, ToSql, functions::FunctionFlags, named_params,
+    types::ToSqlOutput,
+};
 use time::OffsetDateTime;
 
-use crate::{HistoryFilters, history::model::History};
+use crate::{
+    HistoryFilters, OrderBy,
+    history::model::{EXIT_UNKNOWN, History, HistoryUpdate, IMPORTED_CWD},
+    import::aliases::Alias,
+};
 
-use super::{Database, DatabaseError};
+use super::{Database, DatabaseError, DatabaseErrorKind};
 
 const DATABASE_FILE: &str = "raven.db";
-const LATEST_STABLE_SCHEMA: SchemaVersion = SchemaVersion::V3;
+
+/// The most recent history first, used for searches without a query or filters.
+const SEARCH_RECENT_SQL: &str = "SELECT id, command, cwd, exit_code, timestamp, session_id, tty, source, duration, updated_at \
+    FROM history ORDER BY timestamp DESC, id DESC LIMIT ?1 OFFSET ?2";
+
+/// The newest run of each of the most recently run distinct commands, from the cache kept by the
+/// `recent_commands` triggers.
+const RECENT_COMMANDS_SQL: &str = "SELECT h.id, h.command, h.cwd, h.exit_code, h.timestamp, h.session_id, h.tty, h.source, h.duration, h.updated_at \
+    FROM recent_commands r JOIN history h ON h.id = r.history_id \
+    ORDER BY r.timestamp DESC, r.history_id DESC LIMIT ?1";
+
+/// Inserts a history row unless one with the same command, cwd and timestamp is stored, the
+/// lookup is served by `idx_history_cwd`.
+const INSERT_IF_NEW_SQL: &str = "INSERT INTO history \
+    (timestamp, command, cwd, exit_code, session_id, tty, source, duration) \
+    SELECT :timestamp, :command, :cwd, :exit_code, :session_id, :tty, :source, :duration \
+    WHERE NOT EXISTS (SELECT 1 FROM history \
+    WHERE cwd = :cwd AND timestamp = :timestamp AND command = :command)";
+
+/// Records an alias, replacing any earlier expansion of the same name.
+const SAVE_ALIAS_SQL: &str = "INSERT INTO aliases (name, expansion) VALUES (:name, :expansion) \
+    ON CONFLICT (name) DO UPDATE SET expansion = excluded.expansion";
+
+/// Every recorded alias, by name.
+const ALIASES_SQL: &str = "SELECT name, expansion FROM aliases ORDER BY name";
+
+/// The `:window` commands run right before the one at `:timestamp` with `:id`, closest first.
+///
+/// Commands run in the same second are ordered by id. Only commands in `:session_id` are
+/// included, unless it is NULL.
+const NEIGHBORS_BEFORE_SQL: &str = "SELECT id, command, cwd, exit_code, timestamp, session_id, tty, source, duration, updated_at \
+    FROM history WHERE (timestamp < :timestamp OR (timestamp = :timestamp AND id < :id)) \
+    AND (:session_id IS NULL OR session_id = :session_id) \
+    ORDER BY timestamp DESC, id DESC LIMIT :window";
+
+/// The `:window` commands run right after the one at `:timestamp` with `:id`, closest first, see
+/// [`NEIGHBORS_BEFORE_SQL`].
+const NEIGHBORS_AFTER_SQL: &str = "SELECT id, command, cwd, exit_code, timestamp, session_id, tty, source, duration, updated_at \
+    FROM history WHERE (timestamp > :timestamp OR (timestamp = :timestamp AND id > :id)) \
+    AND (:session_id IS NULL OR session_id = :session_id) \
+    ORDER BY timestamp ASC, id ASC LIMIT :window";
+
+/// Replaces the `:from` prefix of the cwd of every row in `:from` or below it with `:to`.
+const REWRITE_CWD_SQL: &str = "UPDATE history \
+    SET cwd = :to || substr(cwd, length(:from) + 1), updated_at = MAX(:updated_at, updated_at + 1) \
+    WHERE cwd = :from OR cwd LIKE :subdirs ESCAPE '\\'";
+
+/// The next `updated_at` value of a row, always moving forward even if the clock does not.
+const UPDATED_AT_NEXT: &str = "MAX(:updated_at, updated_at + 1)";
+/// The schema version new databases are created at, and existing ones are migrated to on open.
+pub const LATEST_STABLE_SCHEMA: SchemaVersion = SchemaVersion::V13;
 
 const MIGRATION_V0_TO_V1: &str = include_str!("./sqlite/sql/migrate/v0_to_v1.sql");
 const MIGRATION_V1_TO_V2: &str = include_str!("./sqlite/sql/migrate/v1_to_v2.sql");
 const MIGRATION_V2_TO_V3: &str = include_str!("./sqlite/sql/migrate/v2_to_v3.sql");
+const MIGRATION_V3_TO_V4: &str = include_str!("./sqlite/sql/migrate/v3_to_v4.sql");
+const MIGRATION_V4_TO_V5: &str = include_str!("./sqlite/sql/migrate/v4_to_v5.sql");
+const MIGRATION_V5_TO_V6: &str = include_str!("./sqlite/sql/migrate/v5_to_v6.sql");
+const MIGRATION_V6_TO_V7: &str = include_str!("./sqlite/sql/migrate/v6_to_v7.sql");
+const MIGRATION_V7_TO_V8: &str = include_str!("./sqlite/sql/migrate/v7_to_v8.sql");
+const MIGRATION_V8_TO_V9: &str = include_str!("./sqlite/sql/migrate/v8_to_v9.sql");
+const MIGRATION_V9_TO_V10: &str = include_str!("./sqlite/sql/migrate/v9_to_v10.sql");
+const MIGRATION_V10_TO_V11: &str = include_str!("./sqlite/sql/migrate/v10_to_v11.sql");
+const MIGRATION_V11_TO_V12: &str = include_str!("./sqlite/sql/migrate/v11_to_v12.sql");
+const MIGRATION_V12_TO_V13: &str = include_str!("./sqlite/sql/migrate/v12_to_v13.sql");
 
 #[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Clone, Copy)]
 #[repr(u32)]
-#[allow(dead_code)] // Allow dead code so all the version enums continue to exist.
 /// Represents the different versions of the database schema.
 /// Used for migrations and ensuring compatibility.
-enum SchemaVersion {
+pub enum SchemaVersion {
     /// V0: The initial state where no raven-specific schema exists.
     V0 = 0,
     /// V1: Introduced the `history` table for command history.
@@ -36,15 +109,39 @@ enum SchemaVersion {
     V2 = 2,
     /// V3: Introduced the `hist_fts` table for full-text search on history.
     V3 = 3,
+    /// V4: Introduced the `session_id` column on `history`.
+    V4 = 4,
+    /// V5: Introduced the `updated_at` column on `history`.
+    V5 = 5,
+    /// V6: Introduced indexes on the `history` columns used by search filters.
+    V6 = 6,
+    /// V7: Introduced the indexed `command_lower` column on `history`.
+    V7 = 7,
+    /// V8: Introduced the `tty` column on `history`.
+    V8 = 8,
+    /// V9: Introduced the `source` column on `history`.
+    V9 = 9,
+    /// V10: Introduced the `recent_commands` cache of the latest distinct commands.
+    V10 = 10,
+    /// V11: Introduced the `aliases` table of imported shell aliases.
+    V11 = 11,
+    /// V12: Introduced the `duration` column on `history`.
+    V12 = 12,
+    /// V13: Replaced the `recent_commands` rebuild triggers with incremental updates.
+    V13 = 13,
 }
 
 impl SchemaVersion {
     /// Converts the `SchemaVersion` enum variant to its underlying `u32` representation.
-    fn to_u32(self) -> u32 {
+    #[must_use]
+    pub fn to_u32(self) -> u32 {
         self as u32
     }
 }
 
+/// The number of virtual machine instructions `SQLite` runs between checks of the search timeout.
+const TIMEOUT_CHECK_OPS: i32 = 1000;
+
 /// Sqlite database wrapper using rusqlite
 pub struct Sqlite {
     pub conn: Connection,
@@ -65,12 +162,324 @@ impl Sqlite {
     /// - The database connection cannot be established.
     #[must_use]
     pub fn new(config: &Config) -> Self {
+        let database_path = Self::database_path(config);
+        if let Some(path) = database_path.parent() {
+            let _ = fs::create_dir_all(path);
+        }
+
+        let conn = get_connection(
+            database_path
+                .to_str()
+                .expect("Could not generate database file path."),
+        );
+        let mut db = Self { conn };
+
+        // The tokenizer is chosen when the database is created, there is nothing to reindex yet.
+        let tokenizer = config.tokenizer();
+        if db
+            .tokenizer()
+            .is_ok_and(|current| current != tokenizer.name())
+            && db.get_history_total().is_ok_and(|total| total == 0)
+        {
+            db.reindex(tokenizer)
+                .expect("Failed to apply the configured search tokenizer.");
+        }
+
+        // Existing duplicates keep the index from being created, the history is still usable.
+        if let Err(err) = db.set_unique(config.unique_history()) {
+            error!("Unable to reject duplicate history, remove the duplicates first: {err}");
+        }
+        db
+    }
+
+    /// Applies the migrations between the current schema version and `target`.
+    ///
+    /// Databases are migrated to [`LATEST_STABLE_SCHEMA`] when opened, this is for tooling which
+    /// needs a specific version. Each migration runs in its own transaction, so a failure leaves
+    /// the database at the last version which applied cleanly.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if `target` is older than the current version, as migrations cannot be
+    /// reverted, or if a migration failed.
+    pub fn migrate_to(&mut self, target: SchemaVersion) -> Result<(), DatabaseError> {
+        let current_version = get_user_version(&self.conn)?;
+        if current_version > target.to_u32() {
+            return Err(DatabaseError {
+                msg: format!(
+                    "cannot migrate from v{current_version} down to v{}",
+                    target.to_u32()
+                ),
+                ..Default::default()
+            });
+        }
+        run_migrations(&mut self.conn, current_version, Some(target))
+    }
+
+    /// Deletes the history rows a search for `query` with `filters` matches, regardless of its
+    /// limit.
+    ///
+    /// Falls back to a substring match without the search index, like `search_count`, so the
+    /// rows deleted are the ones counted.
+    fn delete_search(
+        &self,
+        query: &str,
+        mut filters: HistoryFilters,
+    ) -> Result<usize, DatabaseError> {
+        check_regex(query, &filters)?;
+        self.fall_back_without_index(query, &mut filters);
+        let mut select = Query::select().column("h.id").to_owned();
+        let params_map = apply_search_filters(&mut select, query, &filters);
+        let named_params_vec = to_named_params(&params_map);
+        let sql = format!("DELETE FROM history WHERE id IN ({})", select.to_sql());
+        debug!("Executing delete SQL: {sql}");
+
+        Ok(self.conn.execute(&sql, &*named_params_vec)?)
+    }
+
+    /// Runs the `sql` inserting a history row for each of `history` in one transaction.
+    ///
+    /// Rows the statement does not insert, and those rejected as duplicates by the unique index,
+    /// are skipped and have no id returned.
+    fn insert_bulk(&mut self, history: &[History], sql: &str) -> Result<Vec<i64>, DatabaseError> {
+        let mut row_ids: Vec<i64> = Vec::new();
+
+        let mut tx = self.conn.transaction().expect("expected transaction");
+        tx.set_drop_behavior(DropBehavior::Rollback);
+
+        let mut stmt = tx.prepare(sql).unwrap();
+        for h in history {
+            match stmt.execute(named_params! {
+                ":timestamp": h.timestamp.unix_timestamp(),
+                ":command": h.command,
+                ":cwd": h.cwd,
+                ":exit_code": h.exit_code,
+                ":session_id": h.session_id,
+                ":tty": h.tty,
+                ":source": h.source,
+                ":duration": h.duration,
+            }) {
+                Ok(0) => debug!("Skipping stored history: {}", h.command),
+                Ok(_) => row_ids.push(tx.last_insert_rowid()),
+                // Only this insert is undone, the rest of the transaction goes ahead.
+                Err(err) if is_duplicate(&err) => {
+                    debug!("Skipping duplicate history: {}", h.command);
+                }
+                Err(err) => {
+                    // Transaction is automatically rolled back due to DropBehavior::Rollback
+                    return Err(err.into());
+                }
+            }
+        }
+        drop(stmt); // Explicitly drop statement before committing transaction
+        tx.commit()?; // Commit the transaction
+        Ok(row_ids)
+    }
+
+    /// Add or remove the unique index rejecting duplicate history, see `[history] unique`.
+    ///
+    /// History is a duplicate if its command, cwd and timestamp are all already stored.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the index could not be changed, such as when adding it while
+    /// duplicates are stored.
+    pub fn set_unique(&self, unique: bool) -> Result<(), DatabaseError> {
+        if unique {
+            self.conn.execute_batch(
+                "CREATE UNIQUE INDEX IF NOT EXISTS idx_history_unique \
+                 ON history(command, cwd, timestamp);",
+            )?;
+        } else {
+            self.conn
+                .execute_batch("DROP INDEX IF EXISTS idx_history_unique;")?;
+        }
+        Ok(())
+    }
+
+    /// Whether the `history_fts` search index exists.
+    ///
+    /// It is missing from databases which were only partially migrated to V3 or later.
+    fn has_search_index(&self) -> bool {
+        self.conn
+            .query_row(
+                "SELECT COUNT(*) FROM sqlite_master WHERE type = 'table' AND name = 'history_fts'",
+                [],
+                |row| row.get::<usize, i64>(0),
+            )
+            .is_ok_and(|count| count > 0)
+    }
+
+    /// Match `query` as a plain substring when there is no search index to match it against.
+    fn fall_back_without_index(&self, query: &str, filters: &mut HistoryFilters) {
+        if !uses_fts(query, filters) || self.has_search_index() {
+            return;
+        }
+        warn!(
+            "history_fts is missing, falling back to a slower substring search. Run `raven reindex` to recreate the search index."
+        );
+        filters.no_fts = true;
+    }
+
+    /// The name of the FTS5 tokenizer the search index was built with.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the search index definition could not be read.
+    pub fn tokenizer(&self) -> Result<String, DatabaseError> {
+        let sql: String = self.conn.query_row(
+            "SELECT sql FROM sqlite_master WHERE name = 'history_fts'",
+            [],
+            |row| row.get(0),
+        )?;
+
+        // Tables created without a tokenize option use the FTS5 default.
+        Ok(sql
+            .split_once("tokenize='")
+            .and_then(|(_, rest)| rest.split_once('\''))
+            .map_or_else(
+                || Tokenizer::default().name().to_string(),
+                |(tokenizer, _)| tokenizer.to_string(),
+            ))
+    }
+
+    /// Rebuilds the search index with the given tokenizer.
+    ///
+    /// Reads every history row, so this can take a while for large histories.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the search index could not be rebuilt, the existing index is kept.
+    pub fn reindex(&mut self, tokenizer: Tokenizer) -> Result<(), DatabaseError> {
+        let tx = self.conn.transaction()?;
+        // The history triggers refer to the index by name, so they keep working once it is
+        // recreated.
+        tx.execute_batch(&format!(
+            "DROP TABLE IF EXISTS history_fts;
+            CREATE VIRTUAL TABLE history_fts USING fts5(command, cwd, content='history', content_rowid='id', tokenize='{}');
+            INSERT INTO history_fts(history_fts) VALUES('rebuild');",
+            tokenizer.name()
+        ))?;
+        tx.commit()?;
+        Ok(())
+    }
+
+    /// Streams the matches of a query with filters, the general path of `search_each`.
+    fn search_each_filtered(
+        &self,
+        query: &str,
+        filters: &HistoryFilters,
+        f: &mut dyn FnMut(History),
+    ) -> Result<(), DatabaseError> {
+        let (sql_query, params_map) = search_query(query, filters);
+
+        // Parameter Vec preparation, convert the hashmap into a tuple Vec.
+        let named_params_vec = to_named_params(&params_map);
+
+        #[cfg(debug_assertions)]
+        {
+            // Increased logging for dev binaries.
+            let sql_string = sql_query.to_sql();
+            debug!("Executing search SQL: {}", sql_string);
+            debug!(
+                "With parameters: {:?}",
+                format_named_params_for_debug(&named_params_vec)
+            );
+        }
+
+        let mut stmt = self.conn.prepare(&sql_query.to_sql())?;
+
+        match stmt.query_map(&*named_params_vec, history_from_row) {
+            Ok(rows) => {
+                // Hand each row over as it is read, stopping at the first row error.
+                for row in rows {
+                    f(row?);
+                }
+                Ok(())
+            }
+            Err(e) => {
+                debug!(
+                    "Search query failed: Query='{}', Params={:?}, Error={}",
+                    sql_query.to_sql(),
+                    format_named_params_for_debug(&named_params_vec),
+                    e
+                );
+                Err(e.into())
+            }
+        }
+    }
+
+    /// Streams the most recent `History` entries, the fast path of `search_each` for an empty
+    /// query without filters.
+    ///
+    /// * `limit`: The maximum number of entries, or every entry if `None`.
+    /// * `offset`: The number of most recent entries to skip.
+    fn search_each_recent(
+        &self,
+        limit: Option<usize>,
+        offset: Option<usize>,
+        f: &mut dyn FnMut(History),
+    ) -> Result<(), DatabaseError> {
+        // A negative LIMIT has no upper bound in SQLite.
+        let limit = limit.map_or(-1, |limit| i64::try_from(limit).unwrap_or(i64::MAX));
+        let offset = offset.map_or(0, |offset| i64::try_from(offset).unwrap_or(i64::MAX));
+        let mut stmt = self.conn.prepare_cached(SEARCH_RECENT_SQL)?;
+        for row in stmt.query_map([limit, offset], history_from_row)? {
+            f(row?);
+        }
+        Ok(())
+    }
+
+    /// Opens a new in-memory database migrated to the latest schema.
+    ///
+    /// Nothing is persisted, useful for tests and benchmarks.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the in-memory connection cannot be opened or the migrations fail.
+    #[must_use]
+    pub fn memory() -> Self {
+        let mut conn = Connection::open_in_memory().expect("Failed to open in-memory database");
+        register_functions(&conn).expect("Failed to register SQL functions.");
+        run_migrations(&mut conn, SchemaVersion::V0.to_u32(), None)
+            .expect("Failure during migrations of in-memory database.");
+        Self { conn }
+    }
+
+    /// Run a search, interrupting it once it has run for longer than `timeout`, if given.
+    fn timed<T>(
+        &self,
+        timeout: Option<Duration>,
+        search: impl FnOnce() -> Result<T, DatabaseError>,
+    ) -> Result<T, DatabaseError> {
+        let Some(timeout) = timeout else {
+            return search();
+        };
+        let deadline = Instant::now() + timeout;
+        self.conn
+            .progress_handler(TIMEOUT_CHECK_OPS, Some(move || Instant::now() >= deadline));
+        let result = search();
+        self.conn.progress_handler(0, None::<fn() -> bool>);
+        result.map_err(|err| match err.kind {
+            DatabaseErrorKind::Timeout => DatabaseError {
+                msg: format!("search timed out after {}ms", timeout.as_millis()),
+                kind: DatabaseErrorKind::Timeout,
+            },
+            _ => err,
+        })
+    }
+
+    /// Resolves the full path to the database file for the provided configuration.
+    ///
+    /// Falls back to the raven data directory and the default database file name
+    /// for any values not set in the config.
+    #[must_use]
+    pub fn database_path(config: &Config) -> PathBuf {
         let path = config
             .database
             .as_ref()
             .and_then(|config| config.database_path.clone())
             .unwrap_or(get_data_dir());
-        let _ = fs::create_dir_all(&path);
 
         let file = config
             .database
@@ -78,13 +487,7 @@ impl Sqlite {
             .and_then(|config| config.database_file.clone())
             .unwrap_or(String::from(DATABASE_FILE));
 
-        let database_path = path.join(file);
-        let conn = get_connection(
-            database_path
-                .to_str()
-                .expect("Could not generate database file path."),
-        );
-        Self { conn }
+        path.join(file)
     }
 }
 
@@ -92,33 +495,66 @@ impl Sqlite {
 ///
 /// Args:
 ///   query: The user-provided search string.
-///   mode: The desired FTS5 matching mode (`Fuzzy` or `Prefix`).
+///   mode: The desired FTS5 matching mode (`Fuzzy`, `FuzzyAny`, `Prefix` or `Raw`).
+///   policy: Which words match as prefixes of longer words. `Prefix` mode treats the query as a
+///     single word, `Raw` ignores it.
 ///
 /// Returns:
 ///   A string suitable for use as the right-hand operand of an FTS5 `MATCH` operator.
 ///   Returns an empty string if the input query is empty, signifying no FTS filtering.
 #[must_use]
-pub fn generate_fts5_match_parameter(query: &str, mode: MatchMode) -> String {
+pub fn generate_fts5_match_parameter(query: &str, mode: MatchMode, policy: PrefixPolicy) -> String {
     if query.is_empty() {
         return String::new();
     }
 
+    let suffix = |is_last: bool| match policy {
+        PrefixPolicy::All => "*",
+        PrefixPolicy::LastOnly if is_last => "*",
+        PrefixPolicy::LastOnly | PrefixPolicy::None => "",
+    };
     match mode {
-        MatchMode::Fuzzy => {
+        MatchMode::Fuzzy | MatchMode::FuzzyAny => {
+            let count = query.split_whitespace().count();
             let words: Vec<String> = query
                 .split_whitespace()
-                .map(|word| {
+                .enumerate()
+                .map(|(idx, word)| {
                     let escaped_word = word.replace('"', "\"\"");
-                    format!("\"{escaped_word}\"*")
+                    format!("\"{escaped_word}\"{}", suffix(idx + 1 == count))
                 })
                 .collect();
-            words.join(" ")
+            // FTS5 ANDs terms separated by whitespace implicitly.
+            if mode == MatchMode::FuzzyAny {
+                words.join(" OR ")
+            } else {
+                words.join(" ")
+            }
         }
         MatchMode::Prefix => {
             let escaped_query = query.replace('"', "\"\"");
-            format!("^\"{escaped_query}\"*")
+            format!("^\"{escaped_query}\"{}", suffix(true))
+        }
+        // Still bound as a parameter, so only the FTS expression is up to the user.
+        MatchMode::Raw => query.to_string(),
+        // Never matched against the index, see `apply_search_filters`.
+        MatchMode::Regex => query.to_string(),
+    }
+}
+
+/// Escapes the LIKE wildcards (`%`, `_`) and the `\` escape character itself in `value`.
+///
+/// The result must be used with a clause that declares `ESCAPE '\'`.
+#[must_use]
+pub fn escape_like(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for c in value.chars() {
+        if matches!(c, '\\' | '%' | '_') {
+            escaped.push('\\');
         }
+        escaped.push(c);
     }
+    escaped
 }
 
 /// Provides a default `Sqlite` instance.
@@ -141,8 +577,17 @@ impl Default for Sqlite {
 /// into a more general database error type used within the application.
 impl From<rusqlite::Error> for DatabaseError {
     fn from(value: rusqlite::Error) -> Self {
+        let kind = if is_duplicate(&value) {
+            DatabaseErrorKind::Duplicate
+        } else if is_interrupted(&value) {
+            // Only the search timeout interrupts a statement.
+            DatabaseErrorKind::Timeout
+        } else {
+            DatabaseErrorKind::Other
+        };
         Self {
             msg: format!("{value}"),
+            kind,
         }
     }
 }
@@ -167,6 +612,10 @@ impl Database for Sqlite {
             .column("command")
             .column("cwd")
             .column("exit_code")
+            .column("session_id")
+            .column("tty")
+            .column("source")
+            .column("duration")
             .table("history")
             .to_owned();
 
@@ -176,6 +625,10 @@ impl Database for Sqlite {
             ":command": history.command,
             ":cwd": history.cwd,
             ":exit_code": history.exit_code,
+            ":session_id": history.session_id,
+            ":tty": history.tty,
+            ":source": history.source,
+            ":duration": history.duration,
         });
         Ok(result?)
     }
@@ -192,36 +645,32 @@ impl Database for Sqlite {
     /// * `Err(DatabaseError)` - If there was an error during the database operation.
     ///   The transaction will be rolled back in case of an error.
     fn save_bulk(&mut self, history: &[History]) -> Result<Vec<i64>, DatabaseError> {
-        let mut row_ids: Vec<i64> = Vec::new();
-
-        let mut tx = self.conn.transaction().expect("expected transaction");
-        tx.set_drop_behavior(DropBehavior::Rollback);
-
         let query = Query::insert()
             .column("timestamp")
             .column("command")
             .column("cwd")
             .column("exit_code")
+            .column("session_id")
+            .column("tty")
+            .column("source")
+            .column("duration")
             .table("history")
             .to_owned();
-        let mut stmt = tx.prepare(query.to_sql().as_str()).unwrap();
-        for h in history {
-            match stmt.insert(named_params! {
-                ":timestamp": h.timestamp.unix_timestamp(),
-                ":command": h.command,
-                ":cwd": h.cwd,
-                ":exit_code": h.exit_code,
-            }) {
-                Ok(row_id) => row_ids.push(row_id),
-                Err(err) => {
-                    // Transaction is automatically rolled back due to DropBehavior::Rollback
-                    return Err(err.into());
-                }
-            }
-        }
-        drop(stmt); // Explicitly drop statement before committing transaction
-        tx.commit()?; // Commit the transaction
-        Ok(row_ids)
+        self.insert_bulk(history, &query.to_sql())
+    }
+
+    /// Saves multiple `History` entries like `save_bulk`, skipping any whose command, cwd and
+    /// timestamp are already stored.
+    ///
+    /// Entries repeated within `history` are only saved once.
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(Vec<i64>)` - A vector containing the row IDs of the newly inserted entries.
+    /// * `Err(DatabaseError)` - If there was an error during the database operation.
+    ///   The transaction will be rolled back in case of an error.
+    fn save_bulk_dedup(&mut self, history: &[History]) -> Result<Vec<i64>, DatabaseError> {
+        self.insert_bulk(history, INSERT_IF_NEW_SQL)
     }
 
     /// Retrieves a single `History` entry from the database by its ID.
@@ -242,24 +691,18 @@ impl Database for Sqlite {
             .column("cwd")
             .column("exit_code")
             .column("timestamp")
+            .column("session_id")
+            .column("tty")
+            .column("source")
+            .column("duration")
+            .column("updated_at")
             .from("history")
             .r#where("id")
             .to_owned();
 
         let mut stmt = self.conn.prepare(query.to_sql().as_str())?;
 
-        let h = stmt.query_row([id], |row| {
-            Ok(History::builder()
-                .id(row.get("id")?)
-                .command(row.get("command")?)
-                .cwd(row.get("cwd")?)
-                .exit_code(row.get("exit_code")?)
-                .timestamp(
-                    OffsetDateTime::from_unix_timestamp(row.get("timestamp")?)
-                        .expect("Failed to parse timestamp"),
-                )
-                .build())
-        });
+        let h = stmt.query_row([id], history_from_row);
 
         match h {
             Ok(h) => Ok(Some(h)),
@@ -284,6 +727,7 @@ impl Database for Sqlite {
         if history.id == -1 {
             return Err(DatabaseError {
                 msg: "Cannot update object with -1 ID, try save first.".to_string(),
+                ..Default::default()
             });
         }
 
@@ -293,7 +737,13 @@ impl Database for Sqlite {
             .column("cwd")
             .column("exit_code")
             .column("timestamp")
+            .column("session_id")
+            .column("tty")
+            .column("source")
+            .column("duration")
+            .set("updated_at", UPDATED_AT_NEXT)
             .r#where("id")
+            .r#where("updated_at")
             .to_owned();
 
         let mut stmt = self.conn.prepare(query.to_sql().as_str())?;
@@ -303,140 +753,192 @@ impl Database for Sqlite {
             ":cwd": history.cwd,
             ":exit_code": history.exit_code,
             ":timestamp": history.timestamp.unix_timestamp(),
+            ":session_id": history.session_id,
+            ":tty": history.tty,
+            ":source": history.source,
+            ":duration": history.duration,
+            ":updated_at": now_millis(),
             ":w_id": history.id, // Parameter name must match the `where` clause in the SQL
+            ":w_updated_at": history.updated_at,
         }) {
-            Ok(rows) => {
-                if rows == 1 {
-                    Ok(())
-                } else {
-                    // Can happen if the ID doesn't exist
-                    Err(DatabaseError {
-                        msg: format!(
-                            "Update affected {rows} rows, expected 1 for ID {}",
-                            history.id
-                        ),
-                    })
-                }
-            }
+            Ok(1) => Ok(()),
+            // The row exists, but was written since it was read.
+            Ok(0) if self.get(history.id)?.is_some() => Err(DatabaseError {
+                msg: format!(
+                    "History {} was changed since it was read, reload it before updating",
+                    history.id
+                ),
+                kind: DatabaseErrorKind::Conflict,
+            }),
+            // Can happen if the ID doesn't exist
+            Ok(rows) => Err(DatabaseError {
+                msg: format!(
+                    "Update affected {rows} rows, expected 1 for ID {}",
+                    history.id
+                ),
+                ..Default::default()
+            }),
             Err(e) => Err(e.into()),
         }
     }
 
-    /// Searches for `History` entries based on a query string and filters.
+    /// Updates only the fields set in `update` for an existing `History` entry.
+    ///
+    /// Fields left as `None` are not part of the `UPDATE` statement, so values written by
+    /// someone else in the meantime are preserved. An empty update does not touch the database.
     ///
     /// # Arguments
     ///
-    /// * `query` - The search string to match against the `command` field.
-    /// * `filters` - A `HistoryFilters` struct containing additional filtering criteria (limit, exit code, cwd, suggest mode).
+    /// * `id` - The unique ID of the `History` entry to update.
+    /// * `update` - The fields to change.
     ///
     /// # Returns
     ///
-    /// * `Ok(Vec<History>)` - A vector of matching `History` entries, ordered by timestamp descending.
-    /// * `Err(DatabaseError)` - If there was an error during the database operation.
-    fn search(&self, query: &str, filters: HistoryFilters) -> Result<Vec<History>, DatabaseError> {
-        debug!("search with query: '{}', filters: {:?}", query, filters);
-
-        let mut params_map: std::collections::HashMap<String, Box<dyn ToSql>> =
-            std::collections::HashMap::new();
-
-        let mut sql_query = Query::select()
-            .column("h.id") // No alias needed
-            .column("h.command")
-            .column("h.cwd")
-            .column("h.exit_code")
-            .column("h.timestamp")
-            .from("history h")
-            // Order by timestamp when not using FTS relevance
-            .orderby("timestamp", "DESC")
-            .to_owned();
-
-        if !query.is_empty() {
-            // Reset the from table to use history_fts and join on history.
-            sql_query.from.clear();
-            sql_query
-                .from("history_fts fts JOIN history h ON h.id = fts.rowid")
-                .match_fts("fts.command");
-
-            let fts5_query = generate_fts5_match_parameter(query, filters.mode);
-            // Add the search tokens to the query parameters.
-            params_map.insert(":fts_command".to_string(), Box::new(fts5_query));
+    /// * `Ok(())` - If the update was successful.
+    /// * `Err(DatabaseError)` - If the history ID does not exist or if there was a database error.
+    fn update_partial(&self, id: i64, update: &HistoryUpdate) -> Result<(), DatabaseError> {
+        if update.is_empty() {
+            return Ok(());
         }
 
-        if let Some(exit) = filters.exit {
-            let param_name = ":h_exit_code"; // Need distinct param name
-            sql_query.r#where("h.exit_code"); // WHERE h.exit_code = :h_exit_code
-            params_map.insert(param_name.to_string(), Box::new(exit));
-        }
+        let mut query = Query::update().table("history").to_owned();
+        let mut params_map: QueryParams = HashMap::new();
 
-        if let Some(cwd) = filters.cwd.as_ref() {
-            let param_name = ":h_cwd"; // Need distinct param name
-            sql_query.r#where("h.cwd"); // WHERE h.cwd = :h_cwd
-            params_map.insert(param_name.to_string(), Box::new(cwd.clone()));
+        if let Some(command) = update.command.as_ref() {
+            query.column("command");
+            params_map.insert(":command".to_string(), Box::new(command.clone()));
         }
-
-        // Apply limit regardless of path
-        if let Some(limit) = filters.limit {
-            sql_query.limit(limit);
+        if let Some(cwd) = update.cwd.as_ref() {
+            query.column("cwd");
+            params_map.insert(":cwd".to_string(), Box::new(cwd.clone()));
         }
-
-        // Parameter Vec preparation, convert the hashmap into a tuple Vec.
-        let named_params_vec: Vec<(&str, &dyn ToSql)> = params_map
-            .iter()
-            .map(|(k, v)| (k.as_str(), v.as_ref()))
-            .collect();
-
-        #[cfg(debug_assertions)]
-        {
-            // Increased logging for dev binaries.
-            let sql_string = sql_query.to_sql();
-            debug!("Executing search SQL: {}", sql_string);
-            debug!(
-                "With parameters: {:?}",
-                format_named_params_for_debug(&named_params_vec)
+        if let Some(exit_code) = update.exit_code {
+            query.column("exit_code");
+            params_map.insert(":exit_code".to_string(), Box::new(exit_code));
+        }
+        if let Some(timestamp) = update.timestamp {
+            query.column("timestamp");
+            params_map.insert(
+                ":timestamp".to_string(),
+                Box::new(timestamp.unix_timestamp()),
             );
         }
+        if let Some(session_id) = update.session_id.as_ref() {
+            query.column("session_id");
+            params_map.insert(":session_id".to_string(), Box::new(session_id.clone()));
+        }
 
-        let mut stmt = self.conn.prepare(&sql_query.to_sql())?;
+        query.set("updated_at", UPDATED_AT_NEXT).r#where("id");
+        params_map.insert(":updated_at".to_string(), Box::new(now_millis()));
+        params_map.insert(":w_id".to_string(), Box::new(id));
 
-        match stmt.query_map(&*named_params_vec, |row| {
-            Ok(History::builder()
-                .id(row.get("id")?)
-                .command(row.get("command")?)
-                .cwd(row.get("cwd")?)
-                .exit_code(row.get("exit_code")?)
-                .timestamp(OffsetDateTime::from_unix_timestamp(row.get("timestamp")?).unwrap())
-                .build())
-        }) {
-            Ok(rows) => {
-                // Collect results, handling potential errors during row processing
-                rows.collect::<Result<Vec<History>, rusqlite::Error>>()
-                    .map_err(DatabaseError::from)
-            }
-            Err(e) => {
-                debug!(
-                    "Search query failed: Query='{}', Params={:?}, Error={}",
-                    sql_query.to_sql(),
-                    format_named_params_for_debug(&named_params_vec),
-                    e
-                );
-                Err(e.into())
-            }
+        let mut stmt = self.conn.prepare(query.to_sql().as_str())?;
+        match stmt.execute(&*to_named_params(&params_map))? {
+            1 => Ok(()),
+            // Can happen if the ID doesn't exist
+            rows => Err(DatabaseError {
+                msg: format!("Update affected {rows} rows, expected 1 for ID {id}"),
+                ..Default::default()
+            }),
         }
     }
 
-    /// Deletes a `History` entry from the database by its ID.
+    /// Searches for `History` entries based on a query string and filters.
     ///
     /// # Arguments
     ///
-    /// * `id` - The ID of the history entry to delete.
+    /// * `query` - The search string to match against the `command` field.
+    /// * `filters` - A `HistoryFilters` struct containing additional filtering criteria (limit, exit code, cwd, suggest mode).
     ///
     /// # Returns
     ///
-    /// * `Ok(())` - If the deletion was successful or if the entry did not exist.
-    /// * `Err(DatabaseError)` - If there was an unexpected database error.
-    fn delete(&self, id: i64) -> Result<(), DatabaseError> {
-        debug!("Deleting history entry with id: {}", id);
-        let query = Query::delete().table("history").r#where("id").to_owned();
+    /// * `Ok(Vec<History>)` - A vector of matching `History` entries, ordered by timestamp descending.
+    /// * `Err(DatabaseError)` - If there was an error during the database operation.
+    fn search(&self, query: &str, filters: HistoryFilters) -> Result<Vec<History>, DatabaseError> {
+        let mut results = Vec::new();
+        self.search_each(query, filters, &mut |h| results.push(h))?;
+        Ok(results)
+    }
+
+    /// Streams the `History` entries matching a query string and filters to a callback.
+    ///
+    /// Rows are handed to `f` as they are read, so the full result set is never held in memory.
+    ///
+    /// # Arguments
+    ///
+    /// * `query` - The search string to match against the `command` field.
+    /// * `filters` - A `HistoryFilters` struct containing additional filtering criteria (limit, exit code, cwd, suggest mode).
+    /// * `f` - Called once for each matching `History` entry, ordered by timestamp descending.
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(())` - If every matching row was passed to `f`.
+    /// * `Err(DatabaseError)` - If there was an error during the database operation.
+    fn search_each(
+        &self,
+        query: &str,
+        mut filters: HistoryFilters,
+        f: &mut dyn FnMut(History),
+    ) -> Result<(), DatabaseError> {
+        debug!("search with query: '{}', filters: {:?}", query, filters);
+        check_regex(query, &filters)?;
+        self.fall_back_without_index(query, &mut filters);
+
+        self.timed(filters.timeout, || {
+            // The initial TUI load and the up-key run this on every invocation, skip building a
+            // query.
+            if query.is_empty() && filters.is_unfiltered() {
+                return self.search_each_recent(filters.limit, filters.offset, f);
+            }
+            self.search_each_filtered(query, &filters, f)
+        })
+    }
+
+    /// Counts the `History` entries matching a query string and filters.
+    ///
+    /// The `limit` filter is ignored, so this reports every match.
+    ///
+    /// # Arguments
+    ///
+    /// * `query` - The search string to match against the `command` field.
+    /// * `filters` - A `HistoryFilters` struct containing additional filtering criteria.
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(i64)` - The number of matching `History` entries.
+    /// * `Err(DatabaseError)` - If there was an error during the database operation.
+    fn search_count(&self, query: &str, mut filters: HistoryFilters) -> Result<i64, DatabaseError> {
+        check_regex(query, &filters)?;
+        self.fall_back_without_index(query, &mut filters);
+        let mut sql_query = Query::select().count("*", "count").to_owned();
+        let params_map = apply_search_filters(&mut sql_query, query, &filters);
+        let named_params_vec = to_named_params(&params_map);
+
+        self.timed(filters.timeout, || {
+            let mut stmt = self.conn.prepare(&sql_query.to_sql())?;
+            let count = stmt.query_row(&*named_params_vec, |row| row.get::<usize, i64>(0));
+            Ok(count?)
+        })
+    }
+
+    fn count(&self, filters: HistoryFilters) -> Result<i64, DatabaseError> {
+        // Without a query the FTS table is not joined, so this is a plain filtered count.
+        self.search_count("", filters)
+    }
+
+    /// Deletes a `History` entry from the database by its ID.
+    ///
+    /// # Arguments
+    ///
+    /// * `id` - The ID of the history entry to delete.
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(())` - If the deletion was successful or if the entry did not exist.
+    /// * `Err(DatabaseError)` - If there was an unexpected database error.
+    fn delete(&self, id: i64) -> Result<(), DatabaseError> {
+        debug!("Deleting history entry with id: {}", id);
+        let query = Query::delete().table("history").r#where("id").to_owned();
 
         match self.conn.execute(&query.to_sql(), [id]) {
             Ok(rows_affected) => {
@@ -457,6 +959,7 @@ impl Database for Sqlite {
                         msg: format!(
                             "Unexpected number of rows ({rows_affected}) affected during deletion for id {id}",
                         ),
+                        ..Default::default()
                     })
                 }
             }
@@ -467,6 +970,112 @@ impl Database for Sqlite {
         }
     }
 
+    fn delete_bulk(&self, ids: &[i64]) -> Result<usize, DatabaseError> {
+        debug!("Deleting {} history entries", ids.len());
+        let query = Query::delete().table("history").r#where("id").to_owned();
+
+        // No other method holds a transaction across calls, so this one is never nested.
+        let mut tx = self.conn.unchecked_transaction()?;
+        tx.set_drop_behavior(DropBehavior::Rollback);
+        let mut deleted = 0;
+        {
+            let mut stmt = tx.prepare(&query.to_sql())?;
+            for id in ids {
+                deleted += stmt.execute([id])?;
+            }
+        }
+        tx.commit()?;
+        Ok(deleted)
+    }
+
+    fn rewrite_cwd(&self, from: &str, to: &str) -> Result<usize, DatabaseError> {
+        // A trailing slash would keep the subdirectories of `from` from matching.
+        let from = from.trim_end_matches('/');
+        let to = to.trim_end_matches('/');
+        if from.is_empty() {
+            return Err(DatabaseError {
+                msg: "refusing to rewrite the history of the root directory".to_string(),
+                ..Default::default()
+            });
+        }
+
+        debug!("Rewriting cwd {from} to {to}");
+        let rewritten = self.conn.execute(
+            REWRITE_CWD_SQL,
+            named_params! {
+                ":from": from,
+                ":to": to,
+                ":subdirs": format!("{}/%", escape_like(from)),
+                ":updated_at": now_millis(),
+            },
+        )?;
+        Ok(rewritten)
+    }
+
+    fn delete_where(
+        &self,
+        filters: HistoryFilters,
+        force_all: bool,
+    ) -> Result<usize, DatabaseError> {
+        if filters.is_unfiltered() && !force_all {
+            error!("Refusing to delete history without any filter");
+            return Err(DatabaseError {
+                msg: "refusing to delete all history without force_all".to_string(),
+                kind: DatabaseErrorKind::Unfiltered,
+            });
+        }
+
+        self.delete_search("", filters)
+    }
+
+    fn delete_matching(
+        &self,
+        query: &str,
+        filters: HistoryFilters,
+    ) -> Result<usize, DatabaseError> {
+        if query.trim().is_empty() && filters.is_unfiltered() {
+            error!("Refusing to delete history without a query or filter");
+            return Err(DatabaseError {
+                msg: "refusing to delete all history without a query or filter".to_string(),
+                kind: DatabaseErrorKind::Unfiltered,
+            });
+        }
+        self.delete_search(query, filters)
+    }
+
+    fn delete_range(
+        &self,
+        after: Option<OffsetDateTime>,
+        before: Option<OffsetDateTime>,
+        force_all: bool,
+    ) -> Result<usize, DatabaseError> {
+        if after.is_none() && before.is_none() && !force_all {
+            error!("Refusing to delete history without a time range");
+            return Err(DatabaseError {
+                msg: "refusing to delete all history without force_all".to_string(),
+                kind: DatabaseErrorKind::Unfiltered,
+            });
+        }
+
+        let mut query = Query::delete().table("history").to_owned();
+        let mut params: Vec<(&str, i64)> = Vec::new();
+        if let Some(after) = after {
+            query.where_op("timestamp", ">=", "after");
+            params.push((":w_after", after.unix_timestamp()));
+        }
+        if let Some(before) = before {
+            query.where_op("timestamp", "<=", "before");
+            params.push((":w_before", before.unix_timestamp()));
+        }
+        let named_params_vec: Vec<(&str, &dyn ToSql)> = params
+            .iter()
+            .map(|(name, value)| (*name, value as &dyn ToSql))
+            .collect();
+        debug!("Executing delete SQL: {}", query.to_sql());
+
+        Ok(self.conn.execute(&query.to_sql(), &*named_params_vec)?)
+    }
+
     /// Gets the total number of history entries in the database.
     ///
     /// # Returns
@@ -482,6 +1091,481 @@ impl Database for Sqlite {
         let count = stmt.query_row([], |row| row.get::<usize, i64>(0)); // Get count by index
         Ok(count?)
     }
+
+    /// Lists the most recently active sessions.
+    ///
+    /// Rows without a session (such as imported history) are ignored.
+    ///
+    /// # Arguments
+    ///
+    /// * `n` - The maximum number of sessions to return.
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(Vec<(String, OffsetDateTime, i64)>)` - The session id, time of its latest command and
+    ///   its command count, ordered by the latest command, most recent first.
+    /// * `Err(DatabaseError)` - If there was an error during the database operation.
+    fn recent_sessions(
+        &self,
+        n: usize,
+    ) -> Result<Vec<(String, OffsetDateTime, i64)>, DatabaseError> {
+        let query = Query::select()
+            .column("session_id")
+            .max("timestamp", "last_active")
+            .count("*", "count")
+            .from("history")
+            .where_op("session_id", "!=", "no_session")
+            .groupby("session_id")
+            .orderby("MAX(timestamp)", "DESC")
+            .limit(n)
+            .to_owned();
+
+        let mut stmt = self.conn.prepare(query.to_sql().as_str())?;
+        let rows = stmt.query_map(named_params! {":no_session": ""}, |row| {
+            Ok((
+                row.get("session_id")?,
+                OffsetDateTime::from_unix_timestamp(row.get("last_active")?)
+                    .expect("Failed to parse timestamp"),
+                row.get("count")?,
+            ))
+        })?;
+        Ok(rows.collect::<Result<Vec<_>, rusqlite::Error>>()?)
+    }
+
+    /// Lists the most recently run distinct commands, from the `recent_commands` cache.
+    ///
+    /// # Arguments
+    ///
+    /// * `n` - The maximum number of commands to return, at most the 200 the cache holds.
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(Vec<History>)` - The newest run of each command, most recent first.
+    /// * `Err(DatabaseError)` - If there was an error during the database operation.
+    fn recent_commands(&self, n: usize) -> Result<Vec<History>, DatabaseError> {
+        let mut stmt = self.conn.prepare(RECENT_COMMANDS_SQL)?;
+        let limit = i64::try_from(n).unwrap_or(i64::MAX);
+        let rows = stmt.query_map([limit], history_from_row)?;
+        Ok(rows.collect::<Result<Vec<_>, rusqlite::Error>>()?)
+    }
+
+    /// Records shell aliases in a single transaction.
+    ///
+    /// # Arguments
+    ///
+    /// * `aliases` - The aliases to record, replacing any earlier expansion of the same name.
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(usize)` - The number of aliases recorded.
+    /// * `Err(DatabaseError)` - If there was an error during the database operation, in which
+    ///   case none are recorded.
+    fn save_aliases(&mut self, aliases: &[Alias]) -> Result<usize, DatabaseError> {
+        let tx = self.conn.transaction()?;
+        {
+            let mut stmt = tx.prepare(SAVE_ALIAS_SQL)?;
+            for alias in aliases {
+                stmt.execute(named_params! {
+                    ":name": alias.name,
+                    ":expansion": alias.expansion,
+                })?;
+            }
+        }
+        tx.commit()?;
+        Ok(aliases.len())
+    }
+
+    /// Lists every recorded shell alias.
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(Vec<Alias>)` - The aliases, ordered by name.
+    /// * `Err(DatabaseError)` - If there was an error during the database operation.
+    fn aliases(&self) -> Result<Vec<Alias>, DatabaseError> {
+        let mut stmt = self.conn.prepare(ALIASES_SQL)?;
+        let rows = stmt.query_map([], |row| {
+            Ok(Alias {
+                name: row.get("name")?,
+                expansion: row.get("expansion")?,
+            })
+        })?;
+        Ok(rows.collect::<Result<Vec<_>, rusqlite::Error>>()?)
+    }
+
+    /// Gets the commands run right before and after a history entry.
+    ///
+    /// # Arguments
+    ///
+    /// * `id` - The id of the entry.
+    /// * `window` - The maximum number of commands to return on each side.
+    /// * `same_session` - Only include commands from the session of the entry.
+    ///
+    /// # Returns
+    ///
+    /// * `Ok((Vec<History>, Vec<History>))` - The commands before and after the entry, each
+    ///   ordered oldest first. Both are empty if the entry does not exist.
+    /// * `Err(DatabaseError)` - If there was an error during the database operation.
+    fn neighbors(
+        &self,
+        id: i64,
+        window: usize,
+        same_session: bool,
+    ) -> Result<(Vec<History>, Vec<History>), DatabaseError> {
+        let Some(h) = self.get(id)? else {
+            return Ok((Vec::new(), Vec::new()));
+        };
+        let session_id = same_session.then_some(h.session_id.as_str());
+        let params = named_params! {
+            ":id": h.id,
+            ":timestamp": h.timestamp.unix_timestamp(),
+            ":session_id": session_id,
+            ":window": window,
+        };
+
+        let mut before = self
+            .conn
+            .prepare(NEIGHBORS_BEFORE_SQL)?
+            .query_map(params, history_from_row)?
+            .collect::<Result<Vec<_>, rusqlite::Error>>()?;
+        before.reverse();
+        let after = self
+            .conn
+            .prepare(NEIGHBORS_AFTER_SQL)?
+            .query_map(params, history_from_row)?
+            .collect::<Result<Vec<_>, rusqlite::Error>>()?;
+        Ok((before, after))
+    }
+
+    /// The name of this backend, `sqlite`.
+    fn backend_name(&self) -> &'static str {
+        "sqlite"
+    }
+
+    /// Gets the schema version from the ``user_version`` PRAGMA.
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(u32)` - The schema version the database is migrated to.
+    /// * `Err(DatabaseError)` - If there was an error during the database operation.
+    fn schema_version(&self) -> Result<u32, DatabaseError> {
+        Ok(get_user_version(&self.conn)?)
+    }
+
+    /// Runs the ``integrity_check`` and ``foreign_key_check`` PRAGMAs, and the FTS5
+    /// ``integrity-check`` command which compares the search index with the history table.
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(bool)` - Whether both checks passed.
+    /// * `Err(DatabaseError)` - If there was an error during the database operation.
+    fn health_check(&self) -> Result<bool, DatabaseError> {
+        // A sound database reports a single "ok" row.
+        let mut stmt = self.conn.prepare("PRAGMA integrity_check;")?;
+        let problems = stmt
+            .query_map([], |row| row.get::<_, String>(0))?
+            .collect::<Result<Vec<_>, rusqlite::Error>>()?
+            .into_iter()
+            .filter(|message| message != "ok")
+            .inspect(|message| error!("integrity check: {message}"))
+            .count();
+
+        // A sound database reports no rows, each row is a violating (table, rowid, parent).
+        let mut stmt = self.conn.prepare("PRAGMA foreign_key_check;")?;
+        let violations = stmt
+            .query_map([], |row| {
+                Ok((row.get::<_, String>(0)?, row.get::<_, Option<i64>>(1)?))
+            })?
+            .collect::<Result<Vec<_>, rusqlite::Error>>()?
+            .into_iter()
+            .inspect(|(table, rowid)| error!("foreign key check: {table} row {rowid:?}"))
+            .count();
+
+        // A partial migration can leave no index to check, which is a problem of its own.
+        if !self.has_search_index() {
+            error!("search index missing");
+            return Ok(false);
+        }
+
+        // The PRAGMAs do not compare an external content index with its table, so a missed
+        // trigger is only caught here.
+        let index_ok = match self.conn.execute(
+            "INSERT INTO history_fts(history_fts, rank) VALUES ('integrity-check', 1);",
+            [],
+        ) {
+            Ok(_) => true,
+            Err(rusqlite::Error::SqliteFailure(err, message))
+                if err.code == rusqlite::ErrorCode::DatabaseCorrupt =>
+            {
+                error!(
+                    "search index check: {}",
+                    message.unwrap_or_else(|| err.to_string())
+                );
+                false
+            }
+            Err(err) => return Err(err.into()),
+        };
+
+        Ok(problems == 0 && violations == 0 && index_ok)
+    }
+}
+
+/// Build a `History` from a row selecting every `history` column.
+fn history_from_row(row: &Row) -> rusqlite::Result<History> {
+    Ok(History::builder()
+        .id(row.get("id")?)
+        .command(row.get("command")?)
+        .cwd(row.get("cwd")?)
+        .exit_code(row.get("exit_code")?)
+        .timestamp(
+            OffsetDateTime::from_unix_timestamp(row.get("timestamp")?)
+                .expect("Failed to parse timestamp"),
+        )
+        .session_id(row.get("session_id")?)
+        .tty(row.get("tty")?)
+        .source(row.get("source")?)
+        .duration(row.get("duration")?)
+        .updated_at(row.get("updated_at")?)
+        .build())
+}
+
+/// The current time in unix milliseconds, used for `updated_at`.
+fn now_millis() -> i64 {
+    i64::try_from(OffsetDateTime::now_utc().unix_timestamp_nanos() / 1_000_000).unwrap_or(i64::MAX)
+}
+
+/// Named query parameters, keyed by their `:name`.
+type QueryParams = HashMap<String, Box<dyn ToSql>>;
+
+/// Build the statement and parameters used to search for `query` with `filters`.
+fn search_query(query: &str, filters: &HistoryFilters) -> (SelectStatement<'static>, QueryParams) {
+    let mut sql_query = Query::select()
+        .column("h.id") // No alias needed
+        .column("h.command")
+        .column("h.cwd")
+        .column("h.exit_code")
+        .column("h.timestamp")
+        .column("h.session_id")
+        .column("h.tty")
+        .column("h.source")
+        .column("h.duration")
+        .column("h.updated_at")
+        .to_owned();
+    let params_map = apply_search_filters(&mut sql_query, query, filters);
+
+    // Relevance only means something when matching against the FTS index, the lowest bm25
+    // rank is the best match. Ties go to the most recently stored row, so pages do not overlap.
+    if filters.order == OrderBy::Relevance && uses_fts(query, filters) {
+        sql_query.orderby("fts.rank", "ASC");
+    } else {
+        sql_query.orderby("h.timestamp", "DESC");
+    }
+    sql_query.orderby("h.id", "DESC");
+
+    // Apply limit and offset regardless of path
+    if let Some(limit) = filters.limit {
+        sql_query.limit(limit);
+    }
+    if let Some(offset) = filters.offset {
+        sql_query.offset(offset);
+    }
+
+    (sql_query, params_map)
+}
+
+/// Whether `query` is matched against the FTS index, rather than scanning the history table.
+fn uses_fts(query: &str, filters: &HistoryFilters) -> bool {
+    !query.is_empty() && !filters.no_fts && filters.mode != MatchMode::Regex
+}
+
+/// Rejects an invalid `Regex` mode pattern before it reaches `SQLite`, which would only report
+/// that the `regexp` function failed.
+fn check_regex(query: &str, filters: &HistoryFilters) -> Result<(), DatabaseError> {
+    if filters.mode != MatchMode::Regex || query.is_empty() {
+        return Ok(());
+    }
+    match Regex::new(query) {
+        Ok(_) => Ok(()),
+        Err(err) => Err(DatabaseError {
+            msg: format!("invalid regular expression: {err}"),
+            kind: DatabaseErrorKind::Other,
+        }),
+    }
+}
+
+/// Registers the `regexp` function, which `SQLite` calls for `X REGEXP Y` as `regexp(Y, X)`.
+///
+/// The compiled pattern is cached for the statement, so it is not recompiled for every row.
+fn register_functions(conn: &Connection) -> rusqlite::Result<()> {
+    conn.create_scalar_function(
+        "regexp",
+        2,
+        FunctionFlags::SQLITE_UTF8 | FunctionFlags::SQLITE_DETERMINISTIC,
+        |ctx| {
+            let regex: Arc<Regex> = ctx.get_or_create_aux(0, |pattern| {
+                Regex::new(pattern.as_str()?)
+                    .map_err(|err| -> Box<dyn std::error::Error + Send + Sync> { err.into() })
+            })?;
+            let text = ctx
+                .get_raw(1)
+                .as_str()
+                .map_err(|err| rusqlite::Error::UserFunctionError(err.into()))?;
+            Ok(regex.is_match(text))
+        },
+    )
+}
+
+/// Adds the FROM and WHERE sections for a history search to `sql_query`.
+///
+/// Shared by searching and counting, so both always agree on which rows match.
+///
+/// * `sql_query`: The select statement to add the sections to.
+/// * `query`: The search string to match against the `command` field.
+/// * `filters`: Additional filtering criteria.
+///
+/// Returns the parameters that must be bound when running `sql_query`.
+fn apply_search_filters(
+    sql_query: &mut SelectStatement,
+    query: &str,
+    filters: &HistoryFilters,
+) -> QueryParams {
+    let mut params_map: QueryParams = HashMap::new();
+
+    if query.is_empty() {
+        sql_query.from("history h");
+    } else if filters.mode == MatchMode::Regex {
+        // Evaluated by the `regexp` function registered on the connection.
+        sql_query.from("history h").regexp("h.command", "h_regex");
+        params_map.insert(":h_regex".to_string(), Box::new(query.to_string()));
+    } else if filters.no_fts {
+        // A plain substring scan, the query's wildcards are matched literally.
+        sql_query.from("history h").like("h.command", "h_command");
+        params_map.insert(
+            ":h_command".to_string(),
+            Box::new(format!("%{}%", escape_like(query))),
+        );
+    } else {
+        // Use history_fts and join on history.
+        sql_query
+            .from("history_fts fts JOIN history h ON h.id = fts.rowid")
+            .match_fts("fts.command");
+
+        let fts5_query = generate_fts5_match_parameter(query, filters.mode, filters.prefix_policy);
+        // Add the search tokens to the query parameters.
+        params_map.insert(":fts_command".to_string(), Box::new(fts5_query));
+    }
+
+    if let Some(exit) = filters.exit {
+        let param_name = ":h_exit_code"; // Need distinct param name
+        sql_query.r#where("h.exit_code"); // WHERE h.exit_code = :h_exit_code
+        params_map.insert(param_name.to_string(), Box::new(exit));
+    }
+
+    if let Some(cwd) = filters.cwd.as_ref() {
+        let param_name = ":h_cwd"; // Need distinct param name
+        sql_query.r#where("h.cwd"); // WHERE h.cwd = :h_cwd
+        params_map.insert(param_name.to_string(), Box::new(cwd.clone()));
+    }
+
+    if let Some(dir) = filters.cwd_within.as_ref() {
+        // The directory itself, or anything below it, but not siblings sharing its name as a
+        // prefix.
+        let dir = dir.trim_end_matches('/');
+        sql_query.where_op(
+            "(h.cwd = :h_within OR h.cwd LIKE :h_within_subdirs ESCAPE '\\')",
+            "=",
+            "h_within_match",
+        );
+        params_map.insert(":h_within".to_string(), Box::new(dir.to_string()));
+        params_map.insert(
+            ":h_within_subdirs".to_string(),
+            Box::new(format!("{}/%", escape_like(dir))),
+        );
+        params_map.insert(":h_within_match".to_string(), Box::new(true));
+    }
+
+    if let Some(session_id) = filters.session_id.as_ref() {
+        sql_query.r#where("h.session_id"); // WHERE h.session_id = :h_session_id
+        params_map.insert(":h_session_id".to_string(), Box::new(session_id.clone()));
+    }
+
+    if let Some(tty) = filters.tty.as_ref() {
+        sql_query.r#where("h.tty"); // WHERE h.tty = :h_tty
+        params_map.insert(":h_tty".to_string(), Box::new(tty.clone()));
+    }
+
+    if let Some(min_length) = filters.min_length {
+        sql_query.where_op("length(h.command)", ">=", "h_min_length");
+        params_map.insert(":h_min_length".to_string(), Box::new(min_length));
+    }
+
+    if let Some(max_length) = filters.max_length {
+        sql_query.where_op("length(h.command)", "<=", "h_max_length");
+        params_map.insert(":h_max_length".to_string(), Box::new(max_length));
+    }
+
+    if !filters.include_running {
+        // Imported commands have no exit code either, but are not running. They are told apart
+        // by their cwd, which histfiles do not record.
+        let completed = format!("(h.exit_code != {EXIT_UNKNOWN} OR h.cwd = '{IMPORTED_CWD}')");
+        sql_query.where_op(&completed, "=", "h_completed");
+        params_map.insert(":h_completed".to_string(), Box::new(true));
+    }
+
+    if filters.failed {
+        // Commands which are still running, or were imported, have no exit code yet.
+        let failed = format!("h.exit_code NOT IN (0, {EXIT_UNKNOWN})");
+        sql_query.where_op(&failed, "=", "h_failed");
+        params_map.insert(":h_failed".to_string(), Box::new(true));
+    }
+
+    if let Some(after) = filters.after {
+        sql_query.where_op("h.timestamp", ">=", "h_after");
+        params_map.insert(":h_after".to_string(), Box::new(after.unix_timestamp()));
+    }
+
+    if let Some(before) = filters.before {
+        sql_query.where_op("h.timestamp", "<", "h_before");
+        params_map.insert(":h_before".to_string(), Box::new(before.unix_timestamp()));
+    }
+
+    for (idx, cwd) in filters.exclude_cwd.iter().enumerate() {
+        // The directory and its subdirectories are hidden, as with `cwd_within`, but not siblings
+        // sharing its name as a prefix.
+        let cwd = cwd.trim_end_matches('/');
+        let param_name = format!("h_exclude_cwd_{idx}");
+        sql_query.where_op(
+            &format!("NOT (h.cwd = :{param_name} OR h.cwd LIKE :{param_name}_subdirs ESCAPE '\\')"),
+            "=",
+            &format!("{param_name}_match"),
+        );
+        params_map.insert(format!(":{param_name}"), Box::new(cwd.to_string()));
+        params_map.insert(
+            format!(":{param_name}_subdirs"),
+            Box::new(format!("{}/%", escape_like(cwd))),
+        );
+        params_map.insert(format!(":{param_name}_match"), Box::new(true));
+    }
+
+    params_map
+}
+
+/// Whether `err` is a violation of a unique index, such as the one rejecting duplicate history.
+fn is_duplicate(err: &rusqlite::Error) -> bool {
+    err.sqlite_error()
+        .is_some_and(|err| err.extended_code == rusqlite::ffi::SQLITE_CONSTRAINT_UNIQUE)
+}
+
+/// Whether a statement failed because it was interrupted, such as by the search timeout.
+fn is_interrupted(err: &rusqlite::Error) -> bool {
+    err.sqlite_error_code() == Some(rusqlite::ErrorCode::OperationInterrupted)
+}
+
+/// Borrows `params` as the named parameter list expected by rusqlite.
+fn to_named_params(params: &QueryParams) -> Vec<(&str, &dyn ToSql)> {
+    params
+        .iter()
+        .map(|(k, v)| (k.as_str(), v.as_ref()))
+        .collect()
 }
 
 /// Get the ``user_version`` PRAGMA from the ``SQLite`` database.
@@ -506,6 +1590,7 @@ fn get_connection(path: &str) -> Connection {
     match Connection::open_with_flags(path, OpenFlags::SQLITE_OPEN_READ_WRITE) {
         Ok(mut connection) => {
             debug!("Opened {path}");
+            register_functions(&connection).expect("Failed to register SQL functions.");
             match get_user_version(&connection) {
                 Ok(current_version) => {
                     debug!("Current database version: {current_version}");
@@ -533,6 +1618,7 @@ fn get_connection(path: &str) -> Connection {
             )) {
                 Ok(mut connection) => {
                     debug!("Created {path}");
+                    register_functions(&connection).expect("Failed to register SQL functions.");
                     if let Err(err) = run_migrations(
                         &mut connection,
                         SchemaVersion::V0.to_u32(),
@@ -578,10 +1664,23 @@ fn run_migrations(
             0 => MIGRATION_V0_TO_V1,
             1 => MIGRATION_V1_TO_V2,
             2 => MIGRATION_V2_TO_V3,
+            3 => MIGRATION_V3_TO_V4,
+            4 => MIGRATION_V4_TO_V5,
+            5 => MIGRATION_V5_TO_V6,
+            6 => MIGRATION_V6_TO_V7,
+            7 => MIGRATION_V7_TO_V8,
+            8 => MIGRATION_V8_TO_V9,
+            9 => MIGRATION_V9_TO_V10,
+            10 => MIGRATION_V10_TO_V11,
+            11 => MIGRATION_V11_TO_V12,
+            12 => MIGRATION_V12_TO_V13,
             _ => {
                 let err_msg = format!("Migration script for {migration_name} not found.");
                 error!("{err_msg}");
-                return Err(DatabaseError { msg: err_msg });
+                return Err(DatabaseError {
+                    msg: err_msg,
+                    ..Default::default()
+                });
             }
         };
         debug!("Attempting to apply migration: {migration_name}");
@@ -600,6 +1699,7 @@ fn run_migrations(
             // Transaction will be rolled back automatically on drop
             return Err(DatabaseError {
                 msg: format!("Migration script failed: {migration_name}. Error: {e}",),
+                ..Default::default()
             });
         }
 
@@ -675,7 +1775,9 @@ fn format_named_params_for_debug(named_params_vec: &Vec<(&str, &dyn ToSql)>) ->
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::history::model::History;
+    use crate::export::ExportFormat;
+    use crate::history::model::{History, HistoryUpdate};
+    use raven_common::config::Tokenizer;
     use rusqlite::Connection;
     use std::time::Duration;
     use time::OffsetDateTime;
@@ -683,6 +1785,7 @@ mod tests {
     // Helper to create an in-memory database and initialize the schema
     fn memory_db(target_version: Option<SchemaVersion>) -> Sqlite {
         let mut conn = Connection::open_in_memory().expect("Failed to open in-memory database");
+        register_functions(&conn).expect("Failed to register SQL functions.");
         let _ = run_migrations(&mut conn, SchemaVersion::V0.to_u32(), target_version);
         Sqlite { conn }
     }
@@ -695,6 +1798,7 @@ mod tests {
             .command(command.to_string())
             .cwd("/tmp".to_string())
             .exit_code(0)
+            .session_id("session".to_string())
             .build()
     }
 
@@ -716,6 +1820,41 @@ mod tests {
         assert!(result.is_ok(), "Migration failed: {:?}", result.err());
     }
 
+    #[test]
+    fn test_migrate_to() {
+        let mut db = memory_db(Some(SchemaVersion::V1));
+        db.conn
+            .execute(
+                "INSERT INTO history (timestamp, command, cwd, exit_code) VALUES (1, 'ls', '/tmp', 0)",
+                [],
+            )
+            .expect("Failed to insert V1 history");
+
+        db.migrate_to(SchemaVersion::V3)
+            .expect("Failed to migrate to V3");
+        assert_eq!(db.schema_version().unwrap(), SchemaVersion::V3.to_u32());
+        assert_eq!(
+            db.conn
+                .query_row(
+                    "SELECT COUNT(*) FROM history_fts WHERE history_fts MATCH 'ls'",
+                    [],
+                    |row| row.get::<_, i64>(0),
+                )
+                .unwrap(),
+            1
+        );
+
+        // Migrating to the current version is a no-op, but migrations cannot be reverted.
+        db.migrate_to(SchemaVersion::V3)
+            .expect("Migrating to the current version should succeed");
+        assert!(db.migrate_to(SchemaVersion::V2).is_err());
+        assert_eq!(db.schema_version().unwrap(), SchemaVersion::V3.to_u32());
+
+        db.migrate_to(LATEST_STABLE_SCHEMA)
+            .expect("Failed to migrate to the latest schema");
+        assert_eq!(db.schema_version().unwrap(), LATEST_STABLE_SCHEMA.to_u32());
+    }
+
     #[test]
     fn test_run_migrations_v1_to_v2_success() {
         // 1. Setup: Create DB, which initializes to V1 schema and version 1.
@@ -813,22 +1952,287 @@ mod tests {
     }
 
     #[test]
-    fn test_run_migrations_no_migration_needed() {
-        let mut db = memory_db(Some(LATEST_STABLE_SCHEMA));
+    fn test_run_migrations_v3_to_v4_success() {
+        let mut db = memory_db(Some(SchemaVersion::V3));
         let initial_version = get_user_version(&db.conn).expect("Get version failed");
-        assert_eq!(initial_version, LATEST_STABLE_SCHEMA.to_u32());
+        assert_eq!(initial_version, SchemaVersion::V3.to_u32());
 
-        // 2. Act: Run migrations starting from the current (latest) version.
-        let result = run_migrations(&mut db.conn, initial_version, Some(LATEST_STABLE_SCHEMA));
+        // Checks if the `session_id` column exists on the `history` table.
+        let get_session_column_exists = |conn: &Connection| {
+            conn.query_row(
+                "SELECT COUNT(*) FROM pragma_table_info('history') WHERE name = 'session_id'",
+                [],
+                |row| row.get::<_, i64>(0),
+            )
+            .is_ok_and(|count| count == 1)
+        };
 
-        // 3. Assert: Should succeed, version remains unchanged.
         assert!(
-            result.is_ok(),
-            "Migration failed unexpectedly: {:?}",
-            result.err()
+            !get_session_column_exists(&db.conn),
+            "session_id should not exist in V3 schema"
         );
-        assert_eq!(
-            get_user_version(&db.conn).expect("Get version failed"),
+
+        let result = run_migrations(&mut db.conn, initial_version, Some(SchemaVersion::V4));
+
+        assert!(result.is_ok(), "Migration failed: {:?}", result.err());
+        assert_eq!(
+            get_user_version(&db.conn).expect("Get version failed"),
+            SchemaVersion::V4.to_u32(),
+            "Database version should be updated to V4"
+        );
+        assert!(
+            get_session_column_exists(&db.conn),
+            "session_id should exist in V4 schema"
+        );
+    }
+
+    #[test]
+    fn test_run_migrations_v4_to_v5_success() {
+        let mut db = memory_db(Some(SchemaVersion::V4));
+        let initial_version = get_user_version(&db.conn).expect("Get version failed");
+        assert_eq!(initial_version, SchemaVersion::V4.to_u32());
+
+        // Checks if the `updated_at` column exists on the `history` table.
+        let get_updated_at_column_exists = |conn: &Connection| {
+            conn.query_row(
+                "SELECT COUNT(*) FROM pragma_table_info('history') WHERE name = 'updated_at'",
+                [],
+                |row| row.get::<_, i64>(0),
+            )
+            .is_ok_and(|count| count == 1)
+        };
+
+        assert!(
+            !get_updated_at_column_exists(&db.conn),
+            "updated_at should not exist in V4 schema"
+        );
+
+        let result = run_migrations(&mut db.conn, initial_version, Some(SchemaVersion::V5));
+
+        assert!(result.is_ok(), "Migration failed: {:?}", result.err());
+        assert_eq!(
+            get_user_version(&db.conn).expect("Get version failed"),
+            SchemaVersion::V5.to_u32(),
+            "Database version should be updated to V5"
+        );
+        assert!(
+            get_updated_at_column_exists(&db.conn),
+            "updated_at should exist in V5 schema"
+        );
+    }
+
+    #[test]
+    fn test_command_lower_in_sync() {
+        let mut db = memory_db(Some(SchemaVersion::V6));
+        db.conn
+            .execute(
+                "INSERT INTO history (timestamp, command, cwd, exit_code, session_id)
+                VALUES (0, 'Git Status', '/tmp', 0, 'session')",
+                [],
+            )
+            .expect("Failed to insert history");
+        run_migrations(&mut db.conn, SchemaVersion::V6.to_u32(), None).expect("Migration failed");
+
+        let command_lower = |db: &Sqlite, id: i64| {
+            db.conn
+                .query_row(
+                    "SELECT command_lower FROM history WHERE id = ?1",
+                    [id],
+                    |row| row.get::<_, String>(0),
+                )
+                .expect("Failed to read command_lower")
+        };
+
+        // Rows from before the migration, inserts and updates are all kept in sync.
+        assert_eq!(command_lower(&db, 1), "git status");
+        db.save(&sample_history(2, "CARGO Test"))
+            .expect("Failed to save history");
+        assert_eq!(command_lower(&db, 2), "cargo test");
+        db.update_partial(2, &HistoryUpdate::builder().command("Cargo BUILD").build())
+            .expect("Failed to update history");
+        assert_eq!(command_lower(&db, 2), "cargo build");
+
+        let plan: String = db
+            .conn
+            .query_row(
+                "EXPLAIN QUERY PLAN SELECT id FROM history WHERE command_lower = lower(?1)",
+                ["GIT STATUS"],
+                |row| row.get("detail"),
+            )
+            .expect("Failed to explain query");
+        assert!(
+            plan.contains("idx_history_command_lower"),
+            "lookup should use idx_history_command_lower, plan: {plan}"
+        );
+    }
+
+    #[test]
+    fn test_tty_round_trip() {
+        let mut db = memory_db(Some(SchemaVersion::V7));
+        db.conn
+            .execute(
+                "INSERT INTO history (timestamp, command, cwd, exit_code, session_id)
+                VALUES (0, 'ls', '/tmp', 0, 'session')",
+                [],
+            )
+            .expect("Failed to insert history");
+        let before = db.conn.last_insert_rowid();
+        run_migrations(&mut db.conn, SchemaVersion::V7.to_u32(), None).expect("Migration failed");
+
+        let mut history = sample_history(2, "pwd");
+        history.tty = "/dev/pts/3".to_string();
+        let id = db.save(&history).expect("Failed to save history");
+
+        let get_tty = |id| db.get(id).expect("Get failed").expect("Not found").tty;
+        assert_eq!(get_tty(before), "");
+        assert_eq!(get_tty(id), "/dev/pts/3");
+
+        let results = db
+            .search(
+                "",
+                HistoryFilters {
+                    tty: Some("/dev/pts/3".to_string()),
+                    ..Default::default()
+                },
+            )
+            .expect("Search failed");
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0].command, "pwd");
+        assert_eq!(results[0].tty, "/dev/pts/3");
+    }
+
+    #[test]
+    fn test_source_round_trip() {
+        let mut db = memory_db(Some(SchemaVersion::V8));
+        db.conn
+            .execute(
+                "INSERT INTO history (timestamp, command, cwd, exit_code, session_id)
+                VALUES (0, 'ls', '/tmp', 0, 'session')",
+                [],
+            )
+            .expect("Failed to insert history");
+        let before = db.conn.last_insert_rowid();
+        run_migrations(&mut db.conn, SchemaVersion::V8.to_u32(), None).expect("Migration failed");
+
+        let captured: History = History::capture()
+            .timestamp(OffsetDateTime::now_utc())
+            .command("pwd")
+            .cwd("/tmp")
+            .build()
+            .into();
+        let imported: History = History::import()
+            .timestamp(OffsetDateTime::now_utc())
+            .command("git log")
+            .source("zsh")
+            .build()
+            .into();
+        let captured = db.save(&captured).expect("Failed to save captured");
+        let imported = db.save(&imported).expect("Failed to save imported");
+
+        let get_source = |id| db.get(id).expect("Get failed").expect("Not found").source;
+        assert_eq!(get_source(before), "");
+        assert_eq!(
+            get_source(captured),
+            format!("raven:{}", env!("CARGO_PKG_VERSION"))
+        );
+        assert_eq!(get_source(imported), "import:zsh");
+
+        let results = db
+            .search("git", HistoryFilters::default())
+            .expect("Search failed");
+        assert_eq!(results[0].source, "import:zsh");
+    }
+
+    #[test]
+    fn test_duration_round_trip() {
+        let mut db = memory_db(Some(SchemaVersion::V11));
+        db.conn
+            .execute(
+                "INSERT INTO history (timestamp, command, cwd, exit_code, session_id)
+                VALUES (0, 'ls', '/tmp', 0, 'session')",
+                [],
+            )
+            .expect("Failed to insert history");
+        let before = db.conn.last_insert_rowid();
+        run_migrations(
+            &mut db.conn,
+            SchemaVersion::V11.to_u32(),
+            Some(SchemaVersion::V12),
+        )
+        .expect("Migration failed");
+
+        let imported: History = History::import()
+            .timestamp(OffsetDateTime::now_utc())
+            .command("make")
+            .source("zsh")
+            .duration(42)
+            .build()
+            .into();
+        let imported = db.save(&imported).expect("Failed to save imported");
+
+        let get_duration = |id| db.get(id).expect("Get failed").expect("Not found").duration;
+        assert_eq!(get_duration(before), -1);
+        assert_eq!(get_duration(imported), 42);
+
+        let results = db
+            .search("make", HistoryFilters::default())
+            .expect("Search failed");
+        assert_eq!(results[0].duration, 42);
+    }
+
+    #[test]
+    fn test_cwd_search_uses_index() {
+        let db = memory_db(Some(LATEST_STABLE_SCHEMA));
+        let filters = HistoryFilters {
+            cwd: Some("/tmp".to_string()),
+            limit: Some(500),
+            ..Default::default()
+        };
+
+        let mut sql_query = Query::select()
+            .column("h.id")
+            .orderby("timestamp", "DESC")
+            .to_owned();
+        let params_map = apply_search_filters(&mut sql_query, "", &filters);
+        let named_params_vec = to_named_params(&params_map);
+
+        let mut stmt = db
+            .conn
+            .prepare(&format!("EXPLAIN QUERY PLAN {}", sql_query.to_sql()))
+            .expect("Failed to prepare query plan");
+        let plan = stmt
+            .query_map(&*named_params_vec, |row| row.get::<_, String>("detail"))
+            .expect("Failed to explain query")
+            .collect::<Result<Vec<String>, rusqlite::Error>>()
+            .expect("Failed to read query plan");
+
+        assert!(
+            plan.iter().any(|detail| detail.contains("idx_history_cwd")),
+            "cwd search should use idx_history_cwd, plan: {plan:?}"
+        );
+        assert!(
+            !plan.iter().any(|detail| detail.contains("TEMP B-TREE")),
+            "cwd search should be ordered by the index, plan: {plan:?}"
+        );
+    }
+
+    #[test]
+    fn test_run_migrations_no_migration_needed() {
+        let mut db = memory_db(Some(LATEST_STABLE_SCHEMA));
+        let initial_version = get_user_version(&db.conn).expect("Get version failed");
+        assert_eq!(initial_version, LATEST_STABLE_SCHEMA.to_u32());
+
+        // 2. Act: Run migrations starting from the current (latest) version.
+        let result = run_migrations(&mut db.conn, initial_version, Some(LATEST_STABLE_SCHEMA));
+
+        // 3. Assert: Should succeed, version remains unchanged.
+        assert!(
+            result.is_ok(),
+            "Migration failed unexpectedly: {:?}",
+            result.err()
+        );
+        assert_eq!(
+            get_user_version(&db.conn).expect("Get version failed"),
             LATEST_STABLE_SCHEMA.to_u32(),
             "Database version should remain unchanged"
         );
@@ -851,6 +2255,7 @@ mod tests {
         assert_eq!(history_out.command, history_in.command);
         assert_eq!(history_out.cwd, history_in.cwd);
         assert_eq!(history_out.exit_code, history_in.exit_code);
+        assert_eq!(history_out.session_id, history_in.session_id);
         // Timestamps might have slight precision differences, compare within a tolerance if needed
         assert_eq!(
             history_out.timestamp.unix_timestamp(),
@@ -886,6 +2291,8 @@ mod tests {
         history.id = id; // Set the ID after saving
         history.command = "updated command".to_string();
         history.exit_code = 1;
+        history.source = "import:zsh".to_string();
+        history.duration = 1_500;
 
         db.update(&history).expect("Failed to update history");
 
@@ -897,6 +2304,48 @@ mod tests {
         assert_eq!(updated_history.id, id);
         assert_eq!(updated_history.command, "updated command");
         assert_eq!(updated_history.exit_code, 1);
+        assert_eq!(updated_history.source, "import:zsh");
+        assert_eq!(updated_history.duration, 1_500);
+    }
+
+    #[test]
+    fn test_update_conflict() {
+        let mut db = memory_db(Some(LATEST_STABLE_SCHEMA));
+        let id = db
+            .save(&sample_history(-1, "initial command"))
+            .expect("Failed to save history");
+
+        // Two processes read the same row.
+        let mut first = db.get(id).expect("Failed to get").expect("History missing");
+        let mut second = first.clone();
+
+        first.exit_code = 1;
+        db.update(&first).expect("Failed to update history");
+
+        // The second write is based on a stale read and must not clobber the first.
+        second.command = "stale command".to_string();
+        let err = db.update(&second).expect_err("Stale update should fail");
+        assert_eq!(err.kind, DatabaseErrorKind::Conflict);
+
+        let stored = db.get(id).expect("Failed to get").expect("History missing");
+        assert_eq!(stored.command, "initial command");
+        assert_eq!(stored.exit_code, 1);
+
+        // Partial updates move updated_at forward too.
+        db.update_partial(id, &HistoryUpdate::builder().exit_code(2).build())
+            .expect("Failed to update");
+        let err = db.update(&stored).expect_err("Stale update should fail");
+        assert_eq!(err.kind, DatabaseErrorKind::Conflict);
+
+        // Reloading the row allows the update.
+        let mut reloaded = db.get(id).expect("Failed to get").expect("History missing");
+        reloaded.command = "fresh command".to_string();
+        db.update(&reloaded).expect("Failed to update history");
+
+        // A missing row is not a conflict.
+        reloaded.id = id + 1;
+        let err = db.update(&reloaded).expect_err("Missing row should fail");
+        assert_eq!(err.kind, DatabaseErrorKind::Other);
     }
 
     #[test]
@@ -1005,97 +2454,1676 @@ mod tests {
     }
 
     #[test]
-    fn test_delete() {
+    fn test_recent_sessions() {
         let mut db = memory_db(Some(LATEST_STABLE_SCHEMA));
-        let history = sample_history(1, "to be deleted");
-
-        let id = db.save(&history).expect("Failed to save history");
-        assert!(db.get(id).expect("Get failed").is_some());
+        let now = OffsetDateTime::from_unix_timestamp(1_700_000_000).unwrap();
+        let in_session = |id: i64, session_id: &str, secs_ago: u64| {
+            let mut h = sample_history(id, &format!("cmd {id}"));
+            h.session_id = session_id.to_string();
+            h.timestamp = now - Duration::from_secs(secs_ago);
+            h
+        };
+        db.save_bulk(&[
+            in_session(1, "first", 50),
+            in_session(2, "first", 40),
+            in_session(3, "second", 30),
+            in_session(4, "", 5), // Imported rows have no session
+            in_session(5, "current", 10),
+        ])
+        .expect("Failed to save sessions");
 
-        db.delete(id).expect("Failed to delete history");
+        let sessions = db.recent_sessions(5).expect("Failed to list sessions");
+        let summary: Vec<(&str, i64)> = sessions
+            .iter()
+            .map(|(session_id, _, count)| (session_id.as_str(), *count))
+            .collect();
+        assert_eq!(summary, vec![("current", 1), ("second", 1), ("first", 2)]);
+        // The last activity is the latest command in the session.
+        assert_eq!(sessions[0].1, now - Duration::from_secs(10));
+        assert_eq!(sessions[2].1, now - Duration::from_secs(40));
 
-        let result = db.get(id);
-        assert!(result.is_ok());
-        // The result should be None
-        assert!(result.unwrap().is_none());
+        let sessions = db.recent_sessions(1).expect("Failed to list sessions");
+        assert_eq!(sessions.len(), 1);
+        assert_eq!(sessions[0].0, "current");
 
-        // Deleting non-existent should be Ok
-        let delete_again_result = db.delete(id);
-        assert!(delete_again_result.is_ok());
+        assert_eq!(
+            db.previous_session("current")
+                .expect("Failed to resolve previous session"),
+            Some("second".to_string())
+        );
 
-        // Deleting ID -1 should probably fail or be a no-op depending on desired behavior
-        // Current implementation might error in execute due to parameter mismatch if not handled
-        // Let's assume deleting -1 isn't a valid operation here or should be a no-op handled by execute
-        let delete_invalid_result = db.delete(-1);
-        // Depending on rusqlite behavior with [-1] param, this might Err or Ok(0 rows affected)
-        // Given the code `execute(&query.to_sql(), [id])`, rusqlite likely handles it.
-        // Let's assert Ok as the function intends to return Ok for 0 rows affected.
-        assert!(delete_invalid_result.is_ok());
+        let results = db
+            .search(
+                "",
+                HistoryFilters {
+                    session_id: Some("first".to_string()),
+                    ..Default::default()
+                },
+            )
+            .expect("Search failed");
+        assert_eq!(results.len(), 2);
+        assert!(results.iter().all(|h| h.session_id == "first"));
     }
 
     #[test]
-    fn test_get_history_total() {
+    fn test_search_command_length() {
         let mut db = memory_db(Some(LATEST_STABLE_SCHEMA));
+        db.save_bulk(&[
+            sample_history(1, "ls"),
+            sample_history(2, "git status"),
+            sample_history(
+                3,
+                "find . -name '*.rs' | xargs grep -n 'todo' | sort | uniq -c",
+            ),
+        ])
+        .expect("Failed to save for search");
 
-        let total_initial = db.get_history_total().expect("Failed to get initial total");
-        assert_eq!(total_initial, 0);
-
-        db.save(&sample_history(1, "cmd 1")).expect("Save 1 failed");
-        db.save(&sample_history(2, "cmd 2")).expect("Save 2 failed");
-
-        let total_after_saves = db
-            .get_history_total()
-            .expect("Failed to get total after saves");
-        assert_eq!(total_after_saves, 2);
+        let results = db
+            .search(
+                "",
+                HistoryFilters {
+                    min_length: Some(20),
+                    ..Default::default()
+                },
+            )
+            .expect("Search failed");
+        assert_eq!(results.len(), 1);
+        assert!(results[0].command.starts_with("find"));
 
-        let id = db.save(&sample_history(3, "cmd 3")).expect("Save 3 failed");
-        db.delete(id).expect("Delete failed");
+        let results = db
+            .search(
+                "",
+                HistoryFilters {
+                    max_length: Some(10),
+                    ..Default::default()
+                },
+            )
+            .expect("Search failed");
+        assert_eq!(results.len(), 2);
+        assert!(results.iter().all(|h| h.command.len() <= 10));
 
-        let total_after_delete = db
-            .get_history_total()
-            .expect("Failed to get total after delete");
-        assert_eq!(total_after_delete, 2); // Back to 2 after deleting one
+        let results = db
+            .search(
+                "",
+                HistoryFilters {
+                    min_length: Some(3),
+                    max_length: Some(10),
+                    ..Default::default()
+                },
+            )
+            .expect("Search failed");
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0].command, "git status");
     }
 
     #[test]
-    fn test_generate_fts5_match_parameter_empty_query() {
-        assert_eq!(generate_fts5_match_parameter("", MatchMode::Fuzzy), "");
-        assert_eq!(generate_fts5_match_parameter("", MatchMode::Prefix), "");
+    fn test_search_exclude_cwd() {
+        let mut db = memory_db(Some(LATEST_STABLE_SCHEMA));
+        let in_dir = |id: i64, command: &str, cwd: &str| {
+            let mut h = sample_history(id, command);
+            h.cwd = cwd.to_string();
+            h
+        };
+        db.save_bulk(&[
+            in_dir(1, "rm -rf build", "/tmp"),
+            in_dir(2, "tar xf archive.tar", "/tmp/extract"),
+            in_dir(3, "ls", "/home/user/Downloads"),
+            in_dir(4, "cargo build", "/home/user/project"),
+            in_dir(5, "ls -la", "/tmpfiles"),
+        ])
+        .expect("Failed to save for search");
+
+        // A single exclusion hides the directory and everything under it.
+        let results = db
+            .search(
+                "",
+                HistoryFilters {
+                    exclude_cwd: vec!["/tmp".to_string()],
+                    ..Default::default()
+                },
+            )
+            .expect("Search failed");
+        assert_eq!(results.len(), 3);
+        assert!(
+            results
+                .iter()
+                .all(|h| h.cwd != "/tmp" && !h.cwd.starts_with("/tmp/"))
+        );
+        // A sibling sharing the excluded directory's name as a prefix is kept.
+        assert!(results.iter().any(|h| h.cwd == "/tmpfiles"));
+
+        // Multiple exclusions are all applied, and a trailing slash still hides the directory.
+        let results = db
+            .search(
+                "",
+                HistoryFilters {
+                    exclude_cwd: vec!["/tmp/".to_string(), "/home/user/Downloads".to_string()],
+                    ..Default::default()
+                },
+            )
+            .expect("Search failed");
+        assert_eq!(results.len(), 2);
+        assert!(results.iter().any(|h| h.cwd == "/tmpfiles"));
+        assert!(results.iter().any(|h| h.cwd == "/home/user/project"));
     }
 
     #[test]
-    fn test_generate_fts5_match_parameter_fuzzy() {
-        assert_eq!(
-            generate_fts5_match_parameter("hello world", MatchMode::Fuzzy),
-            "\"hello\"* \"world\"*"
-        );
-        assert_eq!(
-            generate_fts5_match_parameter("term1", MatchMode::Fuzzy),
-            "\"term1\"*"
-        );
-        assert_eq!(
-            generate_fts5_match_parameter("with\"quote", MatchMode::Fuzzy),
-            "\"with\"\"quote\"*"
-        );
+    fn test_search_include_running() {
+        let mut db = memory_db(Some(LATEST_STABLE_SCHEMA));
+        let mut finished = sample_history(1, "git status");
+        finished.exit_code = 0;
+        let mut running = sample_history(2, "git push");
+        running.exit_code = EXIT_UNKNOWN;
+        let imported: History = History::import()
+            .timestamp(OffsetDateTime::now_utc())
+            .command("git log")
+            .source("zsh")
+            .build()
+            .into();
+        db.save_bulk(&[finished, running, imported])
+            .expect("Failed to save for search");
+
+        let search = |query: &str, include_running: bool| {
+            let mut commands = db
+                .search(
+                    query,
+                    HistoryFilters {
+                        include_running,
+                        ..Default::default()
+                    },
+                )
+                .expect("Search failed")
+                .into_iter()
+                .map(|h| h.command)
+                .collect::<Vec<_>>();
+            commands.sort_unstable();
+            commands
+        };
+        assert_eq!(search("", true), vec!["git log", "git push", "git status"]);
         assert_eq!(
-            generate_fts5_match_parameter("multiple words \"here\"", MatchMode::Fuzzy),
-            "\"multiple\"* \"words\"* \"\"\"here\"\"\"*"
+            search("git", true),
+            vec!["git log", "git push", "git status"]
         );
+        // Imported commands have no exit code either, but are kept.
+        assert_eq!(search("", false), vec!["git log", "git status"]);
+        assert_eq!(search("git", false), vec!["git log", "git status"]);
     }
 
     #[test]
-    fn test_generate_fts5_match_parameter_initial_prefix() {
+    fn test_search_failed() {
+        let mut db = memory_db(Some(LATEST_STABLE_SCHEMA));
+        let at = |id: i64, command: &str, exit_code: i64, cwd: &str| {
+            let mut h = sample_history(id, command);
+            h.exit_code = exit_code;
+            h.cwd = cwd.to_string();
+            h.timestamp = OffsetDateTime::from_unix_timestamp(1_700_000_000 + id).unwrap();
+            h
+        };
+        db.save_bulk(&[
+            at(1, "cargo test", 101, "/tmp"),
+            at(2, "make", 2, "/src"),
+            at(3, "ls", 0, "/tmp"),
+            at(4, "sleep 100", EXIT_UNKNOWN, "/src"),
+        ])
+        .expect("Failed to save for search");
+
+        let last_failed = |cwd: Option<&str>| {
+            let filters = HistoryFilters {
+                cwd: cwd.map(ToString::to_string),
+                limit: Some(1),
+                failed: true,
+                ..Default::default()
+            };
+            db.search("", filters)
+                .expect("Search failed")
+                .into_iter()
+                .map(|h| h.command)
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(last_failed(None), vec!["make"]);
+        assert_eq!(last_failed(Some("/tmp")), vec!["cargo test"]);
+        assert!(last_failed(Some("/home")).is_empty());
+    }
+
+    #[test]
+    fn test_search_without_search_index() {
+        let mut db = memory_db(Some(LATEST_STABLE_SCHEMA));
+        db.save_bulk(&[sample_history(1, "git status"), sample_history(2, "ls")])
+            .expect("Failed to save for search");
+        // A partial migration can leave the database without the index the V3 migration adds.
+        db.conn
+            .execute_batch(
+                "DROP TRIGGER history_ai; DROP TRIGGER history_ad; DROP TRIGGER history_au;
+                DROP TABLE history_fts;",
+            )
+            .expect("Failed to drop the search index");
+        assert!(!db.has_search_index());
+
+        let results = db
+            .search("stat", HistoryFilters::default())
+            .expect("Search should fall back to a substring match");
+        assert_eq!(
+            results.into_iter().map(|h| h.command).collect::<Vec<_>>(),
+            vec!["git status"]
+        );
+        assert_eq!(
+            db.search_count("stat", HistoryFilters::default())
+                .expect("Count should fall back to a substring match"),
+            1
+        );
+    }
+
+    #[test]
+    fn test_delete_matching_without_search_index() {
+        let mut db = memory_db(Some(LATEST_STABLE_SCHEMA));
+        db.save_bulk(&[sample_history(1, "git status"), sample_history(2, "ls")])
+            .expect("Failed to save for delete");
+        db.conn
+            .execute_batch(
+                "DROP TRIGGER history_ai; DROP TRIGGER history_ad; DROP TRIGGER history_au;
+                DROP TABLE history_fts;",
+            )
+            .expect("Failed to drop the search index");
+
+        let deleted = db
+            .delete_matching("stat", HistoryFilters::default())
+            .expect("Delete should fall back to a substring match");
+        assert_eq!(deleted, 1);
+        assert_eq!(
+            db.search("", HistoryFilters::default())
+                .expect("Search failed")
+                .into_iter()
+                .map(|h| h.command)
+                .collect::<Vec<_>>(),
+            vec!["ls"]
+        );
+    }
+
+    #[test]
+    fn test_neighbors() {
+        let mut db = memory_db(Some(LATEST_STABLE_SCHEMA));
+        let at = |n: i64, timestamp: i64, session_id: &str| {
+            let mut h = sample_history(n, &format!("cmd{n}"));
+            h.timestamp = OffsetDateTime::from_unix_timestamp(timestamp).unwrap();
+            h.session_id = session_id.to_string();
+            h
+        };
+        // Saved out of order, cmd3 and cmd4 share a second and are told apart by their ids.
+        let ids = db
+            .save_bulk(&[
+                at(5, 1_700_000_050, "a"),
+                at(1, 1_700_000_010, "a"),
+                at(2, 1_700_000_020, "a"),
+                at(3, 1_700_000_030, "b"),
+                at(4, 1_700_000_030, "a"),
+                at(6, 1_700_000_060, "b"),
+                at(7, 1_700_000_070, "a"),
+            ])
+            .expect("Failed to save for neighbors");
+        let id_of = |n: usize| ids[[5, 1, 2, 3, 4, 6, 7].iter().position(|&m| m == n).unwrap()];
+
+        let neighbors = |id: i64, window: usize, same_session: bool| {
+            let (before, after) = db
+                .neighbors(id, window, same_session)
+                .expect("Neighbors failed");
+            let commands = |history: Vec<History>| {
+                history
+                    .into_iter()
+                    .map(|h| h.command)
+                    .collect::<Vec<_>>()
+                    .join(" ")
+            };
+            format!("{} | {}", commands(before), commands(after))
+                .trim()
+                .to_string()
+        };
+        assert_eq!(neighbors(id_of(4), 2, false), "cmd2 cmd3 | cmd5 cmd6");
+        assert_eq!(neighbors(id_of(4), 2, true), "cmd1 cmd2 | cmd5 cmd7");
+        assert_eq!(neighbors(id_of(3), 1, false), "cmd2 | cmd4");
+        assert_eq!(neighbors(id_of(1), 3, false), "| cmd2 cmd3 cmd4");
+        assert_eq!(
+            neighbors(id_of(7), 10, false),
+            "cmd1 cmd2 cmd3 cmd4 cmd5 cmd6 |"
+        );
+        assert_eq!(neighbors(id_of(4), 0, false), "|");
+        assert_eq!(neighbors(-1, 2, false), "|");
+    }
+
+    #[test]
+    fn test_export() {
+        let mut db = memory_db(Some(LATEST_STABLE_SCHEMA));
+        let export = |db: &Sqlite, format: ExportFormat| {
+            let mut out = Vec::new();
+            let count = db
+                .export(format, &mut out, HistoryFilters::default())
+                .expect("Export failed");
+            (count, String::from_utf8(out).unwrap())
+        };
+        assert_eq!(export(&db, ExportFormat::Json), (0, "[]\n".to_string()));
+        assert_eq!(export(&db, ExportFormat::Jsonl), (0, String::new()));
+
+        let at = |id: i64, command: &str| {
+            let mut h = sample_history(id, command);
+            h.timestamp = OffsetDateTime::from_unix_timestamp(1_700_000_000 + id).unwrap();
+            h
+        };
+        db.save_bulk(&[at(1, "ls"), at(2, "echo \"a,b\"\nwc")])
+            .expect("Failed to save for export");
+
+        assert_eq!(
+            export(&db, ExportFormat::Json),
+            (
+                2,
+                concat!(
+                    "[\n",
+                    r#"{"id":2,"command":"echo \"a,b\"\nwc","cwd":"/tmp","exit":0,"session_id":"session","timestamp":"2023-11-14T22:13:22Z","tty":""},"#,
+                    "\n",
+                    r#"{"id":1,"command":"ls","cwd":"/tmp","exit":0,"session_id":"session","timestamp":"2023-11-14T22:13:21Z","tty":""}"#,
+                    "\n]\n"
+                )
+                .to_string()
+            )
+        );
+        assert_eq!(
+            export(&db, ExportFormat::Jsonl),
+            (
+                2,
+                concat!(
+                    r#"{"command":"echo \"a,b\"\nwc","cwd":"/tmp","exit":0,"session_id":"session","timestamp":1700000002,"tty":"","source":""}"#,
+                    "\n",
+                    r#"{"command":"ls","cwd":"/tmp","exit":0,"session_id":"session","timestamp":1700000001,"tty":"","source":""}"#,
+                    "\n"
+                )
+                .to_string()
+            )
+        );
+        assert_eq!(
+            export(&db, ExportFormat::Csv),
+            (
+                2,
+                concat!(
+                    "id,command,cwd,exit,session_id,timestamp,tty\n",
+                    "2,\"echo \"\"a,b\"\"\nwc\",/tmp,0,session,2023-11-14T22:13:22Z,\n",
+                    "1,ls,/tmp,0,session,2023-11-14T22:13:21Z,\n"
+                )
+                .to_string()
+            )
+        );
+    }
+
+    #[test]
+    fn test_search_cwd_within() {
+        let mut db = memory_db(Some(LATEST_STABLE_SCHEMA));
+        let at = |id: i64, command: &str, cwd: &str| {
+            let mut h = sample_history(id, command);
+            h.cwd = cwd.to_string();
+            h.timestamp = OffsetDateTime::from_unix_timestamp(1_700_000_000 + id).unwrap();
+            h
+        };
+        db.save_bulk(&[
+            at(1, "make", "/src/raven"),
+            at(2, "cargo test", "/src/raven/crates/raven"),
+            at(3, "ls", "/src/raven-old"),
+            at(4, "pwd", "/src"),
+        ])
+        .expect("Failed to save for search");
+
+        let within = |dir: &str| {
+            let filters = HistoryFilters {
+                cwd_within: Some(dir.to_string()),
+                ..Default::default()
+            };
+            db.search("", filters)
+                .expect("Search failed")
+                .into_iter()
+                .map(|h| h.command)
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(within("/src/raven"), vec!["cargo test", "make"]);
+        assert_eq!(within("/src/raven/"), vec!["cargo test", "make"]);
+        assert_eq!(within("/src"), vec!["pwd", "ls", "cargo test", "make"]);
+        assert!(within("/home").is_empty());
+    }
+
+    #[test]
+    fn test_search_no_fts() {
+        let mut db = memory_db(Some(LATEST_STABLE_SCHEMA));
+        let at = |id: i64, command: &str| {
+            let mut h = sample_history(id, command);
+            h.exit_code = id;
+            h.timestamp = OffsetDateTime::from_unix_timestamp(1_700_000_000 + id).unwrap();
+            h
+        };
+        db.save_bulk(&[
+            at(1, "git checkout feature/login"),
+            at(2, "echo 100%_done"),
+            at(3, "echo 100 done"),
+            at(4, "cargo build --release"),
+        ])
+        .expect("Failed to save for search");
+
+        let search = |query: &str, no_fts: bool| {
+            let filters = HistoryFilters {
+                order: OrderBy::Relevance,
+                no_fts,
+                ..Default::default()
+            };
+            db.search(query, filters)
+                .expect("Search failed")
+                .into_iter()
+                .map(|h| h.command)
+                .collect::<Vec<_>>()
+        };
+
+        // The middle of a token, which the prefix matching FTS index does not find.
+        assert!(search("eckout", false).is_empty());
+        assert_eq!(search("eckout", true), vec!["git checkout feature/login"]);
+        assert_eq!(search("ure/log", true), vec!["git checkout feature/login"]);
+        // LIKE wildcards in the query are matched literally.
+        assert_eq!(search("100%_", true), vec!["echo 100%_done"]);
+        // Results are ordered by timestamp, even when relevance was requested.
+        assert_eq!(
+            search("echo 100", true),
+            vec!["echo 100 done", "echo 100%_done"]
+        );
+    }
+
+    #[test]
+    fn test_search_regex() {
+        let mut db = memory_db(Some(LATEST_STABLE_SCHEMA));
+        let at = |id: i64, command: &str| {
+            let mut h = sample_history(id, command);
+            h.timestamp = OffsetDateTime::from_unix_timestamp(1_700_000_000 + id).unwrap();
+            h
+        };
+        db.save_bulk(&[
+            at(1, "git push --force origin main"),
+            at(2, "git push origin main"),
+            at(3, "echo git push --force"),
+            at(4, "git push -f --force-with-lease"),
+        ])
+        .expect("Failed to save for search");
+
+        let filters = HistoryFilters {
+            mode: MatchMode::Regex,
+            order: OrderBy::Relevance,
+            ..Default::default()
+        };
+        let found = db
+            .search("^git.*--force", filters.clone())
+            .expect("Search failed")
+            .into_iter()
+            .map(|h| h.command)
+            .collect::<Vec<_>>();
+        // Most recent first, even when relevance was requested.
+        assert_eq!(
+            found,
+            vec![
+                "git push -f --force-with-lease",
+                "git push --force origin main"
+            ]
+        );
+        assert_eq!(db.search_count("--force$", filters.clone()).unwrap(), 1);
+
+        let err = db
+            .search("git (push", filters.clone())
+            .expect_err("An invalid pattern should fail the search");
+        assert!(
+            err.msg.contains("invalid regular expression"),
+            "{}",
+            err.msg
+        );
+        assert!(db.search_count("[", filters).is_err());
+    }
+
+    #[test]
+    fn test_search_after() {
+        let mut db = memory_db(Some(LATEST_STABLE_SCHEMA));
+        let at = |id: i64, command: &str, timestamp: i64| {
+            let mut h = sample_history(id, command);
+            h.timestamp = OffsetDateTime::from_unix_timestamp(timestamp).unwrap();
+            h
+        };
+        db.save_bulk(&[
+            at(1, "git log", 1_000),
+            at(2, "git status", 2_000),
+            at(3, "git push", 3_000),
+        ])
+        .expect("Failed to save for search");
+
+        let after = |query: &str, timestamp: i64| {
+            db.search(
+                query,
+                HistoryFilters {
+                    after: Some(OffsetDateTime::from_unix_timestamp(timestamp).unwrap()),
+                    ..Default::default()
+                },
+            )
+            .expect("Search failed")
+            .into_iter()
+            .map(|h| h.command)
+            .collect::<Vec<_>>()
+        };
+        // The bound is inclusive, and applies with and without a query.
+        assert_eq!(after("", 2_000), vec!["git push", "git status"]);
+        assert_eq!(after("git", 2_001), vec!["git push"]);
+        assert!(after("", 3_001).is_empty());
+    }
+
+    #[test]
+    fn test_search_before() {
+        let mut db = memory_db(Some(LATEST_STABLE_SCHEMA));
+        let at = |id: i64, command: &str, timestamp: i64| {
+            let mut h = sample_history(id, command);
+            h.timestamp = OffsetDateTime::from_unix_timestamp(timestamp).unwrap();
+            h
+        };
+        db.save_bulk(&[
+            at(1, "git log", 1_000),
+            at(2, "git status", 2_000),
+            at(3, "git push", 3_000),
+        ])
+        .expect("Failed to save for search");
+
+        let between = |query: &str, after: Option<i64>, before: i64| {
+            db.search(
+                query,
+                HistoryFilters {
+                    after: after.map(|after| OffsetDateTime::from_unix_timestamp(after).unwrap()),
+                    before: Some(OffsetDateTime::from_unix_timestamp(before).unwrap()),
+                    ..Default::default()
+                },
+            )
+            .expect("Search failed")
+            .into_iter()
+            .map(|h| h.command)
+            .collect::<Vec<_>>()
+        };
+        // The bound is exclusive, and combines with `after` into a range.
+        assert_eq!(between("", None, 3_000), vec!["git status", "git log"]);
+        assert_eq!(
+            between("git", Some(1_500), 3_001),
+            vec!["git push", "git status"]
+        );
+        assert!(between("", None, 1_000).is_empty());
+    }
+
+    #[test]
+    fn test_search_exclude_cwd_literal() {
+        let mut db = memory_db(Some(LATEST_STABLE_SCHEMA));
+        let in_dir = |id: i64, cwd: &str| {
+            let mut h = sample_history(id, "ls");
+            h.cwd = cwd.to_string();
+            h
+        };
+        db.save_bulk(&[
+            in_dir(1, "/tmp/my_dir"),
+            in_dir(2, "/tmp/myXdir"),
+            in_dir(3, "/tmp/50%"),
+            in_dir(4, "/tmp/50percent"),
+        ])
+        .expect("Failed to save for search");
+
+        // `_` and `%` in the excluded directory match themselves, not any character.
+        let results = db
+            .search(
+                "",
+                HistoryFilters {
+                    exclude_cwd: vec!["/tmp/my_dir".to_string(), "/tmp/50%".to_string()],
+                    ..Default::default()
+                },
+            )
+            .expect("Search failed");
+        let mut cwds = results.iter().map(|h| h.cwd.as_str()).collect::<Vec<_>>();
+        cwds.sort_unstable();
+        assert_eq!(cwds, vec!["/tmp/50percent", "/tmp/myXdir"]);
+    }
+
+    #[test]
+    fn test_escape_like() {
+        assert_eq!(escape_like("/home/user"), "/home/user");
+        assert_eq!(escape_like("/tmp/50%_off"), "/tmp/50\\%\\_off");
+        assert_eq!(escape_like("C:\\dir"), "C:\\\\dir");
+    }
+
+    #[test]
+    fn test_search_each() {
+        let mut db = memory_db(Some(LATEST_STABLE_SCHEMA));
+        db.save_bulk(&[
+            sample_history(1, "cargo build"),
+            sample_history(2, "cargo test"),
+            sample_history(3, "git status"),
+        ])
+        .expect("Failed to save for search");
+
+        let mut commands = Vec::new();
+        db.search_each("cargo", HistoryFilters::default(), &mut |h| {
+            commands.push(h.command);
+        })
+        .expect("Search failed");
+        commands.sort();
+        assert_eq!(commands, vec!["cargo build", "cargo test"]);
+
+        let mut calls = 0;
+        db.search_each("", HistoryFilters::default(), &mut |_| calls += 1)
+            .expect("Search failed");
+        assert_eq!(calls, 3);
+    }
+
+    #[test]
+    fn test_search_recent_matches_filtered() {
+        let mut db = memory_db(Some(LATEST_STABLE_SCHEMA));
+        let now = OffsetDateTime::now_utc();
+        let history = (0..20)
+            .map(|i| {
+                // Commands are run in pairs, both paths must break the ties the same way.
+                let mut h = sample_history(-1, &format!("command {i}"));
+                h.timestamp = now - Duration::from_secs(i / 2 * 60);
+                h
+            })
+            .collect::<Vec<History>>();
+        db.save_bulk(&history).expect("Failed to save for search");
+
+        for (limit, offset) in [
+            (None, None),
+            (Some(5), None),
+            (Some(0), None),
+            (Some(5), Some(5)),
+            (None, Some(15)),
+            (Some(5), Some(30)),
+        ] {
+            let mut recent = Vec::new();
+            db.search_each_recent(limit, offset, &mut |h| recent.push(h))
+                .expect("Fast path failed");
+
+            let mut filtered = Vec::new();
+            let filters = HistoryFilters {
+                limit,
+                offset,
+                ..Default::default()
+            };
+            db.search_each_filtered("", &filters, &mut |h| filtered.push(h))
+                .expect("General path failed");
+
+            assert_eq!(recent, filtered, "limit {limit:?}, offset {offset:?}");
+        }
+
+        // Paging through the ties lists every command exactly once.
+        let mut paged = Vec::new();
+        for offset in (0..20).step_by(3) {
+            let filters = HistoryFilters {
+                limit: Some(3),
+                offset: Some(offset),
+                ..Default::default()
+            };
+            db.search_each_filtered("", &filters, &mut |h| paged.push(h.id))
+                .expect("General path failed");
+        }
+        assert_eq!(paged.len(), 20);
+        paged.sort_unstable();
+        paged.dedup();
+        assert_eq!(paged.len(), 20);
+    }
+
+    #[test]
+    fn test_search_count() {
+        let mut db = memory_db(Some(LATEST_STABLE_SCHEMA));
+        let mut failed = sample_history(3, "cargo test --all");
+        failed.exit_code = 1;
+        db.save_bulk(&[
+            sample_history(1, "cargo build"),
+            sample_history(2, "cargo test"),
+            failed,
+        ])
+        .expect("Failed to save for search");
+
+        let count = |query: &str, filters: HistoryFilters| {
+            db.search_count(query, filters).expect("Count failed")
+        };
+        assert_eq!(count("", HistoryFilters::default()), 3);
+        assert_eq!(count("test", HistoryFilters::default()), 2);
+        assert_eq!(
+            count(
+                "cargo",
+                HistoryFilters {
+                    exit: Some(0),
+                    ..Default::default()
+                }
+            ),
+            2
+        );
+        // The limit only applies to the returned rows, not the count.
         assert_eq!(
-            generate_fts5_match_parameter("start phrase", MatchMode::Prefix),
+            count(
+                "cargo",
+                HistoryFilters {
+                    limit: Some(1),
+                    ..Default::default()
+                }
+            ),
+            3
+        );
+    }
+
+    #[test]
+    fn test_health_check() {
+        let mut db = memory_db(Some(LATEST_STABLE_SCHEMA));
+        assert!(db.health_check().expect("Health check failed"));
+
+        db.save(&sample_history(1, "ls"))
+            .expect("Failed to save history");
+        assert!(db.health_check().expect("Health check failed"));
+
+        // Changing a row behind the back of the sync triggers leaves the index stale.
+        db.conn
+            .execute_batch(
+                "DROP TRIGGER history_au; UPDATE history SET command = 'pwd' WHERE id = 1;",
+            )
+            .expect("Failed to desync the index");
+        assert!(!db.health_check().expect("Health check failed"));
+    }
+
+    #[test]
+    fn test_health_check_without_search_index() {
+        let db = memory_db(Some(LATEST_STABLE_SCHEMA));
+        db.conn
+            .execute_batch(
+                "DROP TRIGGER history_ai; DROP TRIGGER history_ad; DROP TRIGGER history_au;
+                DROP TABLE history_fts;",
+            )
+            .expect("Failed to drop the search index");
+        assert!(
+            !db.health_check()
+                .expect("Health check should report the missing index")
+        );
+    }
+
+    #[test]
+    fn test_count() {
+        let mut db = memory_db(Some(LATEST_STABLE_SCHEMA));
+        let mut other_dir = sample_history(3, "ls");
+        other_dir.cwd = "/home".to_string();
+        db.save_bulk(&[
+            sample_history(1, "cargo build"),
+            sample_history(2, "cargo test"),
+            other_dir,
+        ])
+        .expect("Failed to save for count");
+
+        assert_eq!(
+            db.count(HistoryFilters::default()).expect("Count failed"),
+            3
+        );
+        assert_eq!(
+            db.count(HistoryFilters {
+                cwd: Some("/tmp".to_string()),
+                limit: Some(1),
+                ..Default::default()
+            })
+            .expect("Count failed"),
+            2
+        );
+    }
+
+    #[test]
+    fn test_delete_bulk() {
+        let mut db = memory_db(Some(LATEST_STABLE_SCHEMA));
+        let ids = db
+            .save_bulk(&[
+                sample_history(1, "ls"),
+                sample_history(2, "pwd"),
+                sample_history(3, "whoami"),
+            ])
+            .expect("Failed to save history");
+
+        let deleted = db
+            .delete_bulk(&[ids[0], ids[2], 999])
+            .expect("Failed to delete history");
+        assert_eq!(deleted, 2);
+        assert_eq!(db.get_history_total().expect("Count failed"), 1);
+        assert!(db.get(ids[1]).expect("Get failed").is_some());
+        assert_eq!(db.delete_bulk(&[]).expect("Failed to delete history"), 0);
+    }
+
+    #[test]
+    fn test_delete_where() {
+        let mut db = memory_db(Some(LATEST_STABLE_SCHEMA));
+        let mut failed = sample_history(2, "false");
+        failed.exit_code = 1;
+        db.save_bulk(&[sample_history(1, "ls"), failed, sample_history(3, "pwd")])
+            .expect("Failed to save history");
+
+        let err = db
+            .delete_where(HistoryFilters::default(), false)
+            .expect_err("Unfiltered delete should be refused");
+        assert_eq!(err.kind, DatabaseErrorKind::Unfiltered);
+        assert_eq!(db.get_history_total().expect("Count failed"), 3);
+
+        let filters = HistoryFilters {
+            exit: Some(1),
+            ..Default::default()
+        };
+        assert_eq!(db.delete_where(filters, false).expect("Delete failed"), 1);
+        assert_eq!(db.get_history_total().expect("Count failed"), 2);
+        assert!(
+            db.search("false", HistoryFilters::default())
+                .expect("Search failed")
+                .is_empty()
+        );
+
+        let deleted = db
+            .delete_where(HistoryFilters::default(), true)
+            .expect("Forced delete failed");
+        assert_eq!(deleted, 2);
+        assert_eq!(db.get_history_total().expect("Count failed"), 0);
+    }
+
+    #[test]
+    fn test_delete_matching() {
+        let mut db = memory_db(Some(LATEST_STABLE_SCHEMA));
+        let mut failed = sample_history(3, "git push --force");
+        failed.exit_code = 1;
+        db.save_bulk(&[
+            sample_history(1, "git status"),
+            sample_history(2, "git push"),
+            failed,
+            sample_history(4, "ls"),
+        ])
+        .expect("Failed to save history");
+
+        let err = db
+            .delete_matching(" ", HistoryFilters::default())
+            .expect_err("Unfiltered delete should be refused");
+        assert_eq!(err.kind, DatabaseErrorKind::Unfiltered);
+
+        // Only the commands matching both the query and the filters go, the limit is ignored.
+        let filters = HistoryFilters {
+            exit: Some(0),
+            limit: Some(1),
+            ..Default::default()
+        };
+        assert_eq!(
+            db.delete_matching("git", filters).expect("Delete failed"),
+            2
+        );
+        let left: Vec<String> = db
+            .search("", HistoryFilters::default())
+            .expect("Search failed")
+            .into_iter()
+            .map(|h| h.command)
+            .collect();
+        assert_eq!(left.len(), 2);
+        assert!(left.contains(&"git push --force".to_string()));
+        assert!(left.contains(&"ls".to_string()));
+    }
+
+    #[test]
+    fn test_delete_range() {
+        let mut db = memory_db(Some(LATEST_STABLE_SCHEMA));
+        let at =
+            |seconds: i64| OffsetDateTime::from_unix_timestamp(1_700_000_000 + seconds).unwrap();
+        let history: Vec<History> = (0..6)
+            .map(|n| {
+                let mut h = sample_history(n, &format!("cmd{n}"));
+                h.timestamp = at(n * 10);
+                h
+            })
+            .collect();
+        db.save_bulk(&history).expect("Failed to save history");
+        let remaining = |db: &Sqlite| {
+            let mut commands: Vec<String> = db
+                .search("", HistoryFilters::default())
+                .expect("Search failed")
+                .into_iter()
+                .map(|h| h.command)
+                .collect();
+            commands.sort();
+            commands.join(" ")
+        };
+
+        let err = db
+            .delete_range(None, None, false)
+            .expect_err("Unbounded delete should be refused");
+        assert_eq!(err.kind, DatabaseErrorKind::Unfiltered);
+        assert_eq!(db.get_history_total().expect("Count failed"), 6);
+
+        // Both bounds are inclusive, the commands either side of the window survive.
+        let deleted = db
+            .delete_range(Some(at(10)), Some(at(30)), false)
+            .expect("Delete failed");
+        assert_eq!(deleted, 3);
+        assert_eq!(remaining(&db), "cmd0 cmd4 cmd5");
+
+        let deleted = db
+            .delete_range(Some(at(45)), None, false)
+            .expect("Delete failed");
+        assert_eq!(deleted, 1);
+        assert_eq!(remaining(&db), "cmd0 cmd4");
+
+        let deleted = db
+            .delete_range(None, Some(at(0)), false)
+            .expect("Delete failed");
+        assert_eq!(deleted, 1);
+        assert_eq!(remaining(&db), "cmd4");
+
+        let deleted = db
+            .delete_range(None, None, true)
+            .expect("Forced delete failed");
+        assert_eq!(deleted, 1);
+        assert_eq!(db.get_history_total().expect("Count failed"), 0);
+    }
+
+    #[test]
+    fn test_recent_commands_in_sync() {
+        let mut db = memory_db(Some(LATEST_STABLE_SCHEMA));
+        let at =
+            |seconds: i64| OffsetDateTime::from_unix_timestamp(1_700_000_000 + seconds).unwrap();
+        // The newest run of each distinct command, worked out from the history table itself.
+        let expected = |db: &Sqlite, n: usize| {
+            let mut all = db
+                .search("", HistoryFilters::default())
+                .expect("Search failed");
+            all.sort_by_key(|h| std::cmp::Reverse((h.timestamp, h.id)));
+            let mut seen = std::collections::HashSet::new();
+            all.retain(|h| seen.insert(h.command.clone()));
+            all.truncate(n);
+            all.into_iter().map(|h| h.id).collect::<Vec<i64>>()
+        };
+        let cached = |db: &Sqlite| {
+            db.recent_commands(usize::MAX)
+                .expect("Recent commands failed")
+                .into_iter()
+                .map(|h| h.id)
+                .collect::<Vec<i64>>()
+        };
+        let run = |command: &str, cwd: &str, seconds: i64| {
+            let mut h = sample_history(-1, command);
+            h.cwd = cwd.to_string();
+            h.timestamp = at(seconds);
+            h
+        };
+
+        let ls = db.save(&run("ls", "/a", 0)).expect("Save failed");
+        let status_old = db.save(&run("git status", "/a", 10)).expect("Save failed");
+        let status_new = db.save(&run("git status", "/b", 20)).expect("Save failed");
+        // Imported history older than the cached run does not replace it.
+        db.save_bulk(&[run("git status", "/c", 5), run("make", "/a", 15)])
+            .expect("Save failed");
+        assert_eq!(cached(&db)[0], status_new);
+        assert_eq!(cached(&db), expected(&db, 200));
+        assert_eq!(
+            db.recent_commands(1).expect("Recent commands failed")[0].command,
+            "git status"
+        );
+
+        // Deleting the newest run falls back to the next newest one.
+        db.delete(status_new).expect("Delete failed");
+        assert_eq!(cached(&db), expected(&db, 200));
+        assert!(cached(&db).contains(&status_old));
+
+        // Deleting the only run drops the command.
+        db.delete(ls).expect("Delete failed");
+        assert_eq!(cached(&db), expected(&db, 200));
+
+        // Moving a run forward in time moves it to the front.
+        let update = HistoryUpdate::builder().timestamp(at(100)).build();
+        db.update_partial(status_old, &update)
+            .expect("Update failed");
+        assert_eq!(cached(&db)[0], status_old);
+        assert_eq!(cached(&db), expected(&db, 200));
+
+        // Moving it back before another run of the same command caches that run instead.
+        let update = HistoryUpdate::builder().timestamp(at(1)).build();
+        db.update_partial(status_old, &update)
+            .expect("Update failed");
+        assert_eq!(cached(&db), expected(&db, 200));
+        assert!(!cached(&db).contains(&status_old));
+
+        // Only the latest 200 distinct commands are cached, deleting one promotes the next.
+        let many: Vec<History> = (0..205)
+            .map(|n| run(&format!("cmd{n}"), "/a", 1000 + n))
+            .collect();
+        db.save_bulk(&many).expect("Save failed");
+        assert_eq!(cached(&db).len(), 200);
+        assert_eq!(cached(&db), expected(&db, 200));
+        let newest = cached(&db)[0];
+        db.delete(newest).expect("Delete failed");
+        assert_eq!(cached(&db), expected(&db, 200));
+
+        // A bulk delete promotes as many older commands as it removes.
+        let deleted = db
+            .delete_matching("cmd", HistoryFilters::default())
+            .expect("Delete failed");
+        assert!(deleted > 0);
+        assert_eq!(cached(&db), expected(&db, 200));
+
+        // An update to a run outside the cache of a command which is cached leaves it as is.
+        let oldest_status = db
+            .search("git status", HistoryFilters::default())
+            .expect("Search failed")
+            .into_iter()
+            .min_by_key(|h| h.timestamp)
+            .expect("A run of git status");
+        let update = HistoryUpdate::builder().timestamp(at(2)).build();
+        db.update_partial(oldest_status.id, &update)
+            .expect("Update failed");
+        assert_eq!(cached(&db), expected(&db, 200));
+    }
+
+    #[test]
+    fn test_recent_commands_migration() {
+        let mut db = memory_db(Some(SchemaVersion::V9));
+        db.conn
+            .execute_batch(
+                "INSERT INTO history (timestamp, command, cwd, exit_code, session_id)
+                VALUES (10, 'ls', '/a', 0, ''), (30, 'ls', '/b', 0, ''), (20, 'pwd', '/a', 0, '');",
+            )
+            .expect("Failed to insert history");
+        run_migrations(&mut db.conn, SchemaVersion::V9.to_u32(), None).expect("Migration failed");
+
+        let recent: Vec<(String, String)> = db
+            .recent_commands(10)
+            .expect("Recent commands failed")
+            .into_iter()
+            .map(|h| (h.command, h.cwd))
+            .collect();
+        assert_eq!(
+            recent,
+            [
+                ("ls".to_string(), "/b".to_string()),
+                ("pwd".to_string(), "/a".to_string())
+            ]
+        );
+    }
+
+    #[test]
+    fn test_rewrite_cwd() {
+        let mut db = memory_db(Some(LATEST_STABLE_SCHEMA));
+        let in_dir = |id: i64, cwd: &str| {
+            let mut h = sample_history(id, "ls");
+            h.cwd = cwd.to_string();
+            h
+        };
+        db.save_bulk(&[
+            in_dir(1, "/home/me/old"),
+            in_dir(2, "/home/me/old/src/bin"),
+            in_dir(3, "/home/me/older"),
+            in_dir(4, "/home/me/old_100%"),
+            in_dir(5, "/tmp"),
+        ])
+        .expect("Failed to save history");
+
+        let rewritten = db
+            .rewrite_cwd("/home/me/old/", "/home/me/new")
+            .expect("Rewrite failed");
+        assert_eq!(rewritten, 2);
+
+        let mut cwds = db
+            .search("", HistoryFilters::default())
+            .expect("Search failed")
+            .into_iter()
+            .map(|h| h.cwd)
+            .collect::<Vec<String>>();
+        cwds.sort_unstable();
+        assert_eq!(
+            cwds,
+            vec![
+                "/home/me/new",
+                "/home/me/new/src/bin",
+                "/home/me/old_100%",
+                "/home/me/older",
+                "/tmp"
+            ]
+        );
+
+        // LIKE wildcards in the directory are matched literally.
+        assert_eq!(db.rewrite_cwd("/home/me/old_100%", "/x").unwrap(), 1);
+        assert_eq!(db.rewrite_cwd("/home/me/nothing", "/x").unwrap(), 0);
+        assert!(db.rewrite_cwd("/", "/x").is_err());
+    }
+
+    #[test]
+    fn test_save_bulk_dedup() {
+        let mut db = memory_db(Some(LATEST_STABLE_SCHEMA));
+        let at = |command: &str, timestamp: i64| {
+            let mut h = sample_history(-1, command);
+            h.timestamp = OffsetDateTime::from_unix_timestamp(timestamp).unwrap();
+            h
+        };
+        let first = db
+            .save_bulk_dedup(&[at("make", 1_000), at("ls", 1_001)])
+            .expect("Failed to save history");
+        assert_eq!(first.len(), 2);
+
+        // Importing again only saves what is new, a repeat within the batch is saved once.
+        let mut failed = at("cargo test", 1_002);
+        failed.exit_code = 1;
+        let ids = db
+            .save_bulk_dedup(&[
+                at("make", 1_000),
+                at("ls", 1_001),
+                at("cargo test", 1_002),
+                failed,
+            ])
+            .expect("Failed to save history");
+        assert_eq!(ids.len(), 1);
+        assert_eq!(db.get(ids[0]).unwrap().unwrap().command, "cargo test");
+        assert_eq!(db.get_history_total().unwrap(), 3);
+
+        // The same command run in another directory is not a duplicate.
+        let mut elsewhere = at("make", 1_000);
+        elsewhere.cwd = "/src".to_string();
+        let ids = db
+            .save_bulk_dedup(&[elsewhere])
+            .expect("Failed to save history");
+        assert_eq!(ids.len(), 1);
+    }
+
+    #[test]
+    fn test_unique_history() {
+        let mut db = memory_db(Some(LATEST_STABLE_SCHEMA));
+        let h = sample_history(1, "make");
+        db.set_unique(true).expect("Failed to add the unique index");
+
+        db.save(&h).expect("Failed to save history");
+        let err = db.save(&h).expect_err("Duplicate should be rejected");
+        assert_eq!(err.kind, DatabaseErrorKind::Duplicate);
+
+        // Bulk saves skip duplicates, including those within the batch.
+        let mut elsewhere = h.clone();
+        elsewhere.cwd = "/src".to_string();
+        let ids = db
+            .save_bulk(&[h.clone(), elsewhere.clone(), elsewhere])
+            .expect("Failed to save history");
+        assert_eq!(ids.len(), 1);
+        assert_eq!(db.get_history_total().unwrap(), 2);
+
+        db.set_unique(false)
+            .expect("Failed to drop the unique index");
+        // A different exit code, as the table already replaces identical command, cwd and exit
+        // code triples.
+        let mut failed = h.clone();
+        failed.exit_code = 1;
+        db.save(&failed).expect("Duplicates are allowed again");
+        assert_eq!(db.get_history_total().unwrap(), 3);
+        assert!(db.set_unique(true).is_err(), "Duplicates are stored");
+    }
+
+    #[test]
+    fn test_update_partial() {
+        let mut db = memory_db(Some(LATEST_STABLE_SCHEMA));
+        let id = db
+            .save(&sample_history(-1, "cargo build"))
+            .expect("Failed to save");
+
+        db.update_partial(id, &HistoryUpdate::builder().exit_code(2).build())
+            .expect("Failed to update");
+
+        let h = db.get(id).expect("Failed to get").expect("History missing");
+        assert_eq!(h.exit_code, 2);
+        assert_eq!(h.command, "cargo build");
+        assert_eq!(h.cwd, "/tmp");
+        assert_eq!(h.session_id, "session");
+
+        // An empty update leaves the row alone.
+        db.update_partial(id, &HistoryUpdate::default())
+            .expect("Failed to update");
+        assert_eq!(db.get(id).expect("Failed to get"), Some(h));
+
+        assert!(
+            db.update_partial(id + 1, &HistoryUpdate::builder().exit_code(0).build())
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn test_delete() {
+        let mut db = memory_db(Some(LATEST_STABLE_SCHEMA));
+        let history = sample_history(1, "to be deleted");
+
+        let id = db.save(&history).expect("Failed to save history");
+        assert!(db.get(id).expect("Get failed").is_some());
+
+        db.delete(id).expect("Failed to delete history");
+
+        let result = db.get(id);
+        assert!(result.is_ok());
+        // The result should be None
+        assert!(result.unwrap().is_none());
+
+        // Deleting non-existent should be Ok
+        let delete_again_result = db.delete(id);
+        assert!(delete_again_result.is_ok());
+
+        // Deleting ID -1 should probably fail or be a no-op depending on desired behavior
+        // Current implementation might error in execute due to parameter mismatch if not handled
+        // Let's assume deleting -1 isn't a valid operation here or should be a no-op handled by execute
+        let delete_invalid_result = db.delete(-1);
+        // Depending on rusqlite behavior with [-1] param, this might Err or Ok(0 rows affected)
+        // Given the code `execute(&query.to_sql(), [id])`, rusqlite likely handles it.
+        // Let's assert Ok as the function intends to return Ok for 0 rows affected.
+        assert!(delete_invalid_result.is_ok());
+    }
+
+    #[test]
+    fn test_get_history_total() {
+        let mut db = memory_db(Some(LATEST_STABLE_SCHEMA));
+
+        let total_initial = db.get_history_total().expect("Failed to get initial total");
+        assert_eq!(total_initial, 0);
+
+        db.save(&sample_history(1, "cmd 1")).expect("Save 1 failed");
+        db.save(&sample_history(2, "cmd 2")).expect("Save 2 failed");
+
+        let total_after_saves = db
+            .get_history_total()
+            .expect("Failed to get total after saves");
+        assert_eq!(total_after_saves, 2);
+
+        let id = db.save(&sample_history(3, "cmd 3")).expect("Save 3 failed");
+        db.delete(id).expect("Delete failed");
+
+        let total_after_delete = db
+            .get_history_total()
+            .expect("Failed to get total after delete");
+        assert_eq!(total_after_delete, 2); // Back to 2 after deleting one
+    }
+
+    #[test]
+    fn test_memory() {
+        let db = Sqlite::memory();
+        assert_eq!(
+            db.schema_version().expect("Failed to get schema version"),
+            LATEST_STABLE_SCHEMA.to_u32()
+        );
+        assert_eq!(db.get_history_total().expect("Failed to count"), 0);
+    }
+
+    #[test]
+    fn test_reindex_trigram() {
+        let mut db = memory_db(Some(LATEST_STABLE_SCHEMA));
+        db.save_bulk(&[
+            sample_history(1, "sudo apt install vim"),
+            sample_history(2, "git status"),
+        ])
+        .expect("Failed to save for search");
+        assert_eq!(
+            db.tokenizer().expect("Failed to read tokenizer"),
+            "unicode61"
+        );
+
+        let search = |db: &Sqlite, query: &str| {
+            db.search(query, HistoryFilters::default())
+                .expect("Search failed")
+                .into_iter()
+                .map(|h| h.command)
+                .collect::<Vec<String>>()
+        };
+        // The default tokenizer only matches from the start of a word.
+        assert!(search(&db, "stall").is_empty());
+
+        db.reindex(Tokenizer::Trigram).expect("Failed to reindex");
+        assert_eq!(db.tokenizer().expect("Failed to read tokenizer"), "trigram");
+        assert_eq!(search(&db, "stall"), vec!["sudo apt install vim"]);
+
+        // Rows saved after the reindex are indexed by the triggers.
+        db.save(&sample_history(3, "make uninstall"))
+            .expect("Failed to save");
+        assert_eq!(search(&db, "stall").len(), 2);
+    }
+
+    #[test]
+    fn test_schema_version() {
+        let db = memory_db(Some(LATEST_STABLE_SCHEMA));
+        assert_eq!(
+            db.schema_version().expect("Failed to get schema version"),
+            LATEST_STABLE_SCHEMA.to_u32()
+        );
+    }
+
+    #[test]
+    fn test_search_timeout() {
+        let mut db = memory_db(Some(LATEST_STABLE_SCHEMA));
+        let history: Vec<History> = (0..2000)
+            .map(|n| sample_history(n, &format!("cargo build {n}")))
+            .collect();
+        db.save_bulk(&history).expect("Failed to save history");
+        let filters = HistoryFilters {
+            no_fts: true,
+            // The deadline passes before the search starts, so the first check interrupts it.
+            timeout: Some(Duration::ZERO),
+            ..Default::default()
+        };
+
+        let err = db
+            .search("build 1999", filters.clone())
+            .expect_err("Search should time out");
+        assert_eq!(err.kind, DatabaseErrorKind::Timeout);
+        assert_eq!(err.msg, "search timed out after 0ms");
+        let err = db
+            .search_count("build", filters.clone())
+            .expect_err("Count should time out");
+        assert_eq!(err.kind, DatabaseErrorKind::Timeout);
+
+        // Only searches are timed, the handler is gone once a search returns.
+        assert!(db.get(1).expect("Get failed").is_some());
+
+        // Searches without a timeout, such as exports, always run to the end.
+        let untimed = HistoryFilters {
+            timeout: None,
+            ..filters.clone()
+        };
+        let results = db.search("build 1999", untimed).expect("Search failed");
+        assert_eq!(results.len(), 1);
+
+        let timed = HistoryFilters {
+            timeout: Some(Duration::from_secs(60)),
+            ..filters
+        };
+        let results = db.search("build 1999", timed).expect("Search failed");
+        assert_eq!(results.len(), 1);
+    }
+
+    #[test]
+    fn test_save_aliases() {
+        let mut db = memory_db(Some(LATEST_STABLE_SCHEMA));
+        let alias = |name: &str, expansion: &str| Alias {
+            name: name.to_string(),
+            expansion: expansion.to_string(),
+        };
+        let saved = db
+            .save_aliases(&[alias("gst", "git status"), alias("ll", "ls -l")])
+            .expect("Failed to save aliases");
+        assert_eq!(saved, 2);
+
+        // Importing again replaces changed expansions instead of failing.
+        db.save_aliases(&[alias("ll", "ls -la")])
+            .expect("Failed to save aliases");
+        assert_eq!(
+            db.aliases().expect("Failed to list aliases"),
+            [alias("gst", "git status"), alias("ll", "ls -la")]
+        );
+    }
+
+    #[test]
+    fn test_backend_name() {
+        let db = memory_db(Some(LATEST_STABLE_SCHEMA));
+        assert_eq!(db.backend_name(), "sqlite");
+    }
+
+    #[test]
+    fn test_generate_fts5_match_parameter_empty_query() {
+        assert_eq!(
+            generate_fts5_match_parameter("", MatchMode::Fuzzy, PrefixPolicy::All),
+            ""
+        );
+        assert_eq!(
+            generate_fts5_match_parameter("", MatchMode::Prefix, PrefixPolicy::All),
+            ""
+        );
+    }
+
+    #[test]
+    fn test_generate_fts5_match_parameter_fuzzy() {
+        assert_eq!(
+            generate_fts5_match_parameter("hello world", MatchMode::Fuzzy, PrefixPolicy::All),
+            "\"hello\"* \"world\"*"
+        );
+        assert_eq!(
+            generate_fts5_match_parameter("term1", MatchMode::Fuzzy, PrefixPolicy::All),
+            "\"term1\"*"
+        );
+        assert_eq!(
+            generate_fts5_match_parameter("with\"quote", MatchMode::Fuzzy, PrefixPolicy::All),
+            "\"with\"\"quote\"*"
+        );
+        assert_eq!(
+            generate_fts5_match_parameter(
+                "multiple words \"here\"",
+                MatchMode::Fuzzy,
+                PrefixPolicy::All
+            ),
+            "\"multiple\"* \"words\"* \"\"\"here\"\"\"*"
+        );
+    }
+
+    #[test]
+    fn test_search_query_order() {
+        let order_sql = |query: &str, order: OrderBy| {
+            let filters = HistoryFilters {
+                order,
+                ..Default::default()
+            };
+            search_query(query, &filters).0.to_sql()
+        };
+
+        assert!(
+            order_sql("git", OrderBy::Recency).ends_with("ORDER BY h.timestamp DESC, h.id DESC")
+        );
+        assert!(order_sql("git", OrderBy::Relevance).ends_with("ORDER BY fts.rank ASC, h.id DESC"));
+        // Without a query there is nothing to rank by.
+        assert!(
+            order_sql("", OrderBy::Relevance).ends_with("ORDER BY h.timestamp DESC, h.id DESC")
+        );
+
+        let filters = HistoryFilters {
+            order: OrderBy::Relevance,
+            limit: Some(5),
+            ..Default::default()
+        };
+        assert!(
+            search_query("git", &filters)
+                .0
+                .to_sql()
+                .contains("ORDER BY fts.rank ASC, h.id DESC LIMIT 5")
+        );
+    }
+
+    #[test]
+    fn test_search_order_relevance() {
+        let mut db = memory_db(Some(LATEST_STABLE_SCHEMA));
+        let base = OffsetDateTime::now_utc();
+        for (id, command) in [
+            (1, "make make make"),
+            (2, "make clean && cargo build --release"),
+        ] {
+            let mut h = sample_history(id, command);
+            h.timestamp = base + time::Duration::seconds(id);
+            db.save(&h).expect("Failed to save history");
+        }
+
+        let search = |order| {
+            let filters = HistoryFilters {
+                order,
+                ..Default::default()
+            };
+            db.search("make", filters)
+                .expect("Search failed")
+                .into_iter()
+                .map(|h| h.command)
+                .collect::<Vec<String>>()
+        };
+
+        assert_eq!(
+            search(OrderBy::Recency),
+            vec!["make clean && cargo build --release", "make make make"]
+        );
+        assert_eq!(
+            search(OrderBy::Relevance),
+            vec!["make make make", "make clean && cargo build --release"]
+        );
+    }
+
+    #[test]
+    fn test_generate_fts5_match_parameter_fuzzy_any() {
+        assert_eq!(
+            generate_fts5_match_parameter("", MatchMode::FuzzyAny, PrefixPolicy::All),
+            ""
+        );
+        assert_eq!(
+            generate_fts5_match_parameter("git docker", MatchMode::FuzzyAny, PrefixPolicy::All),
+            "\"git\"* OR \"docker\"*"
+        );
+        assert_eq!(
+            generate_fts5_match_parameter("term1", MatchMode::FuzzyAny, PrefixPolicy::All),
+            "\"term1\"*"
+        );
+        assert_eq!(
+            generate_fts5_match_parameter(
+                "OR  with\"quote",
+                MatchMode::FuzzyAny,
+                PrefixPolicy::All
+            ),
+            "\"OR\"* OR \"with\"\"quote\"*"
+        );
+    }
+
+    #[test]
+    fn test_search_fuzzy_any() {
+        let mut db = memory_db(Some(LATEST_STABLE_SCHEMA));
+        db.save_bulk(&[
+            sample_history(1, "git status"),
+            sample_history(2, "docker ps"),
+            sample_history(3, "cargo build"),
+        ])
+        .expect("Failed to save for search");
+
+        let search = |mode: MatchMode| {
+            let mut commands = db
+                .search(
+                    "git docker",
+                    HistoryFilters {
+                        mode,
+                        ..Default::default()
+                    },
+                )
+                .expect("Search failed")
+                .into_iter()
+                .map(|h| h.command)
+                .collect::<Vec<_>>();
+            commands.sort_unstable();
+            commands
+        };
+        assert!(search(MatchMode::Fuzzy).is_empty());
+        assert_eq!(search(MatchMode::FuzzyAny), vec!["docker ps", "git status"]);
+    }
+
+    #[test]
+    fn test_generate_fts5_match_parameter_prefix_policy() {
+        let fuzzy =
+            |policy| generate_fts5_match_parameter("git push origin", MatchMode::Fuzzy, policy);
+        assert_eq!(fuzzy(PrefixPolicy::All), "\"git\"* \"push\"* \"origin\"*");
+        assert_eq!(
+            fuzzy(PrefixPolicy::LastOnly),
+            "\"git\" \"push\" \"origin\"*"
+        );
+        assert_eq!(fuzzy(PrefixPolicy::None), "\"git\" \"push\" \"origin\"");
+
+        let any = |policy| generate_fts5_match_parameter("git docker", MatchMode::FuzzyAny, policy);
+        assert_eq!(any(PrefixPolicy::All), "\"git\"* OR \"docker\"*");
+        assert_eq!(any(PrefixPolicy::LastOnly), "\"git\" OR \"docker\"*");
+        assert_eq!(any(PrefixPolicy::None), "\"git\" OR \"docker\"");
+
+        // The whole query is a single prefix in prefix mode.
+        let prefix = |policy| generate_fts5_match_parameter("git pu", MatchMode::Prefix, policy);
+        assert_eq!(prefix(PrefixPolicy::All), "^\"git pu\"*");
+        assert_eq!(prefix(PrefixPolicy::LastOnly), "^\"git pu\"*");
+        assert_eq!(prefix(PrefixPolicy::None), "^\"git pu\"");
+
+        assert_eq!(
+            generate_fts5_match_parameter("git*", MatchMode::Raw, PrefixPolicy::None),
+            "git*"
+        );
+    }
+
+    #[test]
+    fn test_search_prefix_policy() {
+        let mut db = memory_db(Some(LATEST_STABLE_SCHEMA));
+        db.save_bulk(&[
+            sample_history(1, "git status"),
+            sample_history(2, "gitk stash"),
+            sample_history(3, "git st"),
+        ])
+        .expect("Failed to save history");
+        let search = |policy| {
+            let filters = HistoryFilters {
+                prefix_policy: policy,
+                ..Default::default()
+            };
+            let mut commands: Vec<String> = db
+                .search("git st", filters)
+                .expect("Search failed")
+                .into_iter()
+                .map(|h| h.command)
+                .collect();
+            commands.sort_unstable();
+            commands
+        };
+        assert_eq!(
+            search(PrefixPolicy::All),
+            ["git st", "git status", "gitk stash"]
+        );
+        assert_eq!(search(PrefixPolicy::LastOnly), ["git st", "git status"]);
+        assert_eq!(search(PrefixPolicy::None), ["git st"]);
+    }
+
+    #[test]
+    fn test_generate_fts5_match_parameter_initial_prefix() {
+        assert_eq!(
+            generate_fts5_match_parameter("start phrase", MatchMode::Prefix, PrefixPolicy::All),
             "^\"start phrase\"*"
         );
         assert_eq!(
-            generate_fts5_match_parameter("prefix", MatchMode::Prefix),
+            generate_fts5_match_parameter("prefix", MatchMode::Prefix, PrefixPolicy::All),
             "^\"prefix\"*"
         );
         assert_eq!(
-            generate_fts5_match_parameter("prefix with\"quote", MatchMode::Prefix),
+            generate_fts5_match_parameter(
+                "prefix with\"quote",
+                MatchMode::Prefix,
+                PrefixPolicy::All
+            ),
             "^\"prefix with\"\"quote\"*"
         );
     }
+
+    #[test]
+    fn test_generate_fts5_match_parameter_raw() {
+        assert_eq!(
+            generate_fts5_match_parameter("", MatchMode::Raw, PrefixPolicy::All),
+            ""
+        );
+        assert_eq!(
+            generate_fts5_match_parameter(
+                "NEAR(\"git\" \"push\", 3)",
+                MatchMode::Raw,
+                PrefixPolicy::All
+            ),
+            "NEAR(\"git\" \"push\", 3)"
+        );
+    }
+
+    #[test]
+    fn test_search_raw() {
+        let mut db = memory_db(Some(LATEST_STABLE_SCHEMA));
+        db.save_bulk(&[
+            sample_history(1, "git push origin main"),
+            sample_history(2, "git commit -m 'do not push yet' --amend --no-edit"),
+            sample_history(3, "git status"),
+        ])
+        .expect("Failed to save for search");
+
+        let filters = HistoryFilters {
+            mode: MatchMode::Raw,
+            ..Default::default()
+        };
+        let results = db
+            .search("NEAR(git push, 1)", filters.clone())
+            .expect("Search failed");
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0].command, "git push origin main");
+
+        // Malformed FTS5 syntax is reported rather than silently matching nothing.
+        assert!(db.search("NEAR(git", filters).is_err());
+    }
 }
@@ -5,7 +5,8 @@ use std::fmt::Write as _;
 #[derive(Default, Debug, Clone)]
 pub struct DeleteStatement<'a> {
     pub table: &'a str,
-    pub r#where: Vec<(&'a str, Option<&'a str>)>, // Reuse the where clause logic
+    /// The `(clause, operator, parameter)` conditions, rendered as `{clause} {op} :w_{param}`.
+    pub r#where: Vec<(&'a str, &'a str, &'a str)>,
 }
 
 impl SqlString for DeleteStatement<'_> {
@@ -19,7 +20,7 @@ impl SqlString for DeleteStatement<'_> {
             sql.push(' ');
             sql.push_str("WHERE");
             sql.push(' ');
-            for (idx, (clause, option)) in self.r#where.iter().enumerate() {
+            for (idx, (clause, op, param)) in self.r#where.iter().enumerate() {
                 // Separate where clauses with AND if this is not the first
                 if idx != 0 {
                     sql.push(' ');
@@ -28,11 +29,7 @@ impl SqlString for DeleteStatement<'_> {
                 }
 
                 // Using :w_ prefix for parameters like in UpdateStatement
-                if let Some(like) = option {
-                    let _ = write!(sql, "{clause} {like} :w_{clause}");
-                } else {
-                    let _ = write!(sql, "{clause} = :w_{clause}");
-                }
+                let _ = write!(sql, "{clause} {op} :w_{param}");
             }
         }
         // Note: A DELETE statement without a WHERE clause is valid (deletes all rows),
@@ -58,15 +55,19 @@ impl<'a> DeleteStatement<'a> {
     /// NOTE: where parameters are added with the `:w_${clause}` name
     /// to ensure they are distinct from any potential future parameter needs.
     pub fn r#where(&mut self, clause: &'a str) -> &mut Self {
-        self.r#where.push((clause, None));
-        self
+        self.where_op(clause, "=", clause)
     }
 
-    // Optionally, add a 'like' method if needed, similar to SelectStatement
-    // pub fn like(&mut self, clause: &'a str) -> &mut Self {
-    //     self.r#where.push((clause, Some("LIKE")));
-    //     self
-    // }
+    /// Specify a clause to add to the WHERE section of the query using the provided operator,
+    /// such as `<` or `>=`.
+    /// The same clause may be bounded more than once, so the parameter name must be provided
+    /// explicitly.
+    ///
+    /// NOTE: where parameters are added with the `:w_${param}` name
+    pub fn where_op(&mut self, clause: &'a str, op: &'a str, param: &'a str) -> &mut Self {
+        self.r#where.push((clause, op, param));
+        self
+    }
 }
 
 #[cfg(test)]
@@ -97,6 +98,33 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_to_sql_where_op() {
+        for op in ["=", "!=", "<", "<=", ">", ">="] {
+            let query = Query::delete()
+                .table("history")
+                .where_op("timestamp", op, "ts")
+                .to_owned();
+            assert_eq!(
+                query.to_sql(),
+                format!("DELETE FROM history WHERE timestamp {op} :w_ts")
+            );
+        }
+
+        let query = Query::delete()
+            .table("history")
+            .where_op("timestamp", ">=", "after")
+            .where_op("timestamp", "<=", "before")
+            .r#where("cwd")
+            .to_owned();
+        assert_eq!(
+            query.to_sql(),
+            String::from(
+                "DELETE FROM history WHERE timestamp >= :w_after AND timestamp <= :w_before AND cwd = :w_cwd"
+            )
+        );
+    }
+
     #[test]
     fn test_to_sql_delete_all() {
         // Test deleting without a where clause (use with caution!)
@@ -59,9 +59,7 @@ mod tests {
 
         assert_eq!(
             query.to_sql(),
-            String::from(concat!(
-                "INSERT INTO history (command, cwd) VALUES (:command, :cwd)"
-            ))
+            String::from("INSERT INTO history (command, cwd) VALUES (:command, :cwd)")
         );
     }
 }
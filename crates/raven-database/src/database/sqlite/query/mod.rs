@@ -1,6 +1,6 @@
 use delete::DeleteStatement;
 use insert::InsertStatement;
-use select::SelectStatement;
+pub use select::SelectStatement;
 use update::UpdateStatement;
 
 mod delete;
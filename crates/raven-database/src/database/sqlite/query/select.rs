@@ -6,11 +6,11 @@ use std::fmt::Write as _;
 pub struct SelectStatement<'a> {
     pub from: Vec<&'a str>,
     pub selects: Vec<SelectExpr>,
-    // Tuple: (clause, operator_or_none)
-    // None => "=", Some("LIKE") => "LIKE", Some("MATCH") => "MATCH"
-    pub r#where: Vec<(&'a str, Option<&'a str>)>,
+    pub r#where: Vec<WhereExpr>,
     pub limit: Option<usize>,
-    pub orderby: Option<(&'a str, &'a str)>,
+    pub offset: Option<usize>,
+    pub groupby: Option<&'a str>,
+    pub orderby: Vec<(&'a str, &'a str)>,
 }
 
 #[derive(Debug, Clone)]
@@ -19,6 +19,17 @@ pub struct SelectExpr {
     pub alias: Option<String>,
 }
 
+/// A single condition of the WHERE section, rendered as `{clause} {op} :{param}`.
+///
+/// * `escape`: The escape character for LIKE patterns, rendered as `ESCAPE '{escape}'`.
+#[derive(Debug, Clone)]
+pub struct WhereExpr {
+    pub clause: String,
+    pub op: String,
+    pub param: String,
+    pub escape: Option<char>,
+}
+
 impl SqlString for SelectStatement<'_> {
     /// Convert the [`SelectStatement`] into a runnable SQL string.
     fn to_sql(&self) -> String {
@@ -46,7 +57,16 @@ impl SqlString for SelectStatement<'_> {
             sql.push_str("WHERE");
             sql.push(' ');
 
-            for (idx, (clause, option)) in self.r#where.iter().enumerate() {
+            for (
+                idx,
+                WhereExpr {
+                    clause,
+                    op,
+                    param,
+                    escape,
+                },
+            ) in self.r#where.iter().enumerate()
+            {
                 // Separate where clauses with AND if this is not the first
                 if idx != 0 {
                     sql.push(' ');
@@ -54,30 +74,42 @@ impl SqlString for SelectStatement<'_> {
                     sql.push(' ');
                 }
 
-                // Create a valid parameter name by replacing '.' with '_'
-                let param_name = clause.replace('.', "_");
-
-                match option {
-                    Some(op) => {
-                        // Handle other potential future operators if needed, using clause name for parameter
-                        let _ = write!(sql, "{clause} {op} :{param_name}");
-                    }
-                    None => {
-                        // Default to equals, using clause name for parameter
-                        let _ = write!(sql, "{clause} = :{param_name}");
-                    }
+                let _ = write!(sql, "{clause} {op} :{param}");
+                if let Some(escape) = escape {
+                    let _ = write!(sql, " ESCAPE '{escape}'");
                 }
             }
         }
 
-        if let Some((column, direction)) = &self.orderby {
+        if let Some(column) = &self.groupby {
             sql.push(' ');
-            let _ = write!(sql, "ORDER BY {column} {direction}");
+            let _ = write!(sql, "GROUP BY {column}");
         }
 
-        if let Some(limit) = self.limit {
+        if !self.orderby.is_empty() {
+            sql.push_str(" ORDER BY ");
+            sql.push_str(
+                &self
+                    .orderby
+                    .iter()
+                    .map(|(column, direction)| format!("{column} {direction}"))
+                    .collect::<Vec<String>>()
+                    .join(", "),
+            );
+        }
+
+        if self.limit.is_some() || self.offset.is_some() {
             sql.push(' ');
-            let _ = write!(sql, "LIMIT {limit}");
+            // SQLite only accepts OFFSET after a LIMIT, a negative LIMIT has no upper bound.
+            match self.limit {
+                Some(limit) => {
+                    let _ = write!(sql, "LIMIT {limit}");
+                }
+                None => sql.push_str("LIMIT -1"),
+            }
+            if let Some(offset) = self.offset {
+                let _ = write!(sql, " OFFSET {offset}");
+            }
             sql.push(' '); // Add trailing space consistent with previous test
         }
 
@@ -103,6 +135,18 @@ impl<'a> SelectStatement<'a> {
         self
     }
 
+    /// Add a `MAX(column_name)` AS alias in the selected fields.
+    ///
+    /// * `column_name`: The name of the column to find the maximum of.
+    /// * `alias`: The alias for the column in the returned rows.
+    pub fn max(&mut self, column_name: &'a str, alias: &'a str) -> &mut Self {
+        self.selects.push(SelectExpr {
+            expr: format!("MAX({column_name})"),
+            alias: Some(alias.to_string()),
+        });
+        self
+    }
+
     /// Specify the table to select results from. Can be called multiple times for joins
     /// or provide a single string with JOIN syntax.
     pub fn from(&mut self, table_name: &'a str) -> &mut Self {
@@ -111,18 +155,55 @@ impl<'a> SelectStatement<'a> {
     }
 
     /// Specify a clause to add to the WHERE section of the query using `=`.
-    /// NOTE: parameters are added with the name `":{clause}"`
+    /// NOTE: parameters are added with the name `":{clause}"`, with any '.' replaced by '_'.
     pub fn r#where(&mut self, clause: &'a str) -> &mut Self {
-        self.r#where.push((clause, None));
+        self.where_op(clause, "=", &clause.replace('.', "_"))
+    }
+
+    /// Specify a clause to add to the WHERE section of the query using the provided operator.
+    /// The `clause` may be any expression, such as `length(command)`, so the parameter name
+    /// must be provided explicitly.
+    /// NOTE: parameters are added with the name `":{param}"`
+    pub fn where_op(&mut self, clause: &str, op: &str, param: &str) -> &mut Self {
+        self.r#where.push(WhereExpr {
+            clause: clause.to_string(),
+            op: op.to_string(),
+            param: param.to_string(),
+            escape: None,
+        });
         self
     }
 
+    /// Specify a `LIKE` clause to add to the WHERE section of the query.
+    /// The bound pattern must escape its wildcards with `\`, see [`escape_like`].
+    /// NOTE: parameters are added with the name `":{param}"`
+    ///
+    /// [`escape_like`]: crate::database::sqlite::escape_like
+    pub fn like(&mut self, clause: &str, param: &str) -> &mut Self {
+        self.r#where.push(WhereExpr {
+            clause: clause.to_string(),
+            op: String::from("LIKE"),
+            param: param.to_string(),
+            escape: Some('\\'),
+        });
+        self
+    }
+
+    /// Specify a `REGEXP` clause to add to the WHERE section of the query.
+    /// The `regexp` function it calls must be registered on the connection, see
+    /// [`register_functions`].
+    /// NOTE: parameters are added with the name `":{param}"`
+    ///
+    /// [`register_functions`]: crate::database::sqlite::register_functions
+    pub fn regexp(&mut self, clause: &str, param: &str) -> &mut Self {
+        self.where_op(clause, "REGEXP", param)
+    }
+
     /// Specify an FTS5 MATCH clause to add to the WHERE section.
     /// The `match_clause` should typically be the name/alias of the FTS table.
-    /// NOTE: parameters are added with the name `":{match_clause}"`
+    /// NOTE: parameters are added with the name `":{match_clause}"`, with any '.' replaced by '_'.
     pub fn match_fts(&mut self, match_clause: &'a str) -> &mut Self {
-        self.r#where.push((match_clause, Some("MATCH")));
-        self
+        self.where_op(match_clause, "MATCH", &match_clause.replace('.', "_"))
     }
 
     /// Specify which column to add to the selection list.
@@ -140,9 +221,22 @@ impl<'a> SelectStatement<'a> {
         self
     }
 
-    /// Specify an ORDER BY clause to order the results in the provided direction.
+    /// Skip the first `offset` rows, combined with `limit` to page through the results.
+    pub fn offset(&mut self, offset: usize) -> &mut Self {
+        self.offset = Some(offset);
+        self
+    }
+
+    /// Specify a GROUP BY clause to aggregate rows sharing the same `column` value.
+    pub fn groupby(&mut self, column: &'a str) -> &mut Self {
+        self.groupby = Some(column);
+        self
+    }
+
+    /// Specify an ORDER BY clause to order the results in the provided direction. Can be called
+    /// multiple times, later columns break the ties of earlier ones.
     pub fn orderby(&mut self, column: &'a str, direction: &'a str) -> &mut Self {
-        self.orderby = Some((column, direction));
+        self.orderby.push((column, direction));
         self
     }
 }
@@ -180,6 +274,72 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_to_sql_where_op() {
+        let query = Query::select()
+            .column("id")
+            .from("history")
+            .where_op("length(command)", ">=", "min_length")
+            .where_op("length(command)", "<=", "max_length")
+            .to_owned();
+
+        assert_eq!(
+            query.to_sql(),
+            String::from(concat!(
+                "SELECT id FROM history ",
+                "WHERE length(command) >= :min_length AND length(command) <= :max_length",
+            ))
+        );
+    }
+
+    #[test]
+    fn test_to_sql_like() {
+        let query = Query::select()
+            .column("id")
+            .from("history")
+            .like("command", "command")
+            .to_owned();
+
+        assert_eq!(
+            query.to_sql(),
+            String::from("SELECT id FROM history WHERE command LIKE :command ESCAPE '\\'")
+        );
+    }
+
+    #[test]
+    fn test_to_sql_regexp() {
+        let query = Query::select()
+            .column("id")
+            .from("history")
+            .regexp("command", "regex")
+            .to_owned();
+
+        assert_eq!(
+            query.to_sql(),
+            String::from("SELECT id FROM history WHERE command REGEXP :regex")
+        );
+    }
+
+    #[test]
+    fn test_to_sql_groupby() {
+        let query = Query::select()
+            .column("session_id")
+            .max("timestamp", "last_active")
+            .from("history")
+            .groupby("session_id")
+            .orderby("MAX(timestamp)", "DESC")
+            .limit(2)
+            .to_owned();
+
+        assert_eq!(
+            query.to_sql(),
+            String::from(concat!(
+                "SELECT session_id, MAX(timestamp) AS last_active FROM history ",
+                "GROUP BY session_id ORDER BY MAX(timestamp) DESC LIMIT 2 ",
+            ))
+        );
+    }
+
     // Add a separate test for the non-FTS case if needed
     #[test]
     fn test_to_sql_no_fts() {
@@ -201,4 +361,27 @@ mod tests {
             ))
         );
     }
+
+    #[test]
+    fn test_to_sql_offset() {
+        let page = Query::select()
+            .column("id")
+            .from("history")
+            .orderby("timestamp", "DESC")
+            .limit(50)
+            .offset(100)
+            .to_owned();
+        assert_eq!(
+            page.to_sql(),
+            "SELECT id FROM history ORDER BY timestamp DESC LIMIT 50 OFFSET 100 "
+        );
+
+        // SQLite only accepts OFFSET after a LIMIT.
+        let rest = Query::select()
+            .column("id")
+            .from("history")
+            .offset(100)
+            .to_owned();
+        assert_eq!(rest.to_sql(), "SELECT id FROM history LIMIT -1 OFFSET 100 ");
+    }
 }
@@ -5,8 +5,10 @@ use std::fmt::Write as _;
 #[derive(Default, Debug, Clone)]
 pub struct UpdateStatement<'a> {
     pub columns: Vec<&'a str>,
+    pub expressions: Vec<(&'a str, &'a str)>,
     pub table: &'a str,
-    pub r#where: Vec<(&'a str, Option<&'a str>)>,
+    /// The `(clause, operator, parameter)` conditions, rendered as `{clause} {op} :w_{param}`.
+    pub r#where: Vec<(&'a str, &'a str, &'a str)>,
 }
 
 impl SqlString for UpdateStatement<'_> {
@@ -15,20 +17,23 @@ impl SqlString for UpdateStatement<'_> {
         let mut sql = String::new();
         let _ = write!(sql, "UPDATE {} SET", self.table);
 
-        for (idx, &col) in self.columns.iter().enumerate() {
-            sql.push(' ');
-            let _ = write!(sql, "{col} = :{col}");
-
-            if idx < self.columns.len() - 1 {
-                sql.push(',');
-            }
-        }
+        let assignments = self
+            .columns
+            .iter()
+            .map(|col| format!("{col} = :{col}"))
+            .chain(
+                self.expressions
+                    .iter()
+                    .map(|(col, expression)| format!("{col} = {expression}")),
+            )
+            .collect::<Vec<String>>();
+        let _ = write!(sql, " {}", assignments.join(", "));
 
         if !self.r#where.is_empty() {
             sql.push(' ');
             sql.push_str("WHERE");
             sql.push(' ');
-            for (idx, (clause, option)) in self.r#where.iter().enumerate() {
+            for (idx, (clause, op, param)) in self.r#where.iter().enumerate() {
                 // Separate where clauses with AND if this is not the first
                 if idx != 0 {
                     sql.push(' ');
@@ -36,11 +41,7 @@ impl SqlString for UpdateStatement<'_> {
                     sql.push(' ');
                 }
 
-                if let Some(like) = option {
-                    let _ = write!(sql, "{clause} {like} :w_{clause}");
-                } else {
-                    let _ = write!(sql, "{clause} = :w_{clause}");
-                }
+                let _ = write!(sql, "{clause} {op} :w_{param}");
             }
         }
         sql
@@ -64,7 +65,17 @@ impl<'a> UpdateStatement<'a> {
     /// NOTE: where parameters are added with the `:w_${clause}` name
     /// so that they do not conflict with the column values
     pub fn r#where(&mut self, clause: &'a str) -> &mut Self {
-        self.r#where.push((clause, None));
+        self.where_op(clause, "=", clause)
+    }
+
+    /// Specify a clause to add to the WHERE section of the query using the provided operator,
+    /// such as `<` or `>=`.
+    /// The same clause may be bounded more than once, so the parameter name must be provided
+    /// explicitly.
+    ///
+    /// NOTE: where parameters are added with the `:w_${param}` name
+    pub fn where_op(&mut self, clause: &'a str, op: &'a str, param: &'a str) -> &mut Self {
+        self.r#where.push((clause, op, param));
         self
     }
 
@@ -73,6 +84,14 @@ impl<'a> UpdateStatement<'a> {
         self.columns.push(column);
         self
     }
+
+    /// Specify a column to update to the result of an SQL expression.
+    ///
+    /// Expressions are written after the plain columns.
+    pub fn set(&mut self, column: &'a str, expression: &'a str) -> &mut Self {
+        self.expressions.push((column, expression));
+        self
+    }
 }
 
 #[cfg(test)]
@@ -90,9 +109,52 @@ mod tests {
 
         assert_eq!(
             query.to_sql(),
-            String::from(concat!(
-                "UPDATE history SET command = :command, cwd = :cwd WHERE id = :w_id",
-            ))
+            String::from("UPDATE history SET command = :command, cwd = :cwd WHERE id = :w_id")
+        );
+    }
+
+    #[test]
+    fn test_to_sql_set() {
+        let query = Query::update()
+            .table("history")
+            .set("updated_at", "updated_at + 1")
+            .column("cwd")
+            .r#where("id")
+            .to_owned();
+
+        assert_eq!(
+            query.to_sql(),
+            String::from(
+                "UPDATE history SET cwd = :cwd, updated_at = updated_at + 1 WHERE id = :w_id"
+            )
+        );
+    }
+
+    #[test]
+    fn test_to_sql_where_op() {
+        for op in ["=", "!=", "<", "<=", ">", ">="] {
+            let query = Query::update()
+                .table("history")
+                .column("cwd")
+                .where_op("updated_at", op, "updated_at")
+                .to_owned();
+            assert_eq!(
+                query.to_sql(),
+                format!("UPDATE history SET cwd = :cwd WHERE updated_at {op} :w_updated_at")
+            );
+        }
+
+        let query = Query::update()
+            .table("history")
+            .column("cwd")
+            .where_op("timestamp", ">=", "after")
+            .where_op("timestamp", "<", "before")
+            .to_owned();
+        assert_eq!(
+            query.to_sql(),
+            String::from(
+                "UPDATE history SET cwd = :cwd WHERE timestamp >= :w_after AND timestamp < :w_before"
+            )
         );
     }
 }
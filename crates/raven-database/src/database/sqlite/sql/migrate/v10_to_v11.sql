@@ -0,0 +1,6 @@
+-- Shell aliases recorded by `raven import zsh --expand-aliases`, so the command behind an alias
+-- can be found.
+CREATE TABLE aliases (
+  name TEXT PRIMARY KEY NOT NULL,
+  expansion TEXT NOT NULL
+);
@@ -0,0 +1,3 @@
+-- How many seconds a command ran for, as recorded by the zsh extended history format. Commands
+-- without a recorded runtime, including every row from before this migration, have -1.
+ALTER TABLE history ADD COLUMN duration INTEGER NOT NULL DEFAULT -1;
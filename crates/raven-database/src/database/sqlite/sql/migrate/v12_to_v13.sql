@@ -0,0 +1,46 @@
+-- Keep the recent_commands cache in sync incrementally instead of rebuilding it from the whole
+-- history, which made bulk deletes scan the history once per cached row deleted.
+DROP TRIGGER history_recent_ad;
+DROP TRIGGER history_recent_au;
+
+-- A cached row is dropped and the newest run of a command which is not cached takes its place,
+-- which may be an older run of the same command. Rows outside the cache never affect it.
+CREATE TRIGGER history_recent_ad AFTER DELETE ON history
+  WHEN EXISTS (SELECT 1 FROM recent_commands WHERE history_id = old.id)
+  BEGIN
+    DELETE FROM recent_commands WHERE history_id = old.id;
+    INSERT INTO recent_commands (command, history_id, timestamp)
+    SELECT command, id, timestamp FROM history
+    WHERE command NOT IN (SELECT command FROM recent_commands)
+    ORDER BY timestamp DESC, id DESC
+    LIMIT 1;
+  END;
+
+-- The old row is removed as by a delete, with the next command filled in only when it was cached,
+-- then the newest run of the new command is added as by an insert. It may not be the updated row,
+-- when that was moved back in time.
+CREATE TRIGGER history_recent_au AFTER UPDATE OF command, timestamp ON history
+  WHEN old.command IS NOT new.command OR old.timestamp IS NOT new.timestamp
+  BEGIN
+    DELETE FROM recent_commands WHERE history_id = old.id;
+    -- changes() is the number of rows just deleted, a LIMIT of 0 skips the scan of history when
+    -- the old row was not cached.
+    INSERT INTO recent_commands (command, history_id, timestamp)
+    SELECT command, id, timestamp FROM history
+    WHERE command NOT IN (SELECT command FROM recent_commands)
+    ORDER BY timestamp DESC, id DESC
+    LIMIT changes();
+    INSERT INTO recent_commands (command, history_id, timestamp)
+    SELECT command, id, timestamp FROM history
+    WHERE command = new.command
+    ORDER BY timestamp DESC, id DESC
+    LIMIT 1
+    ON CONFLICT (command) DO UPDATE
+      SET history_id = excluded.history_id, timestamp = excluded.timestamp
+      WHERE excluded.timestamp > recent_commands.timestamp
+        OR (excluded.timestamp = recent_commands.timestamp
+          AND excluded.history_id > recent_commands.history_id);
+    DELETE FROM recent_commands WHERE command NOT IN (
+      SELECT command FROM recent_commands ORDER BY timestamp DESC, history_id DESC LIMIT 200
+    );
+  END;
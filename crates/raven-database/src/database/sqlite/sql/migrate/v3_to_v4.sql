@@ -0,0 +1,3 @@
+-- Record the shell session each command was run in.
+-- Rows captured before sessions existed (and imported rows) have no session.
+ALTER TABLE history ADD COLUMN session_id TEXT NOT NULL DEFAULT '';
@@ -0,0 +1,3 @@
+-- Track when each row was last written, so stale updates can be detected.
+-- Existing rows start at 0 and are bumped on their next update.
+ALTER TABLE history ADD COLUMN updated_at INTEGER NOT NULL DEFAULT 0;
@@ -0,0 +1,6 @@
+-- Index the columns used to filter searches, so an empty query scoped to a
+-- directory or exit code does not scan the whole history table.
+-- Each index ends with timestamp, which also serves the ORDER BY.
+CREATE INDEX IF NOT EXISTS idx_history_cwd ON history(cwd, timestamp);
+CREATE INDEX IF NOT EXISTS idx_history_exit_code ON history(exit_code, timestamp);
+CREATE INDEX IF NOT EXISTS idx_history_timestamp ON history(timestamp);
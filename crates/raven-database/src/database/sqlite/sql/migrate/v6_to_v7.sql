@@ -0,0 +1,6 @@
+-- Keep a lower case copy of each command, so case-insensitive exact lookups
+-- and deduplication can use an index instead of scanning the history table.
+-- A virtual generated column is always in sync with `command`, and only the
+-- index stores its values. Only ASCII characters are folded by lower().
+ALTER TABLE history ADD COLUMN command_lower TEXT GENERATED ALWAYS AS (lower(command)) VIRTUAL;
+CREATE INDEX IF NOT EXISTS idx_history_command_lower ON history(command_lower);
@@ -0,0 +1,3 @@
+-- The terminal a command was run in, such as /dev/pts/3.
+-- Rows recorded before this migration have an empty tty.
+ALTER TABLE history ADD COLUMN tty TEXT NOT NULL DEFAULT '';
@@ -0,0 +1,3 @@
+-- What recorded a command: raven:<version> when captured by the shell hooks, or
+-- import:<format> when imported. Rows recorded before this migration have an empty source.
+ALTER TABLE history ADD COLUMN source TEXT NOT NULL DEFAULT '';
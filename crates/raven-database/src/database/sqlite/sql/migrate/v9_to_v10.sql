@@ -0,0 +1,68 @@
+-- The last 200 distinct commands run, newest first, so the up-key can list them without
+-- querying the whole history. Each command points at the newest history row it was run as.
+-- Kept in sync with history by the triggers below.
+CREATE TABLE recent_commands (
+  command TEXT PRIMARY KEY NOT NULL,
+  history_id INTEGER NOT NULL,
+  timestamp INTEGER NOT NULL
+);
+
+CREATE INDEX idx_recent_commands_timestamp ON recent_commands (timestamp);
+
+INSERT INTO recent_commands (command, history_id, timestamp)
+SELECT command, id, timestamp FROM (
+  SELECT command, id, timestamp,
+    row_number() OVER (PARTITION BY command ORDER BY timestamp DESC, id DESC) AS run
+  FROM history
+)
+WHERE run = 1
+ORDER BY timestamp DESC, id DESC
+LIMIT 200;
+
+-- A new row becomes the command's newest run unless an even newer one is cached, then anything
+-- pushed past the limit is dropped.
+CREATE TRIGGER history_recent_ai AFTER INSERT ON history
+  BEGIN
+    INSERT INTO recent_commands (command, history_id, timestamp)
+    VALUES (new.command, new.id, new.timestamp)
+    ON CONFLICT (command) DO UPDATE
+      SET history_id = excluded.history_id, timestamp = excluded.timestamp
+      WHERE excluded.timestamp > recent_commands.timestamp
+        OR (excluded.timestamp = recent_commands.timestamp
+          AND excluded.history_id > recent_commands.history_id);
+    DELETE FROM recent_commands WHERE command NOT IN (
+      SELECT command FROM recent_commands ORDER BY timestamp DESC, history_id DESC LIMIT 200
+    );
+  END;
+
+-- Removing or changing a cached row can promote any older command back into the cache, so the
+-- cache is rebuilt. Rows outside the cache never affect it.
+CREATE TRIGGER history_recent_ad AFTER DELETE ON history
+  WHEN EXISTS (SELECT 1 FROM recent_commands WHERE history_id = old.id)
+  BEGIN
+    DELETE FROM recent_commands;
+    INSERT INTO recent_commands (command, history_id, timestamp)
+    SELECT command, id, timestamp FROM (
+      SELECT command, id, timestamp,
+        row_number() OVER (PARTITION BY command ORDER BY timestamp DESC, id DESC) AS run
+      FROM history
+    )
+    WHERE run = 1
+    ORDER BY timestamp DESC, id DESC
+    LIMIT 200;
+  END;
+
+CREATE TRIGGER history_recent_au AFTER UPDATE OF command, timestamp ON history
+  WHEN old.command IS NOT new.command OR old.timestamp IS NOT new.timestamp
+  BEGIN
+    DELETE FROM recent_commands;
+    INSERT INTO recent_commands (command, history_id, timestamp)
+    SELECT command, id, timestamp FROM (
+      SELECT command, id, timestamp,
+        row_number() OVER (PARTITION BY command ORDER BY timestamp DESC, id DESC) AS run
+      FROM history
+    )
+    WHERE run = 1
+    ORDER BY timestamp DESC, id DESC
+    LIMIT 200;
+  END;
@@ -0,0 +1,162 @@
+//! Export of stored history to JSON, JSON Lines or CSV.
+//!
+//! JSON and CSV rows have the `id`, `command`, `cwd`, `exit`, `session_id`, `timestamp` (RFC 3339,
+//! in UTC) and `tty` fields. JSON Lines is written as by `raven convert`, so it can be imported
+//! again.
+use std::io::{self, Write};
+
+use clap::ValueEnum;
+use serde::Serialize;
+use time::{UtcOffset, format_description::well_known::Rfc3339};
+
+use crate::{history::model::History, import::jsonl::Record};
+
+/// The formats history can be exported to.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub enum ExportFormat {
+    /// A single JSON array of objects.
+    Json,
+    /// One JSON object per line, importable with `raven import jsonl`.
+    Jsonl,
+    /// Comma separated values with a header row.
+    Csv,
+}
+
+/// The fields of an exported JSON object or CSV row.
+#[derive(Debug, Serialize)]
+struct Row<'a> {
+    id: i64,
+    command: &'a str,
+    cwd: &'a str,
+    exit: i64,
+    session_id: &'a str,
+    timestamp: String,
+    tty: &'a str,
+}
+
+impl<'a> From<&'a History> for Row<'a> {
+    fn from(h: &'a History) -> Self {
+        Self {
+            id: h.id,
+            command: &h.command,
+            cwd: &h.cwd,
+            exit: h.exit_code,
+            session_id: &h.session_id,
+            timestamp: rfc3339(h),
+            tty: &h.tty,
+        }
+    }
+}
+
+/// The time `h` was run in RFC 3339, or in unix seconds for years RFC 3339 cannot represent.
+fn rfc3339(h: &History) -> String {
+    h.timestamp
+        .to_offset(UtcOffset::UTC)
+        .format(&Rfc3339)
+        .unwrap_or_else(|_| h.timestamp.unix_timestamp().to_string())
+}
+
+/// The CSV header, the `Row` fields in order.
+const CSV_HEADER: &str = "id,command,cwd,exit,session_id,timestamp,tty";
+
+/// Writes `History` rows to a writer one at a time, so exports never hold every row in memory.
+pub struct Exporter<'w> {
+    format: ExportFormat,
+    writer: &'w mut dyn Write,
+    /// The number of rows written so far.
+    pub count: usize,
+}
+
+impl<'w> Exporter<'w> {
+    /// Start an export, writing anything which comes before the first row.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the writer fails.
+    pub fn new(format: ExportFormat, writer: &'w mut dyn Write) -> io::Result<Self> {
+        match format {
+            ExportFormat::Json => write!(writer, "[")?,
+            ExportFormat::Csv => writeln!(writer, "{CSV_HEADER}")?,
+            ExportFormat::Jsonl => {}
+        }
+        Ok(Self {
+            format,
+            writer,
+            count: 0,
+        })
+    }
+
+    /// Write a single row.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the writer fails.
+    pub fn push(&mut self, h: &History) -> io::Result<()> {
+        match self.format {
+            ExportFormat::Json => {
+                let separator = if self.count == 0 { "\n" } else { ",\n" };
+                write!(self.writer, "{separator}")?;
+                serde_json::to_writer(&mut self.writer, &Row::from(h))?;
+            }
+            ExportFormat::Jsonl => {
+                serde_json::to_writer(&mut self.writer, &Record::from(h))?;
+                writeln!(self.writer)?;
+            }
+            ExportFormat::Csv => {
+                let row = Row::from(h);
+                writeln!(
+                    self.writer,
+                    "{},{},{},{},{},{},{}",
+                    row.id,
+                    csv_field(row.command),
+                    csv_field(row.cwd),
+                    row.exit,
+                    csv_field(row.session_id),
+                    row.timestamp,
+                    csv_field(row.tty),
+                )?;
+            }
+        }
+        self.count += 1;
+        Ok(())
+    }
+
+    /// Write anything which comes after the last row and flush the writer.
+    ///
+    /// Returns the number of rows written.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the writer fails.
+    pub fn finish(self) -> io::Result<usize> {
+        if self.format == ExportFormat::Json {
+            let end = if self.count == 0 { "]" } else { "\n]" };
+            writeln!(self.writer, "{end}")?;
+        }
+        self.writer.flush()?;
+        Ok(self.count)
+    }
+}
+
+/// Quote `value` as a CSV field if it contains a comma, quote or line break.
+fn csv_field(value: &str) -> String {
+    if value.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_csv_field() {
+        assert_eq!(csv_field("ls -la"), "ls -la");
+        assert_eq!(csv_field("echo a,b"), "\"echo a,b\"");
+        assert_eq!(csv_field("echo \"hi\""), "\"echo \"\"hi\"\"\"");
+        assert_eq!(csv_field("for i\ndone"), "\"for i\ndone\"");
+        assert_eq!(csv_field(""), "");
+    }
+}
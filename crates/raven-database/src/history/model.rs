@@ -1,6 +1,18 @@
 use time::OffsetDateTime;
 use typed_builder::TypedBuilder;
 
+/// The exit code of a command which is still running, or was imported without one.
+pub const EXIT_UNKNOWN: i64 = -1;
+
+/// The duration of a command whose runtime was not recorded.
+pub const DURATION_UNKNOWN: i64 = -1;
+
+/// The cwd of imported commands, history files do not record where a command was run.
+pub const IMPORTED_CWD: &str = "unknown";
+
+/// The source of commands captured by the shell hooks, the raven version which recorded them.
+pub const CAPTURED_SOURCE: &str = concat!("raven:", env!("CARGO_PKG_VERSION"));
+
 #[derive(Debug, Clone, PartialEq, Eq, TypedBuilder)]
 /// Represents a full row for a history record in the database.
 ///
@@ -9,6 +21,12 @@ use typed_builder::TypedBuilder;
 /// * `command`: plain-text command that was run
 /// * `cwd`: plain-text working directory
 /// * `exit_code`: the exit code of the command or -1 if not set
+/// * `session_id`: identifier of the shell session the command was run in, or empty if unknown
+/// * `tty`: the terminal the command was run in, or empty if unknown
+/// * `source`: what recorded the command, [`CAPTURED_SOURCE`] or `import:<format>`, or empty if
+///   it was recorded before sources were
+/// * `duration`: seconds the command ran for, or -1 if not recorded
+/// * `updated_at`: unix timestamp in milliseconds of the last update, or 0 if never updated
 pub struct History {
     pub id: i64,
 
@@ -19,16 +37,44 @@ pub struct History {
     pub cwd: String,
 
     pub exit_code: i64,
+
+    #[builder(default)]
+    pub session_id: String,
+
+    #[builder(default)]
+    pub tty: String,
+
+    #[builder(default)]
+    pub source: String,
+
+    #[builder(default = DURATION_UNKNOWN)]
+    pub duration: i64,
+
+    #[builder(default)]
+    pub updated_at: i64,
 }
 
 impl History {
-    fn new(timestamp: OffsetDateTime, command: String, cwd: String, exit_code: i64) -> Self {
+    fn new(
+        timestamp: OffsetDateTime,
+        command: String,
+        cwd: String,
+        exit_code: i64,
+        session_id: String,
+        tty: String,
+        source: String,
+    ) -> Self {
         Self {
             id: -1,
             timestamp,
             command,
             cwd,
             exit_code,
+            session_id,
+            tty,
+            source,
+            duration: DURATION_UNKNOWN,
+            updated_at: 0,
         }
     }
 
@@ -48,6 +94,8 @@ impl History {
 /// * `timestamp`: unix timestamp (since epoc, utc) when the command was run
 /// * `command`: plain-text command that was run
 /// * `cwd`: plain-text working directory
+/// * `session_id`: identifier of the shell session, empty if not known
+/// * `tty`: the terminal the command was run in, empty if not known
 pub struct HistoryCaptured {
     timestamp: OffsetDateTime,
 
@@ -56,11 +104,25 @@ pub struct HistoryCaptured {
 
     #[builder(setter(into))]
     cwd: String,
+
+    #[builder(default, setter(into))]
+    session_id: String,
+
+    #[builder(default, setter(into))]
+    tty: String,
 }
 
 impl From<HistoryCaptured> for History {
     fn from(captured: HistoryCaptured) -> Self {
-        History::new(captured.timestamp, captured.command, captured.cwd, -1)
+        History::new(
+            captured.timestamp,
+            captured.command,
+            captured.cwd,
+            EXIT_UNKNOWN,
+            captured.session_id,
+            captured.tty,
+            String::from(CAPTURED_SOURCE),
+        )
     }
 }
 
@@ -69,20 +131,67 @@ impl From<HistoryCaptured> for History {
 ///
 /// * `timestamp`: unix timestamp (since epoc, utc) when the command was run
 /// * `command`: plain-text command that was run
+/// * `source`: the importer, stored as `import:<source>`
+/// * `duration`: seconds the command ran for, if the history file records it
 pub struct HistoryImported {
     timestamp: OffsetDateTime,
 
     #[builder(setter(into))]
     command: String,
+
+    #[builder(setter(into))]
+    source: String,
+
+    #[builder(default = DURATION_UNKNOWN)]
+    duration: i64,
 }
 
 impl From<HistoryImported> for History {
     fn from(imported: HistoryImported) -> Self {
-        History::new(
-            imported.timestamp,
-            imported.command,
-            String::from("unknown"),
-            -1,
-        )
+        History {
+            duration: imported.duration,
+            ..History::new(
+                imported.timestamp,
+                imported.command,
+                String::from(IMPORTED_CWD),
+                EXIT_UNKNOWN,
+                String::new(),
+                String::new(),
+                format!("import:{}", imported.source),
+            )
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, TypedBuilder)]
+/// A partial update of a history record, fields left as `None` are not changed.
+///
+/// * `timestamp`: unix timestamp (since epoc, utc) when the command was run
+/// * `command`: plain-text command that was run
+/// * `cwd`: plain-text working directory
+/// * `exit_code`: the exit code of the command
+/// * `session_id`: identifier of the shell session the command was run in
+pub struct HistoryUpdate {
+    #[builder(default, setter(strip_option))]
+    pub timestamp: Option<OffsetDateTime>,
+
+    #[builder(default, setter(strip_option, into))]
+    pub command: Option<String>,
+
+    #[builder(default, setter(strip_option, into))]
+    pub cwd: Option<String>,
+
+    #[builder(default, setter(strip_option))]
+    pub exit_code: Option<i64>,
+
+    #[builder(default, setter(strip_option, into))]
+    pub session_id: Option<String>,
+}
+
+impl HistoryUpdate {
+    /// Whether the update does not change any field.
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        *self == Self::default()
     }
 }
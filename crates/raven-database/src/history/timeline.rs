@@ -0,0 +1,241 @@
+//! Grouping of history by when it ran, for timeline and stats views.
+use std::collections::HashMap;
+
+use raven_common::utils::canonical_command;
+use time::{Date, UtcOffset};
+
+use super::model::History;
+
+/// Group `history` by the calendar day each command ran on, as seen from `offset`.
+///
+/// Days are returned in the order they first appear, and commands keep their order within a
+/// day, so results ordered most recent first stay that way.
+///
+/// * `history`: The history to group, usually the result of a search.
+/// * `offset`: The UTC offset of the timezone the days are in.
+#[must_use]
+pub fn group_by_day(history: Vec<History>, offset: UtcOffset) -> Vec<(Date, Vec<History>)> {
+    let mut days: Vec<(Date, Vec<History>)> = Vec::new();
+    let mut index: HashMap<Date, usize> = HashMap::new();
+
+    for h in history {
+        let date = h.timestamp.to_offset(offset).date();
+        let idx = *index.entry(date).or_insert_with(|| {
+            days.push((date, Vec::new()));
+            days.len() - 1
+        });
+        days[idx].1.push(h);
+    }
+
+    days
+}
+
+/// Command counts bucketed by the hour of the day and the day of the week they ran on.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Activity {
+    /// Counts for the hours 0 to 23.
+    pub by_hour: [usize; 24],
+    /// Counts for the days Monday to Sunday.
+    pub by_weekday: [usize; 7],
+}
+
+impl Activity {
+    /// Count a single command, in local time as seen from `offset`.
+    ///
+    /// * `h`: The command to count.
+    /// * `offset`: The UTC offset of the timezone at the time the command ran, which differs
+    ///   between commands on either side of a daylight saving change.
+    pub fn push(&mut self, h: &History, offset: UtcOffset) {
+        let local = h.timestamp.to_offset(offset);
+        self.by_hour[usize::from(local.hour())] += 1;
+        self.by_weekday[usize::from(local.weekday().number_days_from_monday())] += 1;
+    }
+}
+
+/// Command counts grouped by their `canonical_command`, for the most run commands.
+///
+/// Each group is shown as the first command counted in it, the most recent run when the history
+/// is pushed most recent first.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct CommandCounts {
+    strip_sudo: bool,
+    counts: HashMap<String, (String, usize)>,
+}
+
+impl CommandCounts {
+    /// No commands counted yet.
+    ///
+    /// * `strip_sudo`: Group commands with and without a leading `sudo `, see `Config::strip_sudo`.
+    #[must_use]
+    pub fn new(strip_sudo: bool) -> Self {
+        Self {
+            strip_sudo,
+            counts: HashMap::new(),
+        }
+    }
+
+    /// Count a single command.
+    pub fn push(&mut self, h: &History) {
+        self.counts
+            .entry(canonical_command(&h.command, self.strip_sudo))
+            .or_insert_with(|| (h.command.clone(), 0))
+            .1 += 1;
+    }
+
+    /// The `n` most run commands and their counts, ties ordered by command.
+    #[must_use]
+    pub fn top(&self, n: usize) -> Vec<(&str, usize)> {
+        let mut top: Vec<(&str, usize)> = self
+            .counts
+            .values()
+            .map(|(command, count)| (command.as_str(), *count))
+            .collect();
+        top.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(b.0)));
+        top.truncate(n);
+        top
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use time::{Month, OffsetDateTime};
+
+    fn at(timestamp: i64, command: &str) -> History {
+        History::builder()
+            .id(timestamp)
+            .command(command.to_string())
+            .cwd("/tmp".to_string())
+            .exit_code(0)
+            .timestamp(OffsetDateTime::from_unix_timestamp(timestamp).unwrap())
+            .session_id("session".to_string())
+            .build()
+    }
+
+    fn commands(days: &[(Date, Vec<History>)]) -> Vec<(u8, Vec<&str>)> {
+        days.iter()
+            .map(|(date, history)| {
+                (
+                    date.day(),
+                    history.iter().map(|h| h.command.as_str()).collect(),
+                )
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_group_by_day() {
+        // 2023-11-14 21:00, 22:30 and 2023-11-15 01:00 UTC, most recent first.
+        let history = vec![
+            at(1_700_010_000, "make"),
+            at(1_700_001_000, "ls"),
+            at(1_699_995_600, "cd"),
+        ];
+
+        let utc = group_by_day(history.clone(), UtcOffset::UTC);
+        assert_eq!(
+            commands(&utc),
+            vec![(15, vec!["make"]), (14, vec!["ls", "cd"])]
+        );
+        assert_eq!(utc[0].0.month(), Month::November);
+
+        // Two hours ahead, 22:30 UTC is already the next day.
+        let ahead = group_by_day(history.clone(), UtcOffset::from_hms(2, 0, 0).unwrap());
+        assert_eq!(
+            commands(&ahead),
+            vec![(15, vec!["make", "ls"]), (14, vec!["cd"])]
+        );
+
+        // Five hours behind, every command ran on the 14th.
+        let behind = group_by_day(history, UtcOffset::from_hms(-5, 0, 0).unwrap());
+        assert_eq!(commands(&behind), vec![(14, vec!["make", "ls", "cd"])]);
+
+        assert!(group_by_day(Vec::new(), UtcOffset::UTC).is_empty());
+    }
+
+    #[test]
+    fn test_activity() {
+        // 2023-11-13 is a Monday.
+        let monday = 1_699_833_600;
+        let history = [
+            at(monday + 3_600, "one am"),
+            at(monday + 3_600 + 59 * 60, "still one am"),
+            at(monday + 23 * 3_600 + 30 * 60, "half eleven"),
+            at(monday + 2 * 86_400 + 12 * 3_600, "wednesday noon"),
+            at(monday + 6 * 86_400 + 22 * 3_600, "sunday night"),
+        ];
+
+        let mut utc = Activity::default();
+        history.iter().for_each(|h| utc.push(h, UtcOffset::UTC));
+        assert_eq!(utc.by_hour[1], 2);
+        assert_eq!(utc.by_hour[12], 1);
+        assert_eq!(utc.by_hour[22], 1);
+        assert_eq!(utc.by_hour[23], 1);
+        assert_eq!(utc.by_hour.iter().sum::<usize>(), 5);
+        assert_eq!(utc.by_weekday, [3, 0, 1, 0, 0, 0, 1]);
+
+        // Two hours ahead, late Monday rolls over into Tuesday and late Sunday into Monday.
+        let mut ahead = Activity::default();
+        let two_ahead = UtcOffset::from_hms(2, 0, 0).unwrap();
+        history.iter().for_each(|h| ahead.push(h, two_ahead));
+        assert_eq!(ahead.by_hour[3], 2);
+        assert_eq!(ahead.by_hour[1], 1);
+        assert_eq!(ahead.by_hour[14], 1);
+        assert_eq!(ahead.by_hour[0], 1);
+        assert_eq!(ahead.by_weekday, [3, 1, 1, 0, 0, 0, 0]);
+
+        // Five hours behind, the early Monday commands move back to Sunday evening.
+        let mut behind = Activity::default();
+        let five_behind = UtcOffset::from_hms(-5, 0, 0).unwrap();
+        history.iter().for_each(|h| behind.push(h, five_behind));
+        assert_eq!(behind.by_hour[20], 2);
+        assert_eq!(behind.by_hour[18], 1);
+        assert_eq!(behind.by_weekday, [1, 0, 1, 0, 0, 0, 3]);
+
+        // Across a daylight saving change, 09:00 local is 08:00 UTC in winter and 07:00 UTC in
+        // summer, and both count towards the same hour.
+        let winter = UtcOffset::from_hms(1, 0, 0).unwrap();
+        let summer = UtcOffset::from_hms(2, 0, 0).unwrap();
+        let mut dst = Activity::default();
+        dst.push(&at(monday + 8 * 3_600, "winter morning"), winter);
+        dst.push(
+            &at(monday + 180 * 86_400 + 7 * 3_600, "summer morning"),
+            summer,
+        );
+        assert_eq!(dst.by_hour[9], 2);
+        assert_eq!(dst.by_hour.iter().sum::<usize>(), 2);
+    }
+
+    #[test]
+    fn test_command_counts() {
+        // Most recent first, as searches return them.
+        let history = [
+            at(6, "sudo apt  update"),
+            at(5, "ls"),
+            at(4, "apt update "),
+            at(3, "git status"),
+            at(2, "ls "),
+            at(1, "sudo apt update"),
+        ];
+
+        let mut counts = CommandCounts::new(false);
+        history.iter().for_each(|h| counts.push(h));
+        assert_eq!(
+            counts.top(10),
+            vec![
+                ("ls", 2),
+                ("sudo apt  update", 2),
+                ("apt update ", 1),
+                ("git status", 1)
+            ]
+        );
+        assert_eq!(counts.top(1), vec![("ls", 2)]);
+
+        let mut stripped = CommandCounts::new(true);
+        history.iter().for_each(|h| stripped.push(h));
+        assert_eq!(
+            stripped.top(10),
+            vec![("sudo apt  update", 3), ("ls", 2), ("git status", 1)]
+        );
+    }
+}
@@ -0,0 +1,152 @@
+//! Parsing of shell alias definitions, as printed by `alias` or written in a shell rc file.
+//!
+//! Each line is `name=value` or `alias name=value`, with the value optionally quoted in single or
+//! double quotes. Any other line, such as a comment or a function, is skipped.
+use std::{
+    fs::File,
+    io::{BufRead, BufReader},
+    path::Path,
+};
+
+use super::{ImportError, read_lines};
+
+/// A shell alias and the command it expands to.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Alias {
+    pub name: String,
+    pub expansion: String,
+}
+
+/// Read every alias defined in the file at `path`.
+///
+/// # Errors
+///
+/// This function will return an error if the file can not be read.
+pub fn read_aliases(path: &Path) -> Result<Vec<Alias>, ImportError> {
+    parse_aliases(BufReader::new(File::open(path)?))
+}
+
+/// Parse every alias defined in `reader`, skipping lines which do not define one.
+///
+/// # Errors
+///
+/// This function will return an error if reading fails.
+pub fn parse_aliases(reader: impl BufRead) -> Result<Vec<Alias>, ImportError> {
+    let mut aliases = Vec::new();
+    for line in read_lines(reader) {
+        if let Some(alias) = parse_alias(&line?) {
+            aliases.push(alias);
+        }
+    }
+    Ok(aliases)
+}
+
+/// Parse a single `[alias [-flags]] name=value` line.
+fn parse_alias(line: &str) -> Option<Alias> {
+    let mut line = line.trim();
+    if let Some(rest) = line.strip_prefix("alias ") {
+        line = rest.trim_start();
+        // zsh flags such as `-g` for global aliases, which expand anywhere in a command.
+        while let Some(rest) = line.strip_prefix('-') {
+            line = rest.split_once(' ')?.1.trim_start();
+        }
+    }
+    let (name, value) = line.split_once('=')?;
+    if name.is_empty() || name.contains(char::is_whitespace) || name.starts_with('#') {
+        return None;
+    }
+    let expansion = unquote(value)?;
+    if expansion.is_empty() {
+        return None;
+    }
+    Some(Alias {
+        name: name.to_string(),
+        expansion,
+    })
+}
+
+/// Remove the shell quoting from an alias value.
+///
+/// Handles the `'\''` escape `alias` prints for single quotes within a single quoted value, and
+/// backslash escapes in double quoted ones. Returns `None` if a quote is left open.
+fn unquote(value: &str) -> Option<String> {
+    let mut unquoted = String::with_capacity(value.len());
+    let mut chars = value.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\'' => loop {
+                match chars.next()? {
+                    '\'' => break,
+                    c => unquoted.push(c),
+                }
+            },
+            '"' => loop {
+                match chars.next()? {
+                    '"' => break,
+                    '\\' => unquoted.push(chars.next()?),
+                    c => unquoted.push(c),
+                }
+            },
+            '\\' => unquoted.push(chars.next()?),
+            c => unquoted.push(c),
+        }
+    }
+    Some(unquoted)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn alias(name: &str, expansion: &str) -> Option<Alias> {
+        Some(Alias {
+            name: name.to_string(),
+            expansion: expansion.to_string(),
+        })
+    }
+
+    #[test]
+    fn test_parse_alias() {
+        // As printed by `alias` in zsh and bash.
+        assert_eq!(parse_alias("gst='git status'"), alias("gst", "git status"));
+        assert_eq!(parse_alias("ll=ls"), alias("ll", "ls"));
+        assert_eq!(parse_alias("alias la='ls -A'"), alias("la", "ls -A"));
+        // As written in an rc file.
+        assert_eq!(
+            parse_alias("  alias gco=\"git checkout\""),
+            alias("gco", "git checkout")
+        );
+        assert_eq!(parse_alias("alias -g G='| grep'"), alias("G", "| grep"));
+        assert_eq!(
+            parse_alias(r#"say='echo '\''hi'\'''"#),
+            alias("say", "echo 'hi'")
+        );
+        assert_eq!(
+            parse_alias(r#"q="echo \"quoted\"""#),
+            alias("q", "echo \"quoted\"")
+        );
+    }
+
+    #[test]
+    fn test_parse_alias_skips_other_lines() {
+        assert_eq!(parse_alias(""), None);
+        assert_eq!(parse_alias("# gst='git status'"), None);
+        assert_eq!(parse_alias("export PATH=/usr/bin"), None);
+        assert_eq!(parse_alias("unterminated='git status"), None);
+        assert_eq!(parse_alias("empty=''"), None);
+        assert_eq!(parse_alias("gst() { git status }"), None);
+    }
+
+    #[test]
+    fn test_parse_aliases() {
+        let file = "# aliases\ngst='git status'\n\nalias ll='ls -l'\r\nnot an alias\n";
+        let aliases = parse_aliases(file.as_bytes()).expect("Failed to parse aliases");
+        assert_eq!(
+            aliases,
+            [
+                alias("gst", "git status").unwrap(),
+                alias("ll", "ls -l").unwrap()
+            ]
+        );
+    }
+}
@@ -0,0 +1,355 @@
+//! Import of bash history files, with or without `HISTTIMEFORMAT` timestamps.
+//!
+//! When `HISTTIMEFORMAT` is set, bash writes a `#<unix seconds>` line before each command.
+use std::{env, fs::File, io::BufReader, path::PathBuf};
+
+use time::{Duration, OffsetDateTime};
+
+use super::{ImportError, Importer, Loader, modified_at, read_lines};
+use crate::{Clock, history::model::History};
+
+#[derive(Debug)]
+pub struct Bash {
+    histpath: PathBuf,
+    /// The time commands without a timestamp are counted back from, instead of when the history
+    /// file was last written.
+    clock: Option<Clock>,
+    /// Commands with a timestamp before this are skipped.
+    since: Option<OffsetDateTime>,
+}
+
+/// Represents the parsed type of a single line from the history file.
+#[derive(Debug, PartialEq, Eq)]
+enum ParsedLine {
+    /// A `#<unix seconds>` line, the time the next command was run.
+    Timestamp(OffsetDateTime),
+    /// A command, including comments such as `# todo` which only look like timestamps.
+    Command(String),
+    /// An empty or whitespace-only line.
+    Empty,
+}
+
+impl Bash {
+    /// Import the history file at `histpath` instead of the one found in `$HOME`.
+    #[must_use]
+    pub fn from_path(histpath: PathBuf) -> Self {
+        Self {
+            histpath,
+            clock: None,
+            since: None,
+        }
+    }
+
+    /// Count commands without a timestamp back a second at a time from the time `clock` reads,
+    /// instead of from when the history file was last written.
+    #[must_use]
+    pub fn with_clock(mut self, clock: Clock) -> Self {
+        self.clock = Some(clock);
+        self
+    }
+
+    /// Skip commands run before `since`.
+    ///
+    /// Only commands written with `HISTTIMEFORMAT` set have a timestamp to go by, the others are
+    /// always imported.
+    #[must_use]
+    pub fn with_since(mut self, since: OffsetDateTime) -> Self {
+        self.since = Some(since);
+        self
+    }
+
+    fn default_histpath() -> Result<PathBuf, ImportError> {
+        let Ok(home_dir) = env::var("HOME") else {
+            eprintln!("Error: $HOME is not set, cannot locate home directory");
+            return Err(ImportError);
+        };
+
+        let histpath = PathBuf::from(home_dir).join(".bash_history");
+        if !histpath.exists() {
+            eprintln!("Error: Could not find {}", histpath.display());
+            return Err(ImportError);
+        }
+        eprintln!("Found histfile at {}", histpath.display());
+        Ok(histpath)
+    }
+
+    /// Classifies a line of the history file.
+    ///
+    /// Only a `#` followed by nothing but digits is a timestamp, any other line starting with
+    /// `#` is a command, as bash records comments typed at the prompt.
+    fn classify_line(line_text: &str) -> ParsedLine {
+        let trimmed_line = line_text.trim_end();
+        if trimmed_line.trim_start().is_empty() {
+            return ParsedLine::Empty;
+        }
+
+        if let Some(digits) = trimmed_line.strip_prefix('#')
+            && !digits.is_empty()
+            && digits.bytes().all(|b| b.is_ascii_digit())
+            && let Ok(ts_val) = digits.parse::<i64>()
+            && let Ok(timestamp) = OffsetDateTime::from_unix_timestamp(ts_val)
+        {
+            return ParsedLine::Timestamp(timestamp);
+        }
+
+        ParsedLine::Command(trimmed_line.to_string())
+    }
+}
+
+impl Importer for Bash {
+    const NAME: &'static str = "bash";
+
+    fn new() -> Result<Self, ImportError> {
+        Ok(Self {
+            histpath: Bash::default_histpath()?,
+            clock: None,
+            since: None,
+        })
+    }
+
+    fn load(self, loader: &mut impl Loader) -> Result<(), ImportError> {
+        let file = File::open(&self.histpath)?;
+        let now = self
+            .clock
+            .map_or_else(|| modified_at(&file), |clock| clock());
+        let reader = BufReader::new(file);
+
+        let mut non_timestamped_offset_seconds: i64 = 0;
+        // The timestamp of the next command, only ever applied to the command right after it.
+        let mut pending_timestamp: Option<OffsetDateTime> = None;
+
+        for read_line_result in read_lines(reader) {
+            let line_text = match read_line_result {
+                Ok(l) => l,
+                Err(e) => {
+                    eprintln!("Warning: Error reading line from history file: {e}");
+                    continue;
+                }
+            };
+
+            match Bash::classify_line(&line_text) {
+                ParsedLine::Timestamp(timestamp) => {
+                    if let Some(skipped) = pending_timestamp {
+                        eprintln!(
+                            "Warning: Timestamp {} has no command, ignoring it",
+                            skipped.unix_timestamp()
+                        );
+                    }
+                    pending_timestamp = Some(timestamp);
+                }
+                ParsedLine::Command(command) => {
+                    if let Some(timestamp) = pending_timestamp
+                        && self.since.is_some_and(|since| timestamp < since)
+                    {
+                        pending_timestamp = None;
+                        continue;
+                    }
+                    let timestamp = pending_timestamp.take().unwrap_or_else(|| {
+                        let ts = now - Duration::seconds(non_timestamped_offset_seconds);
+                        non_timestamped_offset_seconds += 1;
+                        ts
+                    });
+                    let imported = History::import()
+                        .command(command)
+                        .timestamp(timestamp)
+                        .source(Self::NAME)
+                        .build();
+                    loader.push(imported.into()).map_err(|_| ImportError)?;
+                }
+                ParsedLine::Empty => {}
+            }
+        }
+
+        if let Some(skipped) = pending_timestamp {
+            eprintln!(
+                "Warning: History file ends with timestamp {} without a command, ignoring it",
+                skipped.unix_timestamp()
+            );
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::import::LoadError;
+    use std::io::Write;
+    use tempfile::NamedTempFile;
+
+    struct MockLoader {
+        history: Vec<History>,
+    }
+
+    impl Loader for MockLoader {
+        fn push(&mut self, hist: History) -> Result<(), LoadError> {
+            self.history.push(hist);
+            Ok(())
+        }
+    }
+
+    fn run_importer_with_content(content: &str) -> Vec<History> {
+        let mut temp_file = NamedTempFile::new().expect("Failed to create temp file");
+        write!(temp_file, "{content}").expect("Failed to write to temp file");
+        temp_file.flush().expect("Failed to flush temp file");
+
+        let mut loader = MockLoader {
+            history: Vec::new(),
+        };
+        Bash::from_path(temp_file.path().to_path_buf())
+            .load(&mut loader)
+            .expect("Bash import failed");
+        loader.history
+    }
+
+    fn at(timestamp: i64) -> OffsetDateTime {
+        OffsetDateTime::from_unix_timestamp(timestamp).unwrap()
+    }
+
+    fn is_now(timestamp: OffsetDateTime) -> bool {
+        (OffsetDateTime::now_utc() - timestamp).abs() < Duration::seconds(5)
+    }
+
+    #[test]
+    fn test_load_timestamped() {
+        let history = run_importer_with_content("#1700000000\nls -l\n#1700000010\ncd /tmp\n");
+
+        assert_eq!(history.len(), 2, "Should import 2 commands");
+        assert_eq!(history[0].command, "ls -l");
+        assert_eq!(history[0].timestamp, at(1_700_000_000));
+        assert_eq!(history[1].command, "cd /tmp");
+        assert_eq!(history[1].timestamp, at(1_700_000_010));
+    }
+
+    #[test]
+    fn test_load_with_clock() {
+        let mut temp_file = NamedTempFile::new().expect("Failed to create temp file");
+        write!(temp_file, "ls\n#1600000000\ncd /tmp\npwd\n").expect("Failed to write");
+        temp_file.flush().expect("Failed to flush temp file");
+
+        let mut loader = MockLoader {
+            history: Vec::new(),
+        };
+        Bash::from_path(temp_file.path().to_path_buf())
+            .with_clock(|| at(1_700_000_000))
+            .load(&mut loader)
+            .expect("Bash import failed");
+
+        let timestamps: Vec<OffsetDateTime> = loader.history.iter().map(|h| h.timestamp).collect();
+        assert_eq!(
+            timestamps,
+            [at(1_700_000_000), at(1_600_000_000), at(1_699_999_999)]
+        );
+    }
+
+    #[test]
+    fn test_load_from_modified() {
+        let mut temp_file = NamedTempFile::new().expect("Failed to create temp file");
+        write!(temp_file, "ls\npwd\n").expect("Failed to write");
+        temp_file.flush().expect("Failed to flush temp file");
+        temp_file
+            .as_file()
+            .set_modified(at(1_700_000_000).into())
+            .expect("Failed to set the modification time");
+
+        let mut loader = MockLoader {
+            history: Vec::new(),
+        };
+        Bash::from_path(temp_file.path().to_path_buf())
+            .load(&mut loader)
+            .expect("Bash import failed");
+
+        // Without a clock, commands are counted back from when the file was last written.
+        let timestamps: Vec<OffsetDateTime> = loader.history.iter().map(|h| h.timestamp).collect();
+        assert_eq!(timestamps, [at(1_700_000_000), at(1_699_999_999)]);
+    }
+
+    #[test]
+    fn test_load_without_timestamps() {
+        let history = run_importer_with_content("echo hello\n\n  \npwd");
+
+        assert_eq!(history.len(), 2, "Should import 2 commands");
+        assert_eq!(history[0].command, "echo hello");
+        assert!(is_now(history[0].timestamp));
+        assert_eq!(history[1].command, "pwd");
+        assert_eq!(
+            history[0].timestamp - history[1].timestamp,
+            Duration::seconds(1)
+        );
+    }
+
+    #[test]
+    fn test_load_consecutive_timestamps() {
+        // Only the timestamp right before a command belongs to it.
+        let history =
+            run_importer_with_content("#1700000000\n#1700000005\n#1700000008\nmake\nls\n");
+
+        assert_eq!(history.len(), 2, "Should import 2 commands");
+        assert_eq!(history[0].command, "make");
+        assert_eq!(history[0].timestamp, at(1_700_000_008));
+        // Timestamps are never carried over to later commands.
+        assert_eq!(history[1].command, "ls");
+        assert!(is_now(history[1].timestamp));
+    }
+
+    #[test]
+    fn test_load_commands_containing_hash() {
+        let content = "#1700000000\necho a#b\n# todo: rerun\n#1700000010\nls # list\n#12ab\n#\n";
+        let history = run_importer_with_content(content);
+
+        let commands = history
+            .iter()
+            .map(|h| h.command.as_str())
+            .collect::<Vec<&str>>();
+        assert_eq!(
+            commands,
+            vec!["echo a#b", "# todo: rerun", "ls # list", "#12ab", "#"]
+        );
+        assert_eq!(history[0].timestamp, at(1_700_000_000));
+        assert!(is_now(history[1].timestamp));
+        assert_eq!(history[2].timestamp, at(1_700_000_010));
+        assert!(is_now(history[3].timestamp));
+        assert!(is_now(history[4].timestamp));
+    }
+
+    #[test]
+    fn test_load_trailing_timestamp() {
+        let history = run_importer_with_content("#1700000000\nls\n#1700000010\n");
+
+        assert_eq!(history.len(), 1, "Should import 1 command");
+        assert_eq!(history[0].command, "ls");
+        assert_eq!(history[0].timestamp, at(1_700_000_000));
+
+        assert!(run_importer_with_content("#1700000000\n").is_empty());
+    }
+
+    #[test]
+    fn test_load_out_of_range_timestamp() {
+        // A number too large to be a timestamp is kept as a command rather than dropped.
+        let history = run_importer_with_content("#99999999999999999999\nls\n");
+
+        assert_eq!(history.len(), 2, "Should import 2 commands");
+        assert_eq!(history[0].command, "#99999999999999999999");
+        assert_eq!(history[1].command, "ls");
+    }
+
+    #[test]
+    fn test_load_crlf_and_bom() {
+        let clean = "#1700000000\nls -l\n#1700000010\ncd /tmp\n";
+        let parsed = |content: &str| {
+            run_importer_with_content(content)
+                .into_iter()
+                .map(|h| (h.command, h.timestamp))
+                .collect::<Vec<_>>()
+        };
+        let expected = parsed(clean);
+        assert_eq!(expected.len(), 2, "Should import 2 commands");
+
+        let crlf = clean.replace('\n', "\r\n");
+        assert_eq!(parsed(&crlf), expected);
+        // The first line is still recognized as a timestamp.
+        assert_eq!(parsed(&format!("\u{feff}{clean}")), expected);
+        assert_eq!(parsed(&format!("\u{feff}{crlf}")), expected);
+    }
+}
@@ -0,0 +1,226 @@
+//! Conversion between `History` and JSON Lines files, one JSON object per command.
+//!
+//! Each object has the `command`, `cwd`, `exit`, `session_id`, `timestamp` (unix seconds) and
+//! `tty` keys, named as in `raven get --json`, and the `source` which recorded the command.
+use std::{
+    fs::File,
+    io::{BufReader, Write},
+    path::PathBuf,
+};
+
+use serde::{Deserialize, Serialize};
+use time::OffsetDateTime;
+
+use super::{ImportError, Importer, LoadError, Loader, read_lines};
+use crate::history::model::History;
+
+/// A single line of a JSON Lines history file.
+#[derive(Debug, Serialize, Deserialize)]
+pub(crate) struct Record {
+    command: String,
+    cwd: String,
+    exit: i64,
+    #[serde(default)]
+    session_id: String,
+    timestamp: i64,
+    #[serde(default)]
+    tty: String,
+    /// Kept on import, so converted history is still known to come from its shell.
+    #[serde(default)]
+    source: String,
+}
+
+impl From<&History> for Record {
+    fn from(h: &History) -> Self {
+        Self {
+            command: h.command.clone(),
+            cwd: h.cwd.clone(),
+            exit: h.exit_code,
+            session_id: h.session_id.clone(),
+            timestamp: h.timestamp.unix_timestamp(),
+            tty: h.tty.clone(),
+            source: h.source.clone(),
+        }
+    }
+}
+
+/// A `Loader` which writes each pushed `History` to `writer` as a JSON line, instead of saving
+/// it to the database.
+///
+/// Lets any importer convert its source to JSON Lines.
+pub struct FileLoader<W: Write> {
+    writer: W,
+    /// The number of commands written so far.
+    pub count: usize,
+}
+
+impl<W: Write> FileLoader<W> {
+    pub fn new(writer: W) -> Self {
+        Self { writer, count: 0 }
+    }
+
+    /// Flush and return the underlying writer.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the writer could not be flushed.
+    pub fn into_inner(mut self) -> std::io::Result<W> {
+        self.writer.flush()?;
+        Ok(self.writer)
+    }
+}
+
+impl<W: Write> Loader for FileLoader<W> {
+    fn push(&mut self, hist: History) -> Result<(), LoadError> {
+        let line = serde_json::to_string(&Record::from(&hist)).map_err(|_| LoadError)?;
+        writeln!(self.writer, "{line}").map_err(|_| LoadError)?;
+        self.count += 1;
+        Ok(())
+    }
+}
+
+/// Imports history from a JSON Lines file, such as one written by a `FileLoader`.
+#[derive(Debug)]
+pub struct Jsonl {
+    path: PathBuf,
+    /// Records with a timestamp before this are skipped.
+    since: Option<OffsetDateTime>,
+}
+
+impl Jsonl {
+    /// Import the JSON Lines file at `path`.
+    #[must_use]
+    pub fn from_path(path: PathBuf) -> Self {
+        Self { path, since: None }
+    }
+
+    /// Skip records of commands run before `since`.
+    #[must_use]
+    pub fn with_since(mut self, since: OffsetDateTime) -> Self {
+        self.since = Some(since);
+        self
+    }
+}
+
+impl Importer for Jsonl {
+    const NAME: &'static str = "jsonl";
+
+    /// JSON Lines files have no default location, use `Jsonl::from_path` instead.
+    fn new() -> Result<Self, ImportError> {
+        eprintln!("Error: a JSON Lines import needs the path of the file to import");
+        Err(ImportError)
+    }
+
+    fn load(self, loader: &mut impl Loader) -> Result<(), ImportError> {
+        let reader = BufReader::new(File::open(&self.path)?);
+        for (idx, line) in read_lines(reader).enumerate() {
+            let line = line?;
+            if line.trim().is_empty() {
+                continue;
+            }
+            let record = match serde_json::from_str::<Record>(&line) {
+                Ok(record) => record,
+                Err(err) => {
+                    eprintln!(
+                        "Warning: Skipping line {} of {}: {err}",
+                        idx + 1,
+                        self.path.display()
+                    );
+                    continue;
+                }
+            };
+            let Ok(timestamp) = OffsetDateTime::from_unix_timestamp(record.timestamp) else {
+                eprintln!(
+                    "Warning: Skipping line {} of {}: invalid timestamp {}",
+                    idx + 1,
+                    self.path.display(),
+                    record.timestamp
+                );
+                continue;
+            };
+            if self.since.is_some_and(|since| timestamp < since) {
+                continue;
+            }
+            let h = History::builder()
+                .id(-1)
+                .timestamp(timestamp)
+                .command(record.command)
+                .cwd(record.cwd)
+                .exit_code(record.exit)
+                .session_id(record.session_id)
+                .tty(record.tty)
+                .source(if record.source.is_empty() {
+                    format!("import:{}", Self::NAME)
+                } else {
+                    record.source
+                })
+                .build();
+            loader.push(h).map_err(|_| ImportError)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::import::zsh::Zsh;
+    use std::io::Write;
+    use tempfile::NamedTempFile;
+
+    struct MockLoader {
+        history: Vec<History>,
+    }
+
+    impl Loader for MockLoader {
+        fn push(&mut self, hist: History) -> Result<(), LoadError> {
+            self.history.push(hist);
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_zsh_to_jsonl_and_back() {
+        let mut histfile = NamedTempFile::new().expect("Failed to create temp file");
+        write!(
+            histfile,
+            ": 1700000000:0;git status\n: 1700000010:0;for i in 1 2\\\ndo echo \"$i\"\\\ndone\n"
+        )
+        .expect("Failed to write histfile");
+
+        let mut direct = MockLoader {
+            history: Vec::new(),
+        };
+        Zsh::from_path(histfile.path().to_path_buf())
+            .load(&mut direct)
+            .expect("Zsh import failed");
+
+        let mut file_loader = FileLoader::new(Vec::new());
+        Zsh::from_path(histfile.path().to_path_buf())
+            .load(&mut file_loader)
+            .expect("Zsh conversion failed");
+        assert_eq!(file_loader.count, 2);
+        let jsonl = String::from_utf8(file_loader.into_inner().unwrap()).unwrap();
+        assert_eq!(
+            jsonl.lines().next(),
+            Some(
+                r#"{"command":"git status","cwd":"unknown","exit":-1,"session_id":"","timestamp":1700000000,"tty":"","source":"import:zsh"}"#
+            )
+        );
+
+        let mut converted = NamedTempFile::new().expect("Failed to create temp file");
+        write!(converted, "{jsonl}\nnot json\n").expect("Failed to write jsonl");
+        let mut round_trip = MockLoader {
+            history: Vec::new(),
+        };
+        Jsonl::from_path(converted.path().to_path_buf())
+            .load(&mut round_trip)
+            .expect("Jsonl import failed");
+
+        assert_eq!(round_trip.history, direct.history);
+        assert_eq!(
+            round_trip.history[1].command,
+            "for i in 1 2\ndo echo \"$i\"\ndone"
+        );
+    }
+}
@@ -1,7 +1,15 @@
-use std::io::Error as IoError;
+use std::{
+    fs::File,
+    io::{BufRead, Error as IoError},
+};
+
+use time::OffsetDateTime;
 
 use crate::history::model::History;
 
+pub mod aliases;
+pub mod bash;
+pub mod jsonl;
 pub mod zsh;
 
 #[derive(Debug)]
@@ -17,6 +25,34 @@ impl From<IoError> for ImportError {
 #[derive(Debug)]
 pub struct LoadError;
 
+/// The byte order mark some editors write at the start of UTF-8 files.
+const BOM: char = '\u{feff}';
+
+/// Read the lines of a history file, tolerating files written on Windows.
+///
+/// `\r\n` line endings are removed like `\n` ones, and so is a byte order mark before the first
+/// line, which would otherwise hide the format of that line.
+fn read_lines(reader: impl BufRead) -> impl Iterator<Item = Result<String, IoError>> {
+    reader.lines().enumerate().map(|(idx, line)| {
+        line.map(|line| match line.strip_prefix(BOM) {
+            Some(rest) if idx == 0 => rest.to_string(),
+            _ => line,
+        })
+    })
+}
+
+/// The time commands without a timestamp in the history `file` are counted back from, when it was
+/// last written.
+///
+/// Importing the file again while it is unchanged gives them the same timestamps, so they are
+/// skipped as already stored. Falls back to the current time if the file system does not record
+/// it.
+fn modified_at(file: &File) -> OffsetDateTime {
+    file.metadata()
+        .and_then(|metadata| metadata.modified())
+        .map_or_else(|_| OffsetDateTime::now_utc(), OffsetDateTime::from)
+}
+
 /// The importer handles parsing individual history items from an import source (such as a history
 /// file ), transforming them to `History` objects and passing them to the Loader to be persisted.
 pub trait Importer: Sized {
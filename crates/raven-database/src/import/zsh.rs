@@ -1,18 +1,23 @@
-use std::{
-    env,
-    fs::File,
-    io::{BufRead, BufReader},
-    path::PathBuf,
-};
+use std::{env, fs::File, io::BufReader, path::PathBuf};
 
-use time::{Duration, OffsetDateTime};
+use time::{Duration, OffsetDateTime, UtcOffset};
 
-use super::{ImportError, Importer, Loader};
-use crate::history::model::History;
+use super::{ImportError, Importer, Loader, modified_at, read_lines};
+use crate::{
+    Clock,
+    history::model::{DURATION_UNKNOWN, History},
+};
 
 #[derive(Debug)]
 pub struct Zsh {
     histpath: PathBuf,
+    /// The offset from UTC the extended timestamps in the history file were written in.
+    tz_offset: UtcOffset,
+    /// The time commands without a timestamp are counted back from, instead of when the history
+    /// file was last written.
+    clock: Option<Clock>,
+    /// Commands with an extended timestamp before this are skipped.
+    since: Option<OffsetDateTime>,
 }
 
 /// Represents the type of command currently being accumulated.
@@ -25,6 +30,8 @@ enum ActiveCommandContext {
     /// Accumulating an extended command.
     Extended {
         timestamp: OffsetDateTime,
+        /// Seconds the command ran for, see `Zsh::parse_elapsed`.
+        duration: i64,
         /// True if the last line of this extended command ended with '\', expecting continuation.
         more_lines_expected: bool,
     },
@@ -33,8 +40,8 @@ enum ActiveCommandContext {
 /// Represents the parsed type of a single line from the history file.
 #[derive(Debug)]
 enum ParsedLine {
-    /// A valid extended history header: `timestamp, command_part, ends_with_backslash`
-    ExtendedHeader(OffsetDateTime, String, bool),
+    /// A valid extended history header: `timestamp, duration, command_part, ends_with_backslash`
+    ExtendedHeader(OffsetDateTime, i64, String, bool),
     /// A line that looks like an extended header but is malformed. Contains the original line content.
     MalformedExtended(String),
     /// A simple command line. Contains the line content.
@@ -44,6 +51,45 @@ enum ParsedLine {
 }
 
 impl Zsh {
+    /// Import the history file at `histpath` instead of the one found in `$HOME`.
+    #[must_use]
+    pub fn from_path(histpath: PathBuf) -> Self {
+        Self {
+            histpath,
+            tz_offset: UtcOffset::UTC,
+            clock: None,
+            since: None,
+        }
+    }
+
+    /// Treat the extended timestamps in the history file as written in `offset` instead of UTC.
+    ///
+    /// For history files written by shells whose epochs are skewed from UTC, e.g. by local time.
+    /// Imported timestamps are shifted back by `offset` before they are stored.
+    #[must_use]
+    pub fn with_tz_offset(mut self, offset: UtcOffset) -> Self {
+        self.tz_offset = offset;
+        self
+    }
+
+    /// Count commands without a timestamp back a second at a time from the time `clock` reads,
+    /// instead of from when the history file was last written.
+    #[must_use]
+    pub fn with_clock(mut self, clock: Clock) -> Self {
+        self.clock = Some(clock);
+        self
+    }
+
+    /// Skip commands run before `since`.
+    ///
+    /// Only commands written in the extended format have a timestamp to go by, the others are
+    /// always imported.
+    #[must_use]
+    pub fn with_since(mut self, since: OffsetDateTime) -> Self {
+        self.since = Some(since);
+        self
+    }
+
     fn default_histpath() -> Result<PathBuf, ImportError> {
         let Ok(home_dir) = env::var("HOME") else {
             eprintln!("Error: $HOME is not set, cannot locate home directory");
@@ -71,6 +117,27 @@ impl Zsh {
         Err(ImportError)
     }
 
+    /// Whether a line of the history file continues on the next line.
+    ///
+    /// Zsh writes a newline inside a command as a backslash followed by the newline, and reads
+    /// any line ending in a backslash as continued, regardless of how many backslashes precede
+    /// it. A command typed with a trailing `\` line continuation is therefore written ending
+    /// in two backslashes, and a multi-line command such as a `for` loop ending in one.
+    fn is_continued(line: &str) -> bool {
+        line.ends_with('\\')
+    }
+
+    /// Parses the elapsed seconds field of an extended header as a duration.
+    ///
+    /// Zsh writes 0 for commands that took under a second and for history written before the
+    /// command finished (`INC_APPEND_HISTORY`), so 0 is stored as unknown, like malformed values.
+    fn parse_elapsed(elapsed: &str) -> i64 {
+        match elapsed.trim().parse::<i64>() {
+            Ok(seconds) if seconds > 0 => seconds,
+            _ => DURATION_UNKNOWN,
+        }
+    }
+
     /// Classifies a line and parses it if it's a valid extended header.
     fn classify_and_parse_line(line_text: &str) -> ParsedLine {
         let trimmed_line = line_text.trim_end();
@@ -119,9 +186,10 @@ impl Zsh {
 
         if let Ok(ts_val) = timestamp_str.parse::<i64>() {
             if let Ok(timestamp) = OffsetDateTime::from_unix_timestamp(ts_val) {
-                let ends_with_backslash = command_start_of_line.ends_with(r"\\");
+                let ends_with_backslash = Zsh::is_continued(command_start_of_line);
                 ParsedLine::ExtendedHeader(
                     timestamp,
+                    Zsh::parse_elapsed(parts2[0]),
                     command_start_of_line.to_string(),
                     ends_with_backslash,
                 )
@@ -145,26 +213,44 @@ impl Zsh {
         context: ActiveCommandContext,
         non_extended_offset_seconds: &mut i64,
         now_for_simple: OffsetDateTime,
+        since: Option<OffsetDateTime>,
         loader: &mut impl Loader,
     ) -> Result<(), ImportError> {
         if lines_buffer.is_empty() {
             return Ok(());
         }
 
-        let command_text = lines_buffer.join("\n").replace(r"\\", r"\");
-        let timestamp = match context {
-            ActiveCommandContext::Extended { timestamp, .. } => timestamp,
+        // Only the backslash escaping each newline is removed, any others are part of the command.
+        let command_text = lines_buffer
+            .iter()
+            .map(|line| line.strip_suffix('\\').unwrap_or(line))
+            .collect::<Vec<&str>>()
+            .join("\n");
+        let (timestamp, duration) = match context {
+            ActiveCommandContext::Extended { timestamp, .. }
+                if since.is_some_and(|since| timestamp < since) =>
+            {
+                lines_buffer.clear();
+                return Ok(());
+            }
+            ActiveCommandContext::Extended {
+                timestamp,
+                duration,
+                ..
+            } => (timestamp, duration),
             ActiveCommandContext::Simple | ActiveCommandContext::None => {
                 // None implies simple if buffer not empty
                 let ts = now_for_simple - Duration::seconds(*non_extended_offset_seconds);
                 *non_extended_offset_seconds += 1;
-                ts
+                (ts, DURATION_UNKNOWN)
             }
         };
 
         let imported = History::import()
             .command(command_text)
             .timestamp(timestamp)
+            .source(Self::NAME)
+            .duration(duration)
             .build();
         loader.push(imported.into()).map_err(|_| ImportError)?;
 
@@ -179,21 +265,26 @@ impl Importer for Zsh {
     fn new() -> Result<Self, ImportError> {
         Ok(Self {
             histpath: Zsh::default_histpath()?,
+            tz_offset: UtcOffset::UTC,
+            clock: None,
+            since: None,
         })
     }
 
     #[allow(clippy::too_many_lines)]
     fn load(self, loader: &mut impl Loader) -> Result<(), ImportError> {
         let file = File::open(&self.histpath)?;
+        let now = self
+            .clock
+            .map_or_else(|| modified_at(&file), |clock| clock());
         let reader = BufReader::new(file);
 
         let mut non_extended_offset_seconds: i64 = 0;
-        let now = OffsetDateTime::now_utc();
 
         let mut lines_buffer: Vec<String> = Vec::new();
         let mut active_context = ActiveCommandContext::None;
 
-        for read_line_result in reader.lines() {
+        for read_line_result in read_lines(reader) {
             let line_text = match read_line_result {
                 Ok(l) => l,
                 Err(e) => {
@@ -205,17 +296,21 @@ impl Importer for Zsh {
             let parsed_line = Zsh::classify_and_parse_line(&line_text);
 
             match parsed_line {
-                ParsedLine::ExtendedHeader(timestamp, cmd_part, ends_with_backslash) => {
+                ParsedLine::ExtendedHeader(timestamp, duration, cmd_part, ends_with_backslash) => {
+                    let timestamp =
+                        timestamp - Duration::seconds(self.tz_offset.whole_seconds().into());
                     Zsh::finalize_command_block(
                         &mut lines_buffer,
                         active_context,
                         &mut non_extended_offset_seconds,
                         now,
+                        self.since,
                         loader,
                     )?;
                     lines_buffer.push(cmd_part);
                     active_context = ActiveCommandContext::Extended {
                         timestamp,
+                        duration,
                         more_lines_expected: ends_with_backslash,
                     };
                     if !ends_with_backslash {
@@ -224,6 +319,7 @@ impl Importer for Zsh {
                             active_context,
                             &mut non_extended_offset_seconds,
                             now,
+                            self.since,
                             loader,
                         )?;
                         active_context = ActiveCommandContext::None;
@@ -235,6 +331,7 @@ impl Importer for Zsh {
                         active_context,
                         &mut non_extended_offset_seconds,
                         now,
+                        self.since,
                         loader,
                     )?;
                     lines_buffer.push(original_line);
@@ -245,6 +342,7 @@ impl Importer for Zsh {
                         active_context,
                         &mut non_extended_offset_seconds,
                         now,
+                        self.since,
                         loader,
                     )?;
                     active_context = ActiveCommandContext::None;
@@ -253,13 +351,16 @@ impl Importer for Zsh {
                     match active_context {
                         ActiveCommandContext::Extended {
                             timestamp,
+                            duration,
                             more_lines_expected,
                         } => {
                             if more_lines_expected {
                                 lines_buffer.push(simple_content.clone());
-                                let current_line_ends_backslash = simple_content.ends_with(r"\\");
+                                let current_line_ends_backslash =
+                                    Zsh::is_continued(&simple_content);
                                 active_context = ActiveCommandContext::Extended {
                                     timestamp,
+                                    duration,
                                     more_lines_expected: current_line_ends_backslash,
                                 };
                                 if !current_line_ends_backslash {
@@ -269,6 +370,7 @@ impl Importer for Zsh {
                                         active_context,
                                         &mut non_extended_offset_seconds,
                                         now,
+                                        self.since,
                                         loader,
                                     )?;
                                     active_context = ActiveCommandContext::None;
@@ -280,17 +382,19 @@ impl Importer for Zsh {
                                     active_context, // Finalize the preceding extended command
                                     &mut non_extended_offset_seconds,
                                     now,
+                                    self.since,
                                     loader,
                                 )?;
                                 // Now start new simple command
                                 lines_buffer.push(simple_content.clone());
                                 active_context = ActiveCommandContext::Simple;
-                                if !simple_content.ends_with(r"\\") {
+                                if !Zsh::is_continued(&simple_content) {
                                     Zsh::finalize_command_block(
                                         &mut lines_buffer,
                                         active_context,
                                         &mut non_extended_offset_seconds,
                                         now,
+                                        self.since,
                                         loader,
                                     )?;
                                     active_context = ActiveCommandContext::None;
@@ -301,12 +405,13 @@ impl Importer for Zsh {
                             // If context was None, it becomes Simple. If it was Simple, it continues.
                             lines_buffer.push(simple_content.clone());
                             active_context = ActiveCommandContext::Simple;
-                            if !simple_content.ends_with(r"\\") {
+                            if !Zsh::is_continued(&simple_content) {
                                 Zsh::finalize_command_block(
                                     &mut lines_buffer,
                                     active_context,
                                     &mut non_extended_offset_seconds,
                                     now,
+                                    self.since,
                                     loader,
                                 )?;
                                 active_context = ActiveCommandContext::None;
@@ -327,7 +432,7 @@ impl Importer for Zsh {
                             // else, ignore empty line if not part of an expected continuation.
                         }
                         ActiveCommandContext::Simple => {
-                            if lines_buffer.last().is_some_and(|l| l.ends_with(r"\\")) {
+                            if lines_buffer.last().is_some_and(|l| Zsh::is_continued(l)) {
                                 lines_buffer.push(String::new());
                             }
                             // else, ignore if not after a `\` in simple mode.
@@ -344,6 +449,7 @@ impl Importer for Zsh {
             active_context,
             &mut non_extended_offset_seconds,
             now,
+            self.since,
             loader,
         )?;
 
@@ -384,6 +490,13 @@ mod tests {
 
     // Helper function to write content to a temp file and run the importer
     fn run_importer_with_content(content: &str) -> Result<Vec<History>, ImportError> {
+        run_importer_with_offset(content, UtcOffset::UTC)
+    }
+
+    fn run_importer_with_offset(
+        content: &str,
+        tz_offset: UtcOffset,
+    ) -> Result<Vec<History>, ImportError> {
         let mut temp_file = NamedTempFile::new().expect("Failed to create temp file");
         // Write content as is, to simulate actual file lines. Add a trailing newline if content is not empty
         // and doesn't already end with one, as files often have it.
@@ -396,12 +509,96 @@ mod tests {
         temp_file.flush().expect("Failed to flush temp file"); // Ensure content is written
 
         let histpath = temp_file.path().to_path_buf();
-        let zsh_importer = Zsh { histpath };
+        let zsh_importer = Zsh::from_path(histpath).with_tz_offset(tz_offset);
         let mut mock_loader = MockLoader::new();
         zsh_importer.load(&mut mock_loader)?;
         Ok(mock_loader.history)
     }
 
+    #[test]
+    fn test_load_with_clock() -> Result<(), ImportError> {
+        let mut temp_file = NamedTempFile::new().expect("Failed to create temp file");
+        write!(temp_file, "first\n: 1678888000:0;extended\nsecond\nthird\n")
+            .expect("Failed to write to temp file");
+        temp_file.flush().expect("Failed to flush temp file");
+
+        let mut mock_loader = MockLoader::new();
+        Zsh::from_path(temp_file.path().to_path_buf())
+            .with_clock(|| OffsetDateTime::from_unix_timestamp(1_700_000_000).unwrap())
+            .load(&mut mock_loader)?;
+
+        // Commands without a timestamp count back a second each from the clock, skipping over
+        // the timestamped ones.
+        let timestamps: Vec<(&str, i64)> = mock_loader
+            .history
+            .iter()
+            .map(|h| (h.command.as_str(), h.timestamp.unix_timestamp()))
+            .collect();
+        assert_eq!(
+            timestamps,
+            [
+                ("first", 1_700_000_000),
+                ("extended", 1_678_888_000),
+                ("second", 1_699_999_999),
+                ("third", 1_699_999_998),
+            ]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_load_with_since() -> Result<(), ImportError> {
+        let mut temp_file = NamedTempFile::new().expect("Failed to create temp file");
+        write!(
+            temp_file,
+            ": 1678000000:0;old\n: 1678000500:0;old multi\\\nline\n: 1679000000:0;new\nsimple\n: 1680000000:0;newer\n"
+        )
+        .expect("Failed to write to temp file");
+        temp_file.flush().expect("Failed to flush temp file");
+
+        let mut mock_loader = MockLoader::new();
+        Zsh::from_path(temp_file.path().to_path_buf())
+            .with_since(OffsetDateTime::from_unix_timestamp(1_678_500_000).unwrap())
+            .load(&mut mock_loader)?;
+
+        // Commands without a timestamp can't be compared to the cutoff and are kept.
+        let commands: Vec<&str> = mock_loader
+            .history
+            .iter()
+            .map(|h| h.command.as_str())
+            .collect();
+        assert_eq!(commands, ["new", "simple", "newer"]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_load_elapsed_as_duration() -> Result<(), ImportError> {
+        let content = concat!(
+            ": 1678886400:42;make\n",
+            ": 1678886500:0;ls\n",
+            ": 1678886600:abc;pwd\n",
+            ": 1678886700:7;for f in *\\\ndo echo $f\\\ndone\n",
+            "simple\n",
+        );
+        let history = run_importer_with_content(content)?;
+
+        let durations: Vec<(&str, i64)> = history
+            .iter()
+            .map(|h| (h.command.as_str(), h.duration))
+            .collect();
+        assert_eq!(
+            durations,
+            [
+                ("make", 42),
+                ("ls", -1),
+                ("pwd", -1),
+                ("for f in *\ndo echo $f\ndone", 7),
+                ("simple", -1),
+            ]
+        );
+        Ok(())
+    }
+
     #[test]
     fn test_load_empty_file() -> Result<(), ImportError> {
         let history = run_importer_with_content("")?;
@@ -605,4 +802,79 @@ mod tests {
         );
         Ok(())
     }
+
+    #[test]
+    fn test_load_command_ending_in_one_backslash() -> Result<(), ImportError> {
+        // A multi-line command without explicit continuations, as zsh writes a `for` loop.
+        let content = ": 1678891000:0;for i in 1 2; do\\\n  echo $i\\\ndone\n: 1678891100:0;ls";
+        let history = run_importer_with_content(content)?;
+
+        assert_eq!(history.len(), 2, "Should import 2 commands");
+        assert_eq!(history[0].command, "for i in 1 2; do\n  echo $i\ndone");
+        assert_eq!(history[1].command, "ls");
+        Ok(())
+    }
+
+    #[test]
+    fn test_load_command_ending_in_two_backslashes() -> Result<(), ImportError> {
+        // A command typed with a `\` line continuation keeps its backslash.
+        let content = ": 1678892000:0;make \\\\\n  all\n: 1678892100:0;ls";
+        let history = run_importer_with_content(content)?;
+
+        assert_eq!(history.len(), 2, "Should import 2 commands");
+        assert_eq!(history[0].command, "make \\\n  all");
+        assert_eq!(history[1].command, "ls");
+        Ok(())
+    }
+
+    #[test]
+    fn test_load_keeps_backslashes_inside_command() -> Result<(), ImportError> {
+        let content = ": 1678893000:0;printf 'a\\\\b\\n'\nsed 's/\\\\/\\//g' file";
+        let history = run_importer_with_content(content)?;
+
+        assert_eq!(history.len(), 2, "Should import 2 commands");
+        assert_eq!(history[0].command, "printf 'a\\\\b\\n'");
+        assert_eq!(history[1].command, "sed 's/\\\\/\\//g' file");
+        Ok(())
+    }
+
+    #[test]
+    fn test_load_with_tz_offset() -> Result<(), ImportError> {
+        let content = ": 1678886400:0;ls -l\\\n  -a\npwd";
+        let offset = UtcOffset::from_hms(2, 30, 0).unwrap();
+        let history = run_importer_with_offset(content, offset)?;
+
+        assert_eq!(history.len(), 2, "Should import 2 commands");
+        assert_eq!(history[0].command, "ls -l\n  -a");
+        assert_eq!(
+            history[0].timestamp,
+            OffsetDateTime::from_unix_timestamp(1_678_886_400 - 9000).unwrap()
+        );
+
+        // Commands without a timestamp are not shifted.
+        let now = OffsetDateTime::now_utc();
+        assert!((now - history[1].timestamp).abs() < Duration::seconds(5));
+        Ok(())
+    }
+
+    #[test]
+    fn test_load_crlf_and_bom() -> Result<(), ImportError> {
+        let clean = ": 1678894000:0;ls -l\n: 1678894100:0;for i in 1 2; do\\\n  echo $i\\\ndone\n: 1678894200:0;pwd\n";
+        let parsed = |content: &str| {
+            run_importer_with_content(content).map(|history| {
+                history
+                    .into_iter()
+                    .map(|h| (h.command, h.timestamp))
+                    .collect::<Vec<_>>()
+            })
+        };
+        let expected = parsed(clean)?;
+        assert_eq!(expected.len(), 3, "Should import 3 commands");
+
+        let crlf = clean.replace('\n', "\r\n");
+        assert_eq!(parsed(&crlf)?, expected);
+        assert_eq!(parsed(&format!("\u{feff}{clean}"))?, expected);
+        assert_eq!(parsed(&format!("\u{feff}{crlf}"))?, expected);
+        Ok(())
+    }
 }
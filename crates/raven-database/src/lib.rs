@@ -1,57 +1,167 @@
 use clap::ValueEnum;
 use database::{Database, sqlite::Sqlite};
 use raven_common::{
-    config::{Config, load_config},
+    config::{Config, PrefixPolicy, load_config},
     utils,
 };
+use std::time::Duration;
+
+use time::OffsetDateTime;
 
 pub mod database;
+pub mod export;
 pub mod history;
 pub mod import;
+pub mod rank;
+
+/// The source of the current time, replaced by a fixed time in tests.
+pub type Clock = fn() -> OffsetDateTime;
 
 /// Context object
 ///
 /// * `cwd`: The current working directory of the shell.
 /// * `db`: The Raven database implementation.
+/// * `clock`: The source of the current time, for timestamps given to captured and imported
+///   commands.
 pub struct Context {
     pub cwd: String,
     pub db: Box<dyn Database>,
     pub config: Config,
+    pub clock: Clock,
+}
+
+impl Context {
+    /// Build a context for the current working directory using `config`.
+    #[must_use]
+    pub fn new(config: Config) -> Self {
+        Self {
+            cwd: utils::get_current_dir(),
+            db: Box::new(Sqlite::new(&config)),
+            config,
+            clock: OffsetDateTime::now_utc,
+        }
+    }
 }
 
 /// Specifies the matching mode for queries.
-#[derive(Clone, Debug, Copy, ValueEnum)]
+#[derive(Clone, Debug, Copy, Default, PartialEq, Eq, ValueEnum)]
 pub enum MatchMode {
     /// Query is an initial prefix.
     Prefix,
-    /// Uses a fuzzy matching algorithm to find terms.
+    /// Uses a fuzzy matching algorithm to find terms, commands must match every term.
+    #[default]
     Fuzzy,
+    /// Like `Fuzzy`, but commands matching any one of the terms are found.
+    #[value(skip)]
+    FuzzyAny,
+    /// Query is an FTS5 expression, used as is. Malformed expressions make the search fail.
+    #[value(skip)]
+    Raw,
+    /// Query is a regular expression matched against anywhere in the command, without the FTS
+    /// index. Invalid patterns make the search fail.
+    #[value(skip)]
+    Regex,
 }
 
-impl Default for MatchMode {
-    fn default() -> Self {
-        Self::Fuzzy
-    }
+/// Specifies the order of search results.
+#[derive(Clone, Debug, Copy, Default, PartialEq, Eq, ValueEnum)]
+pub enum OrderBy {
+    /// Most recently run commands first.
+    #[default]
+    Recency,
+    /// Best matches for the query first, most recent first when there is no query.
+    Relevance,
 }
 
 /// Optional filters that can be used for searching for History objects.
-#[derive(Default, Clone, Debug)]
+#[derive(Clone, Debug)]
 pub struct HistoryFilters {
     pub exit: Option<i64>,
     pub cwd: Option<String>,
+    /// Only include commands run in this directory or its subdirectories.
+    pub cwd_within: Option<String>,
     pub limit: Option<usize>,
+    /// Skip this many results, to page through them `limit` at a time.
+    pub offset: Option<usize>,
     pub mode: MatchMode,
+    /// Only include commands run in this shell session.
+    pub session_id: Option<String>,
+    /// Only include commands run in this terminal, such as `/dev/pts/3`.
+    pub tty: Option<String>,
+    /// Only include commands with at least this many characters.
+    pub min_length: Option<usize>,
+    /// Only include commands with at most this many characters.
+    pub max_length: Option<usize>,
+    /// Exclude commands run in any of these directories, or their subdirectories.
+    pub exclude_cwd: Vec<String>,
+    /// Only include commands run at or after this time.
+    pub after: Option<OffsetDateTime>,
+    /// Only include commands run before this time.
+    pub before: Option<OffsetDateTime>,
+    /// Include commands which have started but not yet ended, defaults to true.
+    pub include_running: bool,
+    /// Only include commands which ended with a non-zero exit code.
+    pub failed: bool,
+    /// The order results are returned in.
+    pub order: OrderBy,
+    /// Match the query as a plain substring of the command instead of using the full text search
+    /// index. Slower, but unaffected by how the index tokenizes commands.
+    pub no_fts: bool,
+    /// Which words of a fuzzy query match as prefixes of longer words.
+    pub prefix_policy: PrefixPolicy,
+    /// Abandon the search with a `Timeout` error once it has run for this long, see
+    /// `[search] timeout_ms`. Only the interactive search sets it, so exports and stats always
+    /// read every row.
+    pub timeout: Option<Duration>,
+}
+
+impl Default for HistoryFilters {
+    fn default() -> Self {
+        Self {
+            exit: None,
+            cwd: None,
+            cwd_within: None,
+            limit: None,
+            offset: None,
+            mode: MatchMode::default(),
+            session_id: None,
+            tty: None,
+            min_length: None,
+            max_length: None,
+            exclude_cwd: Vec::new(),
+            after: None,
+            before: None,
+            include_running: true,
+            failed: false,
+            order: OrderBy::default(),
+            no_fts: false,
+            prefix_policy: PrefixPolicy::default(),
+            timeout: None,
+        }
+    }
+}
+
+impl HistoryFilters {
+    /// Whether no filter narrows down the results, `limit`, `offset` and `mode` aside.
+    #[must_use]
+    pub fn is_unfiltered(&self) -> bool {
+        self.exit.is_none()
+            && self.cwd.is_none()
+            && self.cwd_within.is_none()
+            && self.session_id.is_none()
+            && self.tty.is_none()
+            && self.min_length.is_none()
+            && self.max_length.is_none()
+            && self.exclude_cwd.is_empty()
+            && self.after.is_none()
+            && self.before.is_none()
+            && !self.failed
+            && self.include_running
+    }
 }
 
 #[must_use]
 /// Fetch the current Raven context
 pub fn current_context() -> Context {
-    let cwd = utils::get_current_dir();
-    let config = load_config().unwrap_or_default();
-
-    Context {
-        cwd,
-        db: Box::new(Sqlite::new(&config)),
-        config,
-    }
+    Context::new(load_config().unwrap_or_default())
 }
@@ -0,0 +1,91 @@
+//! Reordering of search results after they are fetched from the database.
+//!
+//! Rankers only see the fetched candidates, so they work the same for every backend and can be
+//! tried out without changing how the database searches.
+use std::collections::HashMap;
+
+use raven_common::config::RankerKind;
+
+use crate::history::model::History;
+
+/// Reorders the candidates of a search.
+pub trait Ranker {
+    /// Return `candidates`, found for `query`, in the order they should be shown.
+    fn rank(&self, query: &str, candidates: Vec<History>) -> Vec<History>;
+}
+
+/// Keeps the order of the database, best bm25 match first when ordering by relevance and most
+/// recent first otherwise.
+pub struct Bm25Recency;
+
+impl Ranker for Bm25Recency {
+    fn rank(&self, _query: &str, candidates: Vec<History>) -> Vec<History> {
+        candidates
+    }
+}
+
+/// Lists the commands run most often among the candidates first, commands run equally often
+/// keep the order of the database.
+pub struct Frequency;
+
+impl Ranker for Frequency {
+    fn rank(&self, _query: &str, mut candidates: Vec<History>) -> Vec<History> {
+        let mut counts: HashMap<String, usize> = HashMap::new();
+        for h in &candidates {
+            *counts.entry(h.command.clone()).or_default() += 1;
+        }
+        // A stable sort, so ties are left as the database returned them.
+        candidates.sort_by_key(|h| std::cmp::Reverse(counts[&h.command]));
+        candidates
+    }
+}
+
+/// The ranker picked by `[search] ranker`.
+#[must_use]
+pub fn ranker(kind: RankerKind) -> Box<dyn Ranker> {
+    match kind {
+        RankerKind::Bm25Recency => Box::new(Bm25Recency),
+        RankerKind::Frequency => Box::new(Frequency),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use time::OffsetDateTime;
+
+    use super::*;
+
+    fn history(id: i64, command: &str) -> History {
+        History::builder()
+            .id(id)
+            .command(command.to_string())
+            .cwd(String::from("/tmp"))
+            .exit_code(0)
+            .timestamp(OffsetDateTime::UNIX_EPOCH)
+            .build()
+    }
+
+    fn ids(ranked: &[History]) -> Vec<i64> {
+        ranked.iter().map(|h| h.id).collect()
+    }
+
+    #[test]
+    fn test_bm25_recency_keeps_order() {
+        let candidates = vec![history(3, "git stash"), history(2, "git status")];
+        assert_eq!(ids(&Bm25Recency.rank("git st", candidates)), vec![3, 2]);
+    }
+
+    #[test]
+    fn test_frequency_promotes_oft_run_command() {
+        // The one-off is the most recent and matches the query as well as the others.
+        let candidates = vec![
+            history(5, "git stash"),
+            history(4, "git status"),
+            history(3, "cargo test"),
+            history(2, "git status"),
+            history(1, "git status"),
+        ];
+        let ranked = ranker(RankerKind::Frequency).rank("git st", candidates);
+        assert_eq!(ids(&ranked), vec![4, 2, 1, 5, 3]);
+    }
+}
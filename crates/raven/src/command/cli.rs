@@ -1,45 +1,137 @@
 use clap::Subcommand;
 use raven_database::Context;
+mod clean;
+mod config;
+mod convert;
+mod doctor;
+mod export;
+mod get;
 mod history;
 mod import;
 mod init;
+mod last_failed;
+mod migrate_cwd;
+mod pause;
+mod reindex;
+mod resume;
 mod search;
+mod stats;
+mod version;
 
 #[derive(Subcommand, Debug)]
 #[command(infer_subcommands = true)]
 pub enum Cmd {
+    /// Interactively mark history entries and delete them in bulk.
+    Clean(clean::Cmd),
+
+    /// Show the config file location and the config in effect.
+    #[command(subcommand)]
+    Config(config::Cmd),
+
+    /// Convert a history file to another format, without touching the Raven database.
+    Convert(convert::Cmd),
+
+    /// Check the Raven database for corruption.
+    Doctor(doctor::Cmd),
+
+    /// Export the Raven history to JSON, JSON Lines or CSV.
+    Export(export::Cmd),
+
+    /// Print the command stored for a history id.
+    Get(get::Cmd),
+
     /// Add or update History in the Raven database.
     #[command(subcommand)]
     History(history::Cmd),
 
     /// Import existing history into Raven.
-    #[command(subcommand)]
+    #[command()]
     Import(import::Cmd),
 
     /// Print Raven's shell init script.
     #[command()]
     Init(init::Cmd),
 
+    /// Print the most recent command which failed.
+    LastFailed(last_failed::Cmd),
+
+    /// Move the history of a directory to a new path, e.g. after moving a project.
+    MigrateCwd(migrate_cwd::Cmd),
+
+    /// Stop recording commands until `raven resume` is run.
+    Pause(pause::Cmd),
+
+    /// Rebuild the search index, required after changing the search tokenizer.
+    Reindex(reindex::Cmd),
+
+    /// Start recording commands again after `raven pause`.
+    Resume(resume::Cmd),
+
     /// Search the Raven history database.
-    Search(search::Cmd),
+    Search(Box<search::Cmd>),
+
+    /// Show when commands are run, by hour of the day and day of the week, and the most run ones.
+    Stats(stats::Cmd),
+
+    /// Print the Raven version and diagnostic information.
+    Version(version::Cmd),
 }
 
 impl Cmd {
     pub fn run(self, context: &mut Context) {
         // CLI commands block the current thread until they resolve.
         match self {
+            Self::Clean(clean) => {
+                clean.run(context);
+            }
+            Self::Config(config) => {
+                config.run(context);
+            }
+            Self::Convert(convert) => {
+                convert.run(context);
+            }
+            Self::Doctor(doctor) => {
+                doctor.run(context);
+            }
+            Self::Export(export) => {
+                export.run(context);
+            }
+            Self::Get(get) => {
+                get.run(context);
+            }
             Self::Init(init) => {
                 init.run(context);
             }
             Self::History(history) => {
                 history.run(context);
             }
+            Self::LastFailed(last_failed) => {
+                last_failed.run(context);
+            }
+            Self::MigrateCwd(migrate_cwd) => {
+                migrate_cwd.run(context);
+            }
+            Self::Pause(pause) => {
+                pause.run(context);
+            }
+            Self::Reindex(reindex) => {
+                reindex.run(context);
+            }
+            Self::Resume(resume) => {
+                resume.run(context);
+            }
             Self::Search(search) => {
                 search.run(context);
             }
+            Self::Stats(stats) => {
+                stats.run(context);
+            }
             Self::Import(import) => {
                 import.run(context);
             }
+            Self::Version(version) => {
+                version.run(context);
+            }
         }
     }
 }
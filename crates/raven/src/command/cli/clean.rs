@@ -0,0 +1,51 @@
+//! Clean module for deleting history entries in bulk.
+use clap::Parser;
+use raven_database::{Context, MatchMode};
+
+use super::search;
+
+/// The number of entries listed after a dry run, the rest are only counted.
+const DRY_RUN_SAMPLE: usize = 10;
+
+/// Open the interactive search to mark entries and delete them in one confirmed batch.
+///
+/// * `query`: The initial search query.
+/// * `mode`: The initial search matching mode.
+/// * `dry_run`: List the confirmed entries instead of deleting them.
+#[derive(Parser, Debug)]
+pub struct Cmd {
+    /// The initial search query
+    query: Vec<String>,
+
+    /// Search matching mode
+    #[arg(long, short, value_enum)]
+    mode: Option<MatchMode>,
+
+    /// Print the entries confirmed for deletion instead of deleting them
+    #[arg(long)]
+    dry_run: bool,
+}
+
+impl Cmd {
+    /// Command runner for the interactive clean up.
+    pub fn run(self, context: &mut Context) {
+        let (deleted, would_delete) = search::clean(
+            context,
+            &self.query,
+            self.mode.unwrap_or_default(),
+            self.dry_run,
+        );
+        if !self.dry_run {
+            println!("Deleted {deleted} history entries.");
+            return;
+        }
+
+        println!("Would delete {} history entries.", would_delete.len());
+        for h in would_delete.iter().take(DRY_RUN_SAMPLE) {
+            println!("{}\t{}", h.id, h.command.replace('\n', "\\n"));
+        }
+        if would_delete.len() > DRY_RUN_SAMPLE {
+            println!("... and {} more", would_delete.len() - DRY_RUN_SAMPLE);
+        }
+    }
+}
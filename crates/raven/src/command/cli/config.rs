@@ -0,0 +1,58 @@
+//! Config module for showing where the config lives and what is in effect.
+use std::io::ErrorKind;
+
+use clap::Parser;
+use raven_common::config::{config_path, write_default_config};
+use raven_database::{Context, database::sqlite::Sqlite};
+
+#[derive(Debug, Parser)]
+pub enum Cmd {
+    /// Print the path of the config file
+    Path,
+
+    /// Print the config in effect, including defaults, as TOML
+    Show,
+
+    /// Write a commented config file listing every setting with its default
+    Init {
+        /// Overwrite an existing config file
+        #[arg(long)]
+        force: bool,
+    },
+}
+
+impl Cmd {
+    pub fn run(self, context: &mut Context) {
+        match self {
+            Self::Path => println!("{}", config_path().display()),
+            Self::Show => {
+                let mut config = context.config.effective();
+                config.set_database_file(&Sqlite::database_path(&context.config));
+                match config.to_toml() {
+                    Ok(toml) => print!("{toml}"),
+                    Err(err) => {
+                        eprintln!("unable to show config: {err}");
+                        std::process::exit(1);
+                    }
+                }
+            }
+            Self::Init { force } => {
+                let path = config_path();
+                match write_default_config(&path, force) {
+                    Ok(()) => println!("Wrote {}", path.display()),
+                    Err(err) if err.kind() == ErrorKind::AlreadyExists => {
+                        eprintln!(
+                            "{} already exists, use --force to overwrite it",
+                            path.display()
+                        );
+                        std::process::exit(1);
+                    }
+                    Err(err) => {
+                        eprintln!("unable to write {}: {err}", path.display());
+                        std::process::exit(1);
+                    }
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,94 @@
+//! Convert module for converting shell history between formats.
+use std::path::PathBuf;
+
+use clap::{Parser, ValueEnum};
+use raven_database::{
+    Context,
+    export::ExportFormat,
+    import::{bash::Bash, jsonl::Jsonl, zsh::Zsh},
+};
+
+use super::import;
+
+/// The history formats which can be read.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub enum InputFormat {
+    /// A bash history file, with or without `HISTTIMEFORMAT` timestamps.
+    Bash,
+    /// A zsh history file, with or without extended timestamps.
+    Zsh,
+    /// JSON Lines, as written by `raven convert --to jsonl`.
+    Jsonl,
+}
+
+/// The history formats which can be written.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub enum OutputFormat {
+    /// One JSON object per command, with the keys of `raven get --json`.
+    Jsonl,
+    /// Comma separated values with a header row, as written by `raven export --format csv`.
+    Csv,
+}
+
+impl From<OutputFormat> for ExportFormat {
+    fn from(format: OutputFormat) -> Self {
+        match format {
+            OutputFormat::Jsonl => ExportFormat::Jsonl,
+            OutputFormat::Csv => ExportFormat::Csv,
+        }
+    }
+}
+
+/// Convert a history file to another format, without touching the raven database.
+///
+/// * `from`: The format of the `input` file.
+/// * `input`: The history file to read.
+/// * `to`: The format to write.
+/// * `output`: The file to write, replaced if it exists.
+#[derive(Parser, Debug)]
+pub struct Cmd {
+    /// The format of the input file
+    #[arg(long, value_enum)]
+    from: InputFormat,
+
+    /// The history file to read
+    #[arg(long)]
+    input: PathBuf,
+
+    /// The format to write
+    #[arg(long, value_enum, default_value = "jsonl")]
+    to: OutputFormat,
+
+    /// The file to write, replaced if it exists
+    #[arg(long)]
+    output: PathBuf,
+}
+
+impl Cmd {
+    /// Command runner to convert the history file, exits 1 if it could not be converted.
+    pub fn run(self, context: &mut Context) {
+        let to = self.to.into();
+        let result = match self.from {
+            InputFormat::Bash => import::convert(
+                Ok(Bash::from_path(self.input).with_clock(context.clock)),
+                to,
+                &self.output,
+            ),
+            InputFormat::Zsh => import::convert(
+                Ok(Zsh::from_path(self.input).with_clock(context.clock)),
+                to,
+                &self.output,
+            ),
+            InputFormat::Jsonl => {
+                import::convert(Ok(Jsonl::from_path(self.input)), to, &self.output)
+            }
+        };
+        if let Err(err) = result {
+            eprintln!(
+                "Unable to convert history to {}: {err}",
+                self.output.display()
+            );
+            std::process::exit(1);
+        }
+    }
+}
@@ -0,0 +1,37 @@
+//! Doctor module for checking the raven database for corruption.
+use clap::Parser;
+use log::error;
+use raven_database::{Context, database::sqlite::Sqlite};
+
+use super::pause;
+
+/// Check the raven database for corruption, exits 1 if any problem is found.
+///
+/// Each problem found is written to the raven log.
+#[derive(Parser, Debug)]
+pub struct Cmd {}
+
+impl Cmd {
+    /// Command runner to check the database.
+    pub fn run(self, context: &mut Context) {
+        println!("recording: {}", pause::describe(&pause::state_path()));
+        println!("database backend: {}", context.db.backend_name());
+        let path = Sqlite::database_path(&context.config);
+        match context.db.health_check() {
+            Ok(true) => println!("{}: ok", path.display()),
+            Ok(false) => {
+                eprintln!(
+                    "{}: problems found, see the raven log for details. A stale search index can \
+                     be rebuilt with `raven reindex`.",
+                    path.display()
+                );
+                std::process::exit(1);
+            }
+            Err(err) => {
+                error!("unable to check the database: {err}");
+                eprintln!("{err}");
+                std::process::exit(1);
+            }
+        }
+    }
+}
@@ -0,0 +1,56 @@
+//! Export module for writing the stored history out for backups or analysis.
+use std::{
+    fs::File,
+    io::{self, BufWriter, Write},
+    path::PathBuf,
+};
+
+use clap::Parser;
+use raven_database::{Context, HistoryFilters, export::ExportFormat};
+
+/// Export the Raven history, most recent first.
+///
+/// * `format`: The format to write.
+/// * `output`: The file to write, stdout when not given.
+#[derive(Parser, Debug)]
+pub struct Cmd {
+    /// The format to write
+    #[arg(long, short, value_enum, default_value = "json")]
+    format: ExportFormat,
+
+    /// The file to write, replaced if it exists. Written to stdout when not given
+    #[arg(long, short)]
+    output: Option<PathBuf>,
+}
+
+impl Cmd {
+    /// Command runner to export the history, exits 1 if it could not be written.
+    pub fn run(self, context: &mut Context) {
+        let mut writer: BufWriter<Box<dyn Write>> = match &self.output {
+            Some(path) => match File::create(path) {
+                Ok(file) => BufWriter::new(Box::new(file)),
+                Err(err) => {
+                    eprintln!("raven: unable to create {}: {err}", path.display());
+                    std::process::exit(1);
+                }
+            },
+            None => BufWriter::new(Box::new(io::stdout().lock())),
+        };
+
+        // Rows are written as they are read, so even a large history is never held in memory.
+        match context
+            .db
+            .export(self.format, &mut writer, HistoryFilters::default())
+        {
+            Ok(count) => {
+                if let Some(path) = &self.output {
+                    eprintln!("Exported {count} commands to {}", path.display());
+                }
+            }
+            Err(err) => {
+                eprintln!("raven: export failed: {err}");
+                std::process::exit(1);
+            }
+        }
+    }
+}
@@ -0,0 +1,166 @@
+//! Get module for printing a single stored command.
+use clap::{Parser, ValueEnum};
+use log::error;
+use raven_database::{Context, history::model::History};
+use serde_json::{Map, Value};
+
+/// The fields of a stored `History` which can be printed.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub enum Field {
+    Id,
+    Command,
+    Cwd,
+    Exit,
+    /// Unix timestamp in seconds.
+    Timestamp,
+    SessionId,
+    /// What recorded the command, such as `raven:0.1.5` or `import:zsh`.
+    Source,
+}
+
+impl Field {
+    /// Every field, printed for `--json` when none are requested.
+    const ALL: [Field; 7] = [
+        Field::Id,
+        Field::Command,
+        Field::Cwd,
+        Field::Exit,
+        Field::Timestamp,
+        Field::SessionId,
+        Field::Source,
+    ];
+
+    /// The name of the field, used as the JSON key.
+    fn name(self) -> &'static str {
+        match self {
+            Field::Id => "id",
+            Field::Command => "command",
+            Field::Cwd => "cwd",
+            Field::Exit => "exit",
+            Field::Timestamp => "timestamp",
+            Field::SessionId => "session_id",
+            Field::Source => "source",
+        }
+    }
+
+    /// Read the value of this field from `h`.
+    fn value(self, h: &History) -> Value {
+        match self {
+            Field::Id => Value::from(h.id),
+            Field::Command => Value::from(h.command.as_str()),
+            Field::Cwd => Value::from(h.cwd.as_str()),
+            Field::Exit => Value::from(h.exit_code),
+            Field::Timestamp => Value::from(h.timestamp.unix_timestamp()),
+            Field::SessionId => Value::from(h.session_id.as_str()),
+            Field::Source => Value::from(h.source.as_str()),
+        }
+    }
+}
+
+/// Print the command stored for a history id.
+///
+/// Resolves the lines printed by `raven search --fzf` back into the original command.
+///
+/// * `id`: The raven db id of the command.
+/// * `json`: Print the row as a JSON object instead.
+/// * `fields`: Only print these fields, tab separated unless `json` is set.
+#[derive(Parser, Debug)]
+pub struct Cmd {
+    /// The raven db id of the command
+    id: i64,
+
+    /// Print the row as a JSON object with sorted keys
+    #[arg(long)]
+    json: bool,
+
+    /// Comma separated fields to print, tab separated in the given order
+    #[arg(long, value_enum, value_delimiter = ',')]
+    fields: Vec<Field>,
+}
+
+impl Cmd {
+    /// Command runner to print the stored command, exits 1 if it does not exist.
+    pub fn run(self, context: &mut Context) {
+        match context.db.get(self.id) {
+            Ok(Some(h)) => println!("{}", self.format(&h)),
+            Ok(None) => std::process::exit(1),
+            Err(err) => {
+                error!("unable to get history {}: {err}", self.id);
+                std::process::exit(1);
+            }
+        }
+    }
+
+    /// Format `h` according to the requested output options.
+    fn format(&self, h: &History) -> String {
+        if self.json {
+            let fields = if self.fields.is_empty() {
+                &Field::ALL[..]
+            } else {
+                &self.fields[..]
+            };
+            let object = fields
+                .iter()
+                .map(|field| (field.name().to_string(), field.value(h)))
+                .collect::<Map<String, Value>>();
+            return Value::Object(object).to_string();
+        }
+
+        if self.fields.is_empty() {
+            return h.command.clone();
+        }
+
+        self.fields
+            .iter()
+            .map(|field| match field.value(h) {
+                Value::String(value) => value,
+                value => value.to_string(),
+            })
+            .collect::<Vec<String>>()
+            .join("\t")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use time::OffsetDateTime;
+
+    fn sample() -> History {
+        History::builder()
+            .id(3)
+            .command("echo \"hi\"".to_string())
+            .cwd("/tmp".to_string())
+            .exit_code(1)
+            .timestamp(OffsetDateTime::from_unix_timestamp(1_700_000_000).unwrap())
+            .session_id("s1".to_string())
+            .source("import:zsh".to_string())
+            .build()
+    }
+
+    fn cmd(json: bool, fields: Vec<Field>) -> Cmd {
+        Cmd {
+            id: 3,
+            json,
+            fields,
+        }
+    }
+
+    #[test]
+    fn test_format() {
+        let h = sample();
+        assert_eq!(cmd(false, vec![]).format(&h), "echo \"hi\"");
+        assert_eq!(
+            cmd(false, vec![Field::Exit, Field::Cwd]).format(&h),
+            "1\t/tmp"
+        );
+        assert_eq!(
+            cmd(true, vec![]).format(&h),
+            r#"{"command":"echo \"hi\"","cwd":"/tmp","exit":1,"id":3,"session_id":"s1","source":"import:zsh","timestamp":1700000000}"#
+        );
+        assert_eq!(
+            cmd(true, vec![Field::Command, Field::Id]).format(&h),
+            r#"{"command":"echo \"hi\"","id":3}"#
+        );
+    }
+}
@@ -1,47 +1,159 @@
 //! History module for storing shell history in the raven db.
 use clap::Subcommand;
-use raven_common::utils;
-use raven_database::{Context, current_context, history::model::History};
+use raven_common::{config::Config, utils};
+use raven_database::{
+    Context,
+    database::DatabaseErrorKind,
+    history::model::{History, HistoryUpdate},
+};
+use serde::Deserialize;
 use time::OffsetDateTime;
 
+use super::pause;
+
+/// Printed by `history start` instead of an id when nothing was saved, the shell hooks skip
+/// `history end` for it.
+const DISABLED_ID: &str = "";
+
 /// `History` subcommands for storing shell history in the raven db.
 #[derive(Subcommand, Debug)]
 #[command(infer_subcommands = true)]
 pub enum Cmd {
     /// Stores an initial command to the history database.
-    Start { command: Vec<String> },
+    Start {
+        #[arg(required_unless_present = "json")]
+        command: Vec<String>,
+
+        /// Read every field from a JSON object instead of the environment, e.g.
+        /// '{"command":"ls","cwd":"/tmp","timestamp":1700000000}'. `session_id` and `tty` are
+        /// optional
+        #[arg(long, conflicts_with = "command")]
+        json: Option<String>,
+    },
 
     /// Updates a command with the commands results
     End {
-        id: String,
+        #[arg(required_unless_present = "json")]
+        id: Option<String>,
+
+        #[arg(long, short, required_unless_present = "json")]
+        exit: Option<i64>,
 
-        #[arg(long, short)]
-        exit: i64,
+        /// Read the fields from a JSON object instead, e.g. '{"id":42,"exit":0}'
+        #[arg(long, conflicts_with_all = ["id", "exit"])]
+        json: Option<String>,
     },
 }
 
+/// The `raven history start --json` payload.
+///
+/// * `command`: The command that is about to be run, must not be empty.
+/// * `cwd`: The directory the command is run in.
+/// * `timestamp`: Unix timestamp in seconds when the command was started.
+/// * `session_id`: Identifier of the session running the command.
+/// * `tty`: The terminal the command is run in.
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct StartPayload {
+    command: String,
+    cwd: String,
+    timestamp: i64,
+    #[serde(default)]
+    session_id: String,
+    #[serde(default)]
+    tty: String,
+}
+
+/// The `raven history end --json` payload.
+///
+/// * `id`: The raven db id printed by `raven history start`.
+/// * `exit`: The exit code of the command.
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct EndPayload {
+    id: i64,
+    exit: i64,
+}
+
+impl StartPayload {
+    /// Parse and validate a start payload.
+    fn parse(json: &str) -> Result<History, String> {
+        let payload: StartPayload = serde_json::from_str(json).map_err(|err| err.to_string())?;
+        if payload.command.trim().is_empty() {
+            return Err(String::from("command must not be empty"));
+        }
+        let timestamp = OffsetDateTime::from_unix_timestamp(payload.timestamp)
+            .map_err(|err| format!("invalid timestamp: {err}"))?;
+        Ok(History::capture()
+            .cwd(payload.cwd)
+            .session_id(payload.session_id)
+            .tty(payload.tty)
+            .command(payload.command)
+            .timestamp(timestamp)
+            .build()
+            .into())
+    }
+}
+
 impl Cmd {
     /// Runs the matching [History] subcommand.
     pub fn run(self, context: &mut Context) {
         match self {
-            Self::Start { command } => Self::handle_start(context, &command),
-            Self::End { id, exit } => Self::handle_end(&id, exit),
+            Self::Start {
+                json: Some(json), ..
+            } => match StartPayload::parse(&json) {
+                Ok(history) => println!("{}", Self::save(context, &history)),
+                Err(err) => exit_invalid("start", &err),
+            },
+            Self::Start { command, .. } => println!("{}", Self::handle_start(context, &command)),
+            Self::End {
+                json: Some(json), ..
+            } => match serde_json::from_str::<EndPayload>(&json) {
+                Ok(payload) => Self::handle_end(context, &payload.id.to_string(), payload.exit),
+                Err(err) => exit_invalid("end", &err.to_string()),
+            },
+            Self::End { id, exit, .. } => {
+                // Both are required by clap unless --json is given.
+                if let (Some(id), Some(exit)) = (id, exit) {
+                    Self::handle_end(context, &id, exit);
+                }
+            }
         }
     }
 
     /// Hook for when the next command being run is known, but has not yet been executed.
     /// For ZSH, this is the preexec hook.
     ///
+    /// Returns the id to print, see [`Self::save`].
+    ///
     /// * `command`: The shell command that is about to be run by the shell.
-    fn handle_start(context: &mut Context, command: &[String]) {
+    fn handle_start(context: &mut Context, command: &[String]) -> String {
         let captured = History::capture()
             .cwd(utils::get_current_dir())
+            .session_id(utils::get_session_id())
+            .tty(utils::get_tty())
             .command(command.join(" "))
-            .timestamp(OffsetDateTime::now_utc())
+            .timestamp((context.clock)())
             .build();
-        match context.db.save(&captured.into()) {
-            // Print the ID to stdout, it will be used for history end {id}
-            Ok(id) => println!("{id}"),
+        Self::save(context, &captured.into())
+    }
+
+    /// Save a started command and return its id, used for `history end {id}`.
+    ///
+    /// Nothing is saved while recording is disabled or paused, [`DISABLED_ID`] is returned
+    /// instead.
+    fn save(context: &mut Context, history: &History) -> String {
+        let disable = std::env::var("RAVEN_DISABLE").ok();
+        if !recording_enabled(&context.config, disable.as_deref())
+            || pause::is_paused(&pause::state_path())
+        {
+            return DISABLED_ID.to_string();
+        }
+
+        match context.db.save(history) {
+            Ok(id) => id.to_string(),
+            // Already stored with `[history] unique` set, so there is nothing to end either.
+            Err(err) if err.kind == DatabaseErrorKind::Duplicate => DISABLED_ID.to_string(),
             Err(err) => panic!("{err}"),
         }
     }
@@ -51,8 +163,9 @@ impl Cmd {
     ///
     /// * `id`: The raven db id for the command that just finished.
     /// * `exit`: the exit code for the command
-    fn handle_end(id: &str, exit: i64) {
-        if id.trim() == "" {
+    fn handle_end(context: &Context, id: &str, exit: i64) {
+        let disable = std::env::var("RAVEN_DISABLE").ok();
+        if id.trim() == DISABLED_ID || !recording_enabled(&context.config, disable.as_deref()) {
             return;
         }
 
@@ -61,12 +174,104 @@ impl Cmd {
             return;
         };
 
-        let context = current_context();
-        let Ok(Some(mut h)) = context.db.get(parsed_id) else {
-            return;
+        // Only touch the exit code, the command may have been edited since it started.
+        let _ = context
+            .db
+            .update_partial(parsed_id, &HistoryUpdate::builder().exit_code(exit).build());
+    }
+}
+
+/// Whether commands are recorded, which `[history] enabled = false` or a non-empty
+/// `RAVEN_DISABLE` environment variable turn off.
+///
+/// * `config`: The raven config.
+/// * `disable`: The value of `RAVEN_DISABLE`, if it is set.
+fn recording_enabled(config: &Config, disable: Option<&str>) -> bool {
+    disable.is_none_or(str::is_empty) && config.history_enabled()
+}
+
+/// Report a malformed `--json` payload and exit 1.
+///
+/// * `subcommand`: The history subcommand the payload was given to.
+/// * `err`: Why the payload was rejected.
+fn exit_invalid(subcommand: &str, err: &str) -> ! {
+    eprintln!("raven: invalid history {subcommand} --json payload: {err}");
+    std::process::exit(1);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use raven_common::config::HistoryConfig;
+    use raven_database::database::sqlite::Sqlite;
+    use raven_database::history::model::CAPTURED_SOURCE;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_recording_enabled() {
+        let mut config = Config::default();
+        assert!(recording_enabled(&config, None));
+        assert!(recording_enabled(&config, Some("")));
+        assert!(!recording_enabled(&config, Some("1")));
+
+        config.history = Some(HistoryConfig {
+            enabled: Some(false),
+            ..Default::default()
+        });
+        assert!(!recording_enabled(&config, None));
+    }
+
+    #[test]
+    fn test_handle_start_disabled() {
+        let dir = TempDir::new().expect("Failed to create temp dir");
+        let mut config = Config::default();
+        config.set_database_file(&dir.path().join("raven.db"));
+        config.history = Some(HistoryConfig {
+            enabled: Some(false),
+            ..Default::default()
+        });
+        let mut context = Context {
+            cwd: String::from("/tmp"),
+            db: Box::new(Sqlite::new(&config)),
+            config,
+            clock: || OffsetDateTime::from_unix_timestamp(1_700_000_000).unwrap(),
         };
 
-        h.exit_code = exit;
-        let _ = context.db.update(&h);
+        let id = Cmd::handle_start(&mut context, &[String::from("ls")]);
+        assert_eq!(id, DISABLED_ID);
+        assert_eq!(context.db.get_history_total().unwrap(), 0);
+        // The shell hooks never end a disabled id, but doing so is harmless.
+        Cmd::handle_end(&context, &id, 0);
+
+        context.config.history = None;
+        let id = Cmd::handle_start(&mut context, &[String::from("ls")]);
+        assert!(id.parse::<i64>().is_ok(), "{id}");
+        assert_eq!(context.db.get_history_total().unwrap(), 1);
+        let saved = context.db.get(id.parse().unwrap()).unwrap().unwrap();
+        assert_eq!(saved.timestamp.unix_timestamp(), 1_700_000_000);
+        assert_eq!(saved.source, CAPTURED_SOURCE);
+    }
+
+    #[test]
+    fn test_parse_start_payload() {
+        let h = StartPayload::parse(
+            r#"{"command":"ls -la","cwd":"/tmp","timestamp":1700000000,"tty":"/dev/pts/1"}"#,
+        )
+        .unwrap();
+        assert_eq!(h.command, "ls -la");
+        assert_eq!(h.cwd, "/tmp");
+        assert_eq!(h.timestamp.unix_timestamp(), 1_700_000_000);
+        assert_eq!(h.session_id, "");
+        assert_eq!(h.tty, "/dev/pts/1");
+        assert_eq!(h.exit_code, -1);
+
+        let err = StartPayload::parse(r#"{"command":"ls","timestamp":0}"#).unwrap_err();
+        assert!(err.contains("missing field `cwd`"), "{err}");
+        let err = StartPayload::parse(r#"{"command":" ","cwd":"/","timestamp":0}"#).unwrap_err();
+        assert_eq!(err, "command must not be empty");
+        let err = StartPayload::parse(r#"{"command":"ls","cwd":"/","timestamp":0,"exit":0}"#)
+            .unwrap_err();
+        assert!(err.contains("unknown field `exit`"), "{err}");
+        assert!(StartPayload::parse("{\"command\":").is_err());
     }
 }
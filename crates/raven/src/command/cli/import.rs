@@ -1,59 +1,228 @@
-use std::env;
+use std::{
+    env,
+    fs::{self, File},
+    io::{self, BufWriter},
+    path::{Path, PathBuf},
+};
 
-use clap::Parser;
+use clap::{Parser, Subcommand};
 use raven_database::{
     Context,
     database::DatabaseError,
+    export::{ExportFormat, Exporter},
     history::model::History,
-    import::{ImportError, Importer, LoadError, Loader, zsh::Zsh},
+    import::{
+        ImportError, Importer, LoadError, Loader, aliases::read_aliases, bash::Bash, jsonl::Jsonl,
+        zsh::Zsh,
+    },
 };
+use time::UtcOffset;
+
+use super::search::{TimeBound, parse_time_bound};
 
 #[derive(Debug, Parser)]
-pub enum Cmd {
+pub struct Cmd {
+    #[command(subcommand)]
+    source: Source,
+
+    /// Only import commands run since this date (YYYY-MM-DD) or time (YYYY-MM-DDTHH:MM[:SS]) in
+    /// UTC, or within this long ago, such as 30d. Commands without a timestamp in the history
+    /// file, such as zsh history not written in the extended format, are always imported
+    #[arg(long, global = true, value_parser = parse_time_bound)]
+    since: Option<TimeBound>,
+}
+
+#[derive(Debug, Subcommand)]
+enum Source {
     /// Import history for the current shell
     Auto,
 
+    /// Import history from the bash history file, with or without `HISTTIMEFORMAT` timestamps
+    ///
+    /// Commands without a timestamp are dated counting back from when the history file was last
+    /// written, so they are only skipped as already stored while the file is unchanged
+    Bash,
+
     /// Import history from the zsh history file
-    Zsh,
+    ///
+    /// Commands not written in the extended format are dated counting back from when the history
+    /// file was last written, so they are only skipped as already stored while the file is
+    /// unchanged
+    Zsh {
+        /// Offset from UTC the history file timestamps were written in, e.g. +02:00. Imported
+        /// timestamps are shifted back by it. Defaults to treating them as UTC
+        #[arg(long, value_parser = parse_tz_offset, allow_hyphen_values = true)]
+        tz_offset: Option<UtcOffset>,
+
+        /// Convert the history to a JSON Lines file at this path instead of importing it
+        #[arg(long)]
+        to: Option<PathBuf>,
+
+        /// Also record the aliases defined in this file, such as the output of `alias` or a
+        /// file of `alias name='command'` lines, so the command behind an alias can be found
+        #[arg(long, value_name = "ALIAS_FILE", conflicts_with = "to")]
+        expand_aliases: Option<PathBuf>,
+    },
+
+    /// Import history from a JSON Lines file, such as one written by `raven import zsh --to`
+    Jsonl {
+        /// The file to import
+        path: PathBuf,
+    },
 }
 
 impl Cmd {
     pub fn run(self, context: &mut Context) {
-        match self {
-            Self::Auto => {
+        let since = self.since.map(|since| since.resolve((context.clock)()));
+        let bash = |bash: Bash| match since {
+            Some(since) => bash.with_since(since),
+            None => bash,
+        };
+        let zsh = |zsh: Zsh| match since {
+            Some(since) => zsh.with_since(since),
+            None => zsh,
+        };
+        match self.source {
+            Source::Auto => {
                 let shell = env::var("SHELL").unwrap_or_else(|_| String::from("NO_SHELL"));
                 if shell.ends_with("/zsh") {
                     println!("Detected ZSH!");
-                    import::<Zsh>(context).expect("expected zsh import");
+                    let importer = Zsh::new().map(zsh);
+                    import(context, importer).expect("expected zsh import");
+                    return;
+                }
+                if shell.ends_with("/bash") {
+                    println!("Detected Bash!");
+                    let importer = Bash::new().map(bash);
+                    import(context, importer).expect("expected bash import");
                     return;
                 }
                 panic!("not able to detect a supported shell type.")
             }
-            Self::Zsh => {
+            Source::Bash => {
+                let importer = Bash::new().map(bash);
+                import(context, importer).expect("Expected bash import");
+            }
+            Source::Zsh {
+                tz_offset,
+                to,
+                expand_aliases,
+            } => {
+                let importer =
+                    Zsh::new().map(|z| zsh(z.with_tz_offset(tz_offset.unwrap_or(UtcOffset::UTC))));
+                if let Some(to) = to {
+                    if let Err(err) = convert(importer, ExportFormat::Jsonl, &to) {
+                        eprintln!("Unable to convert history to {}: {err}", to.display());
+                        std::process::exit(1);
+                    }
+                    return;
+                }
                 println!("Importing zsh");
-                import::<Zsh>(context).expect("Expected zsh import");
+                import(context, importer).expect("Expected zsh import");
+                if let Some(path) = expand_aliases {
+                    import_aliases(context, &path);
+                }
+            }
+            Source::Jsonl { path } => {
+                let jsonl = Jsonl::from_path(path);
+                let jsonl = match since {
+                    Some(since) => jsonl.with_since(since),
+                    None => jsonl,
+                };
+                import(context, Ok(jsonl)).expect("Expected jsonl import");
             }
         }
     }
 }
 
+/// Converts shell history to a file in another format, without touching the raven database.
+///
+/// * `importer`: The importer for the shell, or the error creating it
+/// * `format`: The format to write
+/// * `path`: The file to write, replaced if it exists
+pub(super) fn convert<I: Importer>(
+    importer: Result<I, ImportError>,
+    format: ExportFormat,
+    path: &Path,
+) -> io::Result<()> {
+    let importer = importer.map_err(|_| io::Error::other("unable to read the history"))?;
+
+    // Written next to `path` first, so a failed conversion leaves an existing file untouched.
+    let mut partial = path.as_os_str().to_owned();
+    partial.push(".partial");
+    let partial = PathBuf::from(partial);
+
+    let mut writer = BufWriter::new(File::create(&partial)?);
+    let written = Exporter::new(format, &mut writer).and_then(|exporter| {
+        let mut loader = ExportLoader { exporter };
+        let loaded = importer.load(&mut loader);
+        let written = loader.exporter.finish();
+        match loaded {
+            Ok(()) => written,
+            Err(_) => Err(written
+                .err()
+                .unwrap_or_else(|| io::Error::other("unable to convert the history"))),
+        }
+    });
+    drop(writer);
+    let count = match written {
+        Ok(count) => count,
+        Err(err) => {
+            let _ = fs::remove_file(&partial);
+            return Err(err);
+        }
+    };
+    fs::rename(&partial, path)?;
+    println!(
+        "done! Converted {count} {} commands to {}",
+        I::NAME,
+        path.display()
+    );
+    Ok(())
+}
+
 /// Imports Shell history for the provided shell type.
 ///
 /// * `context`: The current raven context
-fn import<I: Importer>(context: &mut Context) -> Result<(), ImportError> {
-    let importer = I::new()?;
+/// * `importer`: The importer for the shell, or the error creating it
+fn import<I: Importer>(
+    context: &mut Context,
+    importer: Result<I, ImportError>,
+) -> Result<(), ImportError> {
+    let importer = importer?;
     println!("Importing history for {}", I::NAME);
     let mut loader = HistoryLoader::new(context);
     let _ = importer.load(&mut loader);
     let _ = loader.flush();
-    println!("done! Imported {} commands", loader.count);
+    println!(
+        "done! Imported {} commands, skipped {} already stored",
+        loader.count, loader.skipped
+    );
     Ok(())
 }
 
+/// Records the aliases defined in the file at `path`, exits 1 if they can't be read or stored.
+fn import_aliases(context: &mut Context, path: &Path) {
+    let Ok(aliases) = read_aliases(path) else {
+        eprintln!("Unable to read aliases from {}", path.display());
+        std::process::exit(1);
+    };
+    match context.db.save_aliases(&aliases) {
+        Ok(count) => println!("done! Recorded {count} aliases"),
+        Err(err) => {
+            eprintln!("Unable to record aliases: {err}");
+            std::process::exit(1);
+        }
+    }
+}
+
 pub struct HistoryLoader<'a> {
     buf: Vec<History>,
     context: &'a mut Context,
+    /// Number of commands saved.
     count: usize,
+    /// Number of commands skipped as they were already stored.
+    skipped: usize,
 }
 
 impl<'a> HistoryLoader<'a> {
@@ -62,14 +231,18 @@ impl<'a> HistoryLoader<'a> {
             buf: Vec::with_capacity(1000),
             context,
             count: 0,
+            skipped: 0,
         }
     }
 
+    /// Save the buffered commands, skipping those already stored.
     fn flush(&mut self) -> Result<(), DatabaseError> {
         if !self.buf.is_empty() {
-            self.context.db.save_bulk(&self.buf)?;
+            let saved = self.context.db.save_bulk_dedup(&self.buf)?.len();
+            self.count += saved;
+            self.skipped += self.buf.len() - saved;
+            self.buf.clear();
         }
-        self.count += self.buf.len();
         Ok(())
     }
 }
@@ -78,12 +251,61 @@ impl Loader for HistoryLoader<'_> {
     fn push(&mut self, hist: History) -> Result<(), raven_database::import::LoadError> {
         self.buf.push(hist);
         if self.buf.len() == self.buf.capacity() {
-            match self.context.db.save_bulk(&self.buf) {
-                Ok(_) => self.buf.clear(),
-                Err(_) => return Err(LoadError),
-            }
-            self.count += self.buf.capacity();
+            self.flush().map_err(|_| LoadError)?;
         }
         Ok(())
     }
 }
+
+/// A `Loader` which writes each pushed `History` with an `Exporter`, instead of saving it to the
+/// database.
+struct ExportLoader<'w> {
+    exporter: Exporter<'w>,
+}
+
+impl Loader for ExportLoader<'_> {
+    fn push(&mut self, hist: History) -> Result<(), LoadError> {
+        self.exporter.push(&hist).map_err(|_| LoadError)
+    }
+}
+
+/// Parse a UTC offset given as `[+-]HH[:MM]`.
+///
+/// * `value`: The offset argument, e.g. `+02:00`, `-5` or `+0530`.
+pub(crate) fn parse_tz_offset(value: &str) -> Result<UtcOffset, String> {
+    let invalid = || format!("invalid offset '{value}', expected [+-]HH[:MM]");
+    let (sign, rest) = match value.split_at_checked(1) {
+        Some(("-", rest)) => (-1, rest),
+        Some(("+", rest)) => (1, rest),
+        _ => (1, value),
+    };
+    let digits = rest.replace(':', "");
+    if digits.is_empty() || digits.len() > 4 || !digits.bytes().all(|b| b.is_ascii_digit()) {
+        return Err(invalid());
+    }
+    let (hours, minutes) = if digits.len() > 2 {
+        digits.split_at(digits.len() - 2)
+    } else {
+        (digits.as_str(), "0")
+    };
+    let hours: i8 = hours.parse().map_err(|_| invalid())?;
+    let minutes: i8 = minutes.parse().map_err(|_| invalid())?;
+    UtcOffset::from_hms(sign * hours, sign * minutes, 0).map_err(|_| invalid())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_tz_offset() {
+        let offset = |h, m| UtcOffset::from_hms(h, m, 0).unwrap();
+        assert_eq!(parse_tz_offset("+02:00"), Ok(offset(2, 0)));
+        assert_eq!(parse_tz_offset("-5"), Ok(offset(-5, 0)));
+        assert_eq!(parse_tz_offset("0530"), Ok(offset(5, 30)));
+        assert_eq!(parse_tz_offset("-09:30"), Ok(offset(-9, -30)));
+        assert!(parse_tz_offset("").is_err());
+        assert!(parse_tz_offset("+2h").is_err());
+        assert!(parse_tz_offset("+30:00").is_err());
+    }
+}
@@ -1,5 +1,6 @@
 use clap::{Parser, ValueEnum};
 use raven_database::Context;
+mod fish;
 mod zsh;
 
 #[derive(Parser, Debug)]
@@ -14,6 +15,7 @@ pub struct Cmd {
 /// An enumeration of supported Shells.
 pub enum Shell {
     Zsh,
+    Fish,
 }
 
 impl Cmd {
@@ -23,6 +25,9 @@ impl Cmd {
             Shell::Zsh => {
                 zsh::init(context);
             }
+            Shell::Fish => {
+                fish::init(context);
+            }
         }
     }
 }
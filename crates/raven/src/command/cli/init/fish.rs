@@ -0,0 +1,33 @@
+use raven_database::Context;
+
+const BIND_UP_ARROW: &str = r"bind \e\[A _raven_search_history_up
+bind \eOA _raven_search_history_up
+bind -M insert \e\[A _raven_search_history_up
+bind -M insert \eOA _raven_search_history_up";
+
+const BIND_CTRL_R: &str = r"bind \cr _raven_search_history
+bind -M insert \cr _raven_search_history";
+
+/// Initialize Raven for the fish shell.
+pub fn init(context: &mut Context) {
+    init_static();
+
+    // Skip printing keybinds if the 'skip_keybinds' configuration option is set to true.
+    // Defaults to `false` (i.e., print keybinds) if the option is not present.
+    if !context
+        .config
+        .keybinds
+        .as_ref()
+        .and_then(|config| config.skip_keybinds)
+        .unwrap_or(false)
+    {
+        println!("{BIND_UP_ARROW}");
+        println!("{BIND_CTRL_R}");
+    }
+}
+
+/// Emits the static fish initialization script to stdout
+fn init_static() {
+    let base = include_str!("../../../shell/raven.fish");
+    println!("{base}");
+}
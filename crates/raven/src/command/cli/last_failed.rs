@@ -0,0 +1,39 @@
+//! Last failed module for finding the most recent command which failed.
+use clap::Parser;
+use log::error;
+use raven_database::{Context, HistoryFilters};
+
+/// Print the most recent command which ended with a non-zero exit code, exits 1 if there is none.
+///
+/// Commands which are still running, or were imported without an exit code, are skipped.
+///
+/// * `cwd`: Only consider commands run in the current directory.
+#[derive(Parser, Debug)]
+pub struct Cmd {
+    /// Only consider commands run in the current directory
+    #[arg(long)]
+    cwd: bool,
+}
+
+impl Cmd {
+    /// Command runner to print the last failed command.
+    pub fn run(self, context: &mut Context) {
+        let filters = HistoryFilters {
+            cwd: self.cwd.then(|| context.cwd.clone()),
+            limit: Some(1),
+            failed: true,
+            ..Default::default()
+        };
+
+        match context.db.search("", filters) {
+            Ok(history) => match history.first() {
+                Some(h) => println!("{}", h.command),
+                None => std::process::exit(1),
+            },
+            Err(err) => {
+                error!("unable to find the last failed command: {err}");
+                std::process::exit(1);
+            }
+        }
+    }
+}
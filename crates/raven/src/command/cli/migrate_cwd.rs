@@ -0,0 +1,34 @@
+//! Migrate cwd module for moving history along with a moved directory.
+use clap::Parser;
+use log::error;
+use raven_database::Context;
+
+/// Rewrite the directory of history run in `from`, or below it, to `to`.
+///
+/// Keeps `--cwd` searches working after a project is moved, e.g. from `/home/me/old` to
+/// `/home/me/new`. Exits 1 if the history could not be rewritten.
+///
+/// * `from`: The old directory.
+/// * `to`: The new directory.
+#[derive(Parser, Debug)]
+pub struct Cmd {
+    /// The old directory
+    from: String,
+
+    /// The new directory
+    to: String,
+}
+
+impl Cmd {
+    /// Command runner to rewrite the stored directories.
+    pub fn run(self, context: &mut Context) {
+        match context.db.rewrite_cwd(&self.from, &self.to) {
+            Ok(rewritten) => println!("Rewrote {rewritten} history entries."),
+            Err(err) => {
+                error!("unable to rewrite {} to {}: {err}", self.from, self.to);
+                eprintln!("{err}");
+                std::process::exit(1);
+            }
+        }
+    }
+}
@@ -0,0 +1,93 @@
+//! Pause module for temporarily stopping history recording.
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+use clap::Parser;
+use log::error;
+use raven_common::utils::get_data_dir;
+use raven_database::Context;
+
+/// The file whose existence marks recording as paused.
+const STATE_FILE: &str = "paused";
+
+/// Stop recording commands until `raven resume` is run.
+///
+/// Unlike `[history] enabled`, the pause applies to every shell and outlives them.
+#[derive(Parser, Debug)]
+pub struct Cmd {}
+
+impl Cmd {
+    /// Command runner to pause recording.
+    pub fn run(self, _context: &mut Context) {
+        if let Err(err) = set_paused(&state_path(), true) {
+            error!("unable to pause recording: {err}");
+            eprintln!("raven: unable to pause recording: {err}");
+            std::process::exit(1);
+        }
+        println!("Recording paused, run `raven resume` to start again.");
+    }
+}
+
+/// The path of the pause state file, which only exists while recording is paused.
+pub(super) fn state_path() -> PathBuf {
+    get_data_dir().join(STATE_FILE)
+}
+
+/// Whether recording is paused, a missing state file means it is not.
+///
+/// * `path`: The pause state file.
+pub(super) fn is_paused(path: &Path) -> bool {
+    path.exists()
+}
+
+/// Describe the pause state for diagnostics, `paused` or `active`.
+///
+/// * `path`: The pause state file.
+pub(super) fn describe(path: &Path) -> &'static str {
+    if is_paused(path) { "paused" } else { "active" }
+}
+
+/// Pause or resume recording, doing nothing if it already is in that state.
+///
+/// * `path`: The pause state file.
+/// * `paused`: Whether recording should be paused.
+pub(super) fn set_paused(path: &Path, paused: bool) -> io::Result<()> {
+    if paused {
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        return fs::write(path, "");
+    }
+
+    match fs::remove_file(path) {
+        Err(err) if err.kind() != io::ErrorKind::NotFound => Err(err),
+        _ => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_set_paused() {
+        let dir = TempDir::new().expect("Failed to create temp dir");
+        let path = dir.path().join("raven").join(STATE_FILE);
+        assert!(!is_paused(&path));
+        assert_eq!(describe(&path), "active");
+
+        set_paused(&path, true).expect("Failed to pause");
+        assert!(is_paused(&path));
+        assert_eq!(describe(&path), "paused");
+        set_paused(&path, true).expect("Failed to pause again");
+        assert!(is_paused(&path));
+
+        set_paused(&path, false).expect("Failed to resume");
+        assert!(!is_paused(&path));
+        set_paused(&path, false).expect("Failed to resume again");
+        assert!(!is_paused(&path));
+    }
+}
@@ -0,0 +1,28 @@
+//! Reindex module for rebuilding the search index.
+use clap::Parser;
+use log::error;
+use raven_database::{Context, database::sqlite::Sqlite};
+
+/// Rebuild the search index with the tokenizer from the `[search]` config.
+///
+/// The tokenizer is only applied automatically when the database is created, so run this after
+/// changing it.
+#[derive(Parser, Debug)]
+pub struct Cmd {}
+
+impl Cmd {
+    /// Command runner to rebuild the search index.
+    pub fn run(self, context: &mut Context) {
+        let tokenizer = context.config.tokenizer();
+        let mut db = Sqlite::new(&context.config);
+        if let Err(err) = db.reindex(tokenizer) {
+            error!("unable to reindex: {err}");
+            eprintln!("{err}");
+            std::process::exit(1);
+        }
+        println!(
+            "Rebuilt the search index with the {} tokenizer.",
+            tokenizer.name()
+        );
+    }
+}
@@ -0,0 +1,22 @@
+//! Resume module for recording history again after `raven pause`.
+use clap::Parser;
+use log::error;
+use raven_database::Context;
+
+use super::pause;
+
+/// Start recording commands again after `raven pause`.
+#[derive(Parser, Debug)]
+pub struct Cmd {}
+
+impl Cmd {
+    /// Command runner to resume recording.
+    pub fn run(self, _context: &mut Context) {
+        if let Err(err) = pause::set_paused(&pause::state_path(), false) {
+            error!("unable to resume recording: {err}");
+            eprintln!("raven: unable to resume recording: {err}");
+            std::process::exit(1);
+        }
+        println!("Recording resumed.");
+    }
+}
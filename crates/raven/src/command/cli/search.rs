@@ -1,18 +1,33 @@
-use std::io::Write;
+use std::{
+    io::{IsTerminal, Read, Write},
+    time::Duration,
+};
 
 use clap::Parser;
 use log::{debug, error};
+use raven_common::{
+    config::{PrefixPolicy, RankerKind},
+    utils,
+};
 use raven_database::{
-    Context, HistoryFilters, MatchMode, current_context, database::DatabaseError,
-    history::model::History,
+    Context, HistoryFilters, MatchMode, OrderBy, database::DatabaseError, history::model::History,
+    rank,
 };
+use time::{Date, Month, OffsetDateTime, PrimitiveDateTime, Time};
 
 mod app;
-mod duration;
+mod cache;
+mod clipboard;
+pub(super) mod duration;
 mod event;
 mod interactive;
 mod tui;
 
+use tui::Screen;
+
+/// The most recent prefix matches checked for one which extends the query, see `--suggest`.
+const SUGGEST_CANDIDATES: usize = 50;
+
 #[derive(Debug, Parser)]
 pub struct Cmd {
     /// Filter search result by directory
@@ -27,10 +42,16 @@ pub struct Cmd {
     #[arg(long, short)]
     limit: Option<usize>,
 
+    /// Skip this many results, e.g. `--limit 50 --offset 50` for the second page
+    #[arg(long, conflicts_with_all = ["interactive", "suggest", "delete"])]
+    offset: Option<usize>,
+
     /// The command to search for
     query: Option<Vec<String>>,
 
-    /// Flag that tells raven it was invoked from a shell up-key binding.
+    /// Flag that tells raven it was invoked from a shell up-key binding. Without a query or
+    /// filters, the latest distinct commands are listed from a cache instead of searching the
+    /// whole history. The interactive search opens on them, across every directory
     #[arg(long = "shell-up-key", hide = true)]
     shell_up_key: bool,
 
@@ -38,45 +59,298 @@ pub struct Cmd {
     #[arg(long, short)]
     interactive: bool,
 
+    /// Keep the interactive search open after a command is selected, writing each selected
+    /// command to stdout as it is picked. Quit with Esc
+    #[arg(long, requires = "interactive")]
+    keep_open: bool,
+
+    /// Write how the command was accepted on a line before it, `execute` for Enter or `buffer`
+    /// for Ctrl+e, so shell widgets can run it or leave it on the command line to edit
+    #[arg(long, requires = "interactive", conflicts_with = "keep_open")]
+    accept_marker: bool,
+
+    /// Start the interactive search with the cursor at this character position in the query,
+    /// instead of at its end. Also read from `RAVEN_CURSOR`
+    #[arg(long, requires = "interactive")]
+    cursor: Option<usize>,
+
+    /// Pre-fill the interactive search with the first line read from stdin, instead of the query
+    /// arguments or `RAVEN_QUERY`
+    #[arg(long, requires = "interactive", conflicts_with = "query")]
+    query_from_stdin: bool,
+
+    /// Render the interactive search inline in the bottom N rows instead of fullscreen, overrides
+    /// the `[ui] height` config
+    #[arg(long, requires = "interactive")]
+    height: Option<u16>,
+
+    /// Render the interactive search fullscreen without switching to the alternate screen or
+    /// capturing the mouse, for terminals where those misbehave. Overrides the `[ui] height`
+    /// config
+    #[arg(long, requires = "interactive", conflicts_with = "height")]
+    no_alt_screen: bool,
+
     /// Search matching mode
     #[arg(long, short, value_enum)]
     mode: Option<MatchMode>,
+
+    /// Use the query as a raw FTS5 expression, e.g. 'NEAR("git" "push", 3)'. Malformed
+    /// expressions make the search fail
+    #[arg(long, conflicts_with_all = ["mode", "interactive"])]
+    raw: bool,
+
+    /// Find commands matching any of the query words instead of all of them, e.g. `git docker`
+    /// finds both `git status` and `docker ps`
+    #[arg(long, conflicts_with_all = ["mode", "raw"])]
+    any: bool,
+
+    /// Match the query as a regular expression anywhere in the command, e.g. '^git .*--force'.
+    /// Skips the full text search index, so results are ordered by recency
+    #[arg(long, conflicts_with_all = ["mode", "raw", "any", "no_fts", "interactive", "order"])]
+    regex: bool,
+
+    /// Match the query as a plain substring instead of using the full text search index. Slower,
+    /// but predictable when the index tokenizes a command unexpectedly. Results are ordered by
+    /// recency
+    #[arg(long, conflicts_with_all = ["raw", "any", "mode", "interactive", "order"])]
+    no_fts: bool,
+
+    /// Which words of the query also match longer words starting with them: all, last-only (the
+    /// word being typed) or none. Overrides the `[search] prefix_policy` config
+    #[arg(long)]
+    prefix_policy: Option<PrefixPolicy>,
+
+    /// Order results by recency or by how well they match the query, defaults to recency. Toggled
+    /// with Ctrl+S in the interactive search
+    #[arg(long, value_enum)]
+    order: Option<OrderBy>,
+
+    /// Only include commands with at least this many characters
+    #[arg(long)]
+    min_length: Option<usize>,
+
+    /// Only include commands with at most this many characters
+    #[arg(long)]
+    max_length: Option<usize>,
+
+    /// Exclude results from this directory and its subdirectories, can be repeated
+    #[arg(long)]
+    exclude_cwd: Vec<String>,
+
+    /// Only include commands run in this terminal, such as /dev/pts/3
+    #[arg(long)]
+    tty: Option<String>,
+
+    /// Only include commands run within this long ago, such as 30m, 2h or 3d
+    #[arg(long, value_parser = duration::parse_duration, conflicts_with = "after")]
+    within: Option<Duration>,
+
+    /// Only include commands run at or after this time, a date (YYYY-MM-DD), a time in UTC
+    /// (YYYY-MM-DDTHH:MM[:SS]) or a duration before now such as 1d
+    #[arg(long, value_parser = parse_time_bound)]
+    after: Option<TimeBound>,
+
+    /// Only include commands run before this time, in the same forms as --after
+    #[arg(long, value_parser = parse_time_bound)]
+    before: Option<TimeBound>,
+
+    /// Leave out commands which have started but not yet ended, such as the one running raven
+    #[arg(long)]
+    completed: bool,
+
+    /// Only include commands from the previous shell session
+    #[arg(long)]
+    since_last_session: bool,
+
+    /// Print a `cwd<TAB><path>` line before each command
+    #[arg(long)]
+    print_cwd: bool,
+
+    /// Print `id`, `cwd`, `exit` and `timestamp` lines, as `<key><TAB><value>`, before each command
+    #[arg(long)]
+    print_meta: bool,
+
+    /// Print the exit code before each command, as `<exit><TAB><command>`
+    #[arg(long)]
+    print_exit: bool,
+
+    /// Print every match as `<id><TAB><command>` for an external picker such as fzf, resolve the
+    /// chosen id with `raven get <id>`
+    #[arg(long, conflicts_with_all = ["interactive", "print_cwd", "print_meta", "print_exit"])]
+    fzf: bool,
+
+    /// Print only the rest of the first command starting with the query, as ordered by
+    /// `[search] ranker`, for inline autosuggestions. Exits 1 if no command extends the query
+    #[arg(long, conflicts_with_all = [
+        "interactive", "fzf", "print_cwd", "print_meta", "print_exit", "mode", "raw", "any",
+        "regex", "limit",
+    ])]
+    suggest: bool,
+
+    /// Delete every command matching the query and filters instead of printing them, ignoring
+    /// --limit. Asks for confirmation unless --yes is given
+    #[arg(long, conflicts_with_all = [
+        "interactive", "fzf", "suggest", "print_cwd", "print_meta", "print_exit",
+    ])]
+    delete: bool,
+
+    /// Delete without asking for confirmation, as needed when not run from a terminal
+    #[arg(long, requires = "delete")]
+    yes: bool,
+}
+
+/// Open the interactive search to mark entries and delete them in bulk.
+///
+/// Returns the number of entries deleted, or with `dry_run` the entries which would have been
+/// deleted.
+pub(super) fn clean(
+    context: &mut Context,
+    query: &[String],
+    mode: MatchMode,
+    dry_run: bool,
+) -> (usize, Vec<History>) {
+    let marking = interactive::clean(context, query, mode, dry_run);
+    (marking.deleted, marking.would_delete)
 }
 
 impl Cmd {
-    pub fn run(self, _context: &mut Context) {
+    pub fn run(self, context: &mut Context) {
         // Unwrap the query
-        let query = self.query.map_or_else(
-            || {
-                std::env::var("RAVEN_QUERY").map_or_else(
-                    |_| vec![],
-                    |query| {
-                        query
-                            .split(' ')
-                            .map(std::string::ToString::to_string)
-                            .collect()
-                    },
-                )
-            },
-            |query| query,
-        );
+        let query = if self.query_from_stdin {
+            query_from_stdin()
+        } else {
+            resolve_query(self.query, std::env::var("RAVEN_QUERY").ok())
+        };
+
+        if let Some(policy) = self.prefix_policy {
+            // The interactive search reads it from the config it is given.
+            context.config.search.get_or_insert_default().prefix_policy = Some(policy);
+        }
+
+        let mode = if self.raw {
+            MatchMode::Raw
+        } else if self.regex {
+            MatchMode::Regex
+        } else if self.any {
+            MatchMode::FuzzyAny
+        } else {
+            self.mode.unwrap_or_default()
+        };
 
         if self.interactive {
-            let Some(h) = interactive::history(&query, self.mode.unwrap_or_default()) else {
+            let screen = if self.no_alt_screen {
+                Screen::Main
+            } else {
+                self.height
+                    .or(context.config.height())
+                    .map_or(Screen::Alternate, Screen::Inline)
+            };
+            let order = self.order.unwrap_or_default();
+            let cursor = self.cursor.or_else(|| {
+                std::env::var("RAVEN_CURSOR")
+                    .ok()
+                    .and_then(|cursor| cursor.parse().ok())
+            });
+            if self.keep_open {
+                let (print_cwd, print_meta, print_exit) =
+                    (self.print_cwd, self.print_meta, self.print_exit);
+                let accepted = interactive::keep_open(
+                    context,
+                    &query,
+                    mode,
+                    order,
+                    screen,
+                    cursor,
+                    move |h| {
+                        write_history_out(h, print_cwd, print_meta, print_exit);
+                    },
+                );
+                if accepted == 0 {
+                    std::process::exit(1);
+                }
+                return;
+            }
+            let Some((h, accept_mode)) = interactive::history(
+                context,
+                &query,
+                mode,
+                order,
+                screen,
+                cursor,
+                self.shell_up_key,
+            ) else {
                 std::process::exit(1);
             };
-            write_command_out(&h.command);
+            if self.accept_marker {
+                write_command_out(&accept_mode.name().to_string());
+            }
+            write_history_out(&h, self.print_cwd, self.print_meta, self.print_exit);
         } else {
+            if self.raw && query.join(" ").trim().is_empty() {
+                eprintln!("raven: --raw requires a query");
+                std::process::exit(1);
+            }
+            if self.regex && query.join(" ").trim().is_empty() {
+                eprintln!("raven: --regex requires a query");
+                std::process::exit(1);
+            }
+
+            let session_id = if self.since_last_session {
+                let Some(session_id) = previous_session(context) else {
+                    std::process::exit(1)
+                };
+                Some(session_id)
+            } else {
+                None
+            };
+
             let filters = HistoryFilters {
                 exit: self.exit,
                 cwd: self.cwd,
+                cwd_within: None,
                 limit: self.limit,
-                mode: self.mode.unwrap_or_default(),
+                offset: self.offset,
+                mode,
+                session_id,
+                tty: self.tty,
+                min_length: self.min_length,
+                max_length: self.max_length,
+                exclude_cwd: self.exclude_cwd,
+                after: match self.after {
+                    Some(after) => Some(after.resolve((context.clock)())),
+                    None => self
+                        .within
+                        .and_then(|within| after_bound((context.clock)(), within)),
+                },
+                before: self.before.map(|before| before.resolve((context.clock)())),
+                include_running: !self.completed,
+                failed: false,
+                order: self.order.unwrap_or_default(),
+                no_fts: self.no_fts,
+                prefix_policy: context.config.prefix_policy(),
+                timeout: None,
             };
             debug!("search with filters {filters:?}");
-            let Ok(entries) = run_non_interactive(&query, filters) else {
-                // All we can do is exit with failed at this point.
-                std::process::exit(1)
+            if self.delete {
+                delete_matching_out(context, &query, filters, self.yes);
+                return;
+            }
+            if self.fzf {
+                write_picker_out(context, &query, filters);
+                return;
+            }
+            if self.suggest {
+                write_suggestion_out(context, &query, filters);
+                return;
+            }
+
+            let entries = match run_non_interactive(context, &query, filters, self.shell_up_key) {
+                Ok(entries) => entries,
+                Err(err) => {
+                    // Such as a malformed --raw expression or --regex pattern.
+                    eprintln!("raven: {err}");
+                    std::process::exit(1)
+                }
             };
 
             debug!("search had {} results", entries.len());
@@ -89,19 +363,326 @@ impl Cmd {
             }
 
             for entry in entries {
-                write_command_out(&entry.command);
+                write_history_out(&entry, self.print_cwd, self.print_meta, self.print_exit);
             }
         }
     }
 }
 
+/// Write a search result to stdout.
+///
+/// Without any print flags only the command is written. Otherwise the requested metadata is
+/// written first, one `<key>\t<value>` line per field in the order `id`, `cwd`, `exit`,
+/// `timestamp` (unix seconds), followed by the command itself.
+///
+/// * `print_cwd`: Include the directory the command was run in.
+/// * `print_meta`: Include every metadata field.
+/// * `print_exit`: Prefix the command with its exit code, see [`command_line`].
+fn write_history_out(h: &History, print_cwd: bool, print_meta: bool, print_exit: bool) {
+    for line in meta_lines(h, print_cwd, print_meta) {
+        write_command_out(&line);
+    }
+    write_command_out(&command_line(h, print_exit));
+}
+
+/// Format the command of a search result, as `<exit>\t<command>` with `print_exit`.
+///
+/// The exit code only prefixes the first line of a multi-line command, so splitting a result at
+/// its first tab recovers the exit code and the full command.
+fn command_line(h: &History, print_exit: bool) -> String {
+    if print_exit {
+        format!("{}\t{}", h.exit_code, h.command)
+    } else {
+        h.command.clone()
+    }
+}
+
+/// Write every match to stdout as a `<id>\t<command>` line, for piping into an external picker.
+/// Matches are ordered by `[search] ranker`.
+///
+/// Newlines within a command are written as `\n` so each match stays on a single line, the
+/// original command can be resolved from the id with `raven get <id>`.
+fn write_picker_out(context: &Context, query: &[String], filters: HistoryFilters) {
+    let query = query.join(" ");
+    let mut found = false;
+    let mut write = |h: &History| {
+        found = true;
+        write_command_out(&picker_line(h));
+    };
+    // The default ranker keeps the database order, so matches are streamed as they are found
+    // instead of being held in memory to be ranked.
+    let result = match context.config.ranker() {
+        RankerKind::Bm25Recency => context.db.search_each(&query, filters, &mut |h| write(&h)),
+        kind => context.db.search(&query, filters).map(|results| {
+            rank::ranker(kind)
+                .rank(&query, results)
+                .iter()
+                .for_each(&mut write);
+        }),
+    };
+
+    if let Err(err) = result {
+        error!("search error {err}");
+        std::process::exit(1);
+    }
+    if !found {
+        std::process::exit(1);
+    }
+}
+
+/// Delete every command matching `query` and `filters`, after confirming on the terminal unless
+/// `yes` is set.
+///
+/// Exits 1 if nothing matches, or the deletion is refused or not confirmed.
+fn delete_matching_out(context: &Context, query: &[String], filters: HistoryFilters, yes: bool) {
+    let query = query.join(" ");
+    if query.trim().is_empty() && filters.is_unfiltered() {
+        eprintln!("raven: --delete requires a query or a filter");
+        std::process::exit(1);
+    }
+    let count = match context.db.search_count(&query, filters.clone()) {
+        Ok(count) => count,
+        Err(err) => {
+            error!("search error {err}");
+            std::process::exit(1);
+        }
+    };
+    if count == 0 {
+        println!("No history entries match.");
+        std::process::exit(1);
+    }
+    println!("{count} history entries match.");
+    if !yes && !confirm(&format!("Delete {count} history entries? [y/N] ")) {
+        eprintln!("raven: nothing deleted, pass --yes to delete without confirming");
+        std::process::exit(1);
+    }
+    match context.db.delete_matching(&query, filters) {
+        Ok(deleted) => println!("Deleted {deleted} history entries."),
+        Err(err) => {
+            eprintln!("raven: {err}");
+            std::process::exit(1);
+        }
+    }
+}
+
+/// Ask `prompt` on the terminal, true if it is answered with y or yes.
+///
+/// Always false when stdin is not a terminal, there is nobody to answer.
+fn confirm(prompt: &str) -> bool {
+    let stdin = std::io::stdin();
+    if !stdin.is_terminal() {
+        return false;
+    }
+    eprint!("{prompt}");
+    let _ = std::io::stderr().flush();
+    let mut answer = String::new();
+    stdin.read_line(&mut answer).is_ok()
+        && matches!(answer.trim().to_lowercase().as_str(), "y" | "yes")
+}
+
+/// Write the rest of the first ranked command which starts with the query, exits 1 if there is
+/// none.
+///
+/// Prefix searches match the start of any word, so the candidates are checked for one which
+/// starts with the query as typed.
+fn write_suggestion_out(context: &Context, query: &[String], mut filters: HistoryFilters) {
+    let prefix = query.join(" ");
+    filters.mode = MatchMode::Prefix;
+    filters.limit = Some(SUGGEST_CANDIDATES);
+    let candidates = match context.db.search(prefix.trim(), filters) {
+        Ok(candidates) => candidates,
+        Err(err) => {
+            error!("search error {err}");
+            std::process::exit(1);
+        }
+    };
+    let candidates = rank::ranker(context.config.ranker()).rank(prefix.trim(), candidates);
+    let Some(suffix) = candidates
+        .iter()
+        .find_map(|h| suggestion_suffix(&prefix, &h.command))
+    else {
+        std::process::exit(1);
+    };
+    write_command_out(&suffix.to_string());
+}
+
+/// The part of `command` after `prefix`, `None` unless it starts with `prefix` and continues
+/// past it.
+fn suggestion_suffix<'a>(prefix: &str, command: &'a str) -> Option<&'a str> {
+    if prefix.is_empty() {
+        return None;
+    }
+    command
+        .strip_prefix(prefix)
+        .filter(|suffix| !suffix.is_empty())
+}
+
+/// Format a `History` as a single `<id>\t<command>` picker line.
+fn picker_line(h: &History) -> String {
+    format!("{}\t{}", h.id, h.command.replace('\n', "\\n"))
+}
+
+/// Build the metadata lines written ahead of a command.
+///
+/// * `print_cwd`: Include the directory the command was run in.
+/// * `print_meta`: Include every metadata field.
+fn meta_lines(h: &History, print_cwd: bool, print_meta: bool) -> Vec<String> {
+    let mut lines = Vec::new();
+    if print_meta {
+        lines.push(format!("id\t{}", h.id));
+    }
+    if print_cwd || print_meta {
+        lines.push(format!("cwd\t{}", h.cwd));
+    }
+    if print_meta {
+        lines.push(format!("exit\t{}", h.exit_code));
+        lines.push(format!("timestamp\t{}", h.timestamp.unix_timestamp()));
+    }
+    lines
+}
+
 /// Run a `query` against the raven database and return the first result.
 fn run_non_interactive(
+    context: &Context,
     query: &[String],
     filters: HistoryFilters,
+    shell_up_key: bool,
 ) -> Result<Vec<History>, DatabaseError> {
-    let context = current_context();
-    context.db.search(query.join(" ").as_str(), filters)
+    let query = query.join(" ");
+    // The up-key only needs the latest distinct commands, which are cached for it.
+    if shell_up_key
+        && query.trim().is_empty()
+        && filters.is_unfiltered()
+        && filters.offset.is_none()
+    {
+        return context
+            .db
+            .recent_commands(filters.limit.unwrap_or(usize::MAX));
+    }
+    let results = context.db.search(&query, filters)?;
+    Ok(rank::ranker(context.config.ranker()).rank(&query, results))
+}
+
+/// The earliest time a command run within `within` of `now` can have, `None` when that reaches
+/// back further than can be represented and so includes every command.
+pub(super) fn after_bound(now: OffsetDateTime, within: Duration) -> Option<OffsetDateTime> {
+    time::Duration::try_from(within)
+        .ok()
+        .and_then(|within| now.checked_sub(within))
+}
+
+/// A point in time given on the command line, see `parse_time_bound`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(super) enum TimeBound {
+    /// This time.
+    At(OffsetDateTime),
+    /// This long before now.
+    Ago(Duration),
+}
+
+impl TimeBound {
+    /// The time the bound refers to, clamped to the earliest time which can be represented when
+    /// it reaches back further, so nothing was run before it.
+    pub(super) fn resolve(self, now: OffsetDateTime) -> OffsetDateTime {
+        match self {
+            Self::At(at) => at,
+            Self::Ago(ago) => {
+                after_bound(now, ago).unwrap_or_else(|| PrimitiveDateTime::MIN.assume_utc())
+            }
+        }
+    }
+}
+
+/// Parse a point in time, either an ISO-8601 `YYYY-MM-DD` date (midnight UTC) or
+/// `YYYY-MM-DDTHH:MM[:SS][Z]` time in UTC, or a duration before now such as `30d`.
+///
+/// * `value`: The time argument.
+pub(super) fn parse_time_bound(value: &str) -> Result<TimeBound, String> {
+    let value = value.trim();
+    if !value.contains('-') {
+        return duration::parse_duration(value).map(TimeBound::Ago);
+    }
+    let invalid = || {
+        format!("invalid time '{value}', expected YYYY-MM-DD, YYYY-MM-DDTHH:MM[:SS] or a duration")
+    };
+    let (date, time) = match value.split_once(['T', ' ']) {
+        Some((date, time)) => (date, Some(time.strip_suffix('Z').unwrap_or(time))),
+        None => (value, None),
+    };
+
+    let parts: Vec<&str> = date.split('-').collect();
+    let [year, month, day] = parts[..] else {
+        return Err(invalid());
+    };
+    let year: i32 = year.parse().map_err(|_| invalid())?;
+    let month: u8 = month.parse().map_err(|_| invalid())?;
+    let month = Month::try_from(month).map_err(|_| invalid())?;
+    let day: u8 = day.parse().map_err(|_| invalid())?;
+    let date = Date::from_calendar_date(year, month, day).map_err(|_| invalid())?;
+
+    let time = match time {
+        Some(time) => {
+            let parts = time
+                .split(':')
+                .map(str::parse::<u8>)
+                .collect::<Result<Vec<_>, _>>()
+                .map_err(|_| invalid())?;
+            let (hour, minute, second) = match parts[..] {
+                [hour, minute] => (hour, minute, 0),
+                [hour, minute, second] => (hour, minute, second),
+                _ => return Err(invalid()),
+            };
+            Time::from_hms(hour, minute, second).map_err(|_| invalid())?
+        }
+        None => Time::MIDNIGHT,
+    };
+    Ok(TimeBound::At(date.with_time(time).assume_utc()))
+}
+
+/// Resolve the most recent session other than the one this shell is running in.
+fn previous_session(context: &Context) -> Option<String> {
+    match context.db.previous_session(&utils::get_session_id()) {
+        Ok(session_id) => session_id,
+        Err(err) => {
+            error!("unable to resolve previous session: {err}");
+            None
+        }
+    }
+}
+
+/// The query to search for, the query arguments or otherwise `RAVEN_QUERY`.
+///
+/// `RAVEN_QUERY` is kept as a single word, so its spacing is passed through intact.
+fn resolve_query(args: Option<Vec<String>>, env_query: Option<String>) -> Vec<String> {
+    args.unwrap_or_else(|| env_query.into_iter().collect())
+}
+
+/// Read the initial query of the interactive search from stdin.
+///
+/// Nothing is read when stdin is a terminal, as nothing was piped in.
+fn query_from_stdin() -> Vec<String> {
+    let stdin = std::io::stdin();
+    if stdin.is_terminal() {
+        return vec![];
+    }
+    let mut input = String::new();
+    if let Err(err) = stdin.lock().read_to_string(&mut input) {
+        error!("unable to read the query from stdin: {err}");
+        return vec![];
+    }
+    parse_stdin_query(&input)
+}
+
+/// The query in `input` read from stdin, its first line as a single word so the spacing within
+/// it is kept.
+fn parse_stdin_query(input: &str) -> Vec<String> {
+    input
+        .lines()
+        .next()
+        .map(str::trim)
+        .filter(|query| !query.is_empty())
+        .map(|query| vec![query.to_string()])
+        .unwrap_or_default()
 }
 
 /// Write the `command` out to stdout
@@ -115,3 +696,173 @@ fn write_command_out(command: &String) {
     }
     let _ = w.flush();
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use time::OffsetDateTime;
+
+    #[test]
+    fn test_suggestion_suffix() {
+        assert_eq!(
+            suggestion_suffix("git ch", "git checkout main"),
+            Some("eckout main")
+        );
+        assert_eq!(suggestion_suffix("git ", "git status"), Some("status"));
+        assert_eq!(suggestion_suffix("ls", "ls -la"), Some(" -la"));
+        // Nothing to complete when the command is exactly what was typed.
+        assert_eq!(suggestion_suffix("ls", "ls"), None);
+        // Prefix searches also match later words, which can't be completed inline.
+        assert_eq!(suggestion_suffix("checkout", "git checkout main"), None);
+        assert_eq!(suggestion_suffix("", "ls"), None);
+        assert_eq!(
+            suggestion_suffix("for f", "for f in *\ndo echo $f\ndone"),
+            Some(" in *\ndo echo $f\ndone")
+        );
+    }
+
+    #[test]
+    fn test_meta_lines() {
+        let h = History::builder()
+            .id(7)
+            .command("ls".to_string())
+            .cwd("/tmp".to_string())
+            .exit_code(2)
+            .timestamp(OffsetDateTime::from_unix_timestamp(1_700_000_000).unwrap())
+            .build();
+
+        assert!(meta_lines(&h, false, false).is_empty());
+        assert_eq!(meta_lines(&h, true, false), vec!["cwd\t/tmp"]);
+        assert_eq!(
+            meta_lines(&h, true, true),
+            vec!["id\t7", "cwd\t/tmp", "exit\t2", "timestamp\t1700000000"]
+        );
+    }
+
+    #[test]
+    fn test_command_line() {
+        let h = |command: &str, exit_code: i64| {
+            History::builder()
+                .id(7)
+                .command(command.to_string())
+                .cwd("/tmp".to_string())
+                .exit_code(exit_code)
+                .timestamp(OffsetDateTime::from_unix_timestamp(1_700_000_000).unwrap())
+                .build()
+        };
+
+        assert_eq!(command_line(&h("git status", 0), false), "git status");
+        assert_eq!(command_line(&h("git status", 0), true), "0\tgit status");
+        assert_eq!(command_line(&h("make", -1), true), "-1\tmake");
+
+        // Tabs and newlines within the command stay part of it.
+        let line = command_line(&h("printf 'a\tb'\necho done", 130), true);
+        assert_eq!(
+            line.split_once('\t'),
+            Some(("130", "printf 'a\tb'\necho done"))
+        );
+    }
+
+    #[test]
+    fn test_resolve_query() {
+        let env_query = Some(String::from("git  commit -m"));
+        assert_eq!(
+            resolve_query(None, env_query.clone()).join(" "),
+            "git  commit -m"
+        );
+        assert_eq!(
+            resolve_query(None, env_query.clone()),
+            vec!["git  commit -m"]
+        );
+        assert_eq!(
+            resolve_query(Some(vec![String::from("ls")]), env_query),
+            vec!["ls"]
+        );
+        assert!(resolve_query(None, None).is_empty());
+    }
+
+    #[test]
+    fn test_parse_stdin_query() {
+        assert_eq!(parse_stdin_query("git push\n"), vec!["git push"]);
+        assert_eq!(
+            parse_stdin_query("git  push  -f\r\n"),
+            vec!["git  push  -f"]
+        );
+        assert_eq!(parse_stdin_query("make\nls\n"), vec!["make"]);
+        assert!(parse_stdin_query("").is_empty());
+        assert!(parse_stdin_query("\n").is_empty());
+        assert!(parse_stdin_query("   \n").is_empty());
+    }
+
+    #[test]
+    fn test_after_bound() {
+        let now = OffsetDateTime::from_unix_timestamp(1_700_000_000).unwrap();
+        let after = |within: &str| {
+            after_bound(now, duration::parse_duration(within).unwrap())
+                .map(OffsetDateTime::unix_timestamp)
+        };
+        assert_eq!(after("30m"), Some(1_700_000_000 - 30 * 60));
+        assert_eq!(after("2h"), Some(1_700_000_000 - 2 * 3600));
+        assert_eq!(after("3d"), Some(1_700_000_000 - 3 * 86400));
+        assert_eq!(after("99999999999w"), None);
+    }
+
+    #[test]
+    fn test_parse_time_bound() {
+        let at = |value: &str| match parse_time_bound(value) {
+            Ok(TimeBound::At(at)) => Some(at.unix_timestamp()),
+            _ => None,
+        };
+        assert_eq!(at("2023-11-14"), Some(1_699_920_000));
+        assert_eq!(at("2023-11-14T22:13"), Some(1_699_999_980));
+        assert_eq!(at("2023-11-14T22:13:20Z"), Some(1_700_000_000));
+        assert_eq!(at("2023-11-14 22:13:20"), Some(1_700_000_000));
+        assert_eq!(
+            parse_time_bound("7d"),
+            Ok(TimeBound::Ago(Duration::from_secs(7 * 86_400)))
+        );
+        for invalid in [
+            "2024-02-30",
+            "2024-13-01",
+            "2024-03",
+            "2024-03-05T25:00",
+            "2024-03-05T10",
+            "yesterday",
+        ] {
+            assert!(parse_time_bound(invalid).is_err(), "{invalid}");
+        }
+
+        let now = OffsetDateTime::from_unix_timestamp(1_700_000_000).unwrap();
+        assert_eq!(
+            TimeBound::Ago(Duration::from_secs(60))
+                .resolve(now)
+                .unix_timestamp(),
+            1_699_999_940
+        );
+        assert_eq!(
+            parse_time_bound("2023-11-14")
+                .unwrap()
+                .resolve(now)
+                .unix_timestamp(),
+            1_699_920_000
+        );
+        // Too long ago to represent, nothing was run before it.
+        assert_eq!(
+            parse_time_bound("99999999999w").unwrap().resolve(now),
+            PrimitiveDateTime::MIN.assume_utc()
+        );
+    }
+
+    #[test]
+    fn test_picker_line() {
+        let h = History::builder()
+            .id(42)
+            .command("for i in 1 2\ndo echo $i\ndone".to_string())
+            .cwd("/tmp".to_string())
+            .exit_code(0)
+            .timestamp(OffsetDateTime::from_unix_timestamp(1_700_000_000).unwrap())
+            .build();
+
+        assert_eq!(picker_line(&h), "42\tfor i in 1 2\\ndo echo $i\\ndone");
+    }
+}
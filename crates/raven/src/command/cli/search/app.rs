@@ -1,96 +1,657 @@
+use std::collections::BTreeSet;
 use std::error;
-
-use super::duration::format_duration;
+use std::io;
+use std::str::FromStr;
+use std::time::{Duration, Instant};
+
+use super::cache::LruCache;
+use super::clipboard;
+use super::duration::{format_duration, format_duration_exact};
+use log::error;
 use ratatui::style::Stylize;
 use ratatui::text::Span;
 use ratatui::{
     buffer::Buffer,
-    layout::{Alignment, Constraint, Layout, Position, Rect},
+    layout::{Alignment, Constraint, Flex, Layout, Position, Rect},
     style::{Color, Style},
     text::Line,
     widgets::{
-        HighlightSpacing, List, ListDirection, ListItem, ListState, Paragraph, StatefulWidgetRef,
-        WidgetRef,
+        Block, Clear, HighlightSpacing, List, ListDirection, ListItem, ListState, Paragraph,
+        StatefulWidgetRef, WidgetRef, Wrap,
     },
 };
-use raven_database::{Context, current_context, history::model::History};
-use raven_database::{HistoryFilters, MatchMode};
+use raven_common::config::{Config, RelativePrecision};
+use raven_database::{Context, database::DatabaseErrorKind, history::model::History};
+use raven_database::{HistoryFilters, MatchMode, OrderBy, rank};
 use time::OffsetDateTime;
 
 const VERSION: &str = env!("CARGO_PKG_VERSION");
 
+/// The height of the preview pane, including its borders.
+const PREVIEW_HEIGHT: u16 = 8;
+/// The commands shown for context before and after the command in the preview.
+const PREVIEW_CONTEXT: usize = 1;
+
+/// The fewest rows the full layout, with its header and shortcuts, is rendered in. Shorter
+/// terminals get the compact layout used inline.
+const FULL_LAYOUT_MIN_HEIGHT: u16 = 29;
+/// The fewest rows and columns the compact layout is rendered in, a smaller terminal only shows
+/// a message asking for more room.
+const COMPACT_MIN_HEIGHT: u16 = 6;
+const COMPACT_MIN_WIDTH: u16 = 20;
+
+/// The maximum number of sessions listed in the session picker.
+const SESSION_PICKER_LIMIT: usize = 10;
+
+/// The number of recent searches kept in memory, so backspacing does not query the database.
+const SEARCH_CACHE_SIZE: usize = 16;
+
+/// How long a status message is shown, unless a key is pressed first.
+const STATUS_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// The query, scope, match mode and order a search was run with.
+type SearchKey = (String, Scope, MatchMode, OrderBy);
+
+/// Receives each command selected while the search is kept open.
+type OnAccept = Box<dyn FnMut(&History) + Send>;
+
+/// Copies a command to the system clipboard.
+type Clipboard = Box<dyn Fn(&str) -> io::Result<()> + Send>;
+
+/// How a selected command is handed back to the shell.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum AcceptMode {
+    /// Run the command right away, selected with Enter.
+    #[default]
+    Execute,
+    /// Place the command on the shell's editing buffer without running it, selected with
+    /// Ctrl + e.
+    Buffer,
+}
+
+impl AcceptMode {
+    /// The name written for the shell widgets, see `raven search --accept-marker`.
+    pub fn name(self) -> &'static str {
+        match self {
+            AcceptMode::Execute => "execute",
+            AcceptMode::Buffer => "buffer",
+        }
+    }
+}
+
+/// The results of a search, see `SearchApp::get_history`.
+///
+/// * `commands`: The listed commands.
+/// * `match_count`: Number of commands matching the query, ignoring the result limit.
+/// * `scope_count`: Number of commands in the scope, regardless of the query.
+#[derive(Clone)]
+struct SearchResults {
+    commands: Vec<History>,
+    match_count: Option<i64>,
+    scope_count: Option<i64>,
+}
+
 /// Application result type.
 pub type AppResult<T> = std::result::Result<T, Box<dyn error::Error>>;
 
 /// The history scope of the current interactive session.
-#[derive(Clone)]
+#[derive(Clone, Debug, PartialEq, Eq)]
 pub enum Scope {
     Cwd,
+    /// Commands run anywhere in the git repository with this root.
+    Project(String),
     All,
+    /// Commands run in the shell session with this id.
+    Session(String),
+}
+
+/// How a command exited, each shown in its own color in the history list.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ExitStatus {
+    Success,
+    /// Killed by a signal, such as Ctrl+C, rather than failing on its own.
+    Interrupted,
+    /// Failed, or is still running.
+    Error,
+}
+
+impl ExitStatus {
+    /// Classify an exit code, shells report a command killed by signal N as 128 + N.
+    pub fn of(exit_code: i64) -> Self {
+        match exit_code {
+            0 => ExitStatus::Success,
+            128..=165 => ExitStatus::Interrupted,
+            _ => ExitStatus::Error,
+        }
+    }
+}
+
+/// The styles of the time since a command ran for each `ExitStatus`, from the `[ui]` config.
+struct ExitStyles {
+    success: Style,
+    interrupted: Style,
+    error: Style,
+}
+
+impl ExitStyles {
+    fn new(config: &Config) -> Self {
+        Self {
+            success: Self::parse(config.success_color(), Color::Blue),
+            interrupted: Self::parse(config.interrupted_color(), Color::Yellow),
+            error: Self::parse(config.error_color(), Color::Red),
+        }
+    }
+
+    /// Parse a configured color, falling back to `default` if it is not a valid color.
+    fn parse(color: &str, default: Color) -> Style {
+        let color = Color::from_str(color).unwrap_or_else(|_| {
+            error!("invalid color '{color}' in config, using {default}");
+            default
+        });
+        Style::new().fg(color)
+    }
+
+    fn style(&self, status: ExitStatus) -> Style {
+        match status {
+            ExitStatus::Success => self.success,
+            ExitStatus::Interrupted => self.interrupted,
+            ExitStatus::Error => self.error,
+        }
+    }
+}
+
+/// How the time since a command ran is shown in the history list, from the `[ui]` config.
+struct RelativeTime {
+    precision: RelativePrecision,
+    /// Commands run less than this long ago are shown as `just now`.
+    just_now: Duration,
 }
 
-pub struct SearchApp {
+impl RelativeTime {
+    fn new(config: &Config) -> Self {
+        Self {
+            precision: config.relative_precision(),
+            just_now: config.just_now(),
+        }
+    }
+
+    fn format(&self, since: Duration) -> String {
+        if since < self.just_now {
+            return String::from("just now");
+        }
+        match self.precision {
+            RelativePrecision::Coarse => format_duration(since),
+            RelativePrecision::Exact => format_duration_exact(since),
+        }
+    }
+}
+
+/// State of the session picker overlay.
+///
+/// * `sessions`: Recent sessions as (session id, last active, command count).
+/// * `list_state`: State object for the session list.
+#[derive(Clone)]
+pub struct SessionPicker {
+    pub sessions: Vec<(String, OffsetDateTime, i64)>,
+    pub list_state: ListState,
+}
+
+/// Entries marked for deletion when the app is used to clean up history.
+///
+/// * `ids`: Ids of the marked entries.
+/// * `deleted`: Number of entries deleted so far.
+/// * `dry_run`: Collect the confirmed entries in `would_delete` instead of deleting them.
+/// * `would_delete`: Entries confirmed for deletion during a dry run.
+#[derive(Clone, Debug, Default)]
+pub struct Marking {
+    pub ids: BTreeSet<i64>,
+    pub deleted: usize,
+    pub dry_run: bool,
+    pub would_delete: Vec<History>,
+}
+
+pub struct SearchApp<'a> {
     pub running: bool,
     pub selected: Option<History>,
+    /// How the selected command is handed back to the shell.
+    pub accept_mode: AcceptMode,
+    /// Set when entries are marked and deleted in bulk instead of selecting a command.
+    pub marking: Option<Marking>,
+    /// Called with each selected command instead of quitting, see `with_keep_open`.
+    on_accept: Option<OnAccept>,
+    /// Number of commands passed to `on_accept`.
+    pub accepted: usize,
+    /// Where `copy_selected` copies the highlighted command to.
+    clipboard: Clipboard,
+    /// List the latest distinct commands from the cache for the first search, see
+    /// `with_recent_commands`.
+    recent_first: bool,
     input: String,
     cursor_position: usize,
     commands: Vec<History>,
-    context: Context,
+    /// Number of commands matching the query, ignoring the result limit.
+    match_count: Option<i64>,
+    /// Number of commands in the current scope, regardless of the query.
+    scope_count: Option<i64>,
+    /// The caller's context, so the database it opened is searched rather than opened again.
+    context: &'a mut Context,
     now: Box<dyn Fn() -> OffsetDateTime + Send>,
+    /// Recent search results, cleared whenever history is deleted.
+    cache: LruCache<SearchKey, SearchResults>,
+    /// How long to wait after the last keystroke before searching.
+    debounce: Duration,
+    /// When the input last changed, if it has not been searched for yet.
+    pending_search: Option<Instant>,
+    /// The id of the command shown in the preview and the commands run around it, fetched when
+    /// the selection changes rather than on every draw.
+    neighbors: Option<(i64, Neighbors)>,
+    /// The exit code colors from the `[ui]` config, parsed once so invalid ones are only logged
+    /// once.
+    exit_styles: ExitStyles,
 }
 
+/// The commands run right before and after a command, both oldest first.
+type Neighbors = (Vec<History>, Vec<History>);
+
 #[derive(Clone)]
 pub struct AppState {
     pub cusor_position: Position,
     pub list_state: ListState,
     pub scope: Scope,
     pub cwd: String,
+    pub session_id: String,
+    /// The root of the git repository containing `cwd`, if there is one.
+    pub project_root: Option<String>,
     pub confirming_delete: bool,
     pub mode: MatchMode,
+    /// The order results are listed in.
+    pub order: OrderBy,
+    pub session_picker: Option<SessionPicker>,
+    pub show_preview: bool,
+    /// Rendered in an inline viewport, so the header and shortcuts are left out.
+    pub inline: bool,
+    /// Whether the help overlay listing every key binding is shown instead of the app.
+    pub showing_help: bool,
+    /// A message about the last operation, the color it is shown in and when it was set. Shown
+    /// in place of the shortcuts title until it times out or a key is pressed.
+    pub status: Option<(String, Color, Instant)>,
 }
 
-impl SearchApp {
+impl<'a> SearchApp<'a> {
     /// Fetch a `History` list from the raven database which matches the current input query.
-    pub fn get_history(&mut self, state: &AppState) {
-        let results = match self.context.db.search(
-            &self.input,
-            HistoryFilters {
-                exit: None,
-                cwd: match state.scope {
-                    Scope::Cwd => Some(self.context.cwd.clone()),
-                    Scope::All => None,
-                },
-                limit: Some(500),
-                mode: state.mode,
+    ///
+    /// Results of recent searches are reused instead of querying the database again.
+    pub fn get_history(&mut self, state: &mut AppState) {
+        if std::mem::take(&mut self.recent_first) && self.input.is_empty() {
+            let limit = self.search_filters(state).limit.unwrap_or(usize::MAX);
+            match self.context.db.recent_commands(limit) {
+                Ok(commands) => {
+                    // Every cached command is listed, there is no further page to load.
+                    let results = SearchResults {
+                        match_count: i64::try_from(commands.len()).ok(),
+                        scope_count: None,
+                        commands,
+                    };
+                    self.show_results(results, state);
+                    return;
+                }
+                Err(err) => error!("Unable to list recent commands, searching instead: {err}"),
+            }
+        }
+
+        let key = self.search_key(state);
+        if let Some(cached) = self.cache.get(&key) {
+            let cached = cached.clone();
+            self.show_results(cached, state);
+            return;
+        }
+
+        let filters = self.search_filters(state);
+        let Some(results) = self.fetch(&filters, state) else {
+            return;
+        };
+        let results = SearchResults {
+            commands: results,
+            match_count: self
+                .context
+                .db
+                .search_count(&self.input, filters.clone())
+                .ok(),
+            scope_count: self.context.db.count(filters).ok(),
+        };
+        self.cache.insert(key, results.clone());
+        self.show_results(results, state);
+    }
+
+    /// Fetch the commands run around the selected command for the preview, unless they are
+    /// already cached for it. Called before each draw, the preview shows what was fetched.
+    pub fn update_neighbors(&mut self, state: &AppState) {
+        let Some(h) = state
+            .list_state
+            .selected()
+            .and_then(|idx| self.commands.get(idx))
+            .filter(|_| state.show_preview)
+        else {
+            return;
+        };
+        if self.neighbors.as_ref().is_some_and(|(id, _)| *id == h.id) {
+            return;
+        }
+        // Commands from other shells are unrelated, unless there is no session to go by.
+        let neighbors = self
+            .context
+            .db
+            .neighbors(h.id, PREVIEW_CONTEXT, !h.session_id.is_empty())
+            .unwrap_or_else(|err| {
+                error!("Unable to fetch the commands around the selected one: {err}");
+                Neighbors::default()
+            });
+        self.neighbors = Some((h.id, neighbors));
+    }
+
+    /// Append the next page of results, once the last listed command is selected and more
+    /// commands match than are listed.
+    pub fn load_more(&mut self, state: &mut AppState) {
+        let listed = self.commands.len();
+        let at_end = state
+            .list_state
+            .selected()
+            .is_some_and(|idx| idx + 1 >= listed);
+        let has_more = self
+            .match_count
+            .is_some_and(|count| usize::try_from(count).is_ok_and(|count| count > listed));
+        if !at_end || !has_more {
+            return;
+        }
+
+        let filters = HistoryFilters {
+            offset: Some(listed),
+            ..self.search_filters(state)
+        };
+        let Some(page) = self.fetch(&filters, state) else {
+            return;
+        };
+        self.commands.extend(page);
+        let key = self.search_key(state);
+        self.cache.insert(
+            key,
+            SearchResults {
+                commands: self.commands.clone(),
+                match_count: self.match_count,
+                scope_count: self.scope_count,
+            },
+        );
+    }
+
+    /// The key the results for the current input and state are cached under.
+    fn search_key(&self, state: &AppState) -> SearchKey {
+        (
+            self.input.clone(),
+            state.scope.clone(),
+            state.mode,
+            state.order,
+        )
+    }
+
+    /// The filters searching the current scope, one page of results at a time.
+    fn search_filters(&self, state: &AppState) -> HistoryFilters {
+        HistoryFilters {
+            exit: None,
+            cwd: match state.scope {
+                Scope::Cwd => Some(self.context.cwd.clone()),
+                Scope::Project(_) | Scope::All | Scope::Session(_) => None,
+            },
+            cwd_within: match &state.scope {
+                Scope::Project(root) => Some(root.clone()),
+                Scope::Cwd | Scope::All | Scope::Session(_) => None,
+            },
+            session_id: match &state.scope {
+                Scope::Session(session_id) => Some(session_id.clone()),
+                Scope::Cwd | Scope::Project(_) | Scope::All => None,
             },
-        ) {
+            limit: Some(match state.scope {
+                Scope::Cwd | Scope::Project(_) => self.context.config.limit_cwd(),
+                Scope::All | Scope::Session(_) => self.context.config.limit_all(),
+            }),
+            mode: state.mode,
+            order: state.order,
+            prefix_policy: self.context.config.prefix_policy(),
+            // Typing on while a slow search runs is worse than a partial list.
+            timeout: self.context.config.search_timeout(),
+            ..Default::default()
+        }
+    }
+
+    /// Search the input with `filters`, ranking the results.
+    ///
+    /// Only the page of results `filters` selects is ranked, so a page loaded by `load_more` is
+    /// listed below the commands already shown rather than ranked among them, which keeps the
+    /// selection in place.
+    ///
+    /// Returns `None` when the search timed out or failed, reported on the status line.
+    fn fetch(&self, filters: &HistoryFilters, state: &mut AppState) -> Option<Vec<History>> {
+        let results = match self.context.db.search(&self.input, filters.clone()) {
             Ok(h) => h,
-            Err(err) => panic! {"{err}"},
+            // The previous results stay listed, the next change to the query searches again.
+            Err(err) if err.kind == DatabaseErrorKind::Timeout => {
+                error!("{err}");
+                SearchApp::set_status(
+                    state,
+                    String::from("Search timed out, refine your query"),
+                    Color::Yellow,
+                );
+                return None;
+            }
+            // Such as a locked database, the search stays open to try again.
+            Err(err) => {
+                error!("{err}");
+                SearchApp::set_status(state, format!("Search failed: {err}"), Color::Red);
+                return None;
+            }
         };
-        self.commands = results;
+        Some(rank::ranker(self.context.config.ranker()).rank(&self.input, results))
+    }
+
+    fn show_results(&mut self, results: SearchResults, state: &mut AppState) {
+        self.commands = results.commands;
+        self.match_count = results.match_count;
+        self.scope_count = results.scope_count;
+        if self.commands.is_empty() && !self.input.is_empty() {
+            SearchApp::set_status(state, String::from("No matching commands"), Color::Yellow);
+        }
+    }
+
+    /// Search for the changed input once `debounce` has passed without another change.
+    ///
+    /// Searches immediately when there is no debounce.
+    fn request_search(&mut self, state: &mut AppState) {
+        if self.debounce.is_zero() {
+            self.get_history(state);
+        } else {
+            self.pending_search = Some(Instant::now());
+        }
+    }
+
+    /// Run the pending search if the input has not changed for the debounce period.
+    ///
+    /// * `now`: The current time.
+    pub fn search_if_idle(&mut self, state: &mut AppState, now: Instant) {
+        if SearchApp::debounce_elapsed(self.pending_search, now, self.debounce) {
+            self.flush_search(state);
+        }
+    }
+
+    /// Run the pending search right away, so the listed commands match the input.
+    pub fn flush_search(&mut self, state: &mut AppState) {
+        if self.pending_search.take().is_some() {
+            self.get_history(state);
+        }
+    }
+
+    /// Whether a search requested at `pending` is due at `now`.
+    fn debounce_elapsed(pending: Option<Instant>, now: Instant, debounce: Duration) -> bool {
+        pending.is_some_and(|pending| now.saturating_duration_since(pending) >= debounce)
     }
 
-    pub fn get_history_count(&self) -> i64 {
-        self.context.db.get_history_total().unwrap_or(-1)
+    /// How long to wait after the last keystroke before searching.
+    pub fn debounce(&self) -> Duration {
+        self.debounce
     }
 
-    pub fn new(query: String) -> Self {
+    pub fn new(query: String, context: &'a mut Context) -> Self {
         let pos = query.chars().count();
+        let debounce = context.config.search_debounce();
+        let now = context.clock;
+        let exit_styles = ExitStyles::new(&context.config);
         Self {
-            context: current_context(),
+            context,
             running: true,
             input: query,
             cursor_position: pos,
             commands: Vec::new(),
+            match_count: None,
+            scope_count: None,
             selected: None,
-            now: Box::new(OffsetDateTime::now_utc),
+            accept_mode: AcceptMode::default(),
+            marking: None,
+            on_accept: None,
+            accepted: 0,
+            clipboard: Box::new(clipboard::copy),
+            recent_first: false,
+            now: Box::new(now),
+            cache: LruCache::new(SEARCH_CACHE_SIZE),
+            debounce,
+            pending_search: None,
+            neighbors: None,
+            exit_styles,
+        }
+    }
+
+    /// Mark and delete entries in bulk instead of selecting a command.
+    #[must_use]
+    pub fn with_marking(mut self) -> Self {
+        self.marking = Some(Marking::default());
+        self
+    }
+
+    /// Preview the marked entries which would be deleted, without deleting anything.
+    #[must_use]
+    pub fn with_dry_run(mut self) -> Self {
+        if let Some(marking) = self.marking.as_mut() {
+            marking.dry_run = true;
         }
+        self
+    }
+
+    /// Start with the cursor at the character `position` of the query instead of at its end.
+    ///
+    /// Positions past the end of the query are clamped to it.
+    #[must_use]
+    pub fn with_cursor(mut self, position: usize) -> Self {
+        self.cursor_position = self.clamp_cursor(position);
+        self
+    }
+
+    /// List the latest distinct commands for the first search when the query is empty, as the
+    /// up-key does, read from the cache the database keeps of them instead of searching.
+    #[must_use]
+    pub fn with_recent_commands(mut self) -> Self {
+        self.recent_first = true;
+        self
+    }
+
+    /// Stay open after a command is selected, passing each selected command to `on_accept`.
+    #[must_use]
+    pub fn with_keep_open(mut self, on_accept: impl FnMut(&History) + Send + 'static) -> Self {
+        self.on_accept = Some(Box::new(on_accept));
+        self
     }
 
     pub fn quit(&mut self) {
         self.running = false;
     }
 
+    /// Mark the selected entry for deletion, or unmark it if it already is.
+    pub fn toggle_mark(&mut self, state: &AppState) {
+        let Some(marking) = self.marking.as_mut() else {
+            return;
+        };
+        if let Some(h) = state
+            .list_state
+            .selected()
+            .and_then(|idx| self.commands.get(idx))
+            && !marking.ids.remove(&h.id)
+        {
+            marking.ids.insert(h.id);
+        }
+    }
+
+    /// Mark every listed entry for deletion, or unmark them all if they already are.
+    pub fn toggle_mark_all(&mut self) {
+        let Some(marking) = self.marking.as_mut() else {
+            return;
+        };
+        if self.commands.iter().all(|h| marking.ids.contains(&h.id)) {
+            for h in &self.commands {
+                marking.ids.remove(&h.id);
+            }
+        } else {
+            marking.ids.extend(self.commands.iter().map(|h| h.id));
+        }
+    }
+
+    /// Deletes the marked entries, or the selected entry if none are marked, in one batch.
+    ///
+    /// During a dry run the entries are collected in `Marking::would_delete` instead.
+    fn confirm_delete_marked(&mut self, state: &mut AppState) {
+        let Some(marking) = self.marking.as_mut() else {
+            return;
+        };
+        let ids = if marking.ids.is_empty() {
+            state
+                .list_state
+                .selected()
+                .and_then(|idx| self.commands.get(idx))
+                .map(|h| vec![h.id])
+                .unwrap_or_default()
+        } else {
+            marking.ids.iter().copied().collect()
+        };
+
+        if marking.dry_run {
+            for h in self.commands.iter().filter(|h| ids.contains(&h.id)) {
+                if !marking.would_delete.iter().any(|w| w.id == h.id) {
+                    marking.would_delete.push(h.clone());
+                }
+            }
+            marking.ids.clear();
+            state.confirming_delete = false;
+            return;
+        }
+
+        match self.context.db.delete_bulk(&ids) {
+            Ok(deleted) => {
+                self.cache.clear();
+                self.neighbors = None;
+                marking.deleted += deleted;
+                marking.ids.clear();
+                self.commands.retain(|h| !ids.contains(&h.id));
+                let last = self.commands.len().checked_sub(1);
+                let selected = state.list_state.selected().zip(last).map(|(s, l)| s.min(l));
+                state.list_state.select(selected);
+            }
+            Err(e) => {
+                error!("Failed to delete history entries: {e}");
+                SearchApp::set_status(
+                    state,
+                    format!("Failed to delete history entries: {e}"),
+                    Color::Red,
+                );
+            }
+        }
+        state.confirming_delete = false;
+    }
+
     pub fn move_cursor_left(&mut self) {
         let cursor_moved_left = self.cursor_position.saturating_sub(1);
         self.cursor_position = self.clamp_cursor(cursor_moved_left);
@@ -118,14 +679,14 @@ impl SearchApp {
             .unwrap_or(self.input.len())
     }
 
-    pub fn enter_char(&mut self, new_char: char, app_state: &AppState) {
+    pub fn enter_char(&mut self, new_char: char, app_state: &mut AppState) {
         let idx = self.byte_index();
         self.input.insert(idx, new_char);
         self.move_cursor_right();
-        self.get_history(app_state);
+        self.request_search(app_state);
     }
 
-    pub fn delete_char(&mut self, app_state: &AppState) {
+    pub fn delete_char(&mut self, app_state: &mut AppState) {
         let is_not_cursor_leftmost = self.cursor_position != 0;
         if is_not_cursor_leftmost {
             // Method "remove" is not used on the saved text for deleting the selected char.
@@ -144,19 +705,51 @@ impl SearchApp {
             // By leaving the selected one out, it is forgotten and therefore deleted.
             self.input = before_char_to_delete.chain(after_char_to_delete).collect();
             self.move_cursor_left();
-            self.get_history(app_state);
+            self.request_search(app_state);
         }
     }
 
-    /// Mark the list item at `idx` as selected and quit the search app.
-    pub fn select(&mut self, idx: usize) {
-        self.selected = Some(self.commands[idx].clone());
+    /// Mark the list item at `idx` as selected, to be handed back as `mode`, and quit the search
+    /// app.
+    ///
+    /// When kept open, the item is passed to `on_accept` instead and the search keeps running.
+    pub fn select(&mut self, idx: usize, mode: AcceptMode) {
+        let h = self.commands[idx].clone();
+        if let Some(on_accept) = self.on_accept.as_mut() {
+            on_accept(&h);
+            self.accepted += 1;
+            return;
+        }
+        self.selected = Some(h);
+        self.accept_mode = mode;
         self.quit();
     }
 
+    /// Copy the highlighted command to the system clipboard, reporting the outcome on the status
+    /// line. The search stays open.
+    pub fn copy_selected(&self, state: &mut AppState) {
+        let Some(h) = state
+            .list_state
+            .selected()
+            .and_then(|idx| self.commands.get(idx))
+        else {
+            return;
+        };
+        let (message, color) = match (self.clipboard)(&h.command) {
+            Ok(()) => (String::from("Copied to the clipboard"), Color::Green),
+            Err(err) => {
+                error!("Unable to copy to the clipboard: {err}");
+                (format!("Unable to copy, {err}"), Color::Red)
+            }
+        };
+        SearchApp::set_status(state, message, color);
+    }
+
     /// Sets the app state to wait for delete confirmation.
-    pub fn initiate_delete(state: &mut AppState) {
-        if state.list_state.selected().is_some() {
+    pub fn initiate_delete(&self, state: &mut AppState) {
+        if state.list_state.selected().is_some()
+            || self.marking.as_ref().is_some_and(|m| !m.ids.is_empty())
+        {
             state.confirming_delete = true;
         }
     }
@@ -166,49 +759,157 @@ impl SearchApp {
         state.confirming_delete = false;
     }
 
-    /// Confirms the deletion of the selected item.
+    /// Confirms the deletion of the selected item, or of the marked items when marking.
     pub fn confirm_delete(&mut self, state: &mut AppState) {
-        if let Some(selected_index) = state.list_state.selected() {
-            if selected_index < self.commands.len() {
-                let item_to_delete = &self.commands[selected_index];
-                let item_id = item_to_delete.id;
-
-                match self.context.db.delete(item_id) {
-                    Ok(()) => {
-                        // Remove from the UI list *only on successful DB delete*
-                        self.commands.remove(selected_index);
-
-                        // Adjust selection after removal
-                        if self.commands.is_empty() {
-                            state.list_state.select(None);
-                        } else if selected_index >= self.commands.len() {
-                            // If the last item was deleted, select the new last item
-                            state
-                                .list_state
-                                .select(Some(self.commands.len().saturating_sub(1)));
-                        } else {
-                            // Otherwise, the selection naturally moves to the next item,
-                            // or stays if it was already pointing correctly.
-                            // Ensure the index is valid if list shrunk
-                            state.list_state.select(Some(
-                                selected_index.min(self.commands.len().saturating_sub(1)),
-                            ));
-                        }
-                    }
-                    Err(e) => {
-                        // TODO: Display this error in the TUI status bar instead of printing
-                        eprintln!("Failed to delete history entry: {e}");
+        if self.marking.is_some() {
+            self.confirm_delete_marked(state);
+            return;
+        }
+        if let Some(selected_index) = state.list_state.selected()
+            && selected_index < self.commands.len()
+        {
+            let item_to_delete = &self.commands[selected_index];
+            let item_id = item_to_delete.id;
+
+            match self.context.db.delete(item_id) {
+                Ok(()) => {
+                    self.cache.clear();
+                    self.neighbors = None;
+                    // Remove from the UI list *only on successful DB delete*
+                    self.commands.remove(selected_index);
+
+                    // Adjust selection after removal
+                    if self.commands.is_empty() {
+                        state.list_state.select(None);
+                    } else if selected_index >= self.commands.len() {
+                        // If the last item was deleted, select the new last item
+                        state
+                            .list_state
+                            .select(Some(self.commands.len().saturating_sub(1)));
+                    } else {
+                        // Otherwise, the selection naturally moves to the next item,
+                        // or stays if it was already pointing correctly.
+                        // Ensure the index is valid if list shrunk
+                        state.list_state.select(Some(
+                            selected_index.min(self.commands.len().saturating_sub(1)),
+                        ));
                     }
                 }
-                // --- End Placeholder ---
+                Err(e) => {
+                    error!("Failed to delete history entry: {e}");
+                    SearchApp::set_status(
+                        state,
+                        format!("Failed to delete history entry: {e}"),
+                        Color::Red,
+                    );
+                }
             }
+            // --- End Placeholder ---
         }
         // Always reset confirmation state after attempting
         state.confirming_delete = false;
     }
+
+    /// Cycles the scope between cwd, the project, everything and the current session.
+    ///
+    /// The project scope is skipped outside of a git repository, and the session scope if the
+    /// shell did not provide a session id.
+    pub fn toggle_scope(&mut self, state: &mut AppState) {
+        state.scope = match state.scope {
+            Scope::Cwd => state
+                .project_root
+                .clone()
+                .map_or(Scope::All, Scope::Project),
+            Scope::Project(_) => Scope::All,
+            Scope::All if !state.session_id.is_empty() => Scope::Session(state.session_id.clone()),
+            Scope::All | Scope::Session(_) => Scope::Cwd,
+        };
+        self.get_history(state);
+    }
+
+    /// Opens the session picker overlay listing the most recent sessions.
+    pub fn open_session_picker(&self, state: &mut AppState) {
+        match self.context.db.recent_sessions(SESSION_PICKER_LIMIT) {
+            Ok(sessions) => {
+                let mut list_state = ListState::default();
+                if !sessions.is_empty() {
+                    list_state.select_first();
+                }
+                state.session_picker = Some(SessionPicker {
+                    sessions,
+                    list_state,
+                });
+            }
+            Err(err) => {
+                error!("unable to list recent sessions: {err}");
+                SearchApp::set_status(
+                    state,
+                    format!("Unable to list recent sessions: {err}"),
+                    Color::Red,
+                );
+            }
+        }
+    }
+
+    /// Show `message` in `color` in the status line, replacing any message shown before. Errors
+    /// are red, warnings yellow.
+    pub fn set_status(state: &mut AppState, message: String, color: Color) {
+        state.status = Some((message, color, Instant::now()));
+    }
+
+    /// Clear the status line, such as when a key is pressed.
+    pub fn clear_status(state: &mut AppState) {
+        state.status = None;
+    }
+
+    /// Clear the status line once its message has been shown for `STATUS_TIMEOUT`.
+    pub fn expire_status(state: &mut AppState, now: Instant) {
+        if state
+            .status
+            .as_ref()
+            .is_some_and(|(_, _, set_at)| now.saturating_duration_since(*set_at) >= STATUS_TIMEOUT)
+        {
+            state.status = None;
+        }
+    }
+
+    /// Whether nothing has been typed into the query, so `?` opens the help instead.
+    pub fn input_is_empty(&self) -> bool {
+        self.input.is_empty()
+    }
+
+    /// Opens the help overlay listing every key binding.
+    pub fn open_help(state: &mut AppState) {
+        state.showing_help = true;
+    }
+
+    /// Closes the help overlay.
+    pub fn close_help(state: &mut AppState) {
+        state.showing_help = false;
+    }
+
+    /// Closes the session picker overlay without changing the scope.
+    pub fn close_session_picker(state: &mut AppState) {
+        state.session_picker = None;
+    }
+
+    /// Scopes the history to the session selected in the picker and closes the picker.
+    pub fn pick_session(&mut self, state: &mut AppState) {
+        let Some(picker) = state.session_picker.take() else {
+            return;
+        };
+        if let Some((session_id, _, _)) = picker
+            .list_state
+            .selected()
+            .and_then(|idx| picker.sessions.get(idx))
+        {
+            state.scope = Scope::Session(session_id.clone());
+            self.get_history(state);
+        }
+    }
 }
 
-impl StatefulWidgetRef for &mut SearchApp {
+impl StatefulWidgetRef for &mut SearchApp<'_> {
     type State = AppState;
 
     fn render_ref(
@@ -219,47 +920,163 @@ impl StatefulWidgetRef for &mut SearchApp {
     ) where
         Self: Sized,
     {
-        // Layout locations
-        let [header, hist_list, query_box, shortcuts] = Layout::vertical([
-            Constraint::Length(4), // header
-            Constraint::Min(5),    // hist_list
-            Constraint::Length(5), // query_box
-            Constraint::Length(5), // shortcuts
-        ])
-        .vertical_margin(4)
-        .horizontal_margin(4)
-        .areas(area);
+        if area.height < COMPACT_MIN_HEIGHT || area.width < COMPACT_MIN_WIDTH {
+            SearchApp::render_too_small(area, buf);
+            return;
+        }
+        if state.showing_help {
+            SearchApp::render_help(
+                area,
+                buf,
+                state,
+                &self.context.config,
+                self.marking.is_some(),
+            );
+            return;
+        }
 
-        SearchApp::render_title(header, buf, self.get_history_count());
+        // Layout locations
+        let (hist_list, query_box, shortcuts) =
+            if state.inline || area.height < FULL_LAYOUT_MIN_HEIGHT {
+                let [hist_list, query_box] = Layout::vertical([
+                    Constraint::Min(1),    // hist_list
+                    Constraint::Length(5), // query_box
+                ])
+                .horizontal_margin(1)
+                .areas(area);
+                (hist_list, query_box, None)
+            } else {
+                let [header, hist_list, query_box, shortcuts] = Layout::vertical([
+                    Constraint::Length(4), // header
+                    Constraint::Min(5),    // hist_list
+                    Constraint::Length(5), // query_box
+                    Constraint::Length(7), // shortcuts
+                ])
+                .vertical_margin(4)
+                .horizontal_margin(4)
+                .areas(area);
+
+                SearchApp::render_title(
+                    header,
+                    buf,
+                    self.commands.len(),
+                    self.match_count,
+                    self.scope_count,
+                    self.marking.as_ref(),
+                );
+                (hist_list, query_box, Some(shortcuts))
+            };
+        let hist_list = if state.show_preview {
+            let [hist_list, preview] =
+                Layout::vertical([Constraint::Min(3), Constraint::Length(PREVIEW_HEIGHT)])
+                    .areas(hist_list);
+            let selected = state
+                .list_state
+                .selected()
+                .and_then(|idx| self.commands.get(idx));
+            let command = selected.map_or("", |h| h.command.as_str());
+            let (before, after) = self
+                .neighbors
+                .as_ref()
+                .filter(|(id, _)| selected.is_some_and(|h| h.id == *id))
+                .map_or((&[][..], &[][..]), |(_, (before, after))| {
+                    (before.as_slice(), after.as_slice())
+                });
+            SearchApp::render_preview(
+                preview,
+                buf,
+                command,
+                (before, after),
+                self.context.config.wrap_preview(),
+            );
+            hist_list
+        } else {
+            hist_list
+        };
         SearchApp::render_history_list(
             hist_list,
             buf,
             &self.commands,
             &mut state.list_state,
             &self.now,
+            &self.context.config,
+            &self.exit_styles,
+            self.marking.as_ref().map(|m| &m.ids),
         );
         SearchApp::render_query_box(query_box, buf, self.input.as_str(), state);
+        if let Some((message, color, _)) = &state.status
+            && shortcuts.is_none()
+        {
+            // Without the shortcuts it is shown in the first row, which the query box leaves empty.
+            Line::styled(message.as_str(), Style::default().fg(*color)).render_ref(
+                Rect {
+                    height: 1,
+                    ..query_box
+                },
+                buf,
+            );
+        }
         state.cusor_position = Position::new(
             query_box.x + 9 + u16::try_from(self.cursor_position).unwrap(),
             query_box.y + 1,
         );
 
-        SearchApp::render_shortcuts(shortcuts, buf, state);
+        if let Some(shortcuts) = shortcuts {
+            SearchApp::render_shortcuts(shortcuts, buf, state, self.marking.as_ref());
+        }
+
+        if let Some(picker) = state.session_picker.as_mut() {
+            SearchApp::render_session_picker(area, buf, picker, &self.now);
+        }
     }
 }
 
-impl SearchApp {
+impl SearchApp<'_> {
+    /// Render a message asking for a bigger terminal, in place of a layout which does not fit.
+    fn render_too_small(area: Rect, buf: &mut Buffer) {
+        Paragraph::new("Terminal too small for raven, make it larger or press Esc")
+            .wrap(Wrap { trim: true })
+            .centered()
+            .style(Style::default().fg(Color::Yellow))
+            .render_ref(area, buf);
+    }
+
     /// Render the interactive screen header.
-    fn render_title(area: Rect, buf: &mut Buffer, history_count: i64) {
+    ///
+    /// * `displayed`: Number of results shown in the history list.
+    /// * `matches`: Number of results matching the query, if known.
+    /// * `scope_count`: Number of commands in the current scope, if known.
+    /// * `marking`: The entries marked for deletion, when cleaning up history.
+    fn render_title(
+        area: Rect,
+        buf: &mut Buffer,
+        displayed: usize,
+        matches: Option<i64>,
+        scope_count: Option<i64>,
+        marking: Option<&Marking>,
+    ) {
         let [left, right] = Layout::horizontal([Constraint::Fill(1); 2]).areas(area);
 
+        let title = match marking {
+            Some(marking) if marking.dry_run => format!(
+                "Dry run: {} marked, {} to delete",
+                marking.ids.len(),
+                marking.would_delete.len()
+            ),
+            Some(marking) => format!(
+                "Clean up: {} marked, {} deleted",
+                marking.ids.len(),
+                marking.deleted
+            ),
+            None => String::from("History"),
+        };
         Paragraph::new(format!(
             "raven {VERSION}\n\
             Press Esc to exit.\n\
-            History"
+            {title}"
         ))
         .render_ref(left, buf);
-        Paragraph::new(format!("history count: {history_count}"))
+        Paragraph::new(SearchApp::format_counts(displayed, matches, scope_count))
             .alignment(Alignment::Right)
             .render_ref(right, buf);
     }
@@ -269,13 +1086,26 @@ impl SearchApp {
     /// * `history`: List of shell `History` objects to display
     /// * `list_state`: State object for the current list.
     /// * `now`: A fn that returns the current timestamp.
+    /// * `config`: The `[ui]` settings for the relative time precision.
+    /// * `styles`: The colors of the exit codes.
+    /// * `marked`: Ids marked for deletion, shown instead of the quick pick shortcuts.
+    #[allow(clippy::too_many_arguments)]
     fn render_history_list(
         area: Rect,
         buf: &mut Buffer,
         history: &[History],
         list_state: &mut ListState,
         now: &dyn Fn() -> OffsetDateTime,
+        config: &Config,
+        styles: &ExitStyles,
+        marked: Option<&BTreeSet<i64>>,
     ) {
+        let relative_time = RelativeTime::new(config);
+        let times: Vec<String> = history
+            .iter()
+            .map(|h| SearchApp::time_since(now, h, &relative_time))
+            .collect();
+        let time_width = SearchApp::time_column_width(&times);
         let shortcuts = if list_state.selected().is_some() {
             let selected_idx = list_state.selected().unwrap();
             [
@@ -290,12 +1120,19 @@ impl SearchApp {
         };
         StatefulWidgetRef::render_ref(
             &List::new(history.iter().enumerate().map(|(i, h)| {
-                let shortcut = if shortcuts.contains(&i) {
-                    Some(shortcuts.iter().position(|&pos| pos == i).expect("not in") + 1)
+                let gutter = if let Some(marked) = marked {
+                    if marked.contains(&h.id) {
+                        Span::styled(" x", Style::new().red().bold())
+                    } else {
+                        Span::default().content("  ")
+                    }
+                } else if shortcuts.contains(&i) {
+                    let shortcut = shortcuts.iter().position(|&pos| pos == i).expect("not in") + 1;
+                    Span::styled(format!(" {shortcut}"), Style::new().magenta())
                 } else {
-                    None
+                    Span::default().content("  ")
                 };
-                SearchApp::history_to_list_item(h, now, shortcut)
+                SearchApp::history_to_list_item(h, gutter, &times[i], time_width, styles)
             }))
             .highlight_style(Style::default().fg(Color::Green))
             .highlight_symbol(">>")
@@ -308,6 +1145,42 @@ impl SearchApp {
         );
     }
 
+    /// Renders the full selected command below the history list.
+    ///
+    /// The list only shows a single line per command, the preview is where long and multi-line
+    /// commands can be read in full.
+    ///
+    /// * `command`: The selected command.
+    /// * `neighbors`: The commands run right before and after it, shown dimmed around it.
+    /// * `wrap`: Wrap long lines instead of clipping them.
+    fn render_preview(
+        area: Rect,
+        buf: &mut Buffer,
+        command: &str,
+        neighbors: (&[History], &[History]),
+        wrap: bool,
+    ) {
+        let context_line = |h: &History| {
+            Line::styled(
+                SearchApp::command_summary(&h.command).0.to_string(),
+                Style::default().fg(Color::DarkGray),
+            )
+        };
+        let (before, after) = neighbors;
+        let lines = before
+            .iter()
+            .map(context_line)
+            .chain(command.lines().map(Line::raw))
+            .chain(after.iter().map(context_line))
+            .collect::<Vec<Line>>();
+        let preview = Paragraph::new(lines).block(Block::bordered().title("Preview"));
+        if wrap {
+            preview.wrap(Wrap { trim: false }).render_ref(area, buf);
+        } else {
+            preview.render_ref(area, buf);
+        }
+    }
+
     /// Renders the cursor and input query.
     fn render_query_box(area: Rect, buf: &mut Buffer, input: &str, app_state: &AppState) {
         let [top, bottom] = Layout::vertical([Constraint::Length(2), Constraint::Fill(1)])
@@ -322,9 +1195,11 @@ impl SearchApp {
         ])
         .areas(bottom);
 
-        let dir_scope = match app_state.scope {
-            Scope::Cwd => app_state.cwd.as_str(),
-            Scope::All => "(Everything)",
+        let dir_scope = match &app_state.scope {
+            Scope::Cwd => app_state.cwd.clone(),
+            Scope::Project(root) => format!("(Project {root})"),
+            Scope::All => String::from("(Everything)"),
+            Scope::Session(session_id) => format!("(Session {session_id})"),
         };
 
         // Mode
@@ -333,11 +1208,25 @@ impl SearchApp {
                 match app_state.mode {
                     MatchMode::Prefix => format!("{:>6}", "[prefix]"),
                     MatchMode::Fuzzy => format!("{:>6}", "[fuzzy]"),
+                    MatchMode::FuzzyAny => format!("{:>6}", "[any]"),
+                    MatchMode::Raw => format!("{:>6}", "[raw]"),
+                    MatchMode::Regex => format!("{:>6}", "[regex]"),
                 },
                 Style::default().fg(Color::LightBlue),
             )])
             .render_ref(mode, buf);
 
+        Line::default()
+            .spans([Span::styled(
+                match app_state.order {
+                    OrderBy::Recency => "[recent]",
+                    OrderBy::Relevance => "[best match]",
+                },
+                Style::default().fg(Color::LightBlue),
+            )])
+            .alignment(Alignment::Right)
+            .render_ref(scope, buf);
+
         // Query
         Line::default()
             .spans([Span::styled(input, Style::default().fg(Color::Yellow))])
@@ -362,14 +1251,21 @@ impl SearchApp {
     /// * `area` - The `Rect` area where the shortcuts or prompt should be rendered.
     /// * `buf` - The `Buffer` to render onto.
     /// * `state` - The current `AppState` containing the application state.
-    fn render_shortcuts(area: Rect, buf: &mut Buffer, state: &AppState) {
+    /// * `marking` - The entries marked for deletion, when cleaning up history.
+    fn render_shortcuts(area: Rect, buf: &mut Buffer, state: &AppState, marking: Option<&Marking>) {
         let [top, bottom] =
             Layout::vertical([Constraint::Length(1), Constraint::Fill(1)]).areas(area);
 
         if state.confirming_delete {
+            let question = match marking {
+                Some(marking) if marking.ids.len() > 1 => {
+                    format!("Delete {} entries? ", marking.ids.len())
+                }
+                _ => String::from("Delete entry? "),
+            };
             // Render confirmation prompt
             let confirm_text = Line::from(vec![
-                Span::styled("Delete entry? ", Style::default().fg(Color::Yellow)),
+                Span::styled(question, Style::default().fg(Color::Yellow)),
                 Span::styled("[y]", Style::default().fg(Color::Green).bold()),
                 Span::styled("/", Style::default().fg(Color::Gray)),
                 Span::styled("[N]", Style::default().fg(Color::Red).bold()),
@@ -380,71 +1276,305 @@ impl SearchApp {
             // Optionally clear the top area or change the title if needed
             Paragraph::new("Confirm Delete").render_ref(top, buf); // Change title
         } else {
-            // Render normal shortcuts
-            Paragraph::new("Shortcuts").render_ref(top, buf); // Keep original title
-            let tab = Line::default()
-                .spans([Span::default().content("<TAB>: Toggle cwd or Global scope")]);
-            let quick_pick = Line::default().spans([
-                Span::default().content("<Alt + "),
-                Span::default().fg(Color::Magenta).content("1..5"),
-                Span::default().content(">: Quick Pick"),
-            ]);
-            let delete_key = Line::default()
-                .spans([Span::default().content("<Alt + d>: Delete selected entry")]);
-            let mode =
-                Line::default().spans([Span::default().content("<Alt + m>: Toggle search mode")]);
-            let shortcuts = List::new([tab, quick_pick, delete_key, mode]);
+            // Render normal shortcuts, titled with the status message while there is one.
+            if let Some((message, color, _)) = &state.status {
+                Line::styled(message.as_str(), Style::default().fg(*color)).render_ref(top, buf);
+            } else {
+                Paragraph::new("Shortcuts").render_ref(top, buf); // Keep original title
+            }
+            let tab =
+                Line::default()
+                    .spans([Span::default()
+                        .content("<TAB>: Cycle cwd, Project, Global or Session scope")]);
+            let (quick_pick, delete_key) = if marking.is_some() {
+                (
+                    Line::default().spans([Span::default()
+                        .content("<Alt + x>: Mark entry, <Alt + a>: Mark all listed entries")]),
+                    Line::default()
+                        .spans([Span::default().content("<Enter>: Delete marked entries")]),
+                )
+            } else {
+                (
+                    Line::default().spans([
+                        Span::default().content("<Alt + "),
+                        Span::default().fg(Color::Magenta).content("1..5"),
+                        Span::default().content(">: Quick Pick"),
+                    ]),
+                    Line::default()
+                        .spans([Span::default().content("<Alt + d>: Delete selected entry")]),
+                )
+            };
+            let mode = Line::default()
+                .spans([Span::default()
+                    .content("<Alt + m>: Toggle search mode, <Ctrl + s>: Toggle order")]);
+            let session =
+                Line::default().spans([Span::default().content("<Alt + s>: Pick a session scope")]);
+            let preview = Line::default()
+                .spans([Span::default().content("<Alt + p>: Toggle preview, <?>: All shortcuts")]);
+            let shortcuts = List::new([tab, quick_pick, delete_key, mode, session, preview]);
             WidgetRef::render_ref(&shortcuts, bottom, buf);
         }
     }
 
-    /// Generates a `ListItem` for the provided `History`.
-    fn history_to_list_item<'a>(
-        h: &'a History,
-        now: &dyn Fn() -> OffsetDateTime,
-        shortcut: Option<usize>,
-    ) -> ListItem<'a> {
-        let shortcut_span = if shortcut.is_some() {
-            Span::styled(format!(" {}", shortcut.unwrap()), Style::new().magenta())
-        } else {
-            Span::default().content("  ")
+    /// Renders the help overlay over the whole app, listing every key binding and the settings
+    /// in effect.
+    ///
+    /// * `state`: The current scope, match mode and order are listed with the settings.
+    /// * `config`: The `[ui]` settings in effect.
+    /// * `marking`: Whether entries are being marked for deletion, which changes what some keys do.
+    fn render_help(area: Rect, buf: &mut Buffer, state: &AppState, config: &Config, marking: bool) {
+        let key = |key: &'static str, action: &'static str| {
+            Line::default().spans([
+                Span::styled(format!("  {key:<16}"), Style::new().fg(Color::Magenta)),
+                Span::default().content(action),
+            ])
         };
+        let setting = |name: &'static str, value: String| {
+            Line::default().spans([
+                Span::default().content(format!("  {name:<22}")),
+                Span::styled(value, Style::new().fg(Color::LightBlue)),
+            ])
+        };
+        let heading = |title: &'static str| Line::styled(title, Style::new().bold());
 
-        let line = Line::default().spans([
-            // Shortcut
-            shortcut_span,
-            // The time since the command was run, color coded by exit_code
-            Span::styled(
-                format!("{:>4}", SearchApp::time_since(&now, h)),
-                match h.exit_code {
-                    0 => Style::new().blue(),
-                    _ => Style::new().red(),
-                },
+        let enter = if marking {
+            "Delete the marked entries"
+        } else {
+            "Run the highlighted command"
+        };
+        let mut lines = vec![heading("Keys"), key("Enter", enter), key("Esc", "Exit")];
+        if !marking {
+            lines.push(key(
+                "Ctrl + e",
+                "Edit the highlighted command before running it",
+            ));
+            lines.push(key("Ctrl + y", "Copy the highlighted command"));
+        }
+        lines.extend([
+            key("Up / Down", "Move through the listed commands"),
+            key("Left / Right", "Move the cursor in the query"),
+            key("Backspace", "Delete the character before the cursor"),
+            key("Tab", "Cycle the scope: cwd, project, everything, session"),
+            key("Alt + s", "Pick a session to scope to"),
+            key("Alt + m", "Toggle the fuzzy and prefix search modes"),
+            key("Ctrl + s", "Toggle ordering by recency or best match"),
+            key("Alt + p", "Toggle the preview pane"),
+            key("Alt + d", "Delete the selected entry"),
+        ]);
+        if marking {
+            lines.push(key("Alt + x", "Mark the selected entry"));
+            lines.push(key("Alt + a", "Mark all listed entries"));
+        } else {
+            lines.push(key("Alt + 1..5", "Select the command that many rows up"));
+        }
+        lines.push(key(
+            "? / F1",
+            "Show this help, ? only while the query is empty",
+        ));
+
+        let scope = match &state.scope {
+            Scope::Cwd => String::from("cwd"),
+            Scope::Project(root) => format!("project {root}"),
+            Scope::All => String::from("everything"),
+            Scope::Session(session_id) => format!("session {session_id}"),
+        };
+        let mode = match state.mode {
+            MatchMode::Prefix => "prefix",
+            MatchMode::Fuzzy => "fuzzy",
+            MatchMode::FuzzyAny => "any",
+            MatchMode::Raw => "raw",
+            MatchMode::Regex => "regex",
+        };
+        let order = match state.order {
+            OrderBy::Recency => "recent",
+            OrderBy::Relevance => "best match",
+        };
+        let precision = match config.relative_precision() {
+            RelativePrecision::Coarse => "coarse",
+            RelativePrecision::Exact => "exact",
+        };
+        lines.extend([
+            Line::default(),
+            heading("Settings"),
+            setting("scope", scope),
+            setting("mode", mode.to_string()),
+            setting("order", order.to_string()),
+            setting("ui.wrap_preview", config.wrap_preview().to_string()),
+            setting("ui.relative_precision", precision.to_string()),
+            setting("ui.just_now_secs", config.just_now().as_secs().to_string()),
+            setting(
+                "ui.search_debounce_ms",
+                config.search_debounce().as_millis().to_string(),
             ),
-            // The command itself
-            Span::styled(format!(" {}", h.command), Style::default()),
+            setting("ui.limit_cwd", config.limit_cwd().to_string()),
+            setting("ui.limit_all", config.limit_all().to_string()),
         ]);
-        ListItem::new(line)
+
+        Clear.render_ref(area, buf);
+        Paragraph::new(lines)
+            .block(Block::bordered().title("Help (Esc or ?: close)"))
+            .render_ref(area, buf);
     }
 
-    /// Get a duration string for how long it has been since the command was run.
+    /// Renders the session picker overlay centered over the app.
     ///
-    /// * `now`: Function which returns the current time
-    /// * `then`: The command
-    fn time_since(now: &dyn Fn() -> OffsetDateTime, then: &History) -> String {
-        let since = (now()) - then.timestamp;
-        format_duration(since.try_into().unwrap_or_default())
+    /// * `picker`: The session picker state.
+    /// * `now`: A fn that returns the current timestamp.
+    fn render_session_picker(
+        area: Rect,
+        buf: &mut Buffer,
+        picker: &mut SessionPicker,
+        now: &dyn Fn() -> OffsetDateTime,
+    ) {
+        // Borders take a row each, and there is always at least one row for the list.
+        let height = u16::try_from(picker.sessions.len().max(1) + 2).unwrap_or(u16::MAX);
+        let [overlay] = Layout::vertical([Constraint::Length(height)])
+            .flex(Flex::Center)
+            .areas(area);
+        let [overlay] = Layout::horizontal([Constraint::Percentage(80)])
+            .flex(Flex::Center)
+            .areas(overlay);
+
+        let block = Block::bordered().title("Sessions (Enter: select, Esc: close)");
+        Clear.render_ref(overlay, buf);
+
+        if picker.sessions.is_empty() {
+            Paragraph::new("No sessions recorded yet.")
+                .block(block)
+                .render_ref(overlay, buf);
+            return;
+        }
+
+        let items = picker
+            .sessions
+            .iter()
+            .map(|(session_id, last_active, count)| {
+                let since = format_duration((now() - *last_active).try_into().unwrap_or_default());
+                ListItem::new(Line::default().spans([
+                    Span::styled(format!("{since:>4}"), Style::new().blue()),
+                    Span::default().content(format!(" {session_id} ")),
+                    Span::styled(format!("({count} commands)"), Style::new().gray()),
+                ]))
+            });
+        StatefulWidgetRef::render_ref(
+            &List::new(items)
+                .block(block)
+                .highlight_style(Style::default().fg(Color::Green))
+                .highlight_symbol(">>")
+                .highlight_spacing(HighlightSpacing::Always),
+            overlay,
+            buf,
+            &mut picker.list_state,
+        );
     }
-}
+
+    /// Generates a `ListItem` for the provided `History`.
+    ///
+    /// * `gutter`: Two columns before the entry, for its quick pick shortcut or mark.
+    /// * `time`: The time since the command ran.
+    /// * `time_width`: Width the time is right aligned to, see `time_column_width`.
+    /// * `styles`: The style of the time since the command ran, by how it exited.
+    fn history_to_list_item<'a>(
+        h: &'a History,
+        gutter: Span<'a>,
+        time: &str,
+        time_width: usize,
+        styles: &ExitStyles,
+    ) -> ListItem<'a> {
+        let (first_line, badge) = SearchApp::command_summary(&h.command);
+        let mut line = Line::default().spans([
+            // Shortcut or mark
+            gutter,
+            // The time since the command was run, color coded by exit_code. Always at least one
+            // space from the gutter, padded so the commands line up below each other.
+            Span::styled(
+                format!(" {time:>time_width$}"),
+                styles.style(ExitStatus::of(h.exit_code)),
+            ),
+            // The first line of the command, the preview pane shows the rest
+            Span::styled(format!(" {first_line}"), Style::default()),
+        ]);
+        if let Some(badge) = badge {
+            line.push_span(Span::styled(
+                format!(" {badge}"),
+                Style::default().fg(Color::DarkGray),
+            ));
+        }
+        ListItem::new(line)
+    }
+
+    /// Split a command into its first line and a badge counting any further lines.
+    ///
+    /// Returns `None` for the badge when the command is a single line.
+    fn command_summary(command: &str) -> (&str, Option<String>) {
+        let mut lines = command.lines();
+        let first_line = lines.next().unwrap_or_default();
+        let badge = match lines.count() {
+            0 => None,
+            1 => Some(String::from("(+1 line)")),
+            n => Some(format!("(+{n} lines)")),
+        };
+        (first_line, badge)
+    }
+
+    /// Width of the time column, the longest of the `times` shown but at least 3 columns.
+    fn time_column_width(times: &[String]) -> usize {
+        times
+            .iter()
+            .map(|time| time.chars().count())
+            .max()
+            .unwrap_or_default()
+            .max(3)
+    }
+
+    /// Get a duration string for how long it has been since the command was run.
+    ///
+    /// * `now`: Function which returns the current time
+    /// * `then`: The command
+    /// * `relative_time`: The precision to show it with, and below which it is `just now`.
+    fn time_since(
+        now: &dyn Fn() -> OffsetDateTime,
+        then: &History,
+        relative_time: &RelativeTime,
+    ) -> String {
+        let since = ((now()) - then.timestamp).try_into().unwrap_or_default();
+        relative_time.format(since)
+    }
+
+    /// Format the result counts shown in the header, e.g. "12/340 matches — 50000 total".
+    ///
+    /// Counts that could not be read are left out.
+    ///
+    /// * `displayed`: Number of results shown in the history list.
+    /// * `matches`: Number of results matching the query, if known.
+    /// * `scope_count`: Number of commands in the current scope, if known.
+    fn format_counts(displayed: usize, matches: Option<i64>, scope_count: Option<i64>) -> String {
+        let shown = match matches {
+            Some(matches) => format!("{displayed}/{matches} matches"),
+            None => format!("{displayed} matches"),
+        };
+        match scope_count {
+            Some(total) => format!("{shown} — {total} total"),
+            None => shown,
+        }
+    }
+}
 
 #[cfg(test)]
 mod tests {
+    use super::super::interactive::handle_key_events;
     use super::*;
+    use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
     use insta::assert_snapshot;
     use ratatui::{Terminal, backend::TestBackend, layout::Position};
 
-    use raven_common::config::Config;
+    use raven_common::config::{Config, UiConfig};
     use raven_database::database::{Database, DatabaseError};
+    use raven_database::history::model::HistoryUpdate;
+    use raven_database::import::aliases::Alias;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::{Arc, Mutex};
+    use std::time::Duration as StdDuration;
     use time::{Duration, OffsetDateTime};
 
     // Helper to create a default AppState for tests
@@ -454,10 +1584,39 @@ mod tests {
             list_state: ListState::default(),
             scope: Scope::All,
             cwd: String::from("/test/dir"),
+            session_id: String::from("current"),
+            project_root: None,
             confirming_delete: false, // Initialize here
             mode: MatchMode::default(),
+            order: OrderBy::default(),
+            session_picker: None,
+            show_preview: false,
+            inline: false,
+            showing_help: false,
+            status: None,
         }
     }
+
+    // Helper to read the rows of a rendered buffer as text
+    fn buffer_lines(buf: &Buffer) -> Vec<String> {
+        let area = buf.area;
+        (area.top()..area.bottom())
+            .map(|y| {
+                (area.left()..area.right())
+                    .map(|x| buf[(x, y)].symbol())
+                    .collect()
+            })
+            .collect()
+    }
+
+    // Helper to draw the app on a terminal of the given size and read the screen as text
+    fn draw_app(app: &mut SearchApp, state: &mut AppState, width: u16, height: u16) -> String {
+        let mut terminal = Terminal::new(TestBackend::new(width, height)).unwrap();
+        terminal
+            .draw(|frame| frame.render_stateful_widget_ref(app, frame.area(), state))
+            .unwrap();
+        buffer_lines(terminal.backend().buffer()).join("\n")
+    }
     // --- Mock Database for Testing ---
 
     #[derive(Clone, Default)]
@@ -466,6 +1625,21 @@ mod tests {
         mock_history: Vec<History>,
         // Control the total history count returned
         mock_total_count: i64,
+        // Sessions returned by recent_sessions
+        mock_sessions: Vec<(String, OffsetDateTime, i64)>,
+        // Number of searches run against the database
+        search_calls: Arc<AtomicUsize>,
+        // The order of the last search run against the database
+        last_order: Arc<Mutex<Option<OrderBy>>>,
+        last_limit: Arc<Mutex<Option<usize>>>,
+        // Whether deleting history fails
+        fail_deletes: bool,
+        // Searches for this query time out
+        timeout_query: Option<String>,
+        // Searches for this query fail
+        fail_query: Option<String>,
+        // Number of neighbor lookups run against the database
+        neighbor_calls: Arc<AtomicUsize>,
     }
 
     // Implement the methods SearchApp actually calls on the database connection.
@@ -474,20 +1648,35 @@ mod tests {
     // or a concrete type with these methods. If it's a trait, implement the trait.
     // For simplicity, implementing inherent methods here.
     impl MockDb {
-        fn search(&self, query: &str, _filters: HistoryFilters) -> Vec<History> {
-            let results = self
-                .mock_history
+        fn search(&self, query: &str, filters: HistoryFilters) -> Vec<History> {
+            self.mock_history
                 .iter()
                 .filter(|h| h.command.contains(query))
+                .filter(|h| {
+                    filters
+                        .session_id
+                        .as_ref()
+                        .is_none_or(|session_id| &h.session_id == session_id)
+                })
+                .skip(filters.offset.unwrap_or(0))
+                .take(filters.limit.unwrap_or(usize::MAX))
                 .cloned()
-                .collect();
-            results
+                .collect()
         }
 
         fn get_history_total(&self) -> i64 {
             self.mock_total_count
         }
 
+        fn search_count(&self, query: &str, filters: HistoryFilters) -> i64 {
+            let every_match = HistoryFilters {
+                limit: None,
+                offset: None,
+                ..filters
+            };
+            self.search(query, every_match).len() as i64
+        }
+
         // Helper to set up mock data for a test
         #[allow(dead_code)] // Used by tests implicitly via create_test_app
         fn set_history(&mut self, history: Vec<History>) {
@@ -510,6 +1699,21 @@ mod tests {
             query: &str,
             filters: HistoryFilters,
         ) -> Result<Vec<History>, DatabaseError> {
+            self.search_calls.fetch_add(1, Ordering::Relaxed);
+            *self.last_order.lock().unwrap() = Some(filters.order);
+            *self.last_limit.lock().unwrap() = filters.limit;
+            if self.timeout_query.as_deref() == Some(query) {
+                return Err(DatabaseError {
+                    msg: String::from("search timed out after 10ms"),
+                    kind: DatabaseErrorKind::Timeout,
+                });
+            }
+            if self.fail_query.as_deref() == Some(query) {
+                return Err(DatabaseError {
+                    msg: String::from("database is locked"),
+                    ..Default::default()
+                });
+            }
             // Delegate to inherent method
             Ok(self.search(query, filters))
         }
@@ -517,6 +1721,23 @@ mod tests {
             // Delegate to inherent method
             Ok(self.get_history_total())
         }
+        fn search_each(
+            &self,
+            query: &str,
+            filters: HistoryFilters,
+            f: &mut dyn FnMut(History),
+        ) -> Result<(), DatabaseError> {
+            self.search(query, filters).into_iter().for_each(f);
+            Ok(())
+        }
+        fn search_count(&self, query: &str, filters: HistoryFilters) -> Result<i64, DatabaseError> {
+            // Delegate to inherent method
+            Ok(self.search_count(query, filters))
+        }
+
+        fn count(&self, filters: HistoryFilters) -> Result<i64, DatabaseError> {
+            Ok(self.search_count("", filters))
+        }
 
         fn save(
             &mut self,
@@ -532,6 +1753,13 @@ mod tests {
             unimplemented!()
         }
 
+        fn save_bulk_dedup(
+            &mut self,
+            _history: &[History],
+        ) -> Result<Vec<i64>, raven_database::database::DatabaseError> {
+            unimplemented!()
+        }
+
         fn get(
             &self,
             _id: i64,
@@ -546,7 +1774,91 @@ mod tests {
             unimplemented!()
         }
 
-        fn delete(&self, _id: i64) -> Result<(), DatabaseError> {
+        fn update_partial(&self, _id: i64, _update: &HistoryUpdate) -> Result<(), DatabaseError> {
+            unimplemented!()
+        }
+
+        fn delete_bulk(&self, ids: &[i64]) -> Result<usize, DatabaseError> {
+            if self.fail_deletes {
+                return Err(DatabaseError {
+                    msg: String::from("database is locked"),
+                    ..Default::default()
+                });
+            }
+            Ok(ids.len())
+        }
+
+        fn delete(&self, id: i64) -> Result<(), DatabaseError> {
+            self.delete_bulk(&[id]).map(|_| ())
+        }
+
+        fn rewrite_cwd(&self, _from: &str, _to: &str) -> Result<usize, DatabaseError> {
+            unimplemented!()
+        }
+
+        fn neighbors(
+            &self,
+            _id: i64,
+            _window: usize,
+            _same_session: bool,
+        ) -> Result<(Vec<History>, Vec<History>), DatabaseError> {
+            self.neighbor_calls.fetch_add(1, Ordering::Relaxed);
+            Ok((Vec::new(), Vec::new()))
+        }
+
+        fn delete_where(
+            &self,
+            _filters: HistoryFilters,
+            _force_all: bool,
+        ) -> Result<usize, DatabaseError> {
+            unimplemented!()
+        }
+
+        fn delete_matching(
+            &self,
+            _query: &str,
+            _filters: HistoryFilters,
+        ) -> Result<usize, DatabaseError> {
+            unimplemented!()
+        }
+
+        fn delete_range(
+            &self,
+            _after: Option<OffsetDateTime>,
+            _before: Option<OffsetDateTime>,
+            _force_all: bool,
+        ) -> Result<usize, DatabaseError> {
+            unimplemented!()
+        }
+
+        fn recent_sessions(
+            &self,
+            n: usize,
+        ) -> Result<Vec<(String, OffsetDateTime, i64)>, DatabaseError> {
+            Ok(self.mock_sessions.iter().take(n).cloned().collect())
+        }
+
+        fn recent_commands(&self, n: usize) -> Result<Vec<History>, DatabaseError> {
+            Ok(self.mock_history.iter().take(n).cloned().collect())
+        }
+
+        fn save_aliases(&mut self, _aliases: &[Alias]) -> Result<usize, DatabaseError> {
+            unimplemented!()
+        }
+
+        fn aliases(&self) -> Result<Vec<Alias>, DatabaseError> {
+            unimplemented!()
+        }
+
+        fn backend_name(&self) -> &'static str {
+            "mock"
+        }
+
+        fn schema_version(&self) -> Result<u32, DatabaseError> {
+            unimplemented!()
+        }
+
+        fn health_check(&self) -> Result<bool, DatabaseError> {
             unimplemented!()
         }
         // ... etc for other trait methods
@@ -559,7 +1871,7 @@ mod tests {
     // require specific environment setup if it tries to access a real database.
     // For more robust tests, consider mocking the Context/DatabaseConnection.
 
-    fn create_test_app(initial_input: &str) -> SearchApp {
+    fn create_test_app(initial_input: &str) -> SearchApp<'static> {
         // Create the mock database and context
         let mut mock_db = Box::new(MockDb::default());
         let fake_history = Vec::from([
@@ -569,6 +1881,11 @@ mod tests {
                 timestamp: OffsetDateTime::now_utc(),
                 exit_code: 0,
                 cwd: String::new(),
+                session_id: String::new(),
+                tty: String::new(),
+                source: String::new(),
+                duration: -1,
+                updated_at: 0,
             },
             History {
                 id: 2,
@@ -576,6 +1893,11 @@ mod tests {
                 timestamp: OffsetDateTime::now_utc(),
                 exit_code: 0,
                 cwd: String::new(),
+                session_id: String::new(),
+                tty: String::new(),
+                source: String::new(),
+                duration: -1,
+                updated_at: 0,
             },
             History {
                 id: 3,
@@ -583,6 +1905,11 @@ mod tests {
                 timestamp: OffsetDateTime::now_utc(),
                 exit_code: 0,
                 cwd: String::new(),
+                session_id: String::new(),
+                tty: String::new(),
+                source: String::new(),
+                duration: -1,
+                updated_at: 0,
             },
             History {
                 id: 4,
@@ -590,6 +1917,11 @@ mod tests {
                 timestamp: OffsetDateTime::now_utc(),
                 exit_code: 0,
                 cwd: String::new(),
+                session_id: String::new(),
+                tty: String::new(),
+                source: String::new(),
+                duration: -1,
+                updated_at: 0,
             },
             History {
                 id: 5,
@@ -597,6 +1929,11 @@ mod tests {
                 timestamp: OffsetDateTime::now_utc(),
                 exit_code: 0,
                 cwd: String::new(),
+                session_id: String::new(),
+                tty: String::new(),
+                source: String::new(),
+                duration: -1,
+                updated_at: 0,
             },
             History {
                 id: 6,
@@ -604,6 +1941,11 @@ mod tests {
                 timestamp: OffsetDateTime::now_utc(),
                 exit_code: 0,
                 cwd: String::new(),
+                session_id: String::new(),
+                tty: String::new(),
+                source: String::new(),
+                duration: -1,
+                updated_at: 0,
             },
             History {
                 id: 7,
@@ -611,9 +1953,26 @@ mod tests {
                 timestamp: OffsetDateTime::now_utc(),
                 exit_code: 0,
                 cwd: String::new(),
+                session_id: String::new(),
+                tty: String::new(),
+                source: String::new(),
+                duration: -1,
+                updated_at: 0,
             },
         ]);
         mock_db.set_history(fake_history);
+        mock_db.mock_sessions = vec![
+            (
+                String::from("current"),
+                OffsetDateTime::from_unix_timestamp(1_699_999_990).unwrap(),
+                7,
+            ),
+            (
+                String::from("previous"),
+                OffsetDateTime::from_unix_timestamp(1_699_990_000).unwrap(),
+                3,
+            ),
+        ];
         let mock_context = Context {
             // Set a fixed CWD for tests
             cwd: "/test/dir".to_string(),
@@ -623,18 +1982,33 @@ mod tests {
             db: mock_db, // Assuming Arc<dyn Trait>
             // Add other Context fields if necessary, using default/test values
             config: Config::default(), // Or a specific test config
+            clock: || OffsetDateTime::from_unix_timestamp(1_700_000_000).unwrap(),
         };
 
         let pos = initial_input.chars().count();
         SearchApp {
-            context: mock_context, // Use the mocked context
+            // Leaked so the app can borrow it for as long as the test runs
+            context: Box::leak(Box::new(mock_context)),
             running: true,
             input: initial_input.to_string(),
             cursor_position: pos,
             commands: Vec::new(),
+            match_count: None,
+            scope_count: None,
             selected: None,
+            accept_mode: AcceptMode::default(),
+            marking: None,
+            on_accept: None,
+            accepted: 0,
+            clipboard: Box::new(|_| Ok(())),
+            recent_first: false,
             // Fixed 'now' function for predictable time tests
             now: Box::new(|| OffsetDateTime::from_unix_timestamp(1_700_000_000).unwrap()),
+            cache: LruCache::new(SEARCH_CACHE_SIZE),
+            debounce: StdDuration::ZERO,
+            pending_search: None,
+            neighbors: None,
+            exit_styles: ExitStyles::new(&Config::default()),
         }
     }
 
@@ -647,6 +2021,21 @@ mod tests {
         assert_eq!(app.clamp_cursor(10), 5); // Clamped to length
     }
 
+    #[test]
+    fn test_with_cursor() {
+        let app = create_test_app("héllo wörld").with_cursor(3);
+        assert_eq!(app.cursor_position, 3);
+        // Positions count characters, so the cursor lands after the multi-byte `é`.
+        assert_eq!(app.byte_index(), 4);
+
+        let app = create_test_app("héllo wörld").with_cursor(100);
+        assert_eq!(app.cursor_position, 11); // Clamped to length
+        assert_eq!(app.byte_index(), "héllo wörld".len());
+
+        let app = create_test_app("").with_cursor(1);
+        assert_eq!(app.cursor_position, 0);
+    }
+
     #[test]
     fn test_move_cursor_left() {
         let mut app = create_test_app("test");
@@ -693,19 +2082,19 @@ mod tests {
     #[test]
     fn test_enter_char() {
         let mut app = create_test_app("test");
-        let state = default_app_state();
+        let mut state = default_app_state();
         app.cursor_position = 2; // te|st
-        app.enter_char('X', &state); // Should become teX|st
+        app.enter_char('X', &mut state); // Should become teX|st
         assert_eq!(app.input, "teXst");
         assert_eq!(app.cursor_position, 3);
 
         app.cursor_position = 0; // |teXst
-        app.enter_char('Y', &state); // Should become Y|teXst
+        app.enter_char('Y', &mut state); // Should become Y|teXst
         assert_eq!(app.input, "YteXst");
         assert_eq!(app.cursor_position, 1);
 
         app.cursor_position = app.input.chars().count(); // YteXst|
-        app.enter_char('Z', &state); // Should become YteXstZ|
+        app.enter_char('Z', &mut state); // Should become YteXstZ|
         assert_eq!(app.input, "YteXstZ");
         assert_eq!(app.cursor_position, 7);
     }
@@ -713,24 +2102,24 @@ mod tests {
     #[test]
     fn test_delete_char() {
         let mut app = create_test_app("test");
-        let state = default_app_state();
+        let mut state = default_app_state();
         app.cursor_position = 3; // tes|t
-        app.delete_char(&state); // Should become te|t
+        app.delete_char(&mut state); // Should become te|t
         assert_eq!(app.input, "tet");
         assert_eq!(app.cursor_position, 2);
 
         app.cursor_position = 1; // t|et
-        app.delete_char(&state); // Should become |et
+        app.delete_char(&mut state); // Should become |et
         assert_eq!(app.input, "et");
         assert_eq!(app.cursor_position, 0);
 
-        app.delete_char(&state); // Cursor at 0, should do nothing
+        app.delete_char(&mut state); // Cursor at 0, should do nothing
         assert_eq!(app.input, "et");
         assert_eq!(app.cursor_position, 0);
 
         let mut app_multi = create_test_app("你好"); // ni hao
         app_multi.cursor_position = 1; // 你|好
-        app_multi.delete_char(&state); // Should become |好
+        app_multi.delete_char(&mut state); // Should become |好
         assert_eq!(app_multi.input, "好");
         assert_eq!(app_multi.cursor_position, 0);
     }
@@ -740,6 +2129,7 @@ mod tests {
     fn test_time_since() {
         // Fixed "now" time: 1700000000 (2023-11-14 22:13:20 UTC)
         let now_fn = || OffsetDateTime::from_unix_timestamp(1_700_000_000).unwrap();
+        let coarse = RelativeTime::new(&Config::default());
 
         // History entry 5 seconds ago
         let hist_5s = History {
@@ -747,9 +2137,14 @@ mod tests {
             command: "cmd1".to_string(),
             exit_code: 0,
             cwd: String::new(),
+            session_id: String::new(),
+            tty: String::new(),
+            source: String::new(),
+            duration: -1,
+            updated_at: 0,
             id: 1,
         };
-        assert_eq!(SearchApp::time_since(&now_fn, &hist_5s), "5s");
+        assert_eq!(SearchApp::time_since(&now_fn, &hist_5s, &coarse), "5s");
 
         // History entry 2 minutes ago
         let hist_2m = History {
@@ -757,9 +2152,14 @@ mod tests {
             command: "cmd2".to_string(),
             exit_code: 0,
             cwd: String::new(),
+            session_id: String::new(),
+            tty: String::new(),
+            source: String::new(),
+            duration: -1,
+            updated_at: 0,
             id: 2,
         };
-        assert_eq!(SearchApp::time_since(&now_fn, &hist_2m), "2m");
+        assert_eq!(SearchApp::time_since(&now_fn, &hist_2m, &coarse), "2m");
 
         // History entry 3 hours ago
         let hist_3h = History {
@@ -767,9 +2167,14 @@ mod tests {
             command: "cmd3".to_string(),
             exit_code: 0,
             cwd: String::new(),
+            session_id: String::new(),
+            tty: String::new(),
+            source: String::new(),
+            duration: -1,
+            updated_at: 0,
             id: 3,
         };
-        assert_eq!(SearchApp::time_since(&now_fn, &hist_3h), "3h");
+        assert_eq!(SearchApp::time_since(&now_fn, &hist_3h, &coarse), "3h");
 
         // History entry 4 days ago
         let hist_4d = History {
@@ -777,9 +2182,14 @@ mod tests {
             command: "cmd4".to_string(),
             exit_code: 0,
             cwd: String::new(),
+            session_id: String::new(),
+            tty: String::new(),
+            source: String::new(),
+            duration: -1,
+            updated_at: 0,
             id: 4,
         };
-        assert_eq!(SearchApp::time_since(&now_fn, &hist_4d), "4d");
+        assert_eq!(SearchApp::time_since(&now_fn, &hist_4d, &coarse), "4d");
 
         // History entry just now (or slightly in future due to precision)
         let hist_now = History {
@@ -787,9 +2197,139 @@ mod tests {
             command: "cmd5".to_string(),
             exit_code: 0,
             cwd: String::new(),
+            session_id: String::new(),
+            tty: String::new(),
+            source: String::new(),
+            duration: -1,
+            updated_at: 0,
             id: 5,
         };
-        assert_eq!(SearchApp::time_since(&now_fn, &hist_now), "0s"); // Assuming format_duration handles 0 correctly
+        assert_eq!(
+            SearchApp::time_since(&now_fn, &hist_now, &coarse),
+            "just now"
+        );
+    }
+
+    #[test]
+    fn test_time_since_just_now() {
+        let now_fn = || OffsetDateTime::from_unix_timestamp(1_700_000_000).unwrap();
+        let at = |ago: Duration| History {
+            timestamp: now_fn() - ago,
+            command: "cmd".to_string(),
+            exit_code: 0,
+            cwd: String::new(),
+            session_id: String::new(),
+            tty: String::new(),
+            source: String::new(),
+            duration: -1,
+            updated_at: 0,
+            id: 1,
+        };
+        let since = |relative_time: &RelativeTime, seconds| {
+            SearchApp::time_since(&now_fn, &at(Duration::seconds(seconds)), relative_time)
+        };
+
+        let default = RelativeTime::new(&Config::default());
+        assert_eq!(since(&default, 4), "just now");
+        assert_eq!(since(&default, 5), "5s");
+        assert_eq!(since(&default, 6), "6s");
+
+        let config = |just_now_secs| Config {
+            ui: Some(UiConfig {
+                just_now_secs: Some(just_now_secs),
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+        let longer = RelativeTime::new(&config(60));
+        assert_eq!(since(&longer, 59), "just now");
+        assert_eq!(since(&longer, 60), "1m");
+        let never = RelativeTime::new(&config(0));
+        assert_eq!(since(&never, 0), "0s");
+    }
+
+    #[test]
+    fn test_time_since_exact() {
+        let now_fn = || OffsetDateTime::from_unix_timestamp(1_700_000_000).unwrap();
+        let at = |ago: Duration| History {
+            timestamp: now_fn() - ago,
+            command: "cmd".to_string(),
+            exit_code: 0,
+            cwd: String::new(),
+            session_id: String::new(),
+            tty: String::new(),
+            source: String::new(),
+            duration: -1,
+            updated_at: 0,
+            id: 1,
+        };
+
+        let relative_time = |precision| RelativeTime {
+            precision,
+            just_now: StdDuration::ZERO,
+        };
+        let exact = |ago| {
+            SearchApp::time_since(&now_fn, &at(ago), &relative_time(RelativePrecision::Exact))
+        };
+        assert_eq!(exact(Duration::seconds(5)), "5s");
+        assert_eq!(exact(Duration::seconds(75)), "75s");
+        assert_eq!(exact(Duration::minutes(90)), "90m");
+        assert_eq!(exact(Duration::days(4)), "96h");
+
+        let coarse = |ago| {
+            SearchApp::time_since(&now_fn, &at(ago), &relative_time(RelativePrecision::Coarse))
+        };
+        assert_eq!(coarse(Duration::seconds(75)), "1m");
+        assert_eq!(coarse(Duration::minutes(90)), "1h");
+    }
+
+    #[test]
+    fn test_exit_status() {
+        assert_eq!(ExitStatus::of(0), ExitStatus::Success);
+        assert_eq!(ExitStatus::of(130), ExitStatus::Interrupted);
+        assert_eq!(ExitStatus::of(137), ExitStatus::Interrupted);
+        assert_eq!(ExitStatus::of(128), ExitStatus::Interrupted);
+        assert_eq!(ExitStatus::of(165), ExitStatus::Interrupted);
+        assert_eq!(ExitStatus::of(1), ExitStatus::Error);
+        assert_eq!(ExitStatus::of(127), ExitStatus::Error);
+        assert_eq!(ExitStatus::of(166), ExitStatus::Error);
+        assert_eq!(ExitStatus::of(-1), ExitStatus::Error);
+    }
+
+    #[test]
+    fn test_exit_styles() {
+        let config: Config = toml::from_str(
+            r##"
+            [ui]
+            interrupted_color = "#ff8800"
+            error_color = "not a color"
+            "##,
+        )
+        .unwrap();
+        let styles = ExitStyles::new(&config);
+        assert_eq!(styles.style(ExitStatus::Success).fg, Some(Color::Blue));
+        assert_eq!(
+            styles.style(ExitStatus::Interrupted).fg,
+            Some(Color::Rgb(0xff, 0x88, 0x00))
+        );
+        assert_eq!(styles.style(ExitStatus::Error).fg, Some(Color::Red));
+    }
+
+    #[test]
+    fn test_format_counts() {
+        assert_eq!(
+            SearchApp::format_counts(12, Some(340), Some(50000)),
+            "12/340 matches — 50000 total"
+        );
+        assert_eq!(
+            SearchApp::format_counts(12, None, Some(50000)),
+            "12 matches — 50000 total"
+        );
+        assert_eq!(
+            SearchApp::format_counts(12, Some(340), None),
+            "12/340 matches"
+        );
+        assert_eq!(SearchApp::format_counts(0, None, None), "0 matches");
     }
 
     #[test]
@@ -810,6 +2350,11 @@ mod tests {
                 timestamp: OffsetDateTime::now_utc(),
                 exit_code: 0,
                 cwd: String::new(),
+                session_id: String::new(),
+                tty: String::new(),
+                source: String::new(),
+                duration: -1,
+                updated_at: 0,
             },
             History {
                 id: 2,
@@ -817,18 +2362,885 @@ mod tests {
                 timestamp: OffsetDateTime::now_utc(),
                 exit_code: 0,
                 cwd: String::new(),
+                session_id: String::new(),
+                tty: String::new(),
+                source: String::new(),
+                duration: -1,
+                updated_at: 0,
             },
         ];
         assert!(app.selected.is_none());
         assert!(app.running);
 
-        app.select(1); // Select the second command ("cmd2")
+        app.select(1, AcceptMode::Execute); // Select the second command ("cmd2")
 
         assert!(app.selected.is_some());
         assert_eq!(app.selected.unwrap().command, "cmd2");
+        assert_eq!(app.accept_mode, AcceptMode::Execute);
         assert!(!app.running); // Selecting should also quit
     }
 
+    #[test]
+    fn test_select_accept_mode() {
+        let select = |key_event| {
+            let mut app = create_test_app("cmd");
+            let mut state = default_app_state();
+            app.get_history(&mut state);
+            state.list_state.select(Some(1));
+            handle_key_events(key_event, &mut app, &mut state);
+            (app.selected.map(|h| h.command), app.accept_mode)
+        };
+
+        assert_eq!(
+            select(KeyEvent::new(KeyCode::Enter, KeyModifiers::NONE)),
+            (Some(String::from("cmd2")), AcceptMode::Execute)
+        );
+        assert_eq!(
+            select(KeyEvent::new(KeyCode::Char('e'), KeyModifiers::CONTROL)),
+            (Some(String::from("cmd2")), AcceptMode::Buffer)
+        );
+        assert_eq!(AcceptMode::Execute.name(), "execute");
+        assert_eq!(AcceptMode::Buffer.name(), "buffer");
+    }
+
+    #[test]
+    fn test_copy_selected() {
+        let copied = Arc::new(Mutex::new(Vec::new()));
+        let sink = Arc::clone(&copied);
+        let mut app = create_test_app("cmd");
+        app.clipboard = Box::new(move |text| {
+            sink.lock().unwrap().push(text.to_string());
+            Ok(())
+        });
+        let mut state = default_app_state();
+        app.get_history(&mut state);
+        state.list_state.select(Some(1));
+
+        handle_key_events(
+            KeyEvent::new(KeyCode::Char('y'), KeyModifiers::CONTROL),
+            &mut app,
+            &mut state,
+        );
+        assert!(app.running);
+        assert!(app.selected.is_none());
+        assert_eq!(*copied.lock().unwrap(), vec!["cmd2"]);
+        assert_eq!(
+            state
+                .status
+                .as_ref()
+                .map(|(message, color, _)| (message.as_str(), *color)),
+            Some(("Copied to the clipboard", Color::Green))
+        );
+
+        // Without a clipboard the search carries on, with the reason on the status line.
+        app.clipboard = Box::new(|_| Err(io::Error::other("no clipboard")));
+        app.copy_selected(&mut state);
+        assert!(app.running);
+        assert_eq!(
+            state
+                .status
+                .as_ref()
+                .map(|(message, color, _)| (message.as_str(), *color)),
+            Some(("Unable to copy, no clipboard", Color::Red))
+        );
+    }
+
+    #[test]
+    fn test_load_more() {
+        let mut app = create_test_app("cmd");
+        app.context.config.ui = Some(UiConfig {
+            limit_all: Some(5),
+            ..Default::default()
+        });
+        let mut state = default_app_state();
+        app.get_history(&mut state);
+        assert_eq!(app.commands.len(), 5);
+        assert_eq!(app.match_count, Some(7));
+
+        // Nothing more is loaded until the last listed command is reached.
+        state.list_state.select(Some(3));
+        app.load_more(&mut state);
+        assert_eq!(app.commands.len(), 5);
+
+        let up = KeyEvent::new(KeyCode::Up, KeyModifiers::NONE);
+        handle_key_events(up, &mut app, &mut state);
+        let commands = |app: &SearchApp| {
+            app.commands
+                .iter()
+                .map(|h| h.command.clone())
+                .collect::<Vec<_>>()
+        };
+        let every_command: Vec<String> = (1..=7).map(|id| format!("cmd{id}")).collect();
+        assert_eq!(commands(&app), every_command);
+        assert_eq!(state.list_state.selected(), Some(4));
+
+        // Every match is listed, so there is nothing more to load.
+        state.list_state.select(Some(6));
+        handle_key_events(up, &mut app, &mut state);
+        assert_eq!(app.commands.len(), 7);
+
+        // The loaded page is kept with the cached results.
+        app.get_history(&mut state);
+        assert_eq!(commands(&app), every_command);
+    }
+
+    #[test]
+    fn test_select_keep_open() {
+        let accepted = Arc::new(Mutex::new(Vec::new()));
+        let sink = Arc::clone(&accepted);
+        let mut app = create_test_app("cmd")
+            .with_keep_open(move |h| sink.lock().unwrap().push(h.command.clone()));
+        app.get_history(&mut default_app_state());
+
+        app.select(1, AcceptMode::Execute);
+        app.select(0, AcceptMode::Buffer);
+
+        assert!(app.running);
+        assert!(app.selected.is_none());
+        assert_eq!(app.accepted, 2);
+        assert_eq!(*accepted.lock().unwrap(), vec!["cmd2", "cmd1"]);
+    }
+
+    #[test]
+    fn test_mark_and_delete() {
+        let mut app = create_test_app("cmd").with_marking();
+        let mut state = default_app_state();
+        app.get_history(&mut state);
+        let listed = app.commands.len();
+        let marked = |app: &SearchApp| app.marking.as_ref().unwrap().ids.len();
+
+        state.list_state.select(Some(0));
+        app.toggle_mark(&state);
+        state.list_state.select(Some(2));
+        app.toggle_mark(&state);
+        assert_eq!(marked(&app), 2);
+        app.toggle_mark(&state);
+        assert_eq!(marked(&app), 1);
+
+        app.toggle_mark_all();
+        assert_eq!(marked(&app), listed);
+        app.toggle_mark_all();
+        assert_eq!(marked(&app), 0);
+
+        app.toggle_mark(&state);
+        state.list_state.select(Some(listed - 1));
+        app.toggle_mark(&state);
+        app.initiate_delete(&mut state);
+        assert!(state.confirming_delete);
+        app.confirm_delete(&mut state);
+
+        assert!(!state.confirming_delete);
+        assert_eq!(app.commands.len(), listed - 2);
+        assert_eq!(marked(&app), 0);
+        assert_eq!(app.marking.as_ref().unwrap().deleted, 2);
+        assert_eq!(state.list_state.selected(), Some(listed - 3));
+    }
+
+    #[test]
+    fn test_failed_delete_sets_status() {
+        let failing_db = || {
+            Box::new(MockDb {
+                mock_history: (1..=3)
+                    .map(|id| History {
+                        id,
+                        command: format!("cmd{id}"),
+                        timestamp: OffsetDateTime::now_utc(),
+                        exit_code: 0,
+                        cwd: String::new(),
+                        session_id: String::new(),
+                        tty: String::new(),
+                        source: String::new(),
+                        duration: -1,
+                        updated_at: 0,
+                    })
+                    .collect(),
+                fail_deletes: true,
+                ..Default::default()
+            })
+        };
+
+        let mut app = create_test_app("cmd");
+        app.context.db = failing_db();
+        let mut state = default_app_state();
+        app.get_history(&mut state);
+        state.list_state.select(Some(1));
+        app.initiate_delete(&mut state);
+        app.confirm_delete(&mut state);
+        assert!(!state.confirming_delete);
+        assert_eq!(app.commands.len(), 3);
+        let (message, color, set_at) = state.status.clone().expect("Status should be set");
+        assert!(message.contains("database is locked"), "{message}");
+        assert_eq!(color, Color::Red);
+
+        SearchApp::expire_status(&mut state, set_at + StdDuration::from_secs(1));
+        assert!(state.status.is_some());
+        SearchApp::expire_status(&mut state, set_at + STATUS_TIMEOUT);
+        assert!(state.status.is_none());
+
+        let mut app = create_test_app("cmd").with_marking();
+        app.context.db = failing_db();
+        let mut state = default_app_state();
+        app.get_history(&mut state);
+        state.list_state.select(Some(0));
+        app.toggle_mark(&state);
+        app.initiate_delete(&mut state);
+        app.confirm_delete(&mut state);
+        assert_eq!(app.commands.len(), 3);
+        assert_eq!(app.marking.as_ref().unwrap().deleted, 0);
+        assert!(state.status.is_some());
+        SearchApp::clear_status(&mut state);
+        assert!(state.status.is_none());
+    }
+
+    #[test]
+    fn test_search_timeout_sets_status() {
+        let mut app = create_test_app("cmd");
+        app.context.db = Box::new(MockDb {
+            mock_history: (1..=3)
+                .map(|id| History {
+                    id,
+                    command: format!("cmd{id}"),
+                    timestamp: OffsetDateTime::now_utc(),
+                    exit_code: 0,
+                    cwd: String::new(),
+                    session_id: String::new(),
+                    tty: String::new(),
+                    source: String::new(),
+                    duration: -1,
+                    updated_at: 0,
+                })
+                .collect(),
+            timeout_query: Some(String::from("cmd2")),
+            ..Default::default()
+        });
+        let mut state = default_app_state();
+        app.get_history(&mut state);
+        assert_eq!(app.commands.len(), 3);
+        assert!(state.status.is_none());
+
+        // The slow search keeps the previous results listed instead of failing.
+        app.enter_char('2', &mut state);
+        app.flush_search(&mut state);
+        assert_eq!(app.commands.len(), 3);
+        let (message, _, _) = state.status.clone().expect("Status should be set");
+        assert_eq!(message, "Search timed out, refine your query");
+
+        // The configured timeout applies to the interactive search only.
+        assert_eq!(app.search_filters(&state).timeout, None);
+        app.context.config = toml::from_str("[search]\ntimeout_ms = 50").unwrap();
+        assert_eq!(
+            app.search_filters(&state).timeout,
+            Some(StdDuration::from_millis(50))
+        );
+    }
+
+    #[test]
+    fn test_failed_search_sets_status() {
+        let mut app = create_test_app("cmd");
+        app.context.db = Box::new(MockDb {
+            mock_history: (1..=3)
+                .map(|id| History {
+                    id,
+                    command: format!("cmd{id}"),
+                    timestamp: OffsetDateTime::now_utc(),
+                    exit_code: 0,
+                    cwd: String::new(),
+                    session_id: String::new(),
+                    tty: String::new(),
+                    source: String::new(),
+                    duration: -1,
+                    updated_at: 0,
+                })
+                .collect(),
+            fail_query: Some(String::from("cmd2")),
+            ..Default::default()
+        });
+        let mut state = default_app_state();
+        app.get_history(&mut state);
+        assert_eq!(app.commands.len(), 3);
+
+        // The error is reported instead of closing the search, the previous results stay listed.
+        app.enter_char('2', &mut state);
+        app.flush_search(&mut state);
+        assert_eq!(app.commands.len(), 3);
+        let (message, color, _) = state.status.clone().expect("Status should be set");
+        assert_eq!(
+            (message.as_str(), color),
+            (
+                "Search failed: Encountered a database error: database is locked",
+                Color::Red
+            )
+        );
+    }
+
+    #[test]
+    fn test_debounce_elapsed() {
+        let start = Instant::now();
+        let debounce = StdDuration::from_millis(50);
+        let at = |millis| start + StdDuration::from_millis(millis);
+
+        assert!(!SearchApp::debounce_elapsed(None, at(100), debounce));
+        assert!(!SearchApp::debounce_elapsed(Some(start), at(49), debounce));
+        assert!(SearchApp::debounce_elapsed(Some(start), at(50), debounce));
+        assert!(SearchApp::debounce_elapsed(Some(start), at(80), debounce));
+        // A clock reading from before the keystroke is never due.
+        assert!(!SearchApp::debounce_elapsed(Some(at(10)), start, debounce));
+    }
+
+    #[test]
+    fn test_debounced_search() {
+        let mut app = create_test_app("cmd");
+        app.debounce = StdDuration::from_millis(50);
+        let mut state = default_app_state();
+        app.get_history(&mut state);
+        let listed = app.commands.len();
+
+        app.enter_char('1', &mut state);
+        assert_eq!(app.commands.len(), listed);
+        let typed = app.pending_search.expect("search should be pending");
+
+        app.search_if_idle(&mut state, typed + StdDuration::from_millis(10));
+        assert_eq!(app.commands.len(), listed);
+        app.search_if_idle(&mut state, typed + StdDuration::from_millis(50));
+        assert_eq!(app.commands.len(), 1);
+        assert!(app.pending_search.is_none());
+
+        app.delete_char(&mut state);
+        app.flush_search(&mut state);
+        assert_eq!(app.commands.len(), listed);
+    }
+
+    #[test]
+    fn test_recent_commands_first_load() {
+        let calls = Arc::new(AtomicUsize::new(0));
+        let mut app = create_test_app("").with_recent_commands();
+        app.context.db = Box::new(MockDb {
+            mock_history: (1..=3)
+                .map(|id| History {
+                    id,
+                    command: format!("cmd{id}"),
+                    timestamp: OffsetDateTime::now_utc(),
+                    exit_code: 0,
+                    cwd: String::new(),
+                    session_id: String::new(),
+                    tty: String::new(),
+                    source: String::new(),
+                    duration: -1,
+                    updated_at: 0,
+                })
+                .collect(),
+            search_calls: calls.clone(),
+            ..Default::default()
+        });
+        let mut state = default_app_state();
+
+        // The first load is read from the cache, without searching the history.
+        app.get_history(&mut state);
+        assert_eq!(calls.load(Ordering::Relaxed), 0);
+        assert_eq!(app.commands.len(), 3);
+        assert_eq!(app.match_count, Some(3));
+
+        // Later loads search as usual.
+        app.get_history(&mut state);
+        assert_eq!(calls.load(Ordering::Relaxed), 1);
+
+        // A query to start with is searched for.
+        let mut app = create_test_app("cmd2").with_recent_commands();
+        app.get_history(&mut default_app_state());
+        assert_eq!(app.commands.len(), 1);
+    }
+
+    #[test]
+    fn test_update_neighbors() {
+        let mut app = create_test_app("cmd");
+        let calls = Arc::new(AtomicUsize::new(0));
+        app.context.db = Box::new(MockDb {
+            mock_history: (1..=3)
+                .map(|id| History {
+                    id,
+                    command: format!("cmd{id}"),
+                    timestamp: OffsetDateTime::now_utc(),
+                    exit_code: 0,
+                    cwd: String::new(),
+                    session_id: String::new(),
+                    tty: String::new(),
+                    source: String::new(),
+                    duration: -1,
+                    updated_at: 0,
+                })
+                .collect(),
+            neighbor_calls: calls.clone(),
+            ..Default::default()
+        });
+        let mut state = default_app_state();
+        app.get_history(&mut state);
+        state.list_state.select_first();
+        let lookups = || calls.load(Ordering::Relaxed);
+
+        // Nothing is looked up while the preview is hidden.
+        app.update_neighbors(&state);
+        assert_eq!(lookups(), 0);
+
+        // Each selected command is looked up once, not on every draw.
+        state.show_preview = true;
+        app.update_neighbors(&state);
+        app.update_neighbors(&state);
+        assert_eq!(lookups(), 1);
+        state.list_state.select_next();
+        app.update_neighbors(&state);
+        assert_eq!(lookups(), 2);
+        assert_eq!(
+            app.neighbors.as_ref().map(|(id, _)| *id),
+            Some(app.commands[1].id)
+        );
+    }
+
+    #[test]
+    fn test_search_cache() {
+        let mut app = create_test_app("cmd").with_marking();
+        let calls = Arc::new(AtomicUsize::new(0));
+        app.context.db = Box::new(MockDb {
+            mock_history: (1..=3)
+                .map(|id| History {
+                    id,
+                    command: format!("cmd{id}"),
+                    timestamp: OffsetDateTime::now_utc(),
+                    exit_code: 0,
+                    cwd: String::new(),
+                    session_id: String::new(),
+                    tty: String::new(),
+                    source: String::new(),
+                    duration: -1,
+                    updated_at: 0,
+                })
+                .collect(),
+            search_calls: calls.clone(),
+            ..Default::default()
+        });
+        let mut state = default_app_state();
+        let searches = || calls.load(Ordering::Relaxed);
+
+        app.get_history(&mut state);
+        assert_eq!(searches(), 1);
+        app.enter_char('1', &mut state);
+        assert_eq!(searches(), 2);
+        assert_eq!(app.commands.len(), 1);
+
+        // Backspacing back to a previous query reuses its results.
+        app.delete_char(&mut state);
+        assert_eq!(searches(), 2);
+        assert_eq!(app.commands.len(), 3);
+        assert_eq!(app.match_count, Some(3));
+
+        // A different scope or mode is a different search.
+        state.scope = Scope::Cwd;
+        app.get_history(&mut state);
+        assert_eq!(searches(), 3);
+
+        // Deleting history invalidates every cached search.
+        state.list_state.select(Some(0));
+        app.initiate_delete(&mut state);
+        app.confirm_delete(&mut state);
+        app.get_history(&mut state);
+        assert_eq!(searches(), 4);
+    }
+
+    #[test]
+    fn test_search_order() {
+        let mut app = create_test_app("cmd");
+        let last_order = Arc::new(Mutex::new(None));
+        app.context.db = Box::new(MockDb {
+            last_order: last_order.clone(),
+            ..Default::default()
+        });
+        let mut state = default_app_state();
+
+        app.get_history(&mut state);
+        assert_eq!(*last_order.lock().unwrap(), Some(OrderBy::Recency));
+
+        // Another order is another search, rather than the cached results.
+        state.order = OrderBy::Relevance;
+        app.get_history(&mut state);
+        assert_eq!(*last_order.lock().unwrap(), Some(OrderBy::Relevance));
+    }
+
+    #[test]
+    fn test_search_limit_per_scope() {
+        let mut app = create_test_app("cmd");
+        let last_limit = Arc::new(Mutex::new(None));
+        app.context.db = Box::new(MockDb {
+            last_limit: last_limit.clone(),
+            ..Default::default()
+        });
+        app.context.config.ui = Some(UiConfig {
+            limit_cwd: Some(1000),
+            limit_all: Some(200),
+            ..Default::default()
+        });
+        let mut state = default_app_state();
+
+        state.scope = Scope::Cwd;
+        app.get_history(&mut state);
+        assert_eq!(*last_limit.lock().unwrap(), Some(1000));
+
+        app.toggle_scope(&mut state);
+        assert_eq!(state.scope, Scope::All);
+        assert_eq!(*last_limit.lock().unwrap(), Some(200));
+
+        state.scope = Scope::Project(String::from("/test"));
+        app.get_history(&mut state);
+        assert_eq!(*last_limit.lock().unwrap(), Some(1000));
+
+        // Both default to 500.
+        app.context.config.ui = None;
+        state.scope = Scope::Session(String::from("previous"));
+        app.get_history(&mut state);
+        assert_eq!(*last_limit.lock().unwrap(), Some(500));
+    }
+
+    #[test]
+    fn test_mark_and_delete_dry_run() {
+        let mut app = create_test_app("cmd").with_marking().with_dry_run();
+        let mut state = default_app_state();
+        app.get_history(&mut state);
+        let listed = app.commands.len();
+        let total = app.context.db.get_history_total().unwrap();
+
+        state.list_state.select(Some(0));
+        app.toggle_mark(&state);
+        state.list_state.select(Some(2));
+        app.toggle_mark(&state);
+        app.initiate_delete(&mut state);
+        app.confirm_delete(&mut state);
+        // Confirming the same entry again does not count it twice.
+        app.initiate_delete(&mut state);
+        app.confirm_delete(&mut state);
+
+        let marking = app.marking.as_ref().unwrap();
+        let would_delete = marking
+            .would_delete
+            .iter()
+            .map(|h| h.id)
+            .collect::<Vec<_>>();
+        assert_eq!(would_delete, vec![app.commands[0].id, app.commands[2].id]);
+        assert_eq!(marking.deleted, 0);
+        assert!(marking.ids.is_empty());
+        assert_eq!(app.commands.len(), listed);
+        assert_eq!(app.context.db.get_history_total().unwrap(), total);
+    }
+
+    #[test]
+    fn test_toggle_scope() {
+        let mut app = create_test_app("");
+        let mut state = default_app_state();
+        state.scope = Scope::Cwd;
+
+        app.toggle_scope(&mut state);
+        assert_eq!(state.scope, Scope::All);
+        app.toggle_scope(&mut state);
+        assert_eq!(state.scope, Scope::Session(String::from("current")));
+        app.toggle_scope(&mut state);
+        assert_eq!(state.scope, Scope::Cwd);
+
+        // Inside a repository the project scope follows the cwd.
+        state.project_root = Some(String::from("/test"));
+        app.toggle_scope(&mut state);
+        assert_eq!(state.scope, Scope::Project(String::from("/test")));
+        app.toggle_scope(&mut state);
+        assert_eq!(state.scope, Scope::All);
+        app.toggle_scope(&mut state);
+        app.toggle_scope(&mut state);
+        assert_eq!(state.scope, Scope::Cwd);
+        state.project_root = None;
+
+        // Without a session id the session scope is skipped.
+        state.session_id = String::new();
+        app.toggle_scope(&mut state);
+        app.toggle_scope(&mut state);
+        assert_eq!(state.scope, Scope::Cwd);
+    }
+
+    #[test]
+    fn test_session_picker() {
+        let mut app = create_test_app("");
+        let mut state = default_app_state();
+
+        app.open_session_picker(&mut state);
+        let picker = state
+            .session_picker
+            .as_mut()
+            .expect("picker should be open");
+        assert_eq!(picker.sessions.len(), 2);
+        assert_eq!(picker.list_state.selected(), Some(0));
+
+        // Closing leaves the scope unchanged.
+        SearchApp::close_session_picker(&mut state);
+        assert!(state.session_picker.is_none());
+        assert_eq!(state.scope, Scope::All);
+
+        // Picking scopes the history to the selected session.
+        app.open_session_picker(&mut state);
+        state
+            .session_picker
+            .as_mut()
+            .unwrap()
+            .list_state
+            .select_next();
+        app.pick_session(&mut state);
+        assert!(state.session_picker.is_none());
+        assert_eq!(state.scope, Scope::Session(String::from("previous")));
+    }
+
+    #[test]
+    fn test_command_summary() {
+        assert_eq!(SearchApp::command_summary("ls -la"), ("ls -la", None));
+        assert_eq!(
+            SearchApp::command_summary("for f in *\ndo\n  echo $f\ndone"),
+            ("for f in *", Some(String::from("(+3 lines)")))
+        );
+        assert_eq!(
+            SearchApp::command_summary("echo a\necho b"),
+            ("echo a", Some(String::from("(+1 line)")))
+        );
+    }
+
+    #[test]
+    fn test_time_column_alignment() {
+        let now = OffsetDateTime::UNIX_EPOCH + Duration::days(1000);
+        let at = |ago: Duration| {
+            History::builder()
+                .id(1)
+                .command(String::from("ls"))
+                .cwd(String::from("/tmp"))
+                .exit_code(0)
+                .timestamp(now - ago)
+                .session_id(String::new())
+                .build()
+        };
+        // 6s, 300d and just now, the longest label sets the width of the column.
+        let history = [
+            at(Duration::seconds(6)),
+            at(Duration::days(300)),
+            at(Duration::seconds(1)),
+        ];
+        let area = Rect::new(0, 0, 30, 3);
+        let mut buf = Buffer::empty(area);
+        SearchApp::render_history_list(
+            area,
+            &mut buf,
+            &history,
+            &mut ListState::default(),
+            &|| now,
+            &Config::default(),
+            &ExitStyles::new(&Config::default()),
+            None,
+        );
+        let rows = buffer_lines(&buf);
+        let columns: Vec<usize> = rows.iter().map(|row| row.find("ls").unwrap()).collect();
+        assert!(
+            columns.iter().all(|&column| column == columns[0]),
+            "{rows:#?}"
+        );
+        assert!(
+            rows.iter().any(|row| row.contains("just now ls")),
+            "{rows:#?}"
+        );
+
+        assert_eq!(SearchApp::time_column_width(&[]), 3);
+        assert_eq!(
+            SearchApp::time_column_width(&[String::from("5s"), String::from("10mo")]),
+            4
+        );
+    }
+
+    #[test]
+    fn test_history_to_list_item_multi_line() {
+        let h = History::builder()
+            .id(1)
+            .command(String::from("for f in *\ndo\n  echo $f\ndone"))
+            .cwd(String::from("/tmp"))
+            .exit_code(0)
+            .timestamp(OffsetDateTime::UNIX_EPOCH)
+            .session_id(String::new())
+            .build();
+        let now = || OffsetDateTime::UNIX_EPOCH;
+        let time = SearchApp::time_since(&now, &h, &RelativeTime::new(&Config::default()));
+        let item = SearchApp::history_to_list_item(
+            &h,
+            Span::raw("  "),
+            &time,
+            SearchApp::time_column_width(std::slice::from_ref(&time)),
+            &ExitStyles::new(&Config::default()),
+        );
+        assert_eq!(item.height(), 1);
+
+        let area = Rect::new(0, 0, 40, 1);
+        let mut buf = Buffer::empty(area);
+        WidgetRef::render_ref(&List::new([item]), area, &mut buf);
+        assert_eq!(
+            buffer_lines(&buf)[0].trim_end(),
+            "   just now for f in * (+3 lines)"
+        );
+    }
+
+    #[test]
+    fn test_render_preview() {
+        let area = Rect::new(0, 0, 12, 4);
+        let command = "echo one two three";
+
+        let mut buf = Buffer::empty(area);
+        SearchApp::render_preview(area, &mut buf, command, (&[], &[]), true);
+        let mut expected = Buffer::with_lines([
+            "┌Preview───┐",
+            "│echo one  │",
+            "│two three │",
+            "└──────────┘",
+        ]);
+        expected.set_style(area, Style::default());
+        assert_eq!(buf, expected);
+
+        let mut buf = Buffer::empty(area);
+        SearchApp::render_preview(area, &mut buf, command, (&[], &[]), false);
+        let mut expected = Buffer::with_lines([
+            "┌Preview───┐",
+            "│echo one t│",
+            "│          │",
+            "└──────────┘",
+        ]);
+        expected.set_style(area, Style::default());
+        assert_eq!(buf, expected);
+    }
+
+    #[test]
+    fn test_render_preview_neighbors() {
+        let area = Rect::new(0, 0, 14, 6);
+        let h = |command: &str| {
+            History::builder()
+                .id(1)
+                .command(command.to_string())
+                .cwd(String::from("/tmp"))
+                .exit_code(0)
+                .timestamp(OffsetDateTime::UNIX_EPOCH)
+                .session_id(String::new())
+                .build()
+        };
+
+        let mut buf = Buffer::empty(area);
+        SearchApp::render_preview(
+            area,
+            &mut buf,
+            "make\nmake test",
+            (&[h("cd src")], &[h("git add\n-p")]),
+            true,
+        );
+        let rows = buffer_lines(&buf);
+        let rows: Vec<&str> = rows[1..5]
+            .iter()
+            .map(|row| row.trim_matches('│').trim_end())
+            .collect();
+        assert_eq!(rows, vec!["cd src", "make", "make test", "git add"]);
+        // The neighbors are dimmed, the command itself is not.
+        assert_eq!(buf[(1, 1)].fg, Color::DarkGray);
+        assert_eq!(buf[(1, 2)].fg, Color::Reset);
+        assert_eq!(buf[(1, 4)].fg, Color::DarkGray);
+    }
+
+    #[test]
+    fn test_render_app_inline() {
+        let mut app = create_test_app("cmd");
+        let mut app_state = AppState {
+            inline: true,
+            ..default_app_state()
+        };
+        app.get_history(&mut app_state);
+        app_state.list_state.select_first();
+        let mut terminal = Terminal::new(TestBackend::new(60, 10)).unwrap();
+        let _ = terminal
+            .draw(|frame| frame.render_stateful_widget_ref(&mut app, frame.area(), &mut app_state));
+        assert_snapshot!(terminal.backend());
+        assert_eq!(app_state.cusor_position, Position::new(13, 6));
+    }
+
+    #[test]
+    fn test_render_app_marking() {
+        let mut app = create_test_app("cmd").with_marking();
+        let mut app_state = default_app_state();
+        app.get_history(&mut app_state);
+        app_state.list_state.select(Some(1));
+        app.toggle_mark(&app_state);
+        app_state.confirming_delete = true;
+        let mut terminal = Terminal::new(TestBackend::new(80, 40)).unwrap();
+        let _ = terminal
+            .draw(|frame| frame.render_stateful_widget_ref(&mut app, frame.area(), &mut app_state));
+        assert_snapshot!(terminal.backend());
+    }
+
+    #[test]
+    fn test_render_app_too_small() {
+        let render = |width, height| {
+            let mut app = create_test_app("cmd");
+            let mut app_state = default_app_state();
+            app.get_history(&mut app_state);
+            app_state.list_state.select_first();
+            draw_app(&mut app, &mut app_state, width, height)
+        };
+
+        let message = render(80, 5);
+        assert!(message.contains("Terminal too small"), "{message}");
+        assert!(!message.contains("cmd1"), "{message}");
+        assert!(render(10, 20).contains("Terminal"));
+
+        // Too short for the header and shortcuts, the commands and query box are still shown.
+        let compact = render(80, 12);
+        assert!(compact.contains("cmd1"), "{compact}");
+        assert!(compact.contains("[fuzzy]  cmd"), "{compact}");
+        assert!(!compact.contains("Terminal too small"), "{compact}");
+    }
+
+    #[test]
+    fn test_render_status() {
+        let render = |height| {
+            let mut app = create_test_app("zzz");
+            let mut app_state = default_app_state();
+            app.get_history(&mut app_state);
+            let screen = draw_app(&mut app, &mut app_state, 80, height);
+            (app_state.status, screen)
+        };
+
+        let (status, screen) = render(30);
+        let (message, color, _) = status.expect("Status should be set");
+        assert_eq!(
+            (message.as_str(), color),
+            ("No matching commands", Color::Yellow)
+        );
+        // It takes the place of the shortcuts title.
+        assert!(screen.contains("No matching commands"), "{screen}");
+        assert!(!screen.contains("Shortcuts"), "{screen}");
+
+        // Without the shortcuts it is shown above the query instead.
+        let (_, compact) = render(12);
+        assert!(compact.contains("No matching commands"), "{compact}");
+
+        let mut app = create_test_app("cmd");
+        let mut app_state = default_app_state();
+        app.get_history(&mut app_state);
+        assert!(app_state.status.is_none());
+    }
+
+    #[test]
+    fn test_render_help() {
+        let mut app = create_test_app("cmd");
+        let mut app_state = AppState {
+            showing_help: true,
+            ..default_app_state()
+        };
+        app.get_history(&mut app_state);
+        let mut terminal = Terminal::new(TestBackend::new(80, 30)).unwrap();
+        let _ = terminal
+            .draw(|frame| frame.render_stateful_widget_ref(&mut app, frame.area(), &mut app_state));
+        assert_snapshot!(terminal.backend());
+    }
+
     #[test]
     fn test_render_app() {
         let mut app = create_test_app("cmd");
@@ -837,10 +3249,18 @@ mod tests {
             list_state: ListState::default(),
             scope: Scope::Cwd,
             cwd: String::new(),
+            session_id: String::new(),
+            project_root: None,
             confirming_delete: false, // Initialize here
             mode: MatchMode::default(),
+            order: OrderBy::default(),
+            session_picker: None,
+            show_preview: false,
+            inline: false,
+            showing_help: false,
+            status: None,
         };
-        app.get_history(&app_state);
+        app.get_history(&mut app_state);
         println!("{:?}", app.commands);
         app_state.list_state.select_first();
         let mut terminal = Terminal::new(TestBackend::new(80, 40)).unwrap();
@@ -0,0 +1,74 @@
+//! A small least recently used cache for the results of the interactive search.
+use std::collections::VecDeque;
+
+/// A bounded cache which evicts the least recently used entry once it is full.
+///
+/// Lookups are linear, so it is only meant for a handful of entries.
+#[derive(Debug)]
+pub struct LruCache<K, V> {
+    capacity: usize,
+    /// Entries ordered from least to most recently used.
+    entries: VecDeque<(K, V)>,
+}
+
+impl<K: PartialEq, V> LruCache<K, V> {
+    /// Create an empty cache holding at most `capacity` entries.
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            entries: VecDeque::with_capacity(capacity),
+        }
+    }
+
+    /// Get the value cached for `key`, marking it as the most recently used.
+    pub fn get(&mut self, key: &K) -> Option<&V> {
+        let idx = self.entries.iter().position(|(k, _)| k == key)?;
+        let entry = self.entries.remove(idx)?;
+        self.entries.push_back(entry);
+        self.entries.back().map(|(_, v)| v)
+    }
+
+    /// Cache `value` for `key`, evicting the least recently used entry if the cache is full.
+    pub fn insert(&mut self, key: K, value: V) {
+        if let Some(idx) = self.entries.iter().position(|(k, _)| *k == key) {
+            self.entries.remove(idx);
+        } else if self.entries.len() >= self.capacity {
+            self.entries.pop_front();
+        }
+        if self.capacity > 0 {
+            self.entries.push_back((key, value));
+        }
+    }
+
+    /// Remove every entry, for when the cached values are no longer accurate.
+    pub fn clear(&mut self) {
+        self.entries.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_lru_cache() {
+        let mut cache = LruCache::new(2);
+        cache.insert("a", 1);
+        cache.insert("b", 2);
+        assert_eq!(cache.get(&"a"), Some(&1));
+
+        // "b" is now the least recently used, so it is evicted first.
+        cache.insert("c", 3);
+        assert_eq!(cache.get(&"b"), None);
+        assert_eq!(cache.get(&"a"), Some(&1));
+        assert_eq!(cache.get(&"c"), Some(&3));
+
+        // Replacing an entry does not evict another.
+        cache.insert("c", 4);
+        assert_eq!(cache.get(&"a"), Some(&1));
+        assert_eq!(cache.get(&"c"), Some(&4));
+
+        cache.clear();
+        assert_eq!(cache.get(&"a"), None);
+    }
+}
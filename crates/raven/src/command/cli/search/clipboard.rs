@@ -0,0 +1,52 @@
+use std::io::{self, Write};
+use std::process::{Command, Stdio};
+
+/// Commands which copy their stdin to the system clipboard, tried in order until one succeeds.
+const CLIPBOARD_COMMANDS: &[(&str, &[&str])] = &[
+    ("pbcopy", &[]),
+    ("wl-copy", &[]),
+    ("xclip", &["-selection", "clipboard"]),
+    ("xsel", &["--clipboard", "--input"]),
+    ("clip.exe", &[]),
+];
+
+/// Copy `text` to the system clipboard with the first available clipboard command.
+///
+/// # Errors
+///
+/// Returns an error if none of the clipboard commands are installed or they all failed, such as
+/// `xclip` without a display to copy to.
+pub fn copy(text: &str) -> io::Result<()> {
+    for (program, args) in CLIPBOARD_COMMANDS {
+        if run(program, args, text).is_ok() {
+            return Ok(());
+        }
+    }
+    Err(io::Error::new(
+        io::ErrorKind::NotFound,
+        "no clipboard, install wl-copy, xclip or xsel",
+    ))
+}
+
+/// Pipe `text` into `program`, which is expected to exit successfully once it has read it.
+fn run(program: &str, args: &[&str], text: &str) -> io::Result<()> {
+    // Its output would be drawn over the search.
+    let mut child = Command::new(program)
+        .args(args)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .spawn()?;
+    // Dropping stdin closes it, so the program sees the end of the text.
+    let written = child
+        .stdin
+        .take()
+        .map_or(Ok(()), |mut stdin| stdin.write_all(text.as_bytes()));
+    let status = child.wait()?;
+    written?;
+    if status.success() {
+        Ok(())
+    } else {
+        Err(io::Error::other(format!("{program} exited with {status}")))
+    }
+}
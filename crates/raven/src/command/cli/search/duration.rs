@@ -28,6 +28,92 @@ pub fn format_duration(f: Duration) -> String {
     F(f).to_string()
 }
 
+/// Formats a `Duration` as a total in the unit below its most significant one,
+/// so less precision is lost than with `format_duration`.
+///
+/// Examples:
+/// - 75 seconds -> "75s"
+/// - 5400 seconds -> "90m"
+/// - 2 days -> "48h"
+/// - 5 seconds -> "5s"
+///
+/// Durations below a second are formatted by `format_duration`.
+///
+/// # Arguments
+///
+/// * `f` - The `Duration` to format.
+///
+/// # Returns
+///
+/// A `String` representation of the duration.
+pub fn format_duration_exact(f: Duration) -> String {
+    const UNITS: [(&str, u64); 6] = [
+        ("y", 31_557_600), // 365.25d
+        ("mo", 2_630_016), // 30.44d
+        ("d", 86400),
+        ("h", 3600),
+        ("m", 60),
+        ("s", 1),
+    ];
+
+    let secs = f.as_secs();
+    let Some(largest) = UNITS.iter().position(|(_, unit_secs)| secs >= *unit_secs) else {
+        return format_duration(f);
+    };
+    let (unit, unit_secs) = UNITS[(largest + 1).min(UNITS.len() - 1)];
+    format!("{}{unit}", secs / unit_secs)
+}
+
+/// Parses a duration such as `30m`, `2h` or `1d12h` into a `Duration`.
+///
+/// Accepts one or more `<number><unit>` pairs, where the unit is one of `s`, `m`, `h`, `d`
+/// or `w`.
+///
+/// # Errors
+///
+/// Returns a message describing the problem if `value` is not a valid duration.
+pub fn parse_duration(value: &str) -> Result<Duration, String> {
+    let value = value.trim();
+    if value.is_empty() {
+        return Err(String::from("expected a duration such as 30m, 2h or 3d"));
+    }
+
+    let mut secs: u64 = 0;
+    let mut rest = value;
+    while !rest.is_empty() {
+        let digits = rest
+            .find(|c: char| !c.is_ascii_digit())
+            .unwrap_or(rest.len());
+        let (number, tail) = rest.split_at(digits);
+        let unit_len = tail
+            .find(|c: char| c.is_ascii_digit())
+            .unwrap_or(tail.len());
+        let (unit, tail) = tail.split_at(unit_len);
+
+        let number = number
+            .parse::<u64>()
+            .map_err(|_| format!("invalid duration '{value}'"))?;
+        let unit_secs = match unit {
+            "s" => 1,
+            "m" => 60,
+            "h" => 3600,
+            "d" => 86400,
+            "w" => 604_800,
+            _ => {
+                return Err(format!(
+                    "invalid duration unit '{unit}', expected s, m, h, d or w"
+                ));
+            }
+        };
+        secs = number
+            .checked_mul(unit_secs)
+            .and_then(|unit_total| secs.checked_add(unit_total))
+            .ok_or_else(|| format!("duration '{value}' is too large"))?;
+        rest = tail;
+    }
+    Ok(Duration::from_secs(secs))
+}
+
 fn format_duration_into(dur: std::time::Duration, f: &mut fmt::Formatter<'_>) -> fmt::Result {
     fn item(unit: &'static str, value: u64) -> ControlFlow<(&'static str, u64)> {
         if value > 0 {
@@ -92,3 +178,36 @@ fn format_duration_into(dur: std::time::Duration, f: &mut fmt::Formatter<'_>) ->
         ControlFlow::Continue(()) => write!(f, "0s"),
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_duration() {
+        assert_eq!(parse_duration("30m"), Ok(Duration::from_secs(30 * 60)));
+        assert_eq!(parse_duration("2h"), Ok(Duration::from_secs(2 * 3600)));
+        assert_eq!(parse_duration("3d"), Ok(Duration::from_secs(3 * 86400)));
+        assert_eq!(parse_duration("1w"), Ok(Duration::from_secs(604_800)));
+        assert_eq!(parse_duration("1h30m"), Ok(Duration::from_secs(5400)));
+        assert!(parse_duration("").is_err());
+        assert!(parse_duration("2").is_err());
+        assert!(parse_duration("h").is_err());
+        assert!(parse_duration("2y").is_err());
+        assert!(parse_duration("99999999999999999999w").is_err());
+    }
+
+    #[test]
+    fn test_format_duration_exact() {
+        assert_eq!(format_duration_exact(Duration::from_millis(500)), "500ms");
+        assert_eq!(format_duration_exact(Duration::from_secs(0)), "0s");
+        assert_eq!(format_duration_exact(Duration::from_secs(5)), "5s");
+        assert_eq!(format_duration_exact(Duration::from_secs(75)), "75s");
+        assert_eq!(format_duration_exact(Duration::from_secs(5400)), "90m");
+        assert_eq!(format_duration_exact(Duration::from_secs(2 * 86400)), "48h");
+        assert_eq!(
+            format_duration_exact(Duration::from_secs(40_000_000)),
+            "15mo"
+        );
+    }
+}
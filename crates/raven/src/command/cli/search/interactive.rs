@@ -1,56 +1,194 @@
 use std::io::{self};
+use std::path::Path;
+use std::time::Instant;
 
 use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
 use ratatui::layout::Position;
 use ratatui::widgets::ListState;
-use ratatui::{Terminal, prelude::CrosstermBackend};
+use ratatui::{Terminal, TerminalOptions, Viewport, prelude::CrosstermBackend};
 use raven_common::utils;
-use raven_database::MatchMode;
 use raven_database::history::model::History;
+use raven_database::{Context, MatchMode, OrderBy};
 
-use super::app::{AppState, Scope, SearchApp};
+use super::app::{AcceptMode, AppState, Marking, Scope, SearchApp};
 use super::event::{Event, EventHandler};
-use super::tui::Tui;
+use super::tui::{Screen, Tui};
 
+/// The fewest rows the inline search is rendered in, enough for the query box and a few results.
+const MIN_INLINE_HEIGHT: u16 = 8;
+
+/// Milliseconds between ticks when no search is debounced.
+const TICK_RATE: u64 = 250;
+
+/// The shortest tick in milliseconds, however short the search debounce is.
+const MIN_TICK_RATE: u64 = 10;
+
+/// Run the interactive search and return the selected `History`, if any, with how it was
+/// accepted.
+///
+/// * `order`: The initial order of the results.
+/// * `screen`: Where the search is drawn in the terminal.
+/// * `cursor`: The character position of the cursor in the query, its end when `None`.
+/// * `up_key`: Opened by the up-key, which without a query lists the latest distinct commands
+///   of every directory first.
 #[allow(clippy::unnecessary_wraps)]
-pub fn history(query: &[String], mode: MatchMode) -> Option<History> {
-    let mut app = SearchApp::new(query.join(" "));
+pub fn history(
+    context: &mut Context,
+    query: &[String],
+    mode: MatchMode,
+    order: OrderBy,
+    screen: Screen,
+    cursor: Option<usize>,
+    up_key: bool,
+) -> Option<(History, AcceptMode)> {
+    let mut app = search_app(context, query, cursor);
+    let mut scope = Scope::Cwd;
+    if up_key && query.join(" ").is_empty() && order == OrderBy::Recency {
+        // The cache holds the latest commands of every directory.
+        app = app.with_recent_commands();
+        scope = Scope::All;
+    }
+    run(&mut app, scope, mode, order, screen);
+    app.selected.map(|h| (h, app.accept_mode))
+}
+
+/// Run the interactive search, passing every selected `History` to `on_accept` until it quits.
+///
+/// Returns the number of commands selected.
+pub fn keep_open(
+    context: &mut Context,
+    query: &[String],
+    mode: MatchMode,
+    order: OrderBy,
+    screen: Screen,
+    cursor: Option<usize>,
+    on_accept: impl FnMut(&History) + Send + 'static,
+) -> usize {
+    let mut app = search_app(context, query, cursor).with_keep_open(on_accept);
+    run(&mut app, Scope::Cwd, mode, order, screen);
+    app.accepted
+}
+
+/// Create the app for searching `query`, with the cursor at `cursor` if given.
+fn search_app<'a>(
+    context: &'a mut Context,
+    query: &[String],
+    cursor: Option<usize>,
+) -> SearchApp<'a> {
+    let app = SearchApp::new(query.join(" "), context);
+    match cursor {
+        Some(position) => app.with_cursor(position),
+        None => app,
+    }
+}
+
+/// Run the interactive search to mark and delete entries in bulk.
+///
+/// Returns the marking state once the search quits, holding the entries deleted or, with
+/// `dry_run`, the entries which would have been deleted.
+pub fn clean(context: &mut Context, query: &[String], mode: MatchMode, dry_run: bool) -> Marking {
+    let mut app = SearchApp::new(query.join(" "), context).with_marking();
+    if dry_run {
+        app = app.with_dry_run();
+    }
+    run(
+        &mut app,
+        Scope::All,
+        mode,
+        OrderBy::default(),
+        Screen::Alternate,
+    );
+    app.marking.unwrap_or_default()
+}
 
+/// Draw `app` and handle its events until it quits.
+///
+/// * `scope`: The history scope to start in.
+/// * `order`: The order to list results in at first.
+/// * `screen`: Where the search is drawn in the terminal.
+fn run(app: &mut SearchApp, scope: Scope, mode: MatchMode, order: OrderBy, screen: Screen) {
     // Establish initial cursor state, this will get updated each draw.
+    let cwd = utils::get_current_dir();
+    let project_root =
+        utils::find_repo_root(Path::new(&cwd)).map(|root| root.display().to_string());
     let mut app_state = AppState {
         cusor_position: Position::default(),
         list_state: ListState::default(),
-        scope: Scope::Cwd,
-        cwd: utils::get_current_dir(),
+        scope,
+        cwd,
+        session_id: utils::get_session_id(),
+        project_root,
         confirming_delete: false,
         mode,
+        order,
+        session_picker: None,
+        show_preview: false,
+        inline: matches!(screen, Screen::Inline(_)),
+        showing_help: false,
+        status: None,
     };
 
     // Fetch initial list
-    app.get_history(&app_state);
+    app.get_history(&mut app_state);
 
     let backend = CrosstermBackend::new(io::stderr());
-    let terminal = Terminal::new(backend).unwrap();
-    let events = EventHandler::new(250);
-    let mut tui = Tui::new(terminal, events);
+    let terminal = match screen {
+        Screen::Inline(height) => Terminal::with_options(
+            backend,
+            TerminalOptions {
+                viewport: Viewport::Inline(height.max(MIN_INLINE_HEIGHT)),
+            },
+        ),
+        Screen::Alternate | Screen::Main => Terminal::new(backend),
+    }
+    .unwrap();
+    // Tick often enough for a debounced search to run soon after typing stops.
+    let tick_rate = match u64::try_from(app.debounce().as_millis()) {
+        Ok(0) | Err(_) => TICK_RATE,
+        Ok(debounce) => debounce.clamp(MIN_TICK_RATE, TICK_RATE),
+    };
+    let events = EventHandler::new(tick_rate);
+    let mut tui = Tui::new(terminal, events, screen);
     tui.init().unwrap();
 
     app_state.list_state.select_first();
 
     while app.running {
-        tui.draw(&mut app, &mut app_state).unwrap();
+        tui.draw(app, &mut app_state).unwrap();
         match tui.events.next().unwrap() {
-            Event::Key(key_event) => handle_key_events(key_event, &mut app, &mut app_state),
-            Event::Mouse(_) | Event::Resize(_, _) | Event::Tick => {}
+            Event::Key(key_event) => handle_key_events(key_event, app, &mut app_state),
+            Event::Tick => {
+                let now = Instant::now();
+                SearchApp::expire_status(&mut app_state, now);
+                app.search_if_idle(&mut app_state, now);
+            }
+            Event::Mouse(_) | Event::Resize(_, _) => {}
         }
     }
     tui.exit().unwrap();
-
-    app.selected
 }
 
 /// Handles the key events and updates the state of [`App`].
 pub fn handle_key_events(key_event: KeyEvent, app: &mut SearchApp, state: &mut AppState) {
+    SearchApp::clear_status(state);
+    if state.showing_help {
+        handle_help_key_events(key_event, state);
+        return;
+    }
+    if state.session_picker.is_some() {
+        handle_session_picker_key_events(key_event, app, state);
+        return;
+    }
+
+    // Anything but typing acts on the listed commands, so they must match the input first.
+    if !matches!(
+        (key_event.modifiers, key_event.code),
+        (KeyModifiers::NONE | KeyModifiers::SHIFT, KeyCode::Char(_))
+            | (KeyModifiers::NONE, KeyCode::Backspace)
+    ) {
+        app.flush_search(state);
+    }
+
     match (key_event.modifiers, key_event.code) {
         // Exit application on `ESC` or `q`
         (KeyModifiers::NONE, KeyCode::Esc) => {
@@ -62,9 +200,26 @@ pub fn handle_key_events(key_event: KeyEvent, app: &mut SearchApp, state: &mut A
             match state.mode {
                 MatchMode::Prefix => state.mode = MatchMode::Fuzzy,
                 MatchMode::Fuzzy => state.mode = MatchMode::Prefix,
+                MatchMode::FuzzyAny | MatchMode::Raw | MatchMode::Regex => {
+                    state.mode = MatchMode::Fuzzy;
+                }
             }
             app.get_history(state);
         }
+        (KeyModifiers::CONTROL, KeyCode::Char('s')) => {
+            state.order = match state.order {
+                OrderBy::Recency => OrderBy::Relevance,
+                OrderBy::Relevance => OrderBy::Recency,
+            };
+            app.get_history(state);
+        }
+        // `?` is typed as usual once there is a query, so commands containing it can be found.
+        (KeyModifiers::NONE | KeyModifiers::SHIFT, KeyCode::Char('?'))
+            if app.input_is_empty() && !state.confirming_delete =>
+        {
+            SearchApp::open_help(state);
+        }
+        (KeyModifiers::NONE, KeyCode::F(1)) => SearchApp::open_help(state),
         (KeyModifiers::NONE | KeyModifiers::SHIFT, KeyCode::Char(to_insert)) => {
             if state.confirming_delete {
                 match to_insert {
@@ -77,37 +232,74 @@ pub fn handle_key_events(key_event: KeyEvent, app: &mut SearchApp, state: &mut A
             }
         }
         // Add keybinding for delete
-        (KeyModifiers::ALT, KeyCode::Char('d')) => {
-            if state.list_state.selected().is_some() {
-                SearchApp::initiate_delete(state);
-            }
+        (KeyModifiers::ALT, KeyCode::Char('d')) if state.list_state.selected().is_some() => {
+            app.initiate_delete(state);
         }
-        (KeyModifiers::ALT, KeyCode::Char(shortcut)) => {
+        (KeyModifiers::ALT, KeyCode::Char('x')) if app.marking.is_some() => app.toggle_mark(state),
+        (KeyModifiers::ALT, KeyCode::Char('a')) if app.marking.is_some() => app.toggle_mark_all(),
+        (KeyModifiers::ALT, KeyCode::Char('s')) => app.open_session_picker(state),
+        (KeyModifiers::ALT, KeyCode::Char('p')) => state.show_preview = !state.show_preview,
+        (KeyModifiers::ALT, KeyCode::Char(shortcut)) if app.marking.is_none() => {
             let shortcuts = ['1', '2', '3', '4', '5'];
 
-            if shortcuts.contains(&shortcut) {
-                if let Some(offset) = shortcut.to_digit(10) {
-                    let current = state.list_state.selected().unwrap_or(0);
-                    let pos = current + offset as usize;
-                    app.select(pos);
-                }
+            if shortcuts.contains(&shortcut)
+                && let Some(offset) = shortcut.to_digit(10)
+            {
+                let current = state.list_state.selected().unwrap_or(0);
+                let pos = current + offset as usize;
+                app.select(pos, AcceptMode::Execute);
             }
         }
         (KeyModifiers::NONE, KeyCode::Backspace) => app.delete_char(state),
+        (KeyModifiers::NONE, KeyCode::Enter) if app.marking.is_some() => {
+            app.initiate_delete(state);
+        }
         (KeyModifiers::NONE, KeyCode::Enter) => {
             if let Some(idx) = state.list_state.selected() {
-                app.select(idx);
+                app.select(idx, AcceptMode::Execute);
             }
         }
-        (KeyModifiers::NONE, KeyCode::Up) => state.list_state.select_next(),
-        (KeyModifiers::NONE, KeyCode::Down) => state.list_state.select_previous(),
-        (KeyModifiers::NONE, KeyCode::Tab) => {
-            match state.scope {
-                super::app::Scope::Cwd => state.scope = Scope::All,
-                super::app::Scope::All => state.scope = Scope::Cwd,
+        (KeyModifiers::CONTROL, KeyCode::Char('e')) if app.marking.is_none() => {
+            if let Some(idx) = state.list_state.selected() {
+                app.select(idx, AcceptMode::Buffer);
             }
-            app.get_history(state);
         }
+        (KeyModifiers::CONTROL, KeyCode::Char('y')) => app.copy_selected(state),
+        (KeyModifiers::NONE, KeyCode::Up) => {
+            state.list_state.select_next();
+            app.load_more(state);
+        }
+        (KeyModifiers::NONE, KeyCode::Down) => state.list_state.select_previous(),
+        (KeyModifiers::NONE, KeyCode::Tab) => app.toggle_scope(state),
+        _ => {}
+    }
+}
+
+/// Handles the key events while the help overlay is open, any other key is ignored.
+fn handle_help_key_events(key_event: KeyEvent, state: &mut AppState) {
+    if matches!(
+        (key_event.modifiers, key_event.code),
+        (KeyModifiers::NONE, KeyCode::Esc | KeyCode::F(1))
+            | (KeyModifiers::NONE | KeyModifiers::SHIFT, KeyCode::Char('?'))
+    ) {
+        SearchApp::close_help(state);
+    }
+}
+
+/// Handles the key events while the session picker overlay is open.
+fn handle_session_picker_key_events(
+    key_event: KeyEvent,
+    app: &mut SearchApp,
+    state: &mut AppState,
+) {
+    let Some(picker) = state.session_picker.as_mut() else {
+        return;
+    };
+    match (key_event.modifiers, key_event.code) {
+        (KeyModifiers::NONE, KeyCode::Esc) => SearchApp::close_session_picker(state),
+        (KeyModifiers::NONE, KeyCode::Up) => picker.list_state.select_previous(),
+        (KeyModifiers::NONE, KeyCode::Down) => picker.list_state.select_next(),
+        (KeyModifiers::NONE, KeyCode::Enter) => app.pick_session(state),
         _ => {}
     }
 }
@@ -6,6 +6,18 @@ use ratatui::Terminal;
 use ratatui::backend::Backend;
 use std::io;
 
+/// Where the interface is drawn in the terminal.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Screen {
+    /// Fullscreen on the alternate screen, leaving the shell untouched afterward.
+    Alternate,
+    /// Inline in this many rows at the bottom of the terminal, cleared afterward.
+    Inline(u16),
+    /// Fullscreen on the main screen without mouse capture, cleared afterward. For terminals
+    /// without a working alternate screen, and for debugging.
+    Main,
+}
+
 /// Representation of a terminal user interface.
 ///
 /// It is responsible for setting up the terminal,
@@ -16,12 +28,20 @@ pub struct Tui<B: Backend> {
     terminal: Terminal<B>,
     /// Terminal event handler.
     pub events: EventHandler,
+    /// Where the interface is drawn.
+    screen: Screen,
 }
 
 impl<B: Backend> Tui<B> {
     /// Constructs a new instance of [`Tui`].
-    pub fn new(terminal: Terminal<B>, events: EventHandler) -> Self {
-        Self { terminal, events }
+    ///
+    /// * `screen`: Where `terminal` draws, only the alternate screen is entered.
+    pub fn new(terminal: Terminal<B>, events: EventHandler, screen: Screen) -> Self {
+        Self {
+            terminal,
+            events,
+            screen,
+        }
     }
 
     /// Initializes the terminal interface.
@@ -29,7 +49,13 @@ impl<B: Backend> Tui<B> {
     /// It enables the raw mode and sets terminal properties.
     pub fn init(&mut self) -> AppResult<()> {
         terminal::enable_raw_mode()?;
-        crossterm::execute!(io::stderr(), EnterAlternateScreen, EnableMouseCapture)?;
+        match self.screen {
+            Screen::Alternate => {
+                crossterm::execute!(io::stderr(), EnterAlternateScreen, EnableMouseCapture)?;
+            }
+            Screen::Inline(_) => crossterm::execute!(io::stderr(), EnableMouseCapture)?,
+            Screen::Main => {}
+        }
         // self.terminal.hide_cursor()?;
         self.terminal.clear()?;
         Ok(())
@@ -40,6 +66,7 @@ impl<B: Backend> Tui<B> {
     /// [`Draw`]: tui::Terminal::draw
     /// [`rendering`]: crate::ui:render
     pub fn draw(&mut self, app: &mut SearchApp, app_state: &mut AppState) -> AppResult<()> {
+        app.update_neighbors(app_state);
         self.terminal.draw(|frame| {
             frame.render_stateful_widget_ref(app, frame.area(), app_state);
             frame.set_cursor_position(app_state.cusor_position);
@@ -50,10 +77,19 @@ impl<B: Backend> Tui<B> {
     /// Exits the terminal interface.
     ///
     /// It disables the raw mode and reverts back the terminal properties.
-    #[allow(clippy::unused_self)]
-    pub fn exit(&self) -> AppResult<()> {
+    pub fn exit(&mut self) -> AppResult<()> {
+        if self.screen != Screen::Alternate {
+            // Leave the cursor at the top of the cleared viewport, where the prompt continues.
+            self.terminal.clear()?;
+        }
         terminal::disable_raw_mode()?;
-        crossterm::execute!(io::stderr(), LeaveAlternateScreen, DisableMouseCapture)?;
+        match self.screen {
+            Screen::Alternate => {
+                crossterm::execute!(io::stderr(), LeaveAlternateScreen, DisableMouseCapture)?;
+            }
+            Screen::Inline(_) => crossterm::execute!(io::stderr(), DisableMouseCapture)?,
+            Screen::Main => {}
+        }
         // self.terminal.show_cursor()?;
         Ok(())
     }
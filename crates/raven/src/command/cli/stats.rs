@@ -0,0 +1,172 @@
+//! Stats module for showing when and which commands are run.
+use clap::Parser;
+use log::error;
+use raven_database::{
+    Context, HistoryFilters,
+    history::timeline::{Activity, CommandCounts},
+};
+use time::{OffsetDateTime, UtcOffset};
+
+use super::import::parse_tz_offset;
+
+/// The width of the longest bar in a histogram.
+const BAR_WIDTH: usize = 40;
+
+/// The names of the weekdays, in the order of `Activity::by_weekday`.
+const WEEKDAYS: [&str; 7] = ["Mon", "Tue", "Wed", "Thu", "Fri", "Sat", "Sun"];
+
+/// Print histograms of how many commands were run by hour of the day and by day of the week, and
+/// of the most run commands.
+///
+/// * `by_hour`: Show the commands run in each hour of the day.
+/// * `by_weekday`: Show the commands run on each day of the week.
+/// * `top`: Show this many of the most run commands.
+/// * `tz_offset`: The UTC offset hours and days are counted in, the local one by default.
+/// * `cwd`: Only count commands run in the current directory.
+#[derive(Parser, Debug)]
+#[command(group = clap::ArgGroup::new("view").required(true).multiple(true))]
+pub struct Cmd {
+    /// Show the number of commands run in each hour of the day
+    #[arg(long, group = "view")]
+    by_hour: bool,
+
+    /// Show the number of commands run on each day of the week
+    #[arg(long, group = "view")]
+    by_weekday: bool,
+
+    /// Show the N most run commands, grouping those which only differ in whitespace, or a leading
+    /// sudo with `[stats] strip_sudo`
+    #[arg(long, value_name = "N", group = "view")]
+    top: Option<usize>,
+
+    /// Offset from UTC to count hours and days in, e.g. +02:00. Defaults to the local offset at
+    /// the time each command ran, or UTC when it cannot be determined
+    #[arg(long, value_parser = parse_tz_offset, allow_hyphen_values = true)]
+    tz_offset: Option<UtcOffset>,
+
+    /// Only count commands run in the current directory
+    #[arg(long)]
+    cwd: bool,
+}
+
+impl Cmd {
+    /// Command runner to print the stats histograms.
+    pub fn run(self, context: &mut Context) {
+        let filters = HistoryFilters {
+            cwd: self.cwd.then(|| context.cwd.clone()),
+            ..Default::default()
+        };
+
+        let mut warned = false;
+        let mut activity = Activity::default();
+        let mut counts = CommandCounts::new(context.config.strip_sudo());
+        if let Err(err) = context.db.search_each("", filters, &mut |h| {
+            let offset = self
+                .tz_offset
+                .unwrap_or_else(|| local_offset_at(h.timestamp, &mut warned));
+            activity.push(&h, offset);
+            counts.push(&h);
+        }) {
+            error!("unable to read history for stats: {err}");
+            std::process::exit(1);
+        }
+
+        let mut sections = Vec::new();
+        if self.by_hour {
+            let hours: Vec<(String, usize)> = activity
+                .by_hour
+                .iter()
+                .enumerate()
+                .map(|(hour, &count)| (format!("{hour:02}"), count))
+                .collect();
+            sections.push(format!("Commands by hour\n{}", histogram(&hours)));
+        }
+        if self.by_weekday {
+            let days: Vec<(String, usize)> = WEEKDAYS
+                .iter()
+                .zip(activity.by_weekday)
+                .map(|(day, count)| ((*day).to_string(), count))
+                .collect();
+            sections.push(format!("Commands by weekday\n{}", histogram(&days)));
+        }
+        if let Some(n) = self.top {
+            let top = counts.top(n);
+            // Padded here, as commands read better aligned to the left than the histogram labels.
+            let width = top
+                .iter()
+                .map(|(command, _)| command.len())
+                .max()
+                .unwrap_or(0);
+            let commands: Vec<(String, usize)> = top
+                .into_iter()
+                .map(|(command, count)| (format!("{command:<width$}"), count))
+                .collect();
+            sections.push(format!("Most run commands\n{}", histogram(&commands)));
+        }
+        print!("{}", sections.join("\n"));
+    }
+}
+
+/// The local UTC offset at `timestamp`, so hours are counted on the wall clock of the time even
+/// across daylight saving changes.
+///
+/// Falls back to UTC when the local offset cannot be determined, warning about it only once.
+///
+/// * `timestamp`: When the command ran.
+/// * `warned`: Whether the fallback has already been warned about.
+fn local_offset_at(timestamp: OffsetDateTime, warned: &mut bool) -> UtcOffset {
+    UtcOffset::local_offset_at(timestamp).unwrap_or_else(|err| {
+        if !*warned {
+            eprintln!("raven: unable to determine the local UTC offset, counting in UTC: {err}");
+            *warned = true;
+        }
+        UtcOffset::UTC
+    })
+}
+
+/// Render `rows` of labels and counts as a bar chart, one line per row.
+///
+/// Bars are scaled so the largest count fills `BAR_WIDTH`, and any non-zero count gets at least
+/// one block.
+fn histogram(rows: &[(String, usize)]) -> String {
+    let max = rows.iter().map(|(_, count)| *count).max().unwrap_or(0);
+    let label_width = rows.iter().map(|(label, _)| label.len()).max().unwrap_or(0);
+    rows.iter()
+        .map(|(label, count)| {
+            let len = if max == 0 {
+                0
+            } else {
+                (count * BAR_WIDTH).div_ceil(max)
+            };
+            let bar = "█".repeat(len);
+            format!("{label:>label_width$} {bar:<BAR_WIDTH$} {count}\n")
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_histogram() {
+        let rows = [
+            ("Mon".to_string(), 80),
+            ("Tue".to_string(), 20),
+            ("Wed".to_string(), 1),
+            ("Thu".to_string(), 0),
+        ];
+        let lines: Vec<String> = histogram(&rows).lines().map(str::to_string).collect();
+        assert_eq!(lines.len(), 4);
+        assert_eq!(lines[0], format!("Mon {} 80", "█".repeat(40)));
+        assert_eq!(
+            lines[1],
+            format!("Tue {}{} 20", "█".repeat(10), " ".repeat(30))
+        );
+        assert_eq!(lines[2], format!("Wed █{} 1", " ".repeat(39)));
+        assert_eq!(lines[3], format!("Thu {} 0", " ".repeat(40)));
+
+        let empty = [("00".to_string(), 0)];
+        assert_eq!(histogram(&empty), format!("00 {} 0\n", " ".repeat(40)));
+    }
+}
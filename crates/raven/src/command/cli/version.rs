@@ -0,0 +1,48 @@
+//! Version module for reporting the raven version and diagnostics.
+use clap::Parser;
+use raven_common::utils::{get_config_dir, get_data_dir};
+use raven_database::{Context, database::sqlite::Sqlite};
+
+use super::pause;
+
+const VERSION: &str = env!("CARGO_PKG_VERSION");
+
+/// Print the raven version, optionally with details useful for bug reports.
+///
+/// * `verbose`: Include the resolved directories and database information.
+#[derive(Parser, Debug)]
+pub struct Cmd {
+    /// Include resolved directories and database information
+    #[arg(long, short)]
+    verbose: bool,
+}
+
+impl Cmd {
+    /// Command runner to print the version information.
+    pub fn run(self, context: &mut Context) {
+        println!("raven {VERSION}");
+        if !self.verbose {
+            return;
+        }
+
+        println!("config dir: {}", get_config_dir().display());
+        println!("data dir: {}", get_data_dir().display());
+        println!(
+            "database: {}",
+            Sqlite::database_path(&context.config).display()
+        );
+        println!("database backend: {}", context.db.backend_name());
+
+        match context.db.schema_version() {
+            Ok(version) => println!("database schema: v{version}"),
+            Err(err) => println!("database schema: unknown ({err})"),
+        }
+
+        match context.db.get_history_total() {
+            Ok(total) => println!("history count: {total}"),
+            Err(err) => println!("history count: unknown ({err})"),
+        }
+
+        println!("recording: {}", pause::describe(&pause::state_path()));
+    }
+}
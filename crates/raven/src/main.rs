@@ -1,11 +1,14 @@
-use std::fs::{self, OpenOptions};
+use std::{
+    fs::{self, OpenOptions},
+    path::PathBuf,
+};
 
 use clap::Parser;
 use command::RavenCmd;
 use env_logger::{Builder, Env, Target};
 use log::debug;
-use raven_common::utils::get_data_dir;
-use raven_database::current_context;
+use raven_common::{config::load_config, utils::get_data_dir};
+use raven_database::Context;
 mod command;
 
 const VERSION: &str = env!("CARGO_PKG_VERSION");
@@ -30,13 +33,21 @@ static HELP_TEMPLATE: &str = "\
     help_template(HELP_TEMPLATE),
 )]
 struct Raven {
+    /// Use this database file instead of the configured one
+    #[arg(long, global = true)]
+    database: Option<PathBuf>,
+
     #[command(subcommand)]
     raven: RavenCmd,
 }
 
 impl Raven {
     fn run(self) {
-        let mut context = current_context();
+        let mut config = load_config().unwrap_or_default();
+        if let Some(database) = self.database.as_deref() {
+            config.set_database_file(database);
+        }
+        let mut context = Context::new(config);
         debug!("context: {:?}", context.config);
         self.raven.run(&mut context);
     }
@@ -0,0 +1,36 @@
+# Identify this shell, so history can be grouped by the session it was run in.
+set -gx RAVEN_SESSION_ID (hostname)-$fish_pid-(date +%s)
+
+function _raven_preexec --on-event fish_preexec
+    set -gx RAVEN_HISTORY_ID (raven history start -- $argv[1])
+end
+
+function _raven_postexec --on-event fish_postexec
+    set -l EXIT $status
+    test -z "$RAVEN_HISTORY_ID"; and return
+
+    raven history end --exit $EXIT -- $RAVEN_HISTORY_ID
+    # Clear the ID for the next command.
+    set -gx RAVEN_HISTORY_ID ""
+end
+
+function _raven_search_history
+    # The first line says whether to run the command (Enter) or leave it to be edited (Ctrl+e).
+    set -l output (RAVEN_QUERY=(commandline -b) raven search $argv --interactive --accept-marker)
+
+    commandline -f repaint
+
+    if test (count $output) -ge 2
+        commandline -r -- (string join \n -- $output[2..])
+        test "$output[1]" = execute; and commandline -f execute
+    end
+end
+
+function _raven_search_history_up
+    # Only trigger if the buffer is a single line
+    if test (count (commandline -b)) -le 1
+        _raven_search_history --shell-up-key $argv
+    else
+        commandline -f up-line
+    end
+end
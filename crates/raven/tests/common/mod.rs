@@ -0,0 +1,14 @@
+//! Helpers shared by the raven binary integration tests.
+use std::{path::Path, process::Command};
+
+/// Run the raven binary with an isolated home directory.
+pub fn raven(home: &Path, args: &[&str]) -> std::process::Output {
+    Command::new(env!("CARGO_BIN_EXE_raven"))
+        .args(args)
+        .env("HOME", home)
+        .env_remove("XDG_DATA_HOME")
+        .env_remove("XDG_CONFIG_HOME")
+        .env_remove("RAVEN_QUERY")
+        .output()
+        .expect("Failed to run raven")
+}
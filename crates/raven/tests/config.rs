@@ -0,0 +1,32 @@
+//! Integration tests for `raven config`.
+mod common;
+
+use common::raven;
+use tempfile::TempDir;
+
+#[test]
+fn test_config_init() {
+    let home = TempDir::new().expect("Failed to create temp home");
+    let config = home.path().join(".config/raven/config.toml");
+
+    let init = raven(home.path(), &["config", "init"]);
+    assert!(init.status.success());
+    assert!(config.exists());
+
+    // An existing config is never overwritten by accident.
+    std::fs::write(&config, "[ui]\nwrap_preview = false\n").expect("Failed to edit config");
+    let again = raven(home.path(), &["config", "init"]);
+    assert!(!again.status.success());
+    assert_eq!(
+        std::fs::read_to_string(&config).expect("Failed to read config"),
+        "[ui]\nwrap_preview = false\n"
+    );
+
+    let forced = raven(home.path(), &["config", "init", "--force"]);
+    assert!(forced.status.success());
+    assert!(
+        std::fs::read_to_string(&config)
+            .expect("Failed to read config")
+            .contains("# wrap_preview = true")
+    );
+}
@@ -0,0 +1,98 @@
+//! Integration tests for `raven convert`.
+mod common;
+
+use common::raven;
+use tempfile::TempDir;
+
+#[test]
+fn test_convert_zsh_to_jsonl() {
+    let home = TempDir::new().expect("Failed to create temp home");
+    let input = home.path().join("histfile");
+    let output = home.path().join("history.jsonl");
+    std::fs::write(
+        &input,
+        ": 1700000000:0;git status\n: 1700000005:0;echo \\\nhi\n",
+    )
+    .expect("Failed to write histfile");
+
+    let convert = raven(
+        home.path(),
+        &[
+            "convert",
+            "--from",
+            "zsh",
+            "--input",
+            input.to_str().unwrap(),
+            "--to",
+            "jsonl",
+            "--output",
+            output.to_str().unwrap(),
+        ],
+    );
+    assert!(convert.status.success());
+    assert_eq!(
+        std::fs::read_to_string(&output).expect("Failed to read output"),
+        concat!(
+            r#"{"command":"git status","cwd":"unknown","exit":-1,"session_id":"","timestamp":1700000000,"tty":"","source":"import:zsh"}"#,
+            "\n",
+            r#"{"command":"echo \nhi","cwd":"unknown","exit":-1,"session_id":"","timestamp":1700000005,"tty":"","source":"import:zsh"}"#,
+            "\n",
+        )
+    );
+
+    let missing = raven(
+        home.path(),
+        &[
+            "convert",
+            "--from",
+            "jsonl",
+            "--input",
+            home.path().join("missing.jsonl").to_str().unwrap(),
+            "--output",
+            output.to_str().unwrap(),
+        ],
+    );
+    assert!(!missing.status.success());
+    // A failed conversion leaves the existing output in place.
+    assert!(
+        std::fs::read_to_string(&output)
+            .expect("Failed to read output")
+            .starts_with(r#"{"command":"git status""#)
+    );
+}
+
+#[test]
+fn test_convert_zsh_to_csv() {
+    let home = TempDir::new().expect("Failed to create temp home");
+    let input = home.path().join("histfile");
+    let output = home.path().join("history.csv");
+    std::fs::write(
+        &input,
+        ": 1700000000:0;git status\n: 1700000005:0;echo a,b\n",
+    )
+    .expect("Failed to write histfile");
+
+    let convert = raven(
+        home.path(),
+        &[
+            "convert",
+            "--from",
+            "zsh",
+            "--input",
+            input.to_str().unwrap(),
+            "--to",
+            "csv",
+            "--output",
+            output.to_str().unwrap(),
+        ],
+    );
+    assert!(convert.status.success());
+    assert_eq!(
+        std::fs::read_to_string(&output).expect("Failed to read output"),
+        concat!(
+            "id,command,cwd,exit,session_id,timestamp,tty\n",
+            "-1,git status,unknown,-1,,2023-11-14T22:13:20Z,\n",
+            "-1,\"echo a,b\",unknown,-1,,2023-11-14T22:13:25Z,\n",
+        )
+    );
+}
@@ -0,0 +1,37 @@
+//! Integration tests for the global `--database` override.
+mod common;
+
+use common::raven;
+use tempfile::TempDir;
+
+#[test]
+fn test_database_override() {
+    let home = TempDir::new().expect("Failed to create temp home");
+    let db_dir = TempDir::new().expect("Failed to create temp database dir");
+    let database = db_dir.path().join("backup.db");
+    let database = database.to_str().unwrap();
+
+    let start = raven(
+        home.path(),
+        &[
+            "--database",
+            database,
+            "history",
+            "start",
+            "echo",
+            "override",
+        ],
+    );
+    assert!(start.status.success());
+    assert!(db_dir.path().join("backup.db").exists());
+
+    // The override is global, so it is also accepted after the subcommand.
+    let found = raven(home.path(), &["search", "--database", database, "override"]);
+    assert!(found.status.success());
+    assert_eq!(String::from_utf8_lossy(&found.stdout), "echo override\n");
+
+    // The configured database was never written to.
+    let missing = raven(home.path(), &["search", "override"]);
+    assert!(!missing.status.success());
+    assert!(missing.stdout.is_empty());
+}
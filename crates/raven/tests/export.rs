@@ -0,0 +1,63 @@
+//! Integration tests for `raven export`.
+mod common;
+
+use common::raven;
+use tempfile::TempDir;
+
+#[test]
+fn test_export() {
+    let home = TempDir::new().expect("Failed to create temp home");
+    let database = home.path().join("raven.db");
+    let database = database.to_str().unwrap();
+    let run = |args: &[&str]| {
+        let mut with_database = vec!["--database", database];
+        with_database.extend_from_slice(args);
+        raven(home.path(), &with_database)
+    };
+
+    for command in ["git status", "echo a,b"] {
+        let start = run(&["history", "start", "--", command]);
+        assert!(start.status.success());
+    }
+
+    let json = run(&["export", "--format", "json"]);
+    assert!(json.status.success());
+    let rows: serde_json::Value =
+        serde_json::from_slice(&json.stdout).expect("Export should be valid JSON");
+    let mut commands: Vec<&str> = rows
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|row| row["command"].as_str().unwrap())
+        .collect();
+    // Both were run within the same second, so their order is not checked.
+    commands.sort_unstable();
+    assert_eq!(commands, vec!["echo a,b", "git status"]);
+    // RFC 3339 in UTC, e.g. 2025-01-01T12:00:00Z.
+    let timestamp = rows[0]["timestamp"].as_str().unwrap();
+    assert!(
+        timestamp.ends_with('Z') && timestamp.contains('T'),
+        "{timestamp}"
+    );
+
+    let output = home.path().join("history.csv");
+    let csv = run(&[
+        "export",
+        "--format",
+        "csv",
+        "--output",
+        output.to_str().unwrap(),
+    ]);
+    assert!(csv.status.success());
+    assert!(csv.stdout.is_empty());
+    assert_eq!(
+        String::from_utf8_lossy(&csv.stderr),
+        format!("Exported 2 commands to {}\n", output.display())
+    );
+    let written = std::fs::read_to_string(&output).expect("Failed to read the export");
+    let mut lines: Vec<&str> = written.lines().collect();
+    assert_eq!(lines[0], "id,command,cwd,exit,session_id,timestamp,tty");
+    lines[1..].sort_unstable();
+    assert!(lines[1].starts_with("1,git status,"), "{written}");
+    assert!(lines[2].starts_with("2,\"echo a,b\","), "{written}");
+}
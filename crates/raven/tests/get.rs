@@ -0,0 +1,45 @@
+//! Integration tests for `raven get`.
+mod common;
+
+use common::raven;
+use tempfile::TempDir;
+
+#[test]
+fn test_get() {
+    let home = TempDir::new().expect("Failed to create temp home");
+    let database = home.path().join("raven.db");
+    let database = database.to_str().unwrap();
+
+    let start = raven(
+        home.path(),
+        &["--database", database, "history", "start", "echo", "get"],
+    );
+    assert!(start.status.success());
+    let id = String::from_utf8_lossy(&start.stdout).trim().to_string();
+
+    let found = raven(home.path(), &["--database", database, "get", &id]);
+    assert!(found.status.success());
+    assert_eq!(String::from_utf8_lossy(&found.stdout), "echo get\n");
+
+    let json = raven(
+        home.path(),
+        &[
+            "--database",
+            database,
+            "get",
+            &id,
+            "--json",
+            "--fields",
+            "id,command",
+        ],
+    );
+    assert!(json.status.success());
+    assert_eq!(
+        String::from_utf8_lossy(&json.stdout),
+        format!("{{\"command\":\"echo get\",\"id\":{id}}}\n")
+    );
+
+    let missing = raven(home.path(), &["--database", database, "get", "999"]);
+    assert_eq!(missing.status.code(), Some(1));
+    assert!(missing.stdout.is_empty());
+}
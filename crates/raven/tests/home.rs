@@ -0,0 +1,34 @@
+//! Integration tests for running raven without `$HOME`.
+use std::process::Command;
+
+use tempfile::TempDir;
+
+#[test]
+fn test_home_unset_falls_back_to_temp_dir() {
+    let tmp = TempDir::new().expect("Failed to create temp dir");
+    let run = |args: &[&str]| {
+        Command::new(env!("CARGO_BIN_EXE_raven"))
+            .args(args)
+            .env_remove("HOME")
+            .env_remove("XDG_DATA_HOME")
+            .env_remove("XDG_CONFIG_HOME")
+            .env_remove("RAVEN_QUERY")
+            .env("TMPDIR", tmp.path())
+            .output()
+            .expect("Failed to run raven")
+    };
+
+    let start = run(&["history", "start", "echo", "hi"]);
+    assert!(
+        start.status.success(),
+        "{}",
+        String::from_utf8_lossy(&start.stderr)
+    );
+    let search = run(&["search", "--limit", "1"]);
+    assert_eq!(String::from_utf8_lossy(&search.stdout), "echo hi\n");
+    assert!(
+        tmp.path()
+            .join("raven/.local/share/raven/raven.db")
+            .exists()
+    );
+}
@@ -0,0 +1,42 @@
+//! Integration tests for `raven import`.
+mod common;
+
+use common::raven;
+use std::time::{Duration, UNIX_EPOCH};
+
+use tempfile::TempDir;
+
+#[test]
+fn test_import_simple_zsh_twice() {
+    let home = TempDir::new().expect("Failed to create temp home");
+    let histfile = home.path().join(".zsh_history");
+    std::fs::write(&histfile, "git status\nmake test\ncargo build\n")
+        .expect("Failed to write histfile");
+    // The commands are dated counting back from when the file was last written.
+    std::fs::File::options()
+        .write(true)
+        .open(&histfile)
+        .and_then(|file| file.set_modified(UNIX_EPOCH + Duration::from_secs(1_700_000_000)))
+        .expect("Failed to set the histfile modification time");
+
+    let first = raven(home.path(), &["import", "zsh"]);
+    assert!(first.status.success());
+    assert!(
+        String::from_utf8_lossy(&first.stdout)
+            .contains("done! Imported 3 commands, skipped 0 already stored")
+    );
+
+    // The commands have no timestamps, they are dated the same way from the unchanged file.
+    let second = raven(home.path(), &["import", "zsh"]);
+    assert!(second.status.success());
+    assert!(
+        String::from_utf8_lossy(&second.stdout)
+            .contains("done! Imported 0 commands, skipped 3 already stored")
+    );
+
+    let export = raven(home.path(), &["export", "--format", "jsonl"]);
+    assert!(export.status.success());
+    let exported = String::from_utf8_lossy(&export.stdout);
+    assert_eq!(exported.lines().count(), 3);
+    assert!(exported.contains(r#""timestamp":1700000000"#), "{exported}");
+}
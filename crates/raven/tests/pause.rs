@@ -0,0 +1,43 @@
+//! Integration tests for `raven pause` and `raven resume`.
+mod common;
+
+use common::raven;
+use tempfile::TempDir;
+
+#[test]
+fn test_pause_resume() {
+    let home = TempDir::new().expect("Failed to create temp home");
+    let state = home.path().join(".local/share/raven/paused");
+    let count = || {
+        let search = raven(home.path(), &["search", "--limit", "10"]);
+        String::from_utf8_lossy(&search.stdout).lines().count()
+    };
+
+    assert!(
+        raven(home.path(), &["history", "start", "echo", "before"])
+            .status
+            .success()
+    );
+    assert_eq!(count(), 1);
+
+    assert!(raven(home.path(), &["pause"]).status.success());
+    assert!(state.exists());
+    let start = raven(home.path(), &["history", "start", "echo", "paused"]);
+    assert!(start.status.success());
+    assert_eq!(String::from_utf8_lossy(&start.stdout), "\n");
+    assert_eq!(count(), 1);
+
+    let doctor = raven(home.path(), &["doctor"]);
+    assert!(String::from_utf8_lossy(&doctor.stdout).contains("recording: paused"));
+
+    assert!(raven(home.path(), &["resume"]).status.success());
+    assert!(!state.exists());
+    // Resuming when not paused is not an error.
+    assert!(raven(home.path(), &["resume"]).status.success());
+    assert!(
+        raven(home.path(), &["history", "start", "echo", "after"])
+            .status
+            .success()
+    );
+    assert_eq!(count(), 2);
+}
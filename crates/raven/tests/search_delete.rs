@@ -0,0 +1,43 @@
+//! Integration tests for `raven search --delete`.
+mod common;
+
+use common::raven;
+use tempfile::TempDir;
+
+#[test]
+fn test_search_delete() {
+    let home = TempDir::new().expect("Failed to create temp home");
+    let database = home.path().join("raven.db");
+    let database = database.to_str().unwrap();
+    let run = |args: &[&str]| {
+        let mut with_database = vec!["--database", database];
+        with_database.extend_from_slice(args);
+        raven(home.path(), &with_database)
+    };
+
+    for command in [["git", "status"], ["git", "push"], ["ls", "-la"]] {
+        let start = run(&["history", "start", "--", command[0], command[1]]);
+        assert!(start.status.success());
+    }
+
+    // Without a terminal to confirm on, nothing is deleted unless --yes is given.
+    let refused = run(&["search", "--delete", "git"]);
+    assert_eq!(refused.status.code(), Some(1));
+    assert_eq!(
+        String::from_utf8_lossy(&refused.stdout),
+        "2 history entries match.\n"
+    );
+
+    let unfiltered = run(&["search", "--delete", "--yes"]);
+    assert_eq!(unfiltered.status.code(), Some(1));
+
+    let deleted = run(&["search", "--delete", "--yes", "--limit", "1", "git"]);
+    assert!(deleted.status.success());
+    assert_eq!(
+        String::from_utf8_lossy(&deleted.stdout),
+        "2 history entries match.\nDeleted 2 history entries.\n"
+    );
+
+    let left = run(&["search", "--limit", "10"]);
+    assert_eq!(String::from_utf8_lossy(&left.stdout), "ls -la\n");
+}
@@ -0,0 +1,58 @@
+//! Integration tests for `[search] ranker` in the non-interactive searches.
+mod common;
+
+use common::raven;
+use tempfile::TempDir;
+
+#[test]
+fn test_search_ranker() {
+    let home = TempDir::new().expect("Failed to create temp home");
+    let database = home.path().join("raven.db");
+    let database = database.to_str().unwrap();
+    let run = |args: &[&str]| {
+        let mut with_database = vec!["--database", database];
+        with_database.extend_from_slice(args);
+        raven(home.path(), &with_database)
+    };
+
+    // `git status` is run most often, `git push` most recently.
+    for (command, cwd, timestamp) in [
+        ("git status", "/a", 1_700_000_000),
+        ("git status", "/b", 1_700_000_001),
+        ("git push", "/a", 1_700_000_002),
+    ] {
+        let payload = format!(r#"{{"command":"{command}","cwd":"{cwd}","timestamp":{timestamp}}}"#);
+        assert!(
+            run(&["history", "start", "--json", &payload])
+                .status
+                .success()
+        );
+    }
+    let picked = |output: std::process::Output| -> Vec<String> {
+        String::from_utf8_lossy(&output.stdout)
+            .lines()
+            .filter_map(|line| {
+                line.split_once('\t')
+                    .map(|(_, command)| command.to_string())
+            })
+            .collect()
+    };
+
+    assert_eq!(
+        picked(run(&["search", "--fzf", "git"])),
+        ["git push", "git status", "git status"]
+    );
+    let suggested = run(&["search", "--suggest", "git"]);
+    assert_eq!(String::from_utf8_lossy(&suggested.stdout), " push\n");
+
+    let config = home.path().join(".config/raven/config.toml");
+    std::fs::create_dir_all(config.parent().unwrap()).expect("Failed to create config dir");
+    std::fs::write(&config, "[search]\nranker = \"frequency\"\n").expect("Failed to write config");
+
+    assert_eq!(
+        picked(run(&["search", "--fzf", "git"])),
+        ["git status", "git status", "git push"]
+    );
+    let suggested = run(&["search", "--suggest", "git"]);
+    assert_eq!(String::from_utf8_lossy(&suggested.stdout), " status\n");
+}